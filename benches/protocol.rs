@@ -0,0 +1,88 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rs_melsec::client::{get_device_number, get_device_type, Client};
+use rs_melsec::db::DataType;
+
+// largest batch read of words a single request allows
+const MAX_WORD_POINTS: usize = 960;
+
+fn e4_response(payload: &[u8]) -> Vec<u8> {
+    let mut response = vec![
+        0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00,
+    ];
+    response.extend_from_slice(&(payload.len() as u16 + 2).to_le_bytes());
+    response.extend_from_slice(&[0x00, 0x00]);
+    response.extend_from_slice(payload);
+    response
+}
+
+fn frame_build(c: &mut Criterion) {
+    let client = Client::new("127.0.0.1".to_string(), 5000, "Q", true);
+    c.bench_function("batch_read_request", |b| {
+        b.iter(|| {
+            client
+                .batch_read_request(black_box("D100"), MAX_WORD_POINTS, &DataType::UWORD)
+                .unwrap()
+        })
+    });
+
+    let mut client = Client::new("127.0.0.1".to_string(), 5000, "Q", true);
+    client.set_comm_type("ascii");
+    c.bench_function("batch_read_request_ascii", |b| {
+        b.iter(|| {
+            client
+                .batch_read_request(black_box("X1F0"), 64, &DataType::BIT)
+                .unwrap()
+        })
+    });
+}
+
+fn device_parse(c: &mut Criterion) {
+    c.bench_function("device_parse", |b| {
+        b.iter(|| {
+            for device in ["D100", "M8304", "X1F", "ZR123456"] {
+                let device_type = get_device_type(black_box(device)).unwrap();
+                let base = if device_type == "X" { 16 } else { 10 };
+                get_device_number(device, base).unwrap();
+            }
+        })
+    });
+}
+
+fn decode_response(c: &mut Criterion) {
+    let client = Client::new("127.0.0.1".to_string(), 5000, "Q", true);
+    let payload: Vec<u8> = (0..MAX_WORD_POINTS * 2).map(|i| i as u8).collect();
+    let response = e4_response(&payload);
+    c.bench_function("decode_960_words", |b| {
+        b.iter(|| {
+            client
+                .parse_batch_read(
+                    "D0",
+                    MAX_WORD_POINTS,
+                    DataType::UWORD,
+                    true,
+                    response.clone(),
+                )
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        })
+    });
+    c.bench_function("decode_480_dwords", |b| {
+        b.iter(|| {
+            client
+                .parse_batch_read(
+                    "D0",
+                    MAX_WORD_POINTS / 2,
+                    DataType::SDWORD,
+                    true,
+                    response.clone(),
+                )
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, frame_build, device_parse, decode_response);
+criterion_main!(benches);
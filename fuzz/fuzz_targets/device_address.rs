@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rs_melsec::client::{get_device_index, get_device_number, get_device_type, Client};
+use rs_melsec::db::DataType;
+
+fuzz_target!(|data: &[u8]| {
+    let device = match std::str::from_utf8(data) {
+        Ok(device) => device,
+        Err(_) => return,
+    };
+    let _ = get_device_type(device);
+    let _ = get_device_index(device);
+    let _ = get_device_number(device, 10);
+    let _ = get_device_number(device, 16);
+    for plc_type in ["Q", "iQ-R"] {
+        let mut client = Client::new("127.0.0.1".to_string(), 5000, plc_type, false);
+        let _ = client.batch_read_request(device, 1, &DataType::UWORD);
+        client.set_comm_type("ascii");
+        let _ = client.batch_read_request(device, 1, &DataType::BIT);
+    }
+});
@@ -0,0 +1,41 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rs_melsec::client::Client;
+use rs_melsec::db::DataType;
+
+const DATA_TYPES: [DataType; 9] = [
+    DataType::BIT,
+    DataType::SWORD,
+    DataType::UWORD,
+    DataType::SDWORD,
+    DataType::UDWORD,
+    DataType::FLOAT,
+    DataType::DOUBLE,
+    DataType::SLWORD,
+    DataType::ULWORD,
+];
+
+// First byte selects frame type, communication type, data type and decode,
+// second byte the read size; the rest is the response frame.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let (config, response) = data.split_at(2);
+    let mut client = Client::new("127.0.0.1".to_string(), 5000, "Q", config[0] & 1 == 1);
+    if config[0] & 2 == 2 {
+        client.set_comm_type("ascii");
+    }
+    let data_type = DATA_TYPES[(config[0] >> 2) as usize % DATA_TYPES.len()].clone();
+    let decode = config[0] & 0x80 == 0;
+    if let Ok(tags) = client.parse_batch_read(
+        "D0",
+        config[1] as usize,
+        data_type,
+        decode,
+        response.to_vec(),
+    ) {
+        for _ in tags {}
+    }
+});
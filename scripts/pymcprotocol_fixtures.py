@@ -0,0 +1,133 @@
+#!/usr/bin/env python3
+"""Prints PYMCPROTOCOL_FIXTURES of src/test_fixtures.rs.
+
+Every call runs against pymcprotocol with its socket replaced: the request
+is the frame pymcprotocol sends and the response is fed back through its
+own parsing, which must return the expected values. Paste the output over
+the table and run `cargo fmt`.
+
+    pip install pymcprotocol==0.3.0
+    python3 scripts/pymcprotocol_fixtures.py
+"""
+
+from importlib.metadata import version
+
+import pymcprotocol
+
+PYMCPROTOCOL_VERSION = "0.3.0"
+
+# call as written in the table comment, the call itself, response data
+# after the end code (binary, ASCII), value returned and frame variants
+CALLS = [
+    (
+        'batchread_wordunits("D100", 3) -> [1, 2, 3]',
+        "batchread_wordunits",
+        lambda plc: plc.batchread_wordunits("D100", 3),
+        (bytes.fromhex("010002000300"), b"000100020003"),
+        [1, 2, 3],
+        [(False, "binary"), (True, "binary"), (False, "ascii")],
+    ),
+    (
+        'batchwrite_wordunits("D10", [1, -1])',
+        "batchwrite_wordunits",
+        lambda plc: plc.batchwrite_wordunits("D10", [1, -1]),
+        (b"", b""),
+        None,
+        [(False, "binary")],
+    ),
+    (
+        'batchwrite_bitunits("M0", [1, 0, 1])',
+        "batchwrite_bitunits",
+        lambda plc: plc.batchwrite_bitunits("M0", [1, 0, 1]),
+        (b"", b""),
+        None,
+        [(False, "binary")],
+    ),
+    (
+        'randomread(["D1000", "D2000"], ["D3000"]) -> [10, 20], [70000]',
+        "randomread",
+        lambda plc: plc.randomread(["D1000", "D2000"], ["D3000"]),
+        (bytes.fromhex("0A00140070110100"), b"000A001400011170"),
+        ([10, 20], [70000]),
+        [(False, "binary"), (False, "ascii")],
+    ),
+    (
+        'randomwrite(["D1000"], [5], ["D3000"], [70000])',
+        "randomwrite",
+        lambda plc: plc.randomwrite(["D1000"], [5], ["D3000"], [70000]),
+        (b"", b""),
+        None,
+        [(False, "binary"), (False, "ascii")],
+    ),
+    (
+        "remote_run(clear_mode=0, force_exec=False)",
+        "remote_run",
+        lambda plc: plc.remote_run(clear_mode=0, force_exec=False),
+        (b"", b""),
+        None,
+        [(False, "binary")],
+    ),
+    (
+        "remote_stop()",
+        "remote_stop",
+        lambda plc: plc.remote_stop(),
+        (b"", b""),
+        None,
+        [(False, "binary")],
+    ),
+]
+
+
+# Response of a Q series PLC with pymcprotocol's default header, end code 0
+def response(use_e4, comm_type, data):
+    if comm_type == "binary":
+        length = (2 + len(data)).to_bytes(2, "little")
+        head = bytes.fromhex("D40000000000" if use_e4 else "D000")
+        return head + bytes.fromhex("00FFFF0300") + length + b"\x00\x00" + data
+    head = b"D4000000000000" if use_e4 else b"D000"
+    return head + b"00FF03FF00" + b"%04X" % (4 + len(data)) + b"0000" + data
+
+
+def capture(call, use_e4, comm_type, data):
+    plc = (pymcprotocol.Type4E if use_e4 else pymcprotocol.Type3E)(plctype="Q")
+    plc.setaccessopt(commtype=comm_type)
+    sent = []
+    plc._is_connected = True
+    plc._send = sent.append
+    plc._recv = lambda: response(use_e4, comm_type, data)
+    result = call(plc)
+    return sent[0], result
+
+
+def rust_frame(frame, comm_type):
+    if comm_type == "ascii":
+        return 'b"%s"' % frame.decode()
+    return "&[%s]" % ", ".join("0x%02X" % b for b in frame)
+
+
+def main():
+    if version("pymcprotocol") != PYMCPROTOCOL_VERSION:
+        raise SystemExit("needs pymcprotocol %s" % PYMCPROTOCOL_VERSION)
+    print("pub const PYMCPROTOCOL_FIXTURES: &[Fixture] = &[")
+    for comment, name, call, data, expected, variants in CALLS:
+        print("    // %s" % comment)
+        for use_e4, comm_type in variants:
+            answer = data[0] if comm_type == "binary" else data[1]
+            request, result = capture(call, use_e4, comm_type, answer)
+            if result != expected:
+                raise SystemExit("%s returned %r" % (name, result))
+            print("    Fixture {")
+            print('        name: "%s",' % name)
+            print("        use_e4: %s," % str(use_e4).lower())
+            print("        comm_type: consts::COMMTYPE_%s," % comm_type.upper())
+            print("        request: %s," % rust_frame(request, comm_type))
+            print(
+                "        response: %s,"
+                % rust_frame(response(use_e4, comm_type, answer), comm_type)
+            )
+            print("    },")
+    print("];")
+
+
+if __name__ == "__main__":
+    main()
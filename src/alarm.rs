@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::SystemTime;
+
+use super::client::{get_device_index, get_device_type, Client};
+use super::tag_map::TagMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlarmEdge {
+    Raised,
+    Cleared,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlarmEvent {
+    pub device: String,
+    pub label: Option<String>,
+    pub edge: AlarmEdge,
+    pub activated_at: SystemTime,
+    pub cleared_at: Option<SystemTime>,
+}
+
+// Watches bit ranges and reports rising/falling edges between scans
+#[derive(Debug, Default)]
+pub struct AlarmScanner {
+    ranges: Vec<(String, i32, usize)>,
+    labels: HashMap<String, String>,
+    active: HashMap<String, SystemTime>,
+}
+
+impl AlarmScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watches `count` bits starting at `start_device`, e.g. ("M1000", 256).
+    pub fn add_range(mut self, start_device: &str, count: usize) -> Result<Self, Box<dyn Error>> {
+        let device_type = get_device_type(start_device)?;
+        let device_index = get_device_index(start_device)?;
+        self.ranges.push((device_type, device_index, count));
+        Ok(self)
+    }
+
+    /// Uses the tag names of `tag_map` as alarm labels.
+    pub fn with_labels(mut self, tag_map: &TagMap) -> Self {
+        for entry in tag_map.iter() {
+            self.labels.insert(entry.device.clone(), entry.name.clone());
+        }
+        self
+    }
+
+    pub fn active_alarms(&self) -> Vec<String> {
+        self.active.keys().cloned().collect()
+    }
+
+    pub fn scan(&mut self, client: &Client) -> Result<Vec<AlarmEvent>, Box<dyn Error>> {
+        let mut events = Vec::new();
+        for range_index in 0..self.ranges.len() {
+            let (ref device_type, device_index, count) = self.ranges[range_index];
+            let start_device = format!("{}{}", device_type, device_index);
+            let mut bits = vec![false; count];
+            client.batch_read_bits_into(&start_device, &mut bits)?;
+            events.extend(self.update(range_index, &bits, SystemTime::now()));
+        }
+        Ok(events)
+    }
+
+    fn update(&mut self, range_index: usize, bits: &[bool], now: SystemTime) -> Vec<AlarmEvent> {
+        let (ref device_type, device_index, _) = self.ranges[range_index];
+        let mut events = Vec::new();
+        for (offset, bit) in bits.iter().enumerate() {
+            let device = format!("{}{}", device_type, device_index + offset as i32);
+            let event = match (*bit, self.active.get(&device)) {
+                (true, None) => {
+                    self.active.insert(device.clone(), now);
+                    Some((AlarmEdge::Raised, now, None))
+                }
+                (false, Some(activated_at)) => {
+                    let activated_at = *activated_at;
+                    self.active.remove(&device);
+                    Some((AlarmEdge::Cleared, activated_at, Some(now)))
+                }
+                _ => None,
+            };
+            if let Some((edge, activated_at, cleared_at)) = event {
+                events.push(AlarmEvent {
+                    label: self.labels.get(&device).cloned(),
+                    device,
+                    edge,
+                    activated_at,
+                    cleared_at,
+                });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests_alarm {
+    use super::*;
+    use crate::db::DataType;
+    use std::time::Duration;
+
+    #[test]
+    fn test_edges() -> Result<(), Box<dyn Error>> {
+        let mut tag_map = TagMap::new();
+        tag_map.insert("Motor overload", "M1001", DataType::BIT);
+        let mut scanner = AlarmScanner::new()
+            .add_range("M1000", 4)?
+            .with_labels(&tag_map);
+
+        let t0 = SystemTime::UNIX_EPOCH;
+        let t1 = t0 + Duration::from_secs(1);
+        let t2 = t0 + Duration::from_secs(2);
+
+        let events = scanner.update(0, &[false, true, false, false], t0);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].device, "M1001");
+        assert_eq!(events[0].label.as_deref(), Some("Motor overload"));
+        assert_eq!(events[0].edge, AlarmEdge::Raised);
+
+        assert!(scanner
+            .update(0, &[false, true, false, false], t1)
+            .is_empty());
+
+        let events = scanner.update(0, &[false, false, false, true], t2);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].edge, AlarmEdge::Cleared);
+        assert_eq!(events[0].activated_at, t0);
+        assert_eq!(events[0].cleared_at, Some(t2));
+        assert_eq!(events[1].device, "M1003");
+        assert_eq!(scanner.active_alarms(), vec!["M1003".to_string()]);
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::db::DataType;
+use super::tag::Tag;
+
+#[derive(Debug, Clone)]
+pub struct CachedTag {
+    pub device: String,
+    pub value: String,
+    pub data_type: DataType,
+    pub updated_at: Instant,
+    pub is_stale: bool,
+}
+
+impl CachedTag {
+    pub fn age(&self) -> Duration {
+        self.updated_at.elapsed()
+    }
+}
+
+// Last good value per device, refreshed by every successful read
+#[derive(Debug)]
+pub struct TagCache {
+    max_age: Duration,
+    // devices kept at most, see `ResourceLimits::max_cached_tags`
+    capacity: Option<usize>,
+    // value, type, time and number of the update that stored it
+    values: Mutex<HashMap<String, (String, DataType, Instant, u64)>>,
+    updates: AtomicU64,
+}
+
+impl TagCache {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            capacity: None,
+            values: Mutex::new(HashMap::new()),
+            updates: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+        let values = self.values.get_mut().unwrap();
+        while capacity.is_some_and(|capacity| values.len() > capacity) {
+            evict_oldest(values);
+        }
+    }
+
+    pub fn update(&self, tags: &[Tag]) {
+        let now = Instant::now();
+        let mut values = self.values.lock().unwrap();
+        for tag in tags {
+            if let Some(ref value) = tag.value {
+                if self.capacity.is_some_and(|capacity| {
+                    values.len() >= capacity && !values.contains_key(&tag.device)
+                }) {
+                    evict_oldest(&mut values);
+                }
+                let update = self.updates.fetch_add(1, Ordering::Relaxed);
+                values.insert(
+                    tag.device.clone(),
+                    (value.clone(), tag.data_type.clone(), now, update),
+                );
+            }
+        }
+    }
+
+    pub fn get(&self, device: &str) -> Option<CachedTag> {
+        let values = self.values.lock().unwrap();
+        values
+            .get(device)
+            .map(|(value, data_type, updated_at, _)| CachedTag {
+                device: device.to_string(),
+                value: value.clone(),
+                data_type: data_type.clone(),
+                updated_at: *updated_at,
+                is_stale: updated_at.elapsed() > self.max_age,
+            })
+    }
+
+    pub fn clear(&self) {
+        self.values.lock().unwrap().clear();
+    }
+}
+
+// removes the device updated least recently
+fn evict_oldest(values: &mut HashMap<String, (String, DataType, Instant, u64)>) {
+    let oldest = values
+        .iter()
+        .min_by_key(|(_, (_, _, _, update))| *update)
+        .map(|(device, _)| device.clone());
+    if let Some(device) = oldest {
+        values.remove(&device);
+    }
+}
@@ -0,0 +1,62 @@
+use rs_melsec::simulator::Simulator;
+use std::env;
+use std::error::Error;
+use std::thread;
+
+const USAGE: &str = "Usage: melsec-cli simulate [--port PORT] [--bind ADDR] [--image FILE]";
+
+// Usage: melsec-cli simulate [--port PORT] [--bind ADDR] [--image FILE]
+// Runs the simulator as a fake Q series PLC answering binary 3E/4E frames
+// until killed. It listens on 127.0.0.1 unless --bind names another
+// address. With --image the device memory is loaded from FILE and saved
+// back to it within half a second of every write.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("simulate") => simulate(&args[1..]),
+        _ => Err(USAGE.into()),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn simulate(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut port = 6000;
+    let mut bind = "127.0.0.1".to_string();
+    let mut image = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} needs a value\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--port" => {
+                port = value()?
+                    .parse()
+                    .map_err(|_| format!("Invalid port\n{}", USAGE))?
+            }
+            "--bind" => bind = value()?.clone(),
+            "--image" => image = Some(value()?.clone()),
+            other => return Err(format!("Unknown option {}\n{}", other, USAGE).into()),
+        }
+    }
+
+    let simulator = Simulator::bind(&format!("{}:{}", bind, port))?;
+    if let Some(ref image) = image {
+        simulator.persist(image)?;
+    }
+    println!(
+        "Simulating a Q series PLC on {}:{}{}",
+        bind,
+        simulator.port(),
+        image
+            .map(|image| format!(", device memory in {}", image))
+            .unwrap_or_default()
+    );
+    loop {
+        thread::park();
+    }
+}
@@ -1,39 +1,203 @@
-use byteorder::{BigEndian, ByteOrder, LittleEndian, NativeEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use hex;
+use log::{debug, warn};
+use std::collections::HashMap;
 use std::error::Error;
-use std::io::Cursor;
 use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use super::cache::{CachedTag, TagCache};
 use super::db::DataType;
-use super::db::{commands, consts, subcommands, DeviceConstants};
+use super::db::{
+    commands, consts, subcommands, CustomDevice, Decoder, DeviceConstants, Endian, PlcType,
+    PlcValue,
+};
 use super::device_info::{DeviceInfo, E3, E4};
 use super::err;
+use super::frame::{AnnotatedFrame, FrameKind};
+use super::interlock::Interlocks;
+use super::limits::ResourceLimits;
+use super::operation_log::{OperationLog, OperationRecord};
+use super::rate_limit::WriteRateLimiter;
+use super::remote::ConnectCheck;
+use super::retry::RetryPolicy;
+use super::sockopt::{self, SocketOptions};
+use super::stats::ClientStats;
 use super::tag::{QueryTag, Tag};
+use super::text::TextEncoding;
+use super::transaction::WriteTransaction;
 
-use regex::Regex;
+// Splits "D100" into "D" and "100" at the first digit
+fn split_device(device: &str) -> (&str, &str) {
+    let split = device
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(device.len());
+    device.split_at(split)
+}
+
+pub fn get_device_type(device: &str) -> Result<String, String> {
+    match split_device(device) {
+        ("", _) => Err(format!("Invalid device type \"{}\"", device)),
+        (device_type, _) => Ok(device_type.to_string()),
+    }
+}
+
+pub fn get_device_index(device: &str) -> Result<i32, String> {
+    match split_device(device) {
+        (_, "") => Err(format!("Invalid device index \"{}\"", device)),
+        (_, index) => index
+            .parse::<i32>()
+            .map_err(|_| format!("Failed to parse device index \"{}\"", index)),
+    }
+}
+
+// Device number in the numbering base of its device, e.g. "X1F" -> 31
+pub fn get_device_number(device: &str, base: u32) -> Result<i32, String> {
+    let (device_type, number) = split_device(device);
+    if device_type.is_empty() || number.is_empty() {
+        return Err(format!("Invalid device \"{}\"", device));
+    }
+    i32::from_str_radix(number, base)
+        .map_err(|_| format!("Failed to parse device number \"{}\"", number))
+}
+
+// Device name written in the numbering base of the device, e.g. X1F, or X17
+// for the 16th input of an iQ-F CPU (X0..X7, X10..X17). A hex number that
+// starts with a letter gets a leading zero, X0A, so the name splits back
+// into device type and number at the first digit.
+pub(crate) fn format_device(device_type: &str, number: i64, base: u32) -> String {
+    match base {
+        16 if format!("{:X}", number).starts_with(|c: char| c.is_ascii_alphabetic()) => {
+            format!("{}0{:X}", device_type, number)
+        }
+        16 => format!("{}{:X}", device_type, number),
+        8 => format!("{}{:o}", device_type, number),
+        _ => format!("{}{}", device_type, number),
+    }
+}
+
+// Random access points used for one value: (word points, dword points).
+// Values wider than a dword take consecutive dword points and, for an odd
+// number of words, a trailing word point.
+fn random_access_points(data_type: &DataType) -> (usize, usize) {
+    let words = data_type.size().div_ceil(2).max(1);
+    (words % 2, words / 2)
+}
 
-fn get_device_type(device: &str) -> Result<String, String> {
-    let re = Regex::new(r"\D+").map_err(|_| "Failed to compile regex".to_string())?;
-    match re.find(device) {
-        Some(mat) => Ok(mat.as_str().to_string()),
-        None => Err(format!("Invalid device type \"{}\"", device)),
+// Number of bytes a value of `mode` occupies on the wire. `BIT` is used for
+// the single-byte fields of the frame (network, pc, counts).
+fn wire_width(mode: &DataType) -> usize {
+    match mode {
+        DataType::BIT => 1,
+        _ => mode.size(),
     }
 }
 
-fn get_device_index(device: &str) -> Result<i32, String> {
-    let re = Regex::new(r"\d.*").map_err(|_| "Failed to compile regex".to_string())?;
-    match re.find(device) {
-        Some(mat) => match mat.as_str().parse::<i32>() {
-            Ok(index) => Ok(index),
-            Err(_) => Err(format!("Failed to parse device index \"{}\"", mat.as_str())),
-        },
-        None => Err(format!("Invalid device index \"{}\"", device)),
+fn write_sized<B: ByteOrder>(
+    buffer: &mut Vec<u8>,
+    value: i64,
+    mode: &DataType,
+    is_signal: bool,
+) -> std::io::Result<()> {
+    match (wire_width(mode), is_signal) {
+        (1, _) => buffer.write_u8(value as u8),
+        (2, true) => buffer.write_i16::<B>(value as i16),
+        (2, false) => buffer.write_u16::<B>(value as u16),
+        (4, true) => buffer.write_i32::<B>(value as i32),
+        (4, false) => buffer.write_u32::<B>(value as u32),
+        (_, true) => buffer.write_i64::<B>(value),
+        (_, false) => buffer.write_u64::<B>(value as u64),
+    }
+}
+
+fn read_sized<B: ByteOrder>(
+    mut cursor: &[u8],
+    mode: &DataType,
+    is_signed: bool,
+) -> std::io::Result<i64> {
+    let value = match (wire_width(mode), is_signed) {
+        (1, _) => cursor.read_u8()? as i64,
+        (2, true) => cursor.read_i16::<B>()? as i64,
+        (2, false) => cursor.read_u16::<B>()? as i64,
+        (4, true) => cursor.read_i32::<B>()? as i64,
+        (4, false) => cursor.read_u32::<B>()? as i64,
+        (_, true) => cursor.read_i64::<B>()?,
+        (_, false) => cursor.read_u64::<B>()? as i64,
+    };
+    Ok(value)
+}
+
+// ASCII frames carry every field as uppercase hex text, one 16-bit word at a
+// time with the most significant digit first. `binary` is the little-endian
+// binary encoding of the field.
+fn binary_to_ascii(binary: &[u8]) -> Vec<u8> {
+    binary
+        .chunks(2)
+        .flat_map(|word| word.iter().rev())
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<String>()
+        .into_bytes()
+}
+
+fn ascii_to_binary(ascii: &[u8]) -> Result<Vec<u8>, hex::FromHexError> {
+    let mut binary = hex::decode(ascii)?;
+    for word in binary.chunks_mut(2) {
+        word.reverse();
+    }
+    Ok(binary)
+}
+
+// A dword access point of a random read or write is one 8 digit number in
+// ASCII, most significant digit first, where batch access sends the same
+// value word by word (12345678h is "12345678", not "56781234"). These
+// convert between the two; `words` is the word by word form.
+fn ascii_dword_point(words: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let binary: [u8; 4] = ascii_to_binary(words)?
+        .try_into()
+        .map_err(|_| "A dword point needs 8 hex digits")?;
+    Ok(format!("{:08X}", u32::from_le_bytes(binary)).into_bytes())
+}
+
+fn ascii_dword_words(point: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let value = u32::from_str_radix(std::str::from_utf8(point)?, 16)
+        .map_err(|_| "Invalid dword point in the response")?;
+    Ok(binary_to_ascii(&value.to_le_bytes()))
+}
+
+fn encode_with_endian(
+    value: i64,
+    mode: &DataType,
+    is_signal: bool,
+    endian: Endian,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    match mode {
+        DataType::CUSTOM { name, .. } => {
+            return Err(format!("Cannot encode custom data type \"{}\"", name).into())
+        }
+        DataType::RAW(_) => return Err("Cannot encode raw bytes as a number".into()),
+        _ => {}
+    }
+    let mut buffer = Vec::new();
+    match endian {
+        Endian::Little => write_sized::<LittleEndian>(&mut buffer, value, mode, is_signal)?,
+        Endian::Big => write_sized::<BigEndian>(&mut buffer, value, mode, is_signal)?,
     }
+    Ok(buffer)
 }
 
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+const MONITORING_TIMER_UNIT_MS: u128 = 250;
+// request destination station of a broadcast to all stations of a network
+const BROADCAST_STATION: u8 = 0xFF;
+const SOCK_TIMEOUT_MARGIN: Duration = Duration::from_secs(1);
+// wrong remote password on Q/L (0xC200) and iQ-R (0xC810)
+const PASSWORD_REJECTED_CODES: [u16; 2] = [0xC200, 0xC810];
+// words of one batch read or write request; longer accesses take several
+pub(crate) const MAX_BATCH_WORDS: usize = 960;
+
 pub struct Client {
     pub plc_type: &'static str,
     pub comm_type: &'static str,
@@ -41,18 +205,43 @@ pub struct Client {
     pub pc: u8,
     pub dest_moduleio: u16,
     pub dest_modulesta: u8,
-    pub timer: u8,
-    pub sock_timeout: u64,
+    monitoring_timer: Duration,
     device_type: Box<dyn DeviceInfo>,
     _is_connected: Arc<Mutex<bool>>,
     _sockbufsize: usize,
     _wordsize: usize,
     _debug: bool,
-    endian: &'static char,
+    endian: Endian,
     host: String,
     port: u16,
+    endpoints: Vec<(String, u16)>,
+    remote_password: Option<String>,
+    file_password: Option<String>,
+    connect_check: Option<ConnectCheck>,
+    socket_options: SocketOptions,
     _sock: Option<TcpStream>,
+    // bytes received after the end of the last response frame
+    recv_buffer: Mutex<Vec<u8>>,
     use_e4: bool,
+    decoders: HashMap<String, Decoder>,
+    custom_devices: HashMap<String, CustomDevice>,
+    cache: Option<TagCache>,
+    limits: ResourceLimits,
+    write_limiter: Option<WriteRateLimiter>,
+    interlocks: Option<Interlocks>,
+    retry_policy: Option<RetryPolicy>,
+    slow_threshold: Option<Duration>,
+    stats: Mutex<ClientStats>,
+    operation_log: Mutex<OperationLog>,
+    // ID of the last request/response round trip, 0 before the first one
+    transaction_id: AtomicU64,
+    serial_per_transaction: bool,
+    read_only: bool,
+    strict: bool,
+    text_encoding: TextEncoding,
+    deadline: Mutex<Option<Instant>>,
+    watch_list: Vec<QueryTag>,
+    watch_values: Mutex<HashMap<String, Option<String>>>,
 }
 
 impl Client {
@@ -74,36 +263,355 @@ impl Client {
             pc: 0xFF,
             dest_moduleio: 0x3FF,
             dest_modulesta: 0x0,
-            timer: 4,
-            sock_timeout: 2,
+            monitoring_timer: Duration::from_secs(1),
             _is_connected: Arc::new(Mutex::new(false)),
             _sockbufsize: 4096,
             _wordsize: 2,
             _debug: false,
-            endian: &consts::ENDIAN_LITTLE,
+            endian: Endian::Little,
             host,
             port,
+            endpoints: Vec::new(),
+            remote_password: None,
+            file_password: None,
+            connect_check: None,
+            socket_options: SocketOptions::default(),
             _sock: None,
+            recv_buffer: Mutex::new(Vec::new()),
             use_e4,
+            decoders: HashMap::new(),
+            custom_devices: HashMap::new(),
+            cache: None,
+            limits: ResourceLimits::default(),
+            write_limiter: None,
+            interlocks: None,
+            retry_policy: None,
+            slow_threshold: None,
+            stats: Mutex::new(ClientStats::default()),
+            operation_log: Mutex::new(OperationLog::default()),
+            transaction_id: AtomicU64::new(0),
+            serial_per_transaction: false,
+            read_only: false,
+            strict: false,
+            text_encoding: TextEncoding::default(),
+            deadline: Mutex::new(None),
+            watch_list: Vec::new(),
+            watch_values: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Logs failed connection attempts and every frame sent and received,
+    /// with its header fields, at debug level through the `log` crate.
     pub fn set_debug(&mut self, enable: bool) {
         self._debug = enable;
     }
 
+    /// Connects to the active endpoint. With failover endpoints configured
+    /// the others are tried in order if it cannot be reached, and the
+    /// first one that accepts becomes the active endpoint.
     pub fn connect(&mut self) -> Result<(), Box<dyn Error>> {
         self.check_plc_type()?;
-        let ip_port = format!("{}:{}", self.host, self.port);
-        let stream = TcpStream::connect(ip_port)?;
-        stream.set_read_timeout(Some(Duration::new(self.sock_timeout, 0)))?;
-        stream.set_write_timeout(Some(Duration::new(self.sock_timeout, 0)))?;
+        let start = self
+            .endpoints
+            .iter()
+            .position(|(host, port)| *host == self.host && *port == self.port)
+            .unwrap_or(0);
+        let mut candidates = vec![(self.host.clone(), self.port)];
+        for offset in 1..self.endpoints.len() {
+            candidates.push(self.endpoints[(start + offset) % self.endpoints.len()].clone());
+        }
+        let mut last_error = None;
+        let mut connected = None;
+        for (host, port) in candidates {
+            match self.connect_endpoint(&host, port) {
+                Ok(stream) => {
+                    connected = Some((stream, host, port));
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let (stream, host, port) = match (connected, last_error) {
+            (Some(connected), _) => connected,
+            (None, Some(e)) => return Err(e.into()),
+            (None, None) => return Err("No endpoint configured".into()),
+        };
+        self.host = host;
+        self.port = port;
+        sockopt::set_timeout(&stream, self.sock_timeout())?;
         self._sock = Some(stream);
-        let mut is_connected = self._is_connected.lock().unwrap();
-        *is_connected = true;
+        self.recv_buffer.lock().unwrap().clear();
+        *self._is_connected.lock().unwrap() = true;
+        if let Some(password) = self.remote_password.clone() {
+            if let Err(e) = self.remote_unlock(&password) {
+                self.close()?;
+                return Err(e);
+            }
+        }
+        if let Some(check) = self.connect_check {
+            if let Err(e) = self.run_connect_check(check) {
+                self.close()?;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Options for the connections made from now on. Fails with
+    /// `io::ErrorKind::Unsupported` if this platform lacks one of them.
+    pub fn set_socket_options(&mut self, options: SocketOptions) -> std::io::Result<()> {
+        options.check_supported()?;
+        self.socket_options = options;
+        Ok(())
+    }
+
+    /// Makes `connect()` send `check` after the TCP handshake and fail with
+    /// `err::NotMcProtocol` unless the peer answers it in MC protocol.
+    pub fn set_connect_check(&mut self, check: Option<ConnectCheck>) {
+        self.connect_check = check;
+    }
+
+    /// Unlocks the remote password on every (re)connect. The password is
+    /// left out of debug output and errors.
+    pub fn set_remote_password(&mut self, password: Option<&str>) -> Result<(), String> {
+        if let Some(password) = password {
+            self.check_remote_password(password)?;
+        }
+        self.remote_password = password.map(str::to_string);
+        Ok(())
+    }
+
+    fn check_remote_password(&self, password: &str) -> Result<(), String> {
+        let valid_length = if self.plc_type == consts::IQR_SERIES {
+            (6..=32).contains(&password.len())
+        } else {
+            password.len() == 4
+        };
+        if !password.is_ascii() || !valid_length {
+            return Err(format!(
+                "Invalid remote password for {}: expected {} ASCII characters",
+                self.plc_type,
+                if self.plc_type == consts::IQR_SERIES {
+                    "6 to 32"
+                } else {
+                    "4"
+                }
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sets the keyword (file password) sent with file access commands,
+    /// needed when the files on the CPU are password protected.
+    pub fn set_file_password(&mut self, password: Option<&str>) -> Result<(), String> {
+        if let Some(password) = password {
+            let max_length = if self.plc_type == consts::IQR_SERIES {
+                32
+            } else {
+                4
+            };
+            if !password.is_ascii() || password.is_empty() || password.len() > max_length {
+                return Err(format!(
+                    "Invalid file password for {}: expected 1 to {} ASCII characters",
+                    self.plc_type, max_length
+                ));
+            }
+        }
+        self.file_password = password.map(str::to_string);
+        Ok(())
+    }
+
+    pub(crate) fn file_password(&self) -> Option<&str> {
+        self.file_password.as_deref()
+    }
+
+    pub fn remote_unlock(&self, password: &str) -> Result<(), Box<dyn Error>> {
+        self.remote_password_command(commands::REMOTE_UNLOCK, password)
+    }
+
+    pub fn remote_lock(&self, password: &str) -> Result<(), Box<dyn Error>> {
+        self.remote_password_command(commands::REMOTE_LOCK, password)
+    }
+
+    fn remote_password_command(&self, command: u16, password: &str) -> Result<(), Box<dyn Error>> {
+        self.check_remote_password(password)?;
+        let mut request_data = self.build_command_data(command, subcommands::ZERO)?;
+        self.push_header_value(&mut request_data, password.len() as u64, 2);
+        // the password is sent as ASCII text in both modes
+        request_data.extend_from_slice(password.as_bytes());
+        let send_data = self.build_send_data(&request_data)?;
+        match self.exchange(&send_data, command, "remote password") {
+            Err(e) => match err::find::<err::MCError>(&*e) {
+                Some(mc_error) if PASSWORD_REJECTED_CODES.contains(&mc_error.code()) => {
+                    Err(Box::new(err::PasswordRejected {
+                        code: mc_error.code(),
+                    }))
+                }
+                _ => Err(e),
+            },
+            Ok(_) => Ok(()),
+        }
+    }
+
+    // Resolves the host on every call, so a DNS change is picked up on
+    // reconnect, and tries each resolved IPv4/IPv6 address in turn.
+    fn connect_endpoint(&self, host: &str, port: u16) -> std::io::Result<TcpStream> {
+        let mut last_error = None;
+        for addr in (host, port).to_socket_addrs()? {
+            match sockopt::connect(addr, &self.socket_options) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    if self._debug {
+                        debug!("connect to {} ({}) failed: {}", host, addr, e);
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} did not resolve to any address", host),
+            )
+        }))
+    }
+
+    /// Address the socket is connected to, as resolved at the last connect.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self._sock.as_ref().and_then(|sock| sock.peer_addr().ok())
+    }
+
+    /// Sets how long the PLC waits for the request to complete before it
+    /// answers with an error. `Duration::ZERO` means wait indefinitely.
+    /// The value is sent in 250ms units, rounded up. The socket timeout
+    /// follows it with a fixed margin.
+    pub fn set_monitoring_timer(&mut self, monitoring_timer: Duration) -> Result<(), String> {
+        let units = monitoring_timer
+            .as_millis()
+            .div_ceil(MONITORING_TIMER_UNIT_MS);
+        let max_units = self.device_type.get_max_monitoring_timer() as u128;
+        if units > max_units {
+            return Err(format!(
+                "Invalid monitoring timer {:?}, maximum is {:?}",
+                monitoring_timer,
+                Duration::from_millis((max_units * MONITORING_TIMER_UNIT_MS) as u64)
+            ));
+        }
+        self.monitoring_timer = monitoring_timer;
+        if let Some(ref sock) = self._sock {
+            sockopt::set_timeout(sock, self.sock_timeout()).map_err(|e| e.to_string())?;
+        }
         Ok(())
     }
 
+    pub fn monitoring_timer(&self) -> Duration {
+        self.monitoring_timer
+    }
+
+    fn monitoring_timer_units(&self) -> u16 {
+        self.monitoring_timer
+            .as_millis()
+            .div_ceil(MONITORING_TIMER_UNIT_MS) as u16
+    }
+
+    fn sock_timeout(&self) -> Option<Duration> {
+        if self.monitoring_timer.is_zero() {
+            None
+        } else {
+            Some(self.monitoring_timer + SOCK_TIMEOUT_MARGIN)
+        }
+    }
+
+    /// Sets the endpoints of one logical PLC, e.g. the built-in Ethernet
+    /// port and an E71 module. The first one becomes the active endpoint.
+    pub fn set_failover_endpoints(&mut self, endpoints: Vec<(String, u16)>) {
+        if let Some((host, port)) = endpoints.first() {
+            self.host = host.clone();
+            self.port = *port;
+        }
+        self.endpoints = endpoints;
+    }
+
+    /// Endpoint the client is, or will be, connected to.
+    pub fn active_endpoint(&self) -> (&str, u16) {
+        (&self.host, self.port)
+    }
+
+    /// Drops the current connection and connects to the next failover
+    /// endpoint, or the one after that if it cannot be reached either.
+    pub fn failover(&mut self) -> Result<(), Box<dyn Error>> {
+        let next = self
+            .endpoints
+            .iter()
+            .position(|(host, port)| *host == self.host && *port == self.port)
+            .map(|index| (index + 1) % self.endpoints.len())
+            .ok_or("No failover endpoints configured")?;
+        let (host, port) = self.endpoints[next].clone();
+        self.set_endpoint(&host, port);
+        self.connect()
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Points the client at another host, closing the current connection.
+    /// Call `connect` afterwards to open the new one.
+    pub fn set_endpoint(&mut self, host: &str, port: u16) {
+        if let Some(sock) = self._sock.take() {
+            // the old peer may already be gone, nothing to report then
+            let _ = sock.shutdown(std::net::Shutdown::Both);
+        }
+        *self._is_connected.lock().unwrap() = false;
+        self.host = host.to_string();
+        self.port = port;
+    }
+
+    pub fn is_connected(&self) -> bool {
+        *self._is_connected.lock().unwrap()
+    }
+
+    /// Every request/response round trip gets the next transaction ID,
+    /// starting at 1. The ID shows up in debug output, slow transaction
+    /// warnings, `TargetStats` and `MelsecError`.
+    pub fn last_transaction_id(&self) -> u64 {
+        self.transaction_id.load(Ordering::SeqCst)
+    }
+
+    /// Sends the low 16 bits of the transaction ID as the serial number of
+    /// 4E frames instead of the fixed subheader serial, so a frame capture
+    /// can be matched with the logs. Has no effect on 3E frames.
+    pub fn set_serial_per_transaction(&mut self, enable: bool) {
+        self.serial_per_transaction = enable;
+    }
+
+    /// Refuses device writes and remote operations with `err::ReadOnly`
+    /// before anything is sent, for collectors that must never change the
+    /// PLC.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Refuses commands, subcommands and devices that the CPUs of the
+    /// configured series do not have with `err::NotSupported` before
+    /// anything is sent, instead of leaving it to the PLC's error code.
+    /// Devices added with `register_device` are not checked.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
     pub fn set_subheader_serial(&mut self, subheader_serial: u16) -> Result<(), String> {
         self.device_type.set_subheader_series(subheader_serial);
         Ok(())
@@ -114,30 +622,206 @@ impl Client {
             sock.shutdown(std::net::Shutdown::Both)?;
         }
         self._sock = None;
+        self.recv_buffer.lock().unwrap().clear();
         let mut is_connected = self._is_connected.lock().unwrap();
         *is_connected = false;
         Ok(())
     }
 
     pub fn send(&self, send_data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if self._debug {
+            debug!(
+                "send #{}: {}",
+                self.last_transaction_id(),
+                self.annotate(send_data, FrameKind::Request)
+            );
+        }
         if *self._is_connected.lock().unwrap() {
-            self._sock.as_ref().unwrap().write_all(send_data)?;
+            let mut sock = self._sock.as_ref().unwrap();
+            if let Some(timeout) = self.deadline_timeout()? {
+                sock.set_write_timeout(Some(timeout))?;
+            }
+            sock.write_all(send_data)
+                .map_err(|e| self.map_deadline_error(e))?;
             Ok(())
         } else {
             Err("Socket is not connected. Please use the connect method.".into())
         }
     }
 
+    /// Reads one response frame. Reading continues until the number of bytes
+    /// announced in the header arrived. If the peer closes the connection
+    /// the client is marked disconnected and `ConnectionClosed` returned.
+    /// A read error after part of the frame arrived, e.g. a timeout, also
+    /// leaves the client disconnected.
+    /// A frame announcing more bytes than `ResourceLimits` allow is not
+    /// read and also leaves the client disconnected.
     pub fn recv(&self) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut recv_data = vec![0u8; self._sockbufsize];
-        let size = self._sock.as_ref().unwrap().read(&mut recv_data)?;
-        recv_data.truncate(size);
+        let mut sock = self
+            ._sock
+            .as_ref()
+            .ok_or("Socket is not connected. Please use the connect method.")?;
+        if let Some(timeout) = self.deadline_timeout()? {
+            sock.set_read_timeout(Some(timeout))?;
+        }
+        // the data length field directly precedes the completion code
+        let length_index =
+            self.device_type.get_response_status_index(self.comm_type) - self._wordsize;
+        let mut recv_data = std::mem::take(&mut *self.recv_buffer.lock().unwrap());
+        let mut buffer = vec![0u8; self._sockbufsize];
+        loop {
+            let expected = recv_data
+                .get(length_index..length_index + self._wordsize)
+                .and_then(|length| self.decode_header_value(length, &DataType::UWORD).ok())
+                .map(|length| length_index + self._wordsize + length as usize);
+            if let Some(expected) = expected {
+                if let Err(e) = self.limits.check_response(expected) {
+                    // the rest of the frame is left unread, so the stream
+                    // cannot be resynchronised
+                    *self._is_connected.lock().unwrap() = false;
+                    return Err(Box::new(e));
+                }
+            }
+            if let Some(expected) = expected.filter(|expected| recv_data.len() >= *expected) {
+                // the start of the next response, e.g. after a late one
+                *self.recv_buffer.lock().unwrap() = recv_data.split_off(expected);
+                break;
+            }
+            let size = match sock.read(&mut buffer) {
+                Ok(size) => size,
+                Err(e) => {
+                    if !recv_data.is_empty() {
+                        // the rest of the frame may still arrive and be
+                        // taken for the response to the next request
+                        *self._is_connected.lock().unwrap() = false;
+                    }
+                    return Err(self.map_deadline_error(e));
+                }
+            };
+            if size == 0 {
+                // the PLC or a gateway closed the connection
+                *self._is_connected.lock().unwrap() = false;
+                return Err(Box::new(err::ConnectionClosed {
+                    received: recv_data.len(),
+                }));
+            }
+            recv_data.extend_from_slice(&buffer[..size]);
+        }
+        if self._debug {
+            debug!(
+                "recv #{}: {}",
+                self.last_transaction_id(),
+                self.annotate(&recv_data, FrameKind::Response)
+            );
+        }
         Ok(recv_data)
     }
 
+    /// Breaks a frame of this client's frame and communication type down
+    /// into its header fields, as logged when debug output is enabled.
+    pub fn annotate(&self, frame: &[u8], kind: FrameKind) -> AnnotatedFrame {
+        AnnotatedFrame::parse(frame, kind, self.use_e4, self.comm_type)
+    }
+
+    // socket timeout capped by the remaining time of the active deadline
+    fn deadline_timeout(&self) -> Result<Option<Duration>, err::DeadlineExceeded> {
+        match *self.deadline.lock().unwrap() {
+            Some(deadline) => {
+                let remaining = deadline
+                    .checked_duration_since(Instant::now())
+                    .filter(|remaining| !remaining.is_zero())
+                    .ok_or(err::DeadlineExceeded)?;
+                Ok(Some(match self.sock_timeout() {
+                    Some(timeout) => timeout.min(remaining),
+                    None => remaining,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn map_deadline_error(&self, e: std::io::Error) -> Box<dyn Error> {
+        let timed_out = matches!(
+            e.kind(),
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        );
+        match *self.deadline.lock().unwrap() {
+            Some(deadline) if timed_out && Instant::now() >= deadline => {
+                Box::new(err::DeadlineExceeded)
+            }
+            _ => Box::new(e),
+        }
+    }
+
+    fn begin_deadline(&self, deadline: Instant) -> Result<(), err::DeadlineExceeded> {
+        if Instant::now() >= deadline {
+            return Err(err::DeadlineExceeded);
+        }
+        *self.deadline.lock().unwrap() = Some(deadline);
+        Ok(())
+    }
+
+    fn end_deadline(&self) {
+        *self.deadline.lock().unwrap() = None;
+        if let Some(ref sock) = self._sock {
+            let _ = sockopt::set_timeout(sock, self.sock_timeout());
+        }
+    }
+
+    /// `batch_read` that fails with `DeadlineExceeded` instead of blocking
+    /// past `deadline`.
+    pub fn batch_read_with_deadline(
+        &mut self,
+        ref_device: &str,
+        read_size: usize,
+        data_type: DataType,
+        decode: bool,
+        deadline: Instant,
+    ) -> Result<Vec<Tag>, Box<dyn Error>> {
+        self.begin_deadline(deadline)?;
+        let result = self.batch_read(ref_device, read_size, data_type, decode);
+        self.end_deadline();
+        result
+    }
+
+    pub fn batch_write_with_deadline(
+        &self,
+        ref_device: &str,
+        values: Vec<i64>,
+        data_type: &DataType,
+        deadline: Instant,
+    ) -> Result<(), Box<dyn Error>> {
+        self.begin_deadline(deadline)?;
+        let result = self.batch_write(ref_device, values, data_type);
+        self.end_deadline();
+        result
+    }
+
+    pub fn read_with_deadline(
+        &self,
+        devices: Vec<QueryTag>,
+        deadline: Instant,
+    ) -> Result<Vec<Tag>, Box<dyn Error>> {
+        self.begin_deadline(deadline)?;
+        let result = self.read(devices);
+        self.end_deadline();
+        result
+    }
+
+    pub fn write_with_deadline(
+        &self,
+        devices: Vec<Tag>,
+        deadline: Instant,
+    ) -> Result<(), Box<dyn Error>> {
+        self.begin_deadline(deadline)?;
+        let result = self.write(devices);
+        self.end_deadline();
+        result
+    }
+
     fn check_plc_type(&mut self) -> Result<(), String> {
         match self.plc_type {
-            "Q" | "L" | "QnA" | "iQ-L" | "iQ-R" => Ok(()),
+            "Q" | "L" | "QnA" | "iQ-L" | "iQ-R" | "iQ-F" => Ok(()),
             _ => Err(format!("Invalid PLC type: {}", self.plc_type)),
         }
     }
@@ -156,308 +840,707 @@ impl Client {
         }
     }
 
-    fn build_send_data(&self, request_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut mc_data = Vec::new();
+    pub(crate) fn build_send_data(&self, request_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.build_routed_send_data(request_data, self.network, self.pc)
+    }
+
+    fn build_routed_send_data(
+        &self,
+        request_data: &[u8],
+        network: u8,
+        pc: u8,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        // 4E binary header is 15 bytes, ASCII doubles it
+        let mut mc_data = Vec::with_capacity(15 * self._wordsize / 2 + request_data.len());
 
         if self.comm_type == consts::COMMTYPE_BINARY {
-            let mut buffer = Vec::new();
-            buffer.write_u16::<BigEndian>(self.device_type.get_subheader())?;
-            mc_data.extend_from_slice(&buffer);
+            mc_data.extend_from_slice(&self.device_type.get_subheader().to_be_bytes());
         } else {
-            let subheader_hex = format!("{:04X}", self.device_type.get_subheader());
-            mc_data.extend_from_slice(subheader_hex.as_bytes());
+            self.push_header_value(&mut mc_data, self.device_type.get_subheader() as u64, 2);
         }
-        mc_data.extend_from_slice(&self.encode_value(
-            self.device_type.get_subheader_serial() as i64,
-            DataType::SWORD,
-            false,
-        )?);
-        mc_data.extend_from_slice(&self.encode_value(0, DataType::SWORD, false)?);
         if self.use_e4 {
-        } else {
-            if self.comm_type == consts::COMMTYPE_BINARY {
-                let mut buffer = Vec::new();
-                buffer.write_u16::<BigEndian>(self.device_type.get_subheader())?;
-                mc_data.extend_from_slice(&buffer);
-            } else {
-                let subheader_hex = format!("{:04X}", self.device_type.get_subheader());
-                mc_data.extend_from_slice(subheader_hex.as_bytes());
-            }
+            self.push_header_value(
+                &mut mc_data,
+                self.device_type.get_subheader_serial() as u64,
+                2,
+            );
+            self.push_header_value(&mut mc_data, 0, 2);
         }
 
-        mc_data.extend_from_slice(&self.encode_value(self.network as i64, DataType::BIT, false)?);
-        mc_data.extend_from_slice(&self.encode_value(self.pc as i64, DataType::BIT, false)?);
-        mc_data.extend_from_slice(&self.encode_value(
-            self.dest_moduleio as i64,
-            DataType::SWORD,
-            false,
-        )?);
-        mc_data.extend_from_slice(&self.encode_value(
-            self.dest_modulesta as i64,
-            DataType::BIT,
-            false,
-        )?);
-        mc_data.extend_from_slice(&self.encode_value(
-            (self._wordsize + request_data.len() as usize) as i64,
-            DataType::SWORD,
-            false,
-        )?);
-        mc_data.extend_from_slice(&self.encode_value(self.timer as i64, DataType::SWORD, false)?);
+        self.push_header_value(&mut mc_data, network as u64, 1);
+        self.push_header_value(&mut mc_data, pc as u64, 1);
+        self.push_header_value(&mut mc_data, self.dest_moduleio as u64, 2);
+        self.push_header_value(&mut mc_data, self.dest_modulesta as u64, 1);
+        self.push_header_value(
+            &mut mc_data,
+            (self._wordsize + request_data.len()) as u64,
+            2,
+        );
+        self.push_header_value(&mut mc_data, self.monitoring_timer_units() as u64, 2);
         mc_data.extend_from_slice(request_data);
         Ok(mc_data)
     }
 
-    fn build_command_data(&self, command: u16, subcommand: u16) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut command_data = Vec::new();
-        command_data.extend_from_slice(&self.encode_value(
-            command as i64,
-            DataType::SWORD,
-            false,
-        )?);
-        command_data.extend_from_slice(&self.encode_value(
-            subcommand as i64,
-            DataType::SWORD,
-            false,
-        )?);
+    pub(crate) fn build_command_data(
+        &self,
+        command: u16,
+        subcommand: u16,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.check_strict_command(command, subcommand)?;
+        let mut command_data = Vec::with_capacity(2 * self._wordsize);
+        self.push_header_value(&mut command_data, command as u64, 2);
+        self.push_header_value(&mut command_data, subcommand as u64, 2);
         Ok(command_data)
     }
 
+    // Appends a `width` byte header field: little-endian bytes in binary
+    // mode, `width * 2` uppercase hex digits in ASCII mode.
+    pub(crate) fn push_header_value(&self, buffer: &mut Vec<u8>, value: u64, width: usize) {
+        if self.comm_type == consts::COMMTYPE_BINARY {
+            buffer.extend_from_slice(&value.to_le_bytes()[..width]);
+        } else {
+            for digit in (0..width * 2).rev() {
+                buffer.push(HEX_DIGITS[(value >> (digit * 4)) as usize & 0xF]);
+            }
+        }
+    }
+
+    pub fn set_endian(&mut self, endian: Endian) {
+        self.endian = endian;
+    }
+
     pub fn encode_value(
         &self,
         value: i64,
         mode: DataType,
         is_signal: bool,
     ) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut buffer = Vec::new();
-
-        let mode_size = mode.size();
-        match *self.endian {
-            consts::ENDIAN_LITTLE => match mode_size {
-                2 => buffer.write_u8(value as u8)?,
-                4 => match is_signal {
-                    true => buffer.write_i16::<LittleEndian>(value as i16)?,
-                    false => buffer.write_u16::<LittleEndian>(value as u16)?,
-                },
-                8 => match is_signal {
-                    true => buffer.write_i32::<LittleEndian>(value as i32)?,
-                    false => buffer.write_u32::<LittleEndian>(value as u32)?,
-                },
-                _ => return Err("Unsupported data type size".into()),
-            },
-            consts::ENDIAN_BIG => match mode_size {
-                2 => buffer.write_u8(value as u8)?,
-                4 => match is_signal {
-                    true => buffer.write_i32::<BigEndian>(value as i32)?,
-                    false => buffer.write_u32::<BigEndian>(value as u32)?,
-                },
-                8 => match is_signal {
-                    true => buffer.write_i32::<BigEndian>(value as i32)?,
-                    false => buffer.write_u32::<BigEndian>(value as u32)?,
-                },
-                _ => return Err("Unsupported data type size".into()),
-            },
-            consts::ENDIAN_NATIVE => match mode_size {
-                2 => buffer.write_u8(value as u8)?,
-                4 => match is_signal {
-                    true => buffer.write_i64::<NativeEndian>(value as i64)?,
-                    false => buffer.write_u64::<NativeEndian>(value as u64)?,
-                },
-                8 => match is_signal {
-                    true => buffer.write_i64::<NativeEndian>(value as i64)?,
-                    false => buffer.write_u64::<NativeEndian>(value as u64)?,
-                },
-                _ => return Err("Unsupported data type size".into()),
-            },
-            _ => return Err("Unsupported endianness".into()),
-        }
+        let binary = encode_with_endian(value, &mode, is_signal, self.endian)?;
+        Ok(self.to_wire(binary))
+    }
 
+    fn encode_header_value(&self, value: i64, mode: DataType) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut buffer = Vec::with_capacity(self._wordsize);
+        self.push_header_value(&mut buffer, value as u64, wire_width(&mode));
         Ok(buffer)
     }
 
+    fn to_wire(&self, binary: Vec<u8>) -> Vec<u8> {
+        if self.comm_type == consts::COMMTYPE_BINARY {
+            binary
+        } else {
+            binary_to_ascii(&binary)
+        }
+    }
+
     fn decode_value(
         &self,
         data: &[u8],
         mode: &DataType,
         is_signed: bool,
     ) -> Result<i64, Box<dyn Error>> {
-        let mut bytes = data.to_vec();
-        if self.comm_type != consts::COMMTYPE_BINARY {
-            bytes = hex::decode(bytes)?;
-        }
-
-        let mode_size = mode.size();
-        let mut cursor = Cursor::new(bytes);
-        let value = match *self.endian {
-            consts::ENDIAN_LITTLE => match mode_size {
-                2 => cursor.read_u8()? as i64,
-                4 => match is_signed {
-                    true => cursor.read_i16::<LittleEndian>()? as i64,
-                    false => cursor.read_u16::<LittleEndian>()? as i64,
-                },
-                8 => match is_signed {
-                    true => cursor.read_i16::<LittleEndian>()? as i64,
-                    false => cursor.read_u16::<LittleEndian>()? as i64,
-                },
-                _ => return Err("Unsupported data type size".into()),
-            },
-            consts::ENDIAN_BIG => match mode_size {
-                2 => cursor.read_u8()? as i64,
-                4 => match is_signed {
-                    true => cursor.read_i16::<BigEndian>()? as i64,
-                    false => cursor.read_u16::<BigEndian>()? as i64,
-                },
-                8 => match is_signed {
-                    true => cursor.read_i16::<BigEndian>()? as i64,
-                    false => cursor.read_u16::<BigEndian>()? as i64,
-                },
-                _ => return Err("Unsupported data type size".into()),
-            },
-            consts::ENDIAN_NATIVE => match mode_size {
-                2 => cursor.read_u8()? as i64,
-                4 => match is_signed {
-                    true => cursor.read_i16::<NativeEndian>()? as i64,
-                    false => cursor.read_u16::<NativeEndian>()? as i64,
-                },
-                8 => match is_signed {
-                    true => cursor.read_i16::<NativeEndian>()? as i64,
-                    false => cursor.read_u16::<NativeEndian>()? as i64,
-                },
-                _ => return Err("Unsupported data type size".into()),
-            },
-            _ => return Err("Unsupported endianness".into()),
-        };
-        Ok(value)
+        self.decode_with_endian(data, mode, is_signed, self.endian)
     }
 
-    fn check_mc_error(status: u16) -> Result<(), err::MCError> {
-        if status == 0 {
-            Ok(())
-        } else {
-            Err(err::MCError::new(status))
-        }
+    /// Registers a decoder for `DataType::CUSTOM` values with the given name.
+    /// The decoder receives the raw payload bytes of one value.
+    pub fn register_decoder<F>(&mut self, name: &str, decoder: F)
+    where
+        F: Fn(&[u8]) -> Result<String, Box<dyn Error>> + Send + Sync + 'static,
+    {
+        self.decoders.insert(name.to_string(), Box::new(decoder));
     }
 
-    pub fn batch_read(
-        &mut self,
-        ref_device: &str,
-        read_size: usize,
-        data_type: DataType,
-        decode: bool,
-    ) -> Result<Vec<Tag>, Box<dyn Error>> {
-        let data_type_size = data_type.size();
-        let device_type = get_device_type(ref_device)?;
-        let device_index: i32 = get_device_index(ref_device)?;
+    /// Adds a device name the built-in table does not know, or overrides
+    /// the code of a built-in one.
+    pub fn register_device(&mut self, name: &str, device: CustomDevice) {
+        self.custom_devices.insert(name.to_string(), device);
+    }
 
-        let command = commands::BATCH_READ;
-        let subcommand = if data_type == DataType::BIT {
-            if self.plc_type == consts::IQR_SERIES {
-                subcommands::THREE
-            } else {
-                subcommands::ONE
-            }
-        } else {
-            if self.plc_type == consts::IQR_SERIES {
-                subcommands::TWO
-            } else {
-                subcommands::ZERO
-            }
-        };
+    /// Encoding of string devices and of file names and contents.
+    pub fn set_text_encoding(&mut self, encoding: TextEncoding) {
+        self.text_encoding = encoding;
+    }
 
-        let mut request_data = Vec::new();
-        request_data.extend(self.build_command_data(command, subcommand)?);
-        request_data.extend(self.build_device_data(ref_device)?);
-        request_data.extend(self.encode_value(
-            (read_size * data_type_size as usize) as i64 / 2,
-            DataType::SWORD,
-            false,
-        )?);
-        let send_data = self.build_send_data(&request_data)?;
+    pub fn text_encoding(&self) -> TextEncoding {
+        self.text_encoding
+    }
+
+    fn check_strict_command(&self, command: u16, subcommand: u16) -> Result<(), err::NotSupported> {
+        let series = match PlcType::from_str(self.plc_type) {
+            Some(series) if self.strict => series,
+            _ => return Ok(()),
+        };
+        let label = match commands::name(command) {
+            Some(name) => format!("{:04X}h ({})", command, name),
+            None => format!("{:04X}h", command),
+        };
+        if !series.supports(command) {
+            return Err(err::NotSupported {
+                plc_type: self.plc_type,
+                what: format!("Command {}", label),
+            });
+        }
+        if !series.supports_subcommand(subcommand) {
+            return Err(err::NotSupported {
+                plc_type: self.plc_type,
+                what: format!("Subcommand {:04X}h of command {}", subcommand, label),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_strict_device(&self, device: &str) -> Result<(), err::NotSupported> {
+        match PlcType::from_str(self.plc_type) {
+            Some(series) if self.strict && !series.supports_device(device) => {
+                Err(err::NotSupported {
+                    plc_type: self.plc_type,
+                    what: format!("Device {}", device),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn binary_device_code(&self, device_type: &str) -> Result<(u16, u32), Box<dyn Error>> {
+        match self.custom_devices.get(device_type) {
+            Some(device) => Ok((device.binary_code, device.base)),
+            None => DeviceConstants::get_binary_device_code(self.plc_type, device_type)
+                .map(|(code, base)| (code as u16, base)),
+        }
+    }
+
+    // Numbering base of `device_type`, 10, 16 or 8 (iQ-F X and Y)
+    pub(crate) fn device_base(&self, device_type: &str) -> Result<u32, Box<dyn Error>> {
+        Ok(self.binary_device_code(device_type)?.1)
+    }
+
+    // "bit", "word" or "dword"; a registered custom device counts as a word device
+    pub(crate) fn device_kind(&self, device_type: &str) -> Result<&'static str, Box<dyn Error>> {
+        if self.custom_devices.contains_key(device_type) {
+            return Ok(DeviceConstants::WORD_DEVICE);
+        }
+        DeviceConstants::get_device_type(self.plc_type, device_type)
+    }
+
+    // Consecutive device names in the numbering base of the device, e.g. X0..XF, X10
+    pub(crate) fn device_range(
+        &self,
+        start_device: &str,
+        count: usize,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let device_type = get_device_type(start_device)?;
+        let base = self.device_base(&device_type)?;
+        let start = get_device_number(start_device, base)? as i64;
+        Ok((0..count as i64)
+            .map(|offset| format_device(&device_type, start + offset, base))
+            .collect())
+    }
+
+    // The device `offset` points after `device`, e.g. D100 + 20 -> D120
+    pub(crate) fn offset_device(
+        &self,
+        device: &str,
+        offset: usize,
+    ) -> Result<String, Box<dyn Error>> {
+        let device_type = get_device_type(device)?;
+        let base = self.device_base(&device_type)?;
+        let number = get_device_number(device, base)? as i64;
+        Ok(format_device(&device_type, number + offset as i64, base))
+    }
+
+    fn ascii_device_code(&self, device_type: &str) -> Result<(String, u32), Box<dyn Error>> {
+        match self.custom_devices.get(device_type) {
+            Some(device) => {
+                let width = if self.plc_type == consts::IQR_SERIES {
+                    4
+                } else {
+                    2
+                };
+                Ok((format!("{:*<width$}", device.ascii_code), device.base))
+            }
+            None => DeviceConstants::get_ascii_device_code(self.plc_type, device_type),
+        }
+    }
+
+    // Long timer/counter current values (LTN, LSTN, LCN) and LZ are 32 bits
+    // wide on iQ-R. 16-bit types are widened so the upper word is not lost.
+    fn access_type(&self, device: &str, data_type: &DataType) -> Result<DataType, Box<dyn Error>> {
+        if self.plc_type != consts::IQR_SERIES {
+            return Ok(data_type.clone());
+        }
+        let device_type = get_device_type(device)?;
+        if !matches!(
+            DeviceConstants::get_device_type(self.plc_type, &device_type),
+            Ok(DeviceConstants::DWORD_DEVICE)
+        ) {
+            return Ok(data_type.clone());
+        }
+        match data_type {
+            DataType::SWORD => Ok(DataType::SDWORD),
+            DataType::UWORD => Ok(DataType::UDWORD),
+            DataType::BIT => {
+                Err(format!("{} is a 32-bit device and has no bit access", device).into())
+            }
+            DataType::CUSTOM { .. } | DataType::RAW(_) if data_type.size() < 4 => Err(format!(
+                "{} is a 32-bit device, {} bytes are too few",
+                device,
+                data_type.size()
+            )
+            .into()),
+            _ => Ok(data_type.clone()),
+        }
+    }
+
+    fn decode_tag_value(&self, data: &[u8], mode: &DataType) -> Result<String, Box<dyn Error>> {
+        match mode {
+            DataType::CUSTOM { name, .. } => match self.decoders.get(name) {
+                Some(decoder) => decoder(data),
+                None => Err(format!("No decoder registered for data type \"{}\"", name).into()),
+            },
+            DataType::RAW(len) => {
+                let mut value = self.raw_hex_value(data)?;
+                value.truncate(len * 2);
+                Ok(value)
+            }
+            _ => {
+                let is_signed =
+                    matches!(mode, DataType::SWORD | DataType::SDWORD | DataType::SLWORD);
+                Ok(format!("{}", self.decode_value(data, mode, is_signed)?))
+            }
+        }
+    }
+
+    pub(crate) fn decode_header_value(
+        &self,
+        data: &[u8],
+        mode: &DataType,
+    ) -> Result<i64, Box<dyn Error>> {
+        self.decode_with_endian(data, mode, false, Endian::Little)
+    }
+
+    fn decode_with_endian(
+        &self,
+        data: &[u8],
+        mode: &DataType,
+        is_signed: bool,
+        endian: Endian,
+    ) -> Result<i64, Box<dyn Error>> {
+        let converted;
+        let bytes = if self.comm_type == consts::COMMTYPE_BINARY {
+            data
+        } else {
+            converted = ascii_to_binary(data)?;
+            &converted
+        };
+        let value = match endian {
+            Endian::Little => read_sized::<LittleEndian>(bytes, mode, is_signed)?,
+            Endian::Big => read_sized::<BigEndian>(bytes, mode, is_signed)?,
+        };
+        Ok(value)
+    }
+
+    fn check_mc_error(&self, status: u16, recv_data: &[u8]) -> Result<(), err::MCError> {
+        if status == 0 {
+            return Ok(());
+        }
+        let error = err::MCError::new(status);
+        match self.error_information(recv_data) {
+            Some(info) => Err(error.with_info(info)),
+            None => Err(error),
+        }
+    }
+
+    // the error information section follows the completion code of an
+    // error response; PLCs that omit it yield None
+    fn error_information(&self, recv_data: &[u8]) -> Option<err::ErrorInformation> {
+        let scale = self._wordsize / 2;
+        let data_index = self.device_type.get_response_data_index(self.comm_type);
+        let section = recv_data.get(data_index..data_index + 9 * scale)?;
+        let field = |offset: usize, width: usize| -> Option<u64> {
+            let raw = &section[offset * scale..(offset + width) * scale];
+            if self.comm_type == consts::COMMTYPE_BINARY {
+                Some(
+                    raw.iter()
+                        .rev()
+                        .fold(0, |value, byte| value << 8 | *byte as u64),
+                )
+            } else {
+                u64::from_str_radix(std::str::from_utf8(raw).ok()?, 16).ok()
+            }
+        };
+        Some(err::ErrorInformation {
+            network: field(0, 1)? as u8,
+            pc: field(1, 1)? as u8,
+            dest_moduleio: field(2, 2)? as u16,
+            dest_modulesta: field(4, 1)? as u8,
+            command: field(5, 2)? as u16,
+            subcommand: field(7, 2)? as u16,
+        })
+    }
+
+    /// With `decode` set to false word values are returned as the hex string
+    /// of their raw bytes (see `Tag::raw_bytes`) instead of being decoded.
+    pub fn batch_read(
+        &mut self,
+        ref_device: &str,
+        read_size: usize,
+        data_type: DataType,
+        decode: bool,
+    ) -> Result<Vec<Tag>, Box<dyn Error>> {
+        let result: Vec<Tag> = self
+            .batch_read_iter(ref_device, read_size, data_type, decode)?
+            .collect::<Result<_, _>>()?;
+        if decode {
+            self.update_cache(&result);
+        }
+        Ok(result)
+    }
+
+    /// Keeps the last value of every successfully read device. Values older
+    /// than `max_age` are reported as stale by `get_cached`.
+    pub fn enable_cache(&mut self, max_age: Duration) {
+        let mut cache = TagCache::new(max_age);
+        cache.set_capacity(self.limits.cached_tags());
+        self.cache = Some(cache);
+    }
+
+    pub fn disable_cache(&mut self) {
+        self.cache = None;
+    }
+
+    pub fn get_cached(&self, device: &str) -> Option<CachedTag> {
+        self.cache.as_ref().and_then(|cache| cache.get(device))
+    }
+
+    pub fn set_limits(&mut self, limits: ResourceLimits) {
+        if let Some(ref mut cache) = self.cache {
+            cache.set_capacity(limits.cached_tags());
+        }
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &ResourceLimits {
+        &self.limits
+    }
+
+    pub fn set_write_rate_limit(&mut self, limiter: Option<WriteRateLimiter>) {
+        self.write_limiter = limiter;
+    }
+
+    /// Conditions checked before remote STOP/RESET and writes to guarded
+    /// devices, see `Interlocks`. `None` removes them.
+    pub fn set_interlocks(&mut self, interlocks: Option<Interlocks>) {
+        self.interlocks = interlocks;
+    }
+
+    pub fn interlocks(&self) -> Option<&Interlocks> {
+        self.interlocks.as_ref()
+    }
+
+    fn acquire_write(&self, devices: &[&str]) -> Result<(), err::Throttled> {
+        match self.write_limiter {
+            Some(ref limiter) => limiter.acquire(devices),
+            None => Ok(()),
+        }
+    }
+
+    /// Completion codes registered as transient in `policy` are retried
+    /// with backoff instead of failing the request. `None` disables retries.
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry_policy = policy;
+    }
+
+    /// Logs a warning and counts the transaction in `stats` whenever a
+    /// request/response round trip takes longer than `threshold`.
+    pub fn set_slow_transaction_threshold(&mut self, threshold: Option<Duration>) {
+        self.slow_threshold = threshold;
+    }
+
+    /// Remote control and write operations sent so far, oldest first.
+    pub fn operation_log(&self) -> Vec<OperationRecord> {
+        self.operation_log.lock().unwrap().records()
+    }
+
+    /// Also appends every operation to the CSV file at `path`.
+    pub fn set_operation_log_file<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+    ) -> Result<(), Box<dyn Error>> {
+        self.operation_log.lock().unwrap().attach_file(path)
+    }
+
+    pub(crate) fn log_operation(
+        &self,
+        operation: &'static str,
+        target: &str,
+        result: &Result<(), Box<dyn Error>>,
+    ) {
+        self.operation_log.lock().unwrap().record(OperationRecord {
+            timestamp: std::time::SystemTime::now(),
+            operation,
+            target: target.to_string(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+    }
+
+    pub fn stats(&self) -> ClientStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    pub(crate) fn record_clock_drift(&self, drift: i64) {
+        self.stats.lock().unwrap().clock_drift = Some(drift);
+    }
+
+    pub fn reset_stats(&self) {
+        *self.stats.lock().unwrap() = ClientStats::default();
+    }
+
+    // "D100-D102" for `count` consecutive devices starting at `start`
+    fn range_label(&self, start: &str, count: usize) -> String {
+        match self.offset_device(start, count.saturating_sub(1)) {
+            Ok(end) if count > 1 => format!("{}-{}", start, end),
+            _ => start.to_string(),
+        }
+    }
+
+    // Sends one request frame and returns the response once its completion
+    // code is zero, retrying transient codes according to the retry policy.
+    // `command` and `target` (the devices accessed) are used for stats and
+    // to wrap any failure in a `MelsecError`.
+    pub(crate) fn exchange(
+        &self,
+        send_data: &[u8],
+        command: u16,
+        target: &str,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        if self.read_only && commands::is_write(command) {
+            return Err(Box::new(err::ReadOnly { command }));
+        }
+        self.exchange_attempts(send_data, command, target)
+            .map_err(|(id, e)| err::MelsecError::new(command, target, self.host(), id, e).into())
+    }
+
+    // On failure also returns the ID of the last attempt
+    fn exchange_attempts(
+        &self,
+        send_data: &[u8],
+        command: u16,
+        target: &str,
+    ) -> Result<Vec<u8>, (u64, Box<dyn Error>)> {
+        let mut attempt = 0;
+        let mut send_data = send_data.to_vec();
+        loop {
+            let id = self.transaction_id.fetch_add(1, Ordering::SeqCst) + 1;
+            let serial = if self.use_e4 && self.serial_per_transaction {
+                let serial = id as u16;
+                let mut field = Vec::with_capacity(self._wordsize);
+                self.push_header_value(&mut field, serial as u64, 2);
+                send_data[self._wordsize..self._wordsize * 2].copy_from_slice(&field);
+                serial
+            } else {
+                self.device_type.get_subheader_serial()
+            };
+            let started = Instant::now();
+            let result = self
+                .send(&send_data)
+                .and_then(|_| self.recv_response(serial))
+                .and_then(|recv_data| Ok((self.response_status(&recv_data)?, recv_data)));
+            let status = match result {
+                Ok((status, _)) => Ok(status),
+                Err(ref e) => Err(e.to_string()),
+            };
+            self.record_transaction(id, command, target, started.elapsed(), status);
+            let (status, recv_data) = result.map_err(|e| (id, e))?;
+            let e = match self.check_mc_error(status, &recv_data) {
+                Ok(()) => return Ok(recv_data),
+                Err(e) => e,
+            };
+            let backoff = match self.retry_policy {
+                Some(ref policy) if policy.is_transient(e.code()) => policy.backoff(attempt),
+                _ => None,
+            };
+            let backoff = match (backoff, *self.deadline.lock().unwrap()) {
+                (Some(backoff), Some(deadline)) if Instant::now() + backoff >= deadline => None,
+                (backoff, _) => backoff,
+            };
+            match backoff {
+                Some(backoff) => std::thread::sleep(backoff),
+                None => return Err((id, e.into())),
+            }
+            attempt += 1;
+        }
+    }
+
+    // `status` is the completion code, or the error if no response arrived
+    fn record_transaction(
+        &self,
+        id: u64,
+        command: u16,
+        target: &str,
+        rtt: Duration,
+        status: Result<u16, String>,
+    ) {
+        let is_slow = self.slow_threshold.is_some_and(|threshold| rtt > threshold);
+        self.stats
+            .lock()
+            .unwrap()
+            .record(id, target, rtt, status, is_slow);
+        if is_slow {
+            warn!(
+                "Slow transaction #{}: command 0x{:04X} on {} took {:?}",
+                id, command, target, rtt
+            );
+        }
+    }
+
+    fn update_cache(&self, tags: &[Tag]) {
+        if let Some(ref cache) = self.cache {
+            cache.update(tags);
+        }
+    }
+
+    /// Same as `batch_read`, but decodes each tag lazily from the response
+    /// buffer as the iterator is advanced.
+    pub fn batch_read_iter(
+        &self,
+        ref_device: &str,
+        read_size: usize,
+        data_type: DataType,
+        decode: bool,
+    ) -> Result<BatchReadIter<'_>, Box<dyn Error>> {
+        let data_type = self.access_type(ref_device, &data_type)?;
+        let recv_data = self.batch_read_response(ref_device, read_size, &data_type)?;
+        self.parse_batch_read(ref_device, read_size, data_type, decode, recv_data)
+    }
 
-        self.send(&send_data)?;
-        let recv_data = self.recv()?;
+    /// Decodes a batch read response frame received for `ref_device` without
+    /// touching the connection.
+    pub fn parse_batch_read(
+        &self,
+        ref_device: &str,
+        read_size: usize,
+        data_type: DataType,
+        decode: bool,
+        recv_data: Vec<u8>,
+    ) -> Result<BatchReadIter<'_>, Box<dyn Error>> {
+        let data_type = self.access_type(ref_device, &data_type)?;
         self.check_command_response(&recv_data)?;
+        self.check_read_length(&recv_data, read_size, &data_type)?;
+        let device_type = get_device_type(ref_device)?;
+        let (_, device_base) = self.binary_device_code(&device_type)?;
+        let device_index = get_device_number(ref_device, device_base)?;
 
-        let mut result = Vec::new();
-        let mut data_index = self.device_type.get_response_data_index(self.comm_type);
+        Ok(BatchReadIter {
+            client: self,
+            recv_data,
+            device_type,
+            device_index,
+            device_base,
+            data_type,
+            decode,
+            index: 0,
+            read_size,
+        })
+    }
+
+    fn decode_batch_tag(
+        &self,
+        recv_data: &[u8],
+        index: usize,
+        data_type: &DataType,
+        decode: bool,
+    ) -> Result<String, Box<dyn Error>> {
+        let data_type_size = data_type.size();
 
-        if data_type == DataType::BIT {
+        if *data_type == DataType::BIT {
             if self.comm_type == consts::COMMTYPE_BINARY {
-                for index in 0..read_size {
-                    data_index = index / 2 + data_index;
-                    let bit_value = if decode {
-                        let value = recv_data[data_index];
-                        if index % 2 == 0 {
-                            if (value & (1 << 4)) != 0 {
-                                1
-                            } else {
-                                0
-                            }
-                        } else {
-                            if (value & (1 << 0)) != 0 {
-                                1
-                            } else {
-                                0
-                            }
-                        }
+                let value = self.response_payload(recv_data, index / 2 + 1)?[index / 2];
+                let bit_value = if decode {
+                    let mask = if index.is_multiple_of(2) {
+                        1 << 4
                     } else {
-                        recv_data[data_index] as i32
+                        1 << 0
                     };
-                    result.push(Tag {
-                        device: format!("{}{}", device_type, device_index + index as i32),
-                        value: format!("{}", bit_value).into(),
-                        data_type: data_type.clone(),
-                    });
-                }
+                    (value & mask != 0) as i32
+                } else {
+                    value as i32
+                };
+                Ok(format!("{}", bit_value))
             } else {
-                for index in 0..read_size {
-                    let bit_value = if decode {
-                        recv_data[data_index] as i32
-                    } else {
-                        recv_data[data_index] as i32
-                    };
-                    result.push(Tag {
-                        device: format!("{}{}", device_type, device_index + index as i32),
-                        value: format!("{}", bit_value).into(),
-                        data_type: data_type.clone(),
-                    });
-                    data_index += 1;
-                }
+                let value = self.response_payload(recv_data, index + 1)?[index];
+                Ok(format!("{}", (value == b'1') as i32))
             }
         } else {
-            for index in 0..read_size {
-                let value = if decode {
-                    let decode_value = self.decode_value(
-                        &recv_data[data_index..data_index + data_type_size as usize].to_vec(),
-                        &data_type,
-                        false,
-                    )?;
-                    format!("{}", decode_value).to_string()
-                } else {
-                    let raw_value = &recv_data[data_index..data_index + data_type_size as usize];
-                    String::from_utf8(raw_value.to_vec())?
-                };
-                result.push(Tag {
-                    device: format!("{}{}", device_type, device_index + index as i32),
-                    value: Some(value),
-                    data_type: data_type.clone(),
-                });
-                data_index += data_type_size as usize;
+            // ASCII frames spend two characters per byte
+            let data_type_size = data_type_size * self._wordsize / 2;
+            let offset = index * data_type_size;
+            let raw_value = &self.response_payload(recv_data, offset + data_type_size)?[offset..];
+            if decode {
+                self.decode_tag_value(raw_value, data_type)
+            } else {
+                self.raw_hex_value(&raw_value[..data_type_size])
             }
         }
+    }
 
-        Ok(result)
+    // Undecoded register bytes as lowercase hex, in binary wire order
+    fn raw_hex_value(&self, raw_value: &[u8]) -> Result<String, Box<dyn Error>> {
+        if self.comm_type == consts::COMMTYPE_BINARY {
+            Ok(hex::encode(raw_value))
+        } else {
+            Ok(hex::encode(ascii_to_binary(raw_value)?))
+        }
     }
 
-    pub fn batch_write(
+    fn batch_read_response(
         &self,
         ref_device: &str,
-        values: Vec<i64>,
+        read_size: usize,
         data_type: &DataType,
-    ) -> Result<(), Box<dyn Error>> {
-        let data_type_size = data_type.size();
-        let write_elements = values.len();
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let send_data = self.batch_read_request(ref_device, read_size, data_type)?;
+        let devices = read_size * data_type.size() / 2;
+        let target = self.range_label(ref_device, devices);
+        let recv_data = self.exchange(&send_data, commands::BATCH_READ, &target)?;
+        self.check_read_length(&recv_data, read_size, data_type)?;
+        Ok(recv_data)
+    }
 
-        let command = commands::BATCH_WRITE;
+    // Fails with `ShortRead` if the response holds fewer than `read_size` points
+    fn check_read_length(
+        &self,
+        recv_data: &[u8],
+        read_size: usize,
+        data_type: &DataType,
+    ) -> Result<(), err::ShortRead> {
+        let data_index = self.device_type.get_response_data_index(self.comm_type);
+        let payload_length = recv_data.len().saturating_sub(data_index);
+        let received = match data_type {
+            DataType::BIT if self.comm_type == consts::COMMTYPE_BINARY => payload_length * 2,
+            DataType::BIT => payload_length,
+            _ => payload_length / (data_type.size().max(1) * self._wordsize / 2),
+        };
+        if received < read_size {
+            return Err(err::ShortRead {
+                requested: read_size,
+                received,
+            });
+        }
+        Ok(())
+    }
+
+    /// Request frame for reading `read_size` values of `data_type` starting
+    /// at `ref_device`.
+    pub fn batch_read_request(
+        &self,
+        ref_device: &str,
+        read_size: usize,
+        data_type: &DataType,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.limits.check_tags(read_size)?;
+        let data_type_size = data_type.size();
+        let command = commands::BATCH_READ;
         let subcommand = if *data_type == DataType::BIT {
             if self.plc_type == consts::IQR_SERIES {
                 subcommands::THREE
@@ -475,426 +1558,2652 @@ impl Client {
         let mut request_data = Vec::new();
         request_data.extend(self.build_command_data(command, subcommand)?);
         request_data.extend(self.build_device_data(ref_device)?);
-        request_data.extend(self.encode_value(
-            (write_elements * data_type_size as usize) as i64 / 2,
-            DataType::SWORD,
-            false,
-        )?);
+        request_data.extend(
+            self.encode_header_value((read_size * data_type_size) as i64 / 2, DataType::SWORD)?,
+        );
+        self.build_send_data(&request_data)
+    }
 
-        if *data_type == DataType::BIT {
-            if self.comm_type == consts::COMMTYPE_BINARY {
-                let mut bit_data = vec![0; (values.len() + 1) / 2];
-                for (index, value) in values.iter().enumerate() {
-                    let value = (*value != 0) as u8;
-                    let value_index = index / 2;
-                    let bit_index = if index % 2 == 0 { 4 } else { 0 };
-                    let bit_value = value << bit_index;
-                    bit_data[value_index] |= bit_value;
-                }
-                request_data.extend(bit_data);
-            } else {
-                for value in values {
-                    request_data.extend(value.to_string().into_bytes());
-                }
+    /// Reads `read_size` words starting at `ref_device` and copies the raw
+    /// payload into `buf` without building any `Tag`. Returns the number of
+    /// bytes written. In ASCII mode the payload is the hex text as received.
+    pub fn batch_read_into(
+        &self,
+        ref_device: &str,
+        read_size: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, Box<dyn Error>> {
+        let recv_data = self.batch_read_response(ref_device, read_size, &DataType::UWORD)?;
+        let data = self.response_payload(&recv_data, read_size * self._wordsize)?;
+        if buf.len() < data.len() {
+            return Err(format!(
+                "Buffer too small: {} bytes needed, {} given",
+                data.len(),
+                buf.len()
+            )
+            .into());
+        }
+        buf[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    pub fn batch_read_bits_into(
+        &self,
+        ref_device: &str,
+        out: &mut [bool],
+    ) -> Result<(), Box<dyn Error>> {
+        let recv_data = self.batch_read_response(ref_device, out.len(), &DataType::BIT)?;
+        if self.comm_type == consts::COMMTYPE_BINARY {
+            let data = self.response_payload(&recv_data, out.len().div_ceil(2))?;
+            for (index, bit) in out.iter_mut().enumerate() {
+                let mask = if index % 2 == 0 { 1 << 4 } else { 1 << 0 };
+                *bit = data[index / 2] & mask != 0;
             }
         } else {
-            for value in values {
-                request_data.extend(self.encode_value(value, data_type.clone(), false)?);
+            let data = self.response_payload(&recv_data, out.len())?;
+            for (bit, value) in out.iter_mut().zip(data) {
+                *bit = *value == b'1';
             }
         }
-
-        let send_data = self.build_send_data(&request_data)?;
-
-        self.send(&send_data)?;
-        let recv_data = self.recv()?;
-        self.check_command_response(&recv_data)?;
         Ok(())
     }
 
-    fn build_device_data(&self, device: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut device_data = Vec::new();
-
-        let device_type = get_device_type(device)?;
+    pub fn batch_read_words_into(
+        &self,
+        ref_device: &str,
+        out: &mut [u16],
+    ) -> Result<(), Box<dyn Error>> {
+        let recv_data = self.batch_read_response(ref_device, out.len(), &DataType::UWORD)?;
+        let data = self.response_payload(&recv_data, out.len() * self._wordsize)?;
+        for (value, chunk) in out.iter_mut().zip(data.chunks_exact(self._wordsize)) {
+            *value = self.decode_word_value(chunk, &DataType::UWORD)? as u16;
+        }
+        Ok(())
+    }
+
+    pub fn batch_read_dwords_into(
+        &self,
+        ref_device: &str,
+        out: &mut [u32],
+    ) -> Result<(), Box<dyn Error>> {
+        let recv_data = self.batch_read_response(ref_device, out.len(), &DataType::UDWORD)?;
+        let data = self.response_payload(&recv_data, out.len() * self._wordsize * 2)?;
+        for (value, chunk) in out.iter_mut().zip(data.chunks_exact(self._wordsize * 2)) {
+            *value = self.decode_word_value(chunk, &DataType::UDWORD)? as u32;
+        }
+        Ok(())
+    }
+
+    /// Reads `count` values from `ref_device` on straight into a `Vec` of
+    /// their Rust type, e.g. `Vec<i16>` or `Vec<f32>`, without building any
+    /// `Tag`.
+    pub fn batch_read_typed<T: PlcValue>(
+        &self,
+        ref_device: &str,
+        count: usize,
+    ) -> Result<Vec<T>, Box<dyn Error>> {
+        let data_type = T::DATA_TYPE;
+        if self.access_type(ref_device, &data_type)? != data_type {
+            return Err(format!(
+                "{} is a 32-bit device, read it as a 32-bit type",
+                ref_device
+            )
+            .into());
+        }
+        let recv_data = self.batch_read_response(ref_device, count, &data_type)?;
+        let width = data_type.size() * self._wordsize / 2;
+        let data = self.response_payload(&recv_data, count * width)?;
+        let mut values = Vec::with_capacity(count);
+        for chunk in data.chunks_exact(width) {
+            values.push(T::from_bits(
+                self.decode_value(chunk, &data_type, false)? as u64
+            ));
+        }
+        Ok(values)
+    }
+
+    pub(crate) fn response_payload<'a>(
+        &self,
+        recv_data: &'a [u8],
+        length: usize,
+    ) -> Result<&'a [u8], Box<dyn Error>> {
+        let data_index = self.device_type.get_response_data_index(self.comm_type);
+        recv_data
+            .get(data_index..data_index + length)
+            .ok_or_else(|| {
+                format!(
+                    "Response too short: {} bytes expected, {} received",
+                    data_index + length,
+                    recv_data.len()
+                )
+                .into()
+            })
+    }
+
+    // decodes binary payload straight from the slice, falling back to the
+    // allocating path for ASCII hex
+    pub(crate) fn decode_word_value(
+        &self,
+        data: &[u8],
+        mode: &DataType,
+    ) -> Result<i64, Box<dyn Error>> {
+        if self.comm_type != consts::COMMTYPE_BINARY {
+            return self.decode_value(data, mode, false);
+        }
+        let value = match (mode.size(), self.endian) {
+            (2, Endian::Little) => LittleEndian::read_u16(data) as i64,
+            (2, Endian::Big) => BigEndian::read_u16(data) as i64,
+            (_, Endian::Little) => LittleEndian::read_u32(data) as i64,
+            (_, Endian::Big) => BigEndian::read_u32(data) as i64,
+        };
+        Ok(value)
+    }
+
+    pub fn batch_write(
+        &self,
+        ref_device: &str,
+        values: Vec<i64>,
+        data_type: &DataType,
+    ) -> Result<(), Box<dyn Error>> {
+        let target = self.range_label(ref_device, values.len());
+        let points = match data_type {
+            DataType::BIT => values.len(),
+            _ => values.len() * data_type.size() / 2,
+        };
+        let result = self
+            .check_write_interlocks(&[(ref_device, points)])
+            .and_then(|_| self.acquire_write(&[ref_device]).map_err(|e| e.into()))
+            .and_then(|_| self.batch_write_frame(ref_device, values, data_type));
+        self.log_operation("batch_write", &target, &result);
+        result
+    }
+
+    pub(crate) fn batch_write_frame(
+        &self,
+        ref_device: &str,
+        values: Vec<i64>,
+        data_type: &DataType,
+    ) -> Result<(), Box<dyn Error>> {
+        let devices = values.len() * data_type.size() / 2;
+        let request_data = self.batch_write_request(ref_device, values, data_type)?;
+        let send_data = self.build_send_data(&request_data)?;
+        let target = self.range_label(ref_device, devices);
+        self.exchange(&send_data, commands::BATCH_WRITE, &target)?;
+        Ok(())
+    }
+
+    /// Writes the same values to every station of `network` in one frame,
+    /// addressed to station 0xFF of that network. Stations do not answer
+    /// a broadcast, so success only means the frame was sent; read back
+    /// from the stations that matter to confirm the write.
+    pub fn broadcast_write(
+        &self,
+        network: u8,
+        ref_device: &str,
+        values: Vec<i64>,
+        data_type: &DataType,
+    ) -> Result<(), Box<dyn Error>> {
+        let points = match data_type {
+            DataType::BIT => values.len(),
+            _ => values.len() * data_type.size() / 2,
+        };
+        let target = format!(
+            "{} on network {}",
+            self.range_label(ref_device, points),
+            network
+        );
+        let result = self
+            .check_write_interlocks(&[(ref_device, points)])
+            .and_then(|_| self.acquire_write(&[ref_device]).map_err(|e| e.into()))
+            .and_then(|_| {
+                if self.read_only {
+                    return Err(err::ReadOnly {
+                        command: commands::BATCH_WRITE,
+                    }
+                    .into());
+                }
+                if network == 0 {
+                    return Err("Broadcast needs a network number, 0 is the own network".into());
+                }
+                let request_data = self.batch_write_request(ref_device, values, data_type)?;
+                let send_data =
+                    self.build_routed_send_data(&request_data, network, BROADCAST_STATION)?;
+                self.send(&send_data)
+            });
+        self.log_operation("broadcast_write", &target, &result);
+        result
+    }
+
+    fn batch_write_request(
+        &self,
+        ref_device: &str,
+        values: Vec<i64>,
+        data_type: &DataType,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.limits.check_tags(values.len())?;
+        let data_type = &self.access_type(ref_device, data_type)?;
+        let data_type_size = data_type.size();
+        let write_elements = values.len();
+
+        let command = commands::BATCH_WRITE;
+        let subcommand = if *data_type == DataType::BIT {
+            if self.plc_type == consts::IQR_SERIES {
+                subcommands::THREE
+            } else {
+                subcommands::ONE
+            }
+        } else {
+            if self.plc_type == consts::IQR_SERIES {
+                subcommands::TWO
+            } else {
+                subcommands::ZERO
+            }
+        };
+
+        let mut request_data = Vec::new();
+        request_data.extend(self.build_command_data(command, subcommand)?);
+        request_data.extend(self.build_device_data(ref_device)?);
+        request_data.extend(self.encode_header_value(
+            (write_elements * data_type_size) as i64 / 2,
+            DataType::SWORD,
+        )?);
+
+        if *data_type == DataType::BIT {
+            if self.comm_type == consts::COMMTYPE_BINARY {
+                let mut bit_data = vec![0; values.len().div_ceil(2)];
+                for (index, value) in values.iter().enumerate() {
+                    let value = (*value != 0) as u8;
+                    let value_index = index / 2;
+                    let bit_index = if index % 2 == 0 { 4 } else { 0 };
+                    let bit_value = value << bit_index;
+                    bit_data[value_index] |= bit_value;
+                }
+                request_data.extend(bit_data);
+            } else {
+                // one '0'/'1' character per point
+                for value in values {
+                    request_data.push(if value != 0 { b'1' } else { b'0' });
+                }
+            }
+        } else {
+            for value in values {
+                request_data.extend(self.encode_value(value, data_type.clone(), false)?);
+            }
+        }
+
+        Ok(request_data)
+    }
+
+    pub(crate) fn build_device_data(&self, device: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut device_data = Vec::new();
+
+        let device_type = get_device_type(device)?;
+        if !self.custom_devices.contains_key(&device_type) {
+            self.check_strict_device(device)?;
+        }
 
         if self.comm_type == consts::COMMTYPE_BINARY {
-            let (device_code, device_base) =
-                DeviceConstants::get_binary_device_code(self.plc_type, &device_type)?;
-            let device_number =
-                i32::from_str_radix(&get_device_index(device)?.to_string(), device_base)?;
+            let (device_code, device_base) = self.binary_device_code(&device_type)?;
+            let device_number = get_device_number(device, device_base)?;
 
             if self.plc_type == consts::IQR_SERIES {
                 let mut buf = [0u8; 6];
-                if *self.endian == consts::ENDIAN_LITTLE {
-                    LittleEndian::write_u32(&mut buf, device_number as u32);
-                } else {
-                    BigEndian::write_u32(&mut buf, device_number as u32);
-                }
-                device_data.extend_from_slice(&buf[0..4]);
-                device_data.extend_from_slice(&buf[4..6]);
+                LittleEndian::write_u32(&mut buf[0..4], device_number as u32);
+                LittleEndian::write_u16(&mut buf[4..6], device_code);
+                device_data.extend_from_slice(&buf);
             } else {
-                let mut buf = [0u8; 4];
-                if *self.endian == consts::ENDIAN_LITTLE {
-                    LittleEndian::write_u32(&mut buf, device_number as u32);
-                } else {
-                    BigEndian::write_u32(&mut buf, device_number as u32);
+                // three bytes of device number before iQ-R
+                if device_number > 0xFF_FFFF {
+                    return Err(format!(
+                        "Device number of {} does not fit the {} frame",
+                        device, self.plc_type
+                    )
+                    .into());
                 }
+                let mut buf = [0u8; 4];
+                LittleEndian::write_u32(&mut buf, device_number as u32);
                 device_data.extend_from_slice(&buf[0..3]);
                 device_data.push(device_code as u8);
             }
         } else {
-            let (device_code, device_base) =
-                DeviceConstants::get_ascii_device_code(self.plc_type, &device_type)?;
-            let device_number = format!(
-                "{:06x}",
-                i32::from_str_radix(&get_device_index(device)?.to_string(), device_base)?
-            );
+            let (device_code, device_base) = self.ascii_device_code(&device_type)?;
+            let device_number = get_device_number(device, device_base)?;
+            let width = if self.plc_type == consts::IQR_SERIES {
+                8
+            } else {
+                6
+            };
+            // written in the numbering base of the device, e.g. X1F -> "X*00001F"
+            let device_number = match device_base {
+                16 => format!("{:0width$X}", device_number, width = width),
+                8 => format!("{:0width$o}", device_number, width = width),
+                _ => format!("{:0width$}", device_number, width = width),
+            };
+            if device_number.len() > width {
+                return Err(format!(
+                    "Device number of {} does not fit the {} frame",
+                    device, self.plc_type
+                )
+                .into());
+            }
 
             device_data.extend_from_slice(device_code.as_bytes());
             device_data.extend_from_slice(device_number.as_bytes());
         }
 
-        Ok(device_data)
+        Ok(device_data)
+    }
+
+    fn check_command_response(&self, recv_data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.check_mc_error(self.response_status(recv_data)?, recv_data)?;
+        Ok(())
+    }
+
+    // Receives the response to the request with `serial`. With serials per
+    // transaction, late responses to requests that timed out and duplicated
+    // responses carry an earlier serial; they are dropped and counted.
+    fn recv_response(&self, serial: u16) -> Result<Vec<u8>, Box<dyn Error>> {
+        loop {
+            let recv_data = self.recv()?;
+            self.check_response_subheader(&recv_data)?;
+            if self.use_e4 && self.serial_per_transaction {
+                let received = self.response_serial(&recv_data)?;
+                // serials wrap around, earlier ones are less than half the range back
+                let behind = serial.wrapping_sub(received);
+                if behind != 0 && behind < 0x8000 {
+                    warn!(
+                        "Dropped response with serial {} while waiting for {}",
+                        received, serial
+                    );
+                    self.stats.lock().unwrap().stale_responses += 1;
+                    continue;
+                }
+            }
+            self.check_response_serial(&recv_data, serial)?;
+            return Ok(recv_data);
+        }
+    }
+
+    // Responses echo the request subheader with the top bit set, D000h for
+    // 3E and D400h for 4E frames
+    fn check_response_subheader(&self, recv_data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut expected = Vec::with_capacity(self._wordsize);
+        let subheader = self.device_type.get_subheader() | 0x8000;
+        if self.comm_type == consts::COMMTYPE_BINARY {
+            expected.extend_from_slice(&subheader.to_be_bytes());
+        } else {
+            self.push_header_value(&mut expected, subheader as u64, 2);
+        }
+        if !recv_data.starts_with(&expected) {
+            return Err(format!(
+                "Response subheader {:02X?} does not match the {} frame",
+                &recv_data[..recv_data.len().min(self._wordsize)],
+                if self.use_e4 { "4E" } else { "3E" }
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn response_serial(&self, recv_data: &[u8]) -> Result<u16, Box<dyn Error>> {
+        let serial_data = recv_data
+            .get(self._wordsize..self._wordsize * 2)
+            .ok_or("Response too short: no serial number")?;
+        Ok(self.decode_header_value(serial_data, &DataType::UWORD)? as u16)
+    }
+
+    fn check_response_serial(&self, recv_data: &[u8], expected: u16) -> Result<(), Box<dyn Error>> {
+        if !self.use_e4 {
+            return Ok(());
+        }
+        let serial = self.response_serial(recv_data)?;
+        if serial != expected {
+            return Err(format!(
+                "Response serial number {} does not match request serial number {}",
+                serial, expected
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn response_status(&self, recv_data: &[u8]) -> Result<u16, Box<dyn Error>> {
+        let response_status_index = self.device_type.get_response_status_index(self.comm_type);
+        let status_data = recv_data
+            .get(response_status_index..response_status_index + self._wordsize)
+            .ok_or_else(|| {
+                format!(
+                    "Response too short: {} bytes received, no completion code",
+                    recv_data.len()
+                )
+            })?;
+        Ok(self.decode_header_value(status_data, &DataType::SWORD)? as u16)
+    }
+
+    /// Random read. 16-bit values use word access points and 32-bit values
+    /// native dword access points; wider values are split over several.
+    pub fn read(&self, devices: Vec<QueryTag>) -> Result<Vec<Tag>, Box<dyn Error>> {
+        if devices.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.limits.check_tags(devices.len())?;
+        let devices = devices
+            .into_iter()
+            .map(|tag| {
+                let data_type = self.access_type(&tag.device, &tag.data_type)?;
+                Ok(QueryTag { data_type, ..tag })
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        let command = commands::RANDOM_READ;
+        let subcommand = if self.plc_type == consts::IQR_SERIES {
+            subcommands::TWO
+        } else {
+            subcommands::ZERO
+        };
+
+        let mut word_devices = Vec::new();
+        let mut dword_devices = Vec::new();
+        for element in &devices {
+            let (words, dwords) = self.split_random_access(&element.device, &element.data_type)?;
+            word_devices.extend(words);
+            dword_devices.extend(dwords);
+        }
+
+        let mut request_data = Vec::new();
+        request_data.extend(self.build_command_data(command, subcommand)?);
+        request_data.extend(self.encode_header_value(word_devices.len() as i64, DataType::BIT)?);
+        request_data.extend(self.encode_header_value(dword_devices.len() as i64, DataType::BIT)?);
+        for device in word_devices.iter().chain(dword_devices.iter()) {
+            request_data.extend(self.build_device_data(device)?);
+        }
+
+        let send_data = self.build_send_data(&request_data)?;
+        let target = word_devices
+            .iter()
+            .chain(dword_devices.iter())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(",");
+        let recv_data = self.exchange(&send_data, commands::RANDOM_READ, &target)?;
+
+        let word_length = word_devices.len() * self._wordsize;
+        let dword_length = dword_devices.len() * self._wordsize * 2;
+        let payload = self.response_payload(&recv_data, word_length + dword_length)?;
+        let mut word_values = payload[..word_length].chunks(self._wordsize);
+        let mut dword_values = payload[word_length..].chunks(self._wordsize * 2);
+
+        let mut output = Vec::new();
+        for element in devices {
+            let (words, dwords) = random_access_points(&element.data_type);
+            let mut data = Vec::new();
+            for _ in 0..dwords {
+                let point = dword_values.next().unwrap_or_default();
+                if self.comm_type == consts::COMMTYPE_BINARY {
+                    data.extend_from_slice(point);
+                } else {
+                    data.extend(ascii_dword_words(point)?);
+                }
+            }
+            for _ in 0..words {
+                data.extend_from_slice(word_values.next().unwrap_or_default());
+            }
+            let value = if element.data_type == DataType::BIT {
+                // word access returns 16 bits starting at the device
+                format!("{}", self.decode_value(&data, &DataType::UWORD, false)? & 1)
+            } else {
+                self.decode_tag_value(&data, &element.data_type)?
+            };
+            output.push(Tag::new(element.device, Some(value), element.data_type));
+        }
+
+        self.update_cache(&output);
+        Ok(output)
+    }
+
+    // Word and dword access point devices covering one value
+    fn split_random_access(
+        &self,
+        device: &str,
+        data_type: &DataType,
+    ) -> Result<(Vec<String>, Vec<String>), Box<dyn Error>> {
+        let (words, dwords) = random_access_points(data_type);
+        let devices = self.device_range(device, dwords * 2 + words)?;
+        Ok((
+            devices.iter().skip(dwords * 2).cloned().collect(),
+            devices.iter().step_by(2).take(dwords).cloned().collect(),
+        ))
+    }
+
+    /// Adds `device` to the watch list read by `poll_once`. Watching a
+    /// device again replaces its data type.
+    pub fn watch(&mut self, device: &str, data_type: DataType) {
+        self.unwatch(device);
+        self.watch_list.push(QueryTag {
+            device: device.to_string(),
+            data_type,
+        });
+    }
+
+    pub fn unwatch(&mut self, device: &str) {
+        self.watch_list.retain(|tag| tag.device != device);
+        self.watch_values.lock().unwrap().remove(device);
+    }
+
+    pub fn watch_list(&self) -> &[QueryTag] {
+        &self.watch_list
+    }
+
+    // Value of `device` at the last `poll_once`, `None` before the first one
+    pub(crate) fn watch_value(&self, device: &str) -> Option<Option<String>> {
+        self.watch_values.lock().unwrap().get(device).cloned()
+    }
+
+    pub(crate) fn set_watch_value(&self, device: &str, value: Option<String>) {
+        self.watch_values
+            .lock()
+            .unwrap()
+            .insert(device.to_string(), value);
+    }
+
+    /// Reads every watched device and returns only those whose value changed
+    /// since the previous call. The first call returns all of them.
+    pub fn poll_once(&self) -> Result<Vec<Tag>, Box<dyn Error>> {
+        if self.watch_list.is_empty() {
+            return Ok(Vec::new());
+        }
+        let tags = self.read(self.watch_list.clone())?;
+        let mut watch_values = self.watch_values.lock().unwrap();
+        Ok(tags
+            .into_iter()
+            .filter(|tag| {
+                watch_values.insert(tag.device.clone(), tag.value.clone())
+                    != Some(tag.value.clone())
+            })
+            .collect())
+    }
+
+    pub fn write_transaction(&self) -> WriteTransaction<'_> {
+        WriteTransaction::new(self)
+    }
+
+    /// Random write, with the same access points as `read`. Bit tags are
+    /// written with a batch write each; tags without a value are skipped.
+    pub fn write(&self, devices: Vec<Tag>) -> Result<(), Box<dyn Error>> {
+        let target = devices
+            .iter()
+            .map(|tag| tag.device.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let result = self.random_write(devices);
+        self.log_operation("write", &target, &result);
+        result
+    }
+
+    fn random_write(&self, devices: Vec<Tag>) -> Result<(), Box<dyn Error>> {
+        self.limits.check_tags(devices.len())?;
+        let ranges: Vec<(&str, usize)> = devices
+            .iter()
+            .filter(|tag| tag.value.is_some())
+            .map(|tag| (tag.device.as_str(), tag.data_type.size().div_ceil(2)))
+            .collect();
+        self.check_write_interlocks(&ranges)?;
+        let write_devices: Vec<&str> = devices.iter().map(|tag| tag.device.as_str()).collect();
+        self.acquire_write(&write_devices)?;
+
+        let command = commands::RANDOM_WRITE;
+        let subcommand = if self.plc_type == consts::IQR_SERIES {
+            subcommands::TWO
+        } else {
+            subcommands::ZERO
+        };
+
+        let mut word_data = Vec::new();
+        let mut dword_data = Vec::new();
+        let mut word_points = 0;
+        let mut dword_points = 0;
+        let mut targets = Vec::new();
+        for element in devices {
+            let value = match element.value {
+                Some(value) => value,
+                None => continue,
+            };
+            let data_type = self.access_type(&element.device, &element.data_type)?;
+            if data_type == DataType::BIT {
+                let values: Vec<i64> = value
+                    .split_whitespace()
+                    .filter_map(|part| part.parse::<i64>().ok())
+                    .collect();
+                self.batch_write_frame(&element.device, values, &data_type)?;
+                continue;
+            }
+            let value = value.parse::<i64>().map_err(|_| {
+                format!("Invalid value \"{}\" for device {}", value, element.device)
+            })?;
+            let encoded = self.encode_value(value, data_type.clone(), false)?;
+            let (words, dwords) = self.split_random_access(&element.device, &data_type)?;
+            let mut chunks = encoded.chunks(self._wordsize * 2);
+            for device in &dwords {
+                dword_data.extend(self.build_device_data(device)?);
+                let point = chunks.next().unwrap_or_default();
+                if self.comm_type == consts::COMMTYPE_BINARY {
+                    dword_data.extend_from_slice(point);
+                } else {
+                    dword_data.extend(ascii_dword_point(point)?);
+                }
+            }
+            for device in &words {
+                word_data.extend(self.build_device_data(device)?);
+                word_data.extend_from_slice(chunks.next().unwrap_or_default());
+            }
+            word_points += words.len();
+            dword_points += dwords.len();
+            targets.push(element.device);
+        }
+        if word_points + dword_points == 0 {
+            return Ok(());
+        }
+
+        let mut request_data = Vec::new();
+        request_data.extend(self.build_command_data(command, subcommand)?);
+        request_data.extend(self.encode_header_value(word_points as i64, DataType::BIT)?);
+        request_data.extend(self.encode_header_value(dword_points as i64, DataType::BIT)?);
+        request_data.extend(word_data);
+        request_data.extend(dword_data);
+
+        let send_data = self.build_send_data(&request_data)?;
+        self.exchange(&send_data, commands::RANDOM_WRITE, &targets.join(","))?;
+        Ok(())
+    }
+}
+
+pub struct BatchReadIter<'a> {
+    client: &'a Client,
+    recv_data: Vec<u8>,
+    device_type: String,
+    device_index: i32,
+    device_base: u32,
+    data_type: DataType,
+    decode: bool,
+    index: usize,
+    read_size: usize,
+}
+
+impl Iterator for BatchReadIter<'_> {
+    type Item = Result<Tag, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.read_size {
+            return None;
+        }
+        let index = self.index;
+        self.index += 1;
+        let value =
+            self.client
+                .decode_batch_tag(&self.recv_data, index, &self.data_type, self.decode);
+        let device_index = self.device_index as i64 + index as i64;
+        let device = format_device(&self.device_type, device_index, self.device_base);
+        Some(value.map(|value| Tag::new(device, Some(value), self.data_type.clone())))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.read_size - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for BatchReadIter<'_> {}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            eprintln!("Error closing connection: {:?}", e);
+        }
+    }
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("frame", &if self.use_e4 { "4E" } else { "3E" })
+            .field("plc_type", &self.plc_type)
+            .field("comm_type", &self.comm_type)
+            .field("network", &self.network)
+            .field("pc", &self.pc)
+            .field("dest_moduleio", &self.dest_moduleio)
+            .field("dest_modulesta", &self.dest_modulesta)
+            .field("monitoring_timer", &self.monitoring_timer)
+            .field("sock_timeout", &self.sock_timeout())
+            .field("_is_connected", &self._is_connected)
+            .field("_sockbufsize", &self._sockbufsize)
+            .field("_wordsize", &self._wordsize)
+            .field("_debug", &self._debug)
+            .field("endian", &self.endian)
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests_client {
+    use super::*;
+    use crate::cancel::CancelToken;
+    use crate::commissioning::{CheckoutResult, IoCheckout};
+    use crate::db::{CustomDevice, PlcType};
+    use crate::diagnostics::{BatteryStatus, ErrorStatus, OperatingStatus, SwitchPosition};
+    use crate::got::GotTransparent;
+    use crate::pool::{ClientPool, Priority};
+    use crate::redundant::{RedundantFailover, RedundantSystem, RedundantTarget};
+    use crate::remote::ConnectCheck;
+    use crate::tag_map::TagMap;
+    use crate::test_fixtures::{assert_frame_eq, client_for, FIXTURES};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    pub fn start_mock_server(port: u16) -> std::net::SocketAddr {
+        let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+        let listener = TcpListener::bind(addr).expect("Failed to bind to address");
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.expect("Failed to accept connection");
+                thread::spawn(move || {
+                    let mut buffer = [0; 1024];
+                    loop {
+                        match stream.read(&mut buffer) {
+                            Ok(0) => break, // Connection closed
+                            Ok(size) => {
+                                let received = &buffer[..size];
+                                stream
+                                    .write_all(received)
+                                    .expect("Failed to write to stream");
+                            }
+                            Err(_) => break,
+                        }
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    // Mock DeviceInfo implementations for testing
+    struct MockDeviceInfo {
+        subheader: u16,
+        subheader_serial: u16,
+    }
+
+    impl DeviceInfo for MockDeviceInfo {
+        fn set_subheader_series(&mut self, subheader_serial: u16) {
+            self.subheader_serial = subheader_serial;
+        }
+
+        fn get_response_data_index(&self, _: &str) -> usize {
+            10
+        }
+        fn get_response_status_index(&self, _: &str) -> usize {
+            11
+        }
+
+        fn get_subheader(&self) -> u16 {
+            self.subheader
+        }
+        fn get_subheader_serial(&self) -> u16 {
+            self.subheader_serial
+        }
+    }
+
+    #[test]
+    fn test_client_new() {
+        let client = Client::new("localhost".to_string(), 8080, "Q", true);
+        assert_eq!(client.host, "localhost");
+        assert_eq!(client.port, 8080);
+        assert_eq!(client.plc_type, "Q");
+        assert!(client.use_e4);
+    }
+
+    #[test]
+    fn test_set_debug() {
+        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
+        client.set_debug(true);
+        assert!(client._debug);
+    }
+
+    #[test]
+    fn test_set_subheader_serial() {
+        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
+        client.device_type = Box::new(MockDeviceInfo {
+            subheader_serial: 0,
+            subheader: 12,
+        });
+        let result = client.set_subheader_serial(1234);
+        assert!(result.is_ok());
+        assert_eq!(client.device_type.get_subheader_serial(), 1234);
+    }
+
+    #[test]
+    fn test_connect() {
+        // This test requires a server running that sends data
+        let server_addr = start_mock_server(9999);
+        let port = server_addr.port();
+        let mut client = Client::new("localhost".to_string(), port, "Q", true);
+        let result = client.connect();
+        assert!(result.is_ok());
+        // recv reads whole frames, so the echo must be one
+        let data_to_send = e4_response(b"Hello, server!");
+        let send_result = client.send(&data_to_send);
+        assert!(send_result.is_ok());
+        let received_data = client.recv().expect("Failed to receive data");
+        assert_eq!(received_data, data_to_send);
+        let close_result = client.close();
+        assert!(close_result.is_ok());
+    }
+
+    #[test]
+    fn test_check_plc_type() {
+        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
+        let result = client.check_plc_type();
+        assert!(result.is_ok());
+
+        client.plc_type = "InvalidType";
+        let result = client.check_plc_type();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_set_comm_type() {
+        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
+        client.set_comm_type("binary");
+        assert_eq!(client.comm_type, consts::COMMTYPE_BINARY);
+        assert_eq!(client._wordsize, 2);
+
+        client.set_comm_type("ascii");
+        assert_eq!(client.comm_type, consts::COMMTYPE_ASCII);
+        assert_eq!(client._wordsize, 4);
+    }
+    #[test]
+    fn test_build_send_data_binary() -> Result<(), Box<dyn Error>> {
+        let client = Client::new("localhost".to_string(), 8080, "Q", true);
+        let request_data = b"test";
+        let expected_length = 19;
+        let result = client.build_send_data(request_data)?;
+        assert_eq!(result.len(), expected_length);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_value_little_endian() -> Result<(), Box<dyn Error>> {
+        let client = Client::new("localhost".to_string(), 8080, "Q", true);
+        let value = 1234;
+        let encoded = client.encode_value(value as i64, DataType::SWORD, false)?;
+        let mut expected = Vec::new();
+        expected.write_u16::<LittleEndian>(value as u16)?;
+        assert_eq!(encoded, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_value_big_endian() -> Result<(), Box<dyn Error>> {
+        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
+        client.set_endian(Endian::Big);
+        let value = 1234;
+        let encoded = client.encode_value(value as i64, DataType::SWORD, false)?;
+        let mut expected = Vec::new();
+        expected.write_u16::<BigEndian>(value as u16)?;
+
+        assert_eq!(encoded, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_decode_value_all_endians() -> Result<(), Box<dyn Error>> {
+        let cases = [
+            (DataType::BIT, 0x12, vec![0x12], vec![0x12]),
+            (DataType::SWORD, -2, vec![0xFE, 0xFF], vec![0xFF, 0xFE]),
+            (DataType::UWORD, 0x1234, vec![0x34, 0x12], vec![0x12, 0x34]),
+            (
+                DataType::SDWORD,
+                -2,
+                vec![0xFE, 0xFF, 0xFF, 0xFF],
+                vec![0xFF, 0xFF, 0xFF, 0xFE],
+            ),
+            (
+                DataType::UDWORD,
+                0x12345678,
+                vec![0x78, 0x56, 0x34, 0x12],
+                vec![0x12, 0x34, 0x56, 0x78],
+            ),
+            (
+                DataType::SLWORD,
+                -2,
+                vec![0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+                vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE],
+            ),
+        ];
+        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
+        for (data_type, value, little, big) in cases {
+            let is_signed = value < 0;
+            for (endian, expected) in [(Endian::Little, little), (Endian::Big, big)] {
+                client.set_endian(endian);
+                let encoded = client.encode_value(value, data_type.clone(), is_signed)?;
+                assert_eq!(encoded, expected, "{:?} {:?}", data_type, endian);
+                let decoded = client.decode_value(&encoded, &data_type, is_signed)?;
+                assert_eq!(decoded, value, "{:?} {:?}", data_type, endian);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_ignores_payload_endian() -> Result<(), Box<dyn Error>> {
+        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
+        let little = client.build_send_data(b"test")?;
+        client.set_endian(Endian::Big);
+        let big = client.build_send_data(b"test")?;
+        assert_eq!(little, big);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_monitoring_timer() {
+        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
+        assert_eq!(client.monitoring_timer_units(), 4);
+        assert_eq!(client.sock_timeout(), Some(Duration::from_secs(2)));
+
+        assert!(client
+            .set_monitoring_timer(Duration::from_millis(300))
+            .is_ok());
+        assert_eq!(client.monitoring_timer_units(), 2);
+        assert_eq!(client.sock_timeout(), Some(Duration::from_millis(1300)));
+
+        assert!(client.set_monitoring_timer(Duration::ZERO).is_ok());
+        assert_eq!(client.monitoring_timer_units(), 0);
+        assert_eq!(client.sock_timeout(), None);
+
+        let result = client.set_monitoring_timer(Duration::from_millis(0xFFFF * 250 + 1));
+        assert!(result.is_err());
+        assert_eq!(client.monitoring_timer(), Duration::ZERO);
+    }
+
+    // Replies to every request with `response`; binds an ephemeral port.
+    fn start_mock_plc(response: Vec<u8>) -> u16 {
+        start_mock_plc_sequence(vec![response])
+    }
+
+    // Replies with `responses` in order per connection, repeating the last one.
+    fn start_mock_plc_sequence(responses: Vec<Vec<u8>>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to address");
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.expect("Failed to accept connection");
+                let responses = responses.clone();
+                thread::spawn(move || {
+                    let mut buffer = [0; 1024];
+                    let mut count = 0;
+                    while let Ok(size) = stream.read(&mut buffer) {
+                        if size == 0 {
+                            break;
+                        }
+                        let response = &responses[count.min(responses.len() - 1)];
+                        count += 1;
+                        stream
+                            .write_all(response)
+                            .expect("Failed to write to stream");
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    // Like `start_mock_plc`, but also hands every request frame to the receiver
+    fn start_capturing_plc(response: Vec<u8>) -> (u16, mpsc::Receiver<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to address");
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("Failed to accept connection");
+            let mut buffer = [0; 1024];
+            while let Ok(size) = stream.read(&mut buffer) {
+                if size == 0 || sender.send(buffer[..size].to_vec()).is_err() {
+                    break;
+                }
+                stream
+                    .write_all(&response)
+                    .expect("Failed to write to stream");
+            }
+        });
+        (port, receiver)
+    }
+
+    // 3E ASCII response with completion code `status` followed by `payload`
+    fn e3_ascii_response(status: u16, payload: &str) -> Vec<u8> {
+        format!(
+            "D00000FF03FF00{:04X}{:04X}{}",
+            payload.len() + 4,
+            status,
+            payload
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_ascii_batch_write_frames() -> Result<(), Box<dyn Error>> {
+        let (port, requests) = start_capturing_plc(e3_ascii_response(0, ""));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", false);
+        client.set_comm_type("ascii");
+        client.set_monitoring_timer(Duration::from_secs(4))?;
+        client.connect()?;
+
+        // M100 to M107 set to 1,1,0,0,1,1,0,0
+        client.batch_write("M100", vec![1, 1, 0, 0, 1, 1, 0, 0], &DataType::BIT)?;
+        assert_eq!(
+            String::from_utf8(requests.recv()?)?,
+            "500000FF03FF000020001014010001M*000100000811001100"
+        );
+
+        // D100 to D102 set to 1995h, 1202h, 1130h
+        client.batch_write("D100", vec![0x1995, 0x1202, 0x1130], &DataType::UWORD)?;
+        assert_eq!(
+            String::from_utf8(requests.recv()?)?,
+            "500000FF03FF000024001014010000D*0001000003199512021130"
+        );
+
+        // hexadecimal devices keep their notation
+        client.batch_write("X1F", vec![1], &DataType::BIT)?;
+        assert_eq!(
+            String::from_utf8(requests.recv()?)?,
+            "500000FF03FF000019001014010001X*00001F00011"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_access_points() -> Result<(), Box<dyn Error>> {
+        let (port, requests) = start_capturing_plc(e4_response(&[
+            0x01, 0x00, 0x34, 0x12, 0x78, 0x56, 0x34, 0x12,
+        ]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+
+        let tags = client.read(vec![
+            QueryTag {
+                device: "M100".to_string(),
+                data_type: DataType::BIT,
+            },
+            QueryTag {
+                device: "D200".to_string(),
+                data_type: DataType::UDWORD,
+            },
+            QueryTag {
+                device: "W1F".to_string(),
+                data_type: DataType::UWORD,
+            },
+        ])?;
+        let request = requests.recv()?;
+        // 2 word points (M100, W1F), 1 dword point (D200)
+        assert_eq!(
+            &request[15..],
+            &[
+                0x03, 0x04, 0x00, 0x00, 0x02, 0x01, 0x64, 0x00, 0x00, 0x90, 0x1F, 0x00, 0x00, 0xB4,
+                0xC8, 0x00, 0x00, 0xA8
+            ]
+        );
+        let values: Vec<String> = tags.into_iter().filter_map(|tag| tag.value).collect();
+        assert_eq!(values, vec!["1", "305419896", "4660"]);
+
+        client.write(vec![
+            Tag::new(
+                "D200".to_string(),
+                Some("305419896".to_string()),
+                DataType::UDWORD,
+            ),
+            Tag::new(
+                "D300".to_string(),
+                Some("4660".to_string()),
+                DataType::UWORD,
+            ),
+            Tag::new("D400".to_string(), None, DataType::UWORD),
+        ])?;
+        let request = requests.recv()?;
+        assert_eq!(
+            &request[15..],
+            &[
+                0x02, 0x14, 0x00, 0x00, 0x01, 0x01, 0x2C, 0x01, 0x00, 0xA8, 0x34, 0x12, 0xC8, 0x00,
+                0x00, 0xA8, 0x78, 0x56, 0x34, 0x12
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_manual_fixtures() -> Result<(), Box<dyn Error>> {
+        for fixture in FIXTURES {
+            let (port, requests) = start_capturing_plc(fixture.response.to_vec());
+            let mut client = client_for(fixture, "127.0.0.1", port);
+            client.connect()?;
+            let values: Vec<Option<String>> = match fixture.name {
+                "batch_read_words" => client.batch_read("D100", 3, DataType::UWORD, true)?,
+                "batch_read_bits" => client.batch_read("M100", 8, DataType::BIT, true)?,
+                "batch_write_words" => {
+                    client.batch_write("D100", vec![0x1995, 0x1202, 0x1130], &DataType::UWORD)?;
+                    Vec::new()
+                }
+                "batch_write_bits" => {
+                    client.batch_write("M100", vec![1, 1, 0, 0, 1, 1, 0, 0], &DataType::BIT)?;
+                    Vec::new()
+                }
+                "random_read" => client.read(vec![
+                    QueryTag {
+                        device: "D0".to_string(),
+                        data_type: DataType::UWORD,
+                    },
+                    QueryTag {
+                        device: "M100".to_string(),
+                        data_type: DataType::UWORD,
+                    },
+                    QueryTag {
+                        device: "D1500".to_string(),
+                        data_type: DataType::UDWORD,
+                    },
+                ])?,
+                "random_write" => {
+                    client.write(vec![
+                        Tag::new("D0".to_string(), Some("1360".to_string()), DataType::UWORD),
+                        Tag::new(
+                            "D1500".to_string(),
+                            Some("305419896".to_string()),
+                            DataType::UDWORD,
+                        ),
+                    ])?;
+                    Vec::new()
+                }
+                "multi_block_read" => {
+                    let image = client.snapshot(&[("D0", 2), ("M0", 1)])?;
+                    ["D0", "D1", "M0"]
+                        .iter()
+                        .map(|device| {
+                            let value = image.get_u16(device).map(|value| value.to_string());
+                            Tag::new(device.to_string(), value, DataType::UWORD)
+                        })
+                        .collect()
+                }
+                "remote_run" => {
+                    client.remote_run(false)?;
+                    Vec::new()
+                }
+                "remote_stop" => {
+                    client.remote_stop()?;
+                    Vec::new()
+                }
+                "remote_reset" => {
+                    client.remote_reset()?;
+                    Vec::new()
+                }
+                "read_cpu_model" => {
+                    let (name, code) = client.read_cpu_model()?;
+                    vec![
+                        Tag::new("name".to_string(), Some(name), DataType::RAW(16)),
+                        Tag::new("code".to_string(), Some(code.to_string()), DataType::UWORD),
+                    ]
+                }
+                "loopback_test" => {
+                    client.loopback_test()?;
+                    Vec::new()
+                }
+                "remote_unlock" => {
+                    client.remote_unlock("1234")?;
+                    Vec::new()
+                }
+                "remote_lock" => {
+                    client.remote_lock("1234")?;
+                    Vec::new()
+                }
+                "open_file" => {
+                    let handle = client.open_file(0, "ABC.CSV")?;
+                    vec![Tag::new(
+                        "handle".to_string(),
+                        Some(handle.to_string()),
+                        DataType::UWORD,
+                    )]
+                }
+                "read_file_chunk" => {
+                    let data = client.read_file_chunk(1, 0, 4)?;
+                    vec![Tag::new(
+                        "data".to_string(),
+                        Some(hex::encode(data)),
+                        DataType::RAW(4),
+                    )]
+                }
+                "close_file" => {
+                    client.close_file(1)?;
+                    Vec::new()
+                }
+                name => panic!("no test for fixture {}", name),
+            }
+            .into_iter()
+            .map(|tag| tag.value)
+            .collect();
+            assert_frame_eq(&requests.recv()?, fixture.request);
+
+            let expected: Vec<&str> = match fixture.name {
+                "batch_read_words" => vec!["6549", "4610", "4400"],
+                "batch_read_bits" => vec!["0", "0", "0", "1", "0", "0", "1", "1"],
+                "random_read" => vec!["1360", "1", "305419896"],
+                "multi_block_read" => vec!["6549", "4610", "48"],
+                "read_cpu_model" => vec!["Q03UDVCPU", "870"],
+                "open_file" => vec!["1"],
+                "read_file_chunk" => vec!["deadbeef"],
+                _ => Vec::new(),
+            };
+            let expected: Vec<Option<String>> =
+                expected.into_iter().map(|v| Some(v.to_string())).collect();
+            assert_eq!(values, expected, "{} {}", fixture.name, fixture.comm_type);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixture_coverage() {
+        // every command a client call sends, in 3E and 4E frames
+        let sent = [
+            commands::BATCH_READ,
+            commands::BATCH_WRITE,
+            commands::RANDOM_READ,
+            commands::RANDOM_WRITE,
+            commands::MULTI_BLOCK_READ,
+            commands::REMOTE_RUN,
+            commands::REMOTE_STOP,
+            commands::REMOTE_RESET,
+            commands::READ_CPU_MODEL,
+            commands::LOOPBACK_TEST,
+            commands::REMOTE_UNLOCK,
+            commands::REMOTE_LOCK,
+            commands::FILE_OPEN,
+            commands::FILE_READ,
+            commands::FILE_CLOSE,
+        ];
+        for command in sent {
+            for use_e4 in [false, true] {
+                let at = if use_e4 { 15 } else { 11 };
+                let fixtures = FIXTURES
+                    .iter()
+                    .filter(|f| f.use_e4 == use_e4 && f.comm_type == consts::COMMTYPE_BINARY)
+                    .filter(|f| LittleEndian::read_u16(&f.request[at..]) == command)
+                    .count();
+                assert!(fixtures > 0, "no fixture for command {:04X}", command);
+            }
+        }
+        assert_eq!(FIXTURES.len() % 4, 0);
+    }
+
+    #[test]
+    fn test_ascii_responses() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e3_ascii_response(0, "0110"));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", false);
+        client.set_comm_type("ascii");
+        client.connect()?;
+        let values: Vec<Option<String>> = client
+            .batch_read("M100", 4, DataType::BIT, true)?
+            .into_iter()
+            .map(|tag| tag.value)
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                Some("0".to_string()),
+                Some("1".to_string()),
+                Some("1".to_string()),
+                Some("0".to_string())
+            ]
+        );
+
+        let port = start_mock_plc(e3_ascii_response(0, "12340010"));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", false);
+        client.set_comm_type("ascii");
+        client.connect()?;
+        let tags = client.batch_read("D100", 2, DataType::UWORD, true)?;
+        assert_eq!(tags[0].value, Some("4660".to_string()));
+        assert_eq!(tags[1].value, Some("16".to_string()));
+
+        let port = start_mock_plc(e3_ascii_response(0xC051, ""));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", false);
+        client.set_comm_type("ascii");
+        client.connect()?;
+        let result = client.batch_write("M100", vec![1], &DataType::BIT);
+        assert_eq!(
+            err::find::<err::MCError>(&*result.unwrap_err())
+                .unwrap()
+                .code(),
+            0xC051
+        );
+        Ok(())
+    }
+
+    // 4E binary response header with a zero completion code
+    fn e4_response(payload: &[u8]) -> Vec<u8> {
+        let mut response = vec![
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00,
+        ];
+        response
+            .write_u16::<LittleEndian>(payload.len() as u16 + 2)
+            .unwrap();
+        response.write_u16::<LittleEndian>(0).unwrap();
+        response.extend_from_slice(payload);
+        response
+    }
+
+    // 4E binary error response with completion code `status`
+    fn e4_error_response(status: u16) -> Vec<u8> {
+        let mut response = e4_response(&[]);
+        LittleEndian::write_u16(&mut response[13..15], status);
+        response
+    }
+
+    #[test]
+    fn test_retry_transient_error() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc_sequence(vec![
+            e4_error_response(0xC05F),
+            e4_error_response(0xC05F),
+            e4_response(&[0x34, 0x12]),
+        ]);
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        let result = client.batch_read("D100", 1, DataType::UWORD, true);
+        assert_eq!(
+            err::find::<err::MCError>(&*result.unwrap_err())
+                .unwrap()
+                .code(),
+            0xC05F
+        );
+
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        client.set_retry_policy(Some(
+            RetryPolicy::new(2, Duration::from_millis(1)).transient(0xC05F),
+        ));
+        let tags = client.batch_read("D100", 1, DataType::UWORD, true)?;
+        assert_eq!(tags[0].value, Some("4660".to_string()));
+
+        let port = start_mock_plc(e4_error_response(0xC056));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        client.set_retry_policy(Some(
+            RetryPolicy::new(2, Duration::from_millis(1)).transient(0xC05F),
+        ));
+        let e = client
+            .batch_read("D100", 1, DataType::UWORD, true)
+            .unwrap_err();
+        assert_eq!(err::find::<err::MCError>(&*e).unwrap().code(), 0xC056);
+        assert!(e
+            .to_string()
+            .starts_with("BatchRead D100 on 127.0.0.1 failed (#1): 0xC056: "));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_malformed_responses() {
+        let response = e4_response(&[0x34, 0x12, 0x78, 0x56]);
+        let client = Client::new("127.0.0.1".to_string(), 5000, "Q", true);
+        for length in 0..response.len() {
+            let result = client.parse_batch_read(
+                "D100",
+                2,
+                DataType::UWORD,
+                true,
+                response[..length].to_vec(),
+            );
+            assert!(result.is_err());
+        }
+        let result = client.parse_batch_read("D100", 3, DataType::UWORD, true, response.clone());
+        let short_read = match result {
+            Err(e) => e.downcast::<err::ShortRead>().unwrap(),
+            Ok(_) => panic!("expected a short read"),
+        };
+        assert_eq!((short_read.requested, short_read.received), (3, 2));
+        let mut client = Client::new("127.0.0.1".to_string(), 5000, "Q", false);
+        client.set_comm_type("ascii");
+        let response = e3_ascii_response(0, "12Z4");
+        for length in 0..response.len() {
+            let result = client.parse_batch_read(
+                "D100",
+                1,
+                DataType::UWORD,
+                true,
+                response[..length].to_vec(),
+            );
+            assert!(result
+                .map(|tags| tags.last().unwrap().is_err())
+                .unwrap_or(true));
+        }
+        assert!(client
+            .parse_batch_read("D", 1, DataType::UWORD, true, Vec::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_slow_transaction_stats() -> Result<(), Box<dyn Error>> {
+        let port =
+            start_mock_plc_sequence(vec![e4_response(&[0x34, 0x12]), e4_error_response(0xC056)]);
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        client.batch_read("D100", 1, DataType::UWORD, true)?;
+        assert!(client.batch_read("D100", 1, DataType::UWORD, true).is_err());
+        let stats = client.stats();
+        assert_eq!(stats.transactions, 2);
+        assert_eq!(stats.failures, 1);
+        assert_eq!(stats.slow_transactions, 0);
+
+        let hot_spots = stats.error_heat_map();
+        assert_eq!(hot_spots.len(), 1);
+        assert_eq!(hot_spots[0].0, "D100");
+        assert_eq!(hot_spots[0].1.transactions, 2);
+        assert_eq!(hot_spots[0].1.last_error_code, Some(0xC056));
+
+        client.reset_stats();
+        client.set_slow_transaction_threshold(Some(Duration::ZERO));
+        let _ = client.batch_read("D100", 1, DataType::UWORD, true);
+        assert_eq!(client.stats().slow_transactions, 1);
+        assert_eq!(client.range_label("X1E", 3), "X1E-X20");
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_read_into() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[0x34, 0x12, 0x78, 0x56, 0x10, 0x00]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+
+        let mut buf = [0u8; 8];
+        let size = client.batch_read_into("D100", 3, &mut buf)?;
+        assert_eq!(size, 6);
+        assert_eq!(&buf[..size], &[0x34, 0x12, 0x78, 0x56, 0x10, 0x00]);
+
+        let mut words = [0u16; 3];
+        client.batch_read_words_into("D100", &mut words)?;
+        assert_eq!(words, [0x1234, 0x5678, 0x0010]);
+
+        let mut dwords = [0u32; 1];
+        client.batch_read_dwords_into("D100", &mut dwords)?;
+        assert_eq!(dwords, [0x56781234]);
+
+        let mut bits = [false; 4];
+        client.batch_read_bits_into("M0", &mut bits)?;
+        assert_eq!(bits, [true, false, true, false]);
+
+        let mut small = [0u8; 2];
+        assert!(client.batch_read_into("D100", 3, &mut small).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_read_iter() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[0x34, 0x12, 0x78, 0x56, 0x10, 0x00]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+
+        let mut tags = client.batch_read_iter("D100", 3, DataType::UWORD, true)?;
+        assert_eq!(tags.len(), 3);
+        let first = tags.next().unwrap()?;
+        assert_eq!(first.device, "D100");
+        assert_eq!(first.value, Some("4660".to_string()));
+        assert_eq!(tags.len(), 2);
+        let rest: Vec<Tag> = tags.collect::<Result<_, _>>()?;
+        assert_eq!(rest[1].device, "D102");
+        assert_eq!(rest[1].value, Some("16".to_string()));
+
+        match client.batch_read_iter("D100", 4, DataType::UWORD, true) {
+            Err(e) => assert!(e.is::<err::ShortRead>()),
+            Ok(_) => panic!("expected a short read"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_read_raw() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[0xFF, 0x80, 0x00, 0xC3]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+
+        let tags = client.batch_read("D100", 2, DataType::UWORD, false)?;
+        assert_eq!(tags[0].value, Some("ff80".to_string()));
+        assert_eq!(tags[1].raw_bytes(), Some(vec![0x00, 0xC3]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_decoder() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[0x24, 0x10, 0x15, 0x09, 0x30, 0x12]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        let timestamp = DataType::CUSTOM {
+            name: "bcd_timestamp".to_string(),
+            size: 6,
+        };
+
+        let result = client.batch_read("D100", 1, timestamp.clone(), true);
+        assert!(result.is_err());
+
+        client.register_decoder("bcd_timestamp", |data| {
+            Ok(format!(
+                "20{:02x}-{:02x}-{:02x} {:02x}:{:02x}:{:02x}",
+                data[0], data[1], data[2], data[3], data[4], data[5]
+            ))
+        });
+        let result = client.batch_read("D100", 1, timestamp.clone(), true)?;
+        assert_eq!(result[0].value, Some("2024-10-15 09:30:12".to_string()));
+
+        // random read answers the D102 word point before the D100 dword point
+        let port = start_mock_plc(e4_response(&[0x30, 0x12, 0x24, 0x10, 0x15, 0x09]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        client.register_decoder("bcd_timestamp", |data| Ok(hex::encode(data)));
+        let result = client.read(vec![QueryTag {
+            device: "D100".to_string(),
+            data_type: timestamp.clone(),
+        }])?;
+        assert_eq!(result[0].value, Some("241015093012".to_string()));
+
+        assert!(client.encode_value(0, timestamp, false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[0x34, 0x12, 0x78, 0x56]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+
+        client.batch_read("D100", 2, DataType::UWORD, true)?;
+        assert!(client.get_cached("D100").is_none());
+
+        client.enable_cache(Duration::from_millis(50));
+        client.batch_read("D100", 2, DataType::UWORD, true)?;
+        let cached = client.get_cached("D101").unwrap();
+        assert_eq!(cached.value, "22136");
+        assert!(!cached.is_stale);
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(client.get_cached("D101").unwrap().is_stale);
+        assert!(client.get_cached("D102").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_pool() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[0x34, 0x12]));
+        let pool = ClientPool::connect("127.0.0.1", port, "Q", true, 2)?;
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..5 {
+                        let tags = pool
+                            .execute(|client| client.batch_read("D100", 1, DataType::UWORD, true))
+                            .unwrap();
+                        assert_eq!(tags[0].value, Some("4660".to_string()));
+                    }
+                });
+            }
+        });
+        let health = pool.health();
+        assert_eq!(health.iter().map(|h| h.total_requests).sum::<u64>(), 20);
+        assert!(health.iter().all(|h| h.healthy && h.total_requests > 0));
+
+        for _ in 0..6 {
+            let result: Result<(), Box<dyn Error>> = pool.execute(|_| Err("timeout".into()));
+            assert!(result.is_err());
+        }
+        assert!(pool.health().iter().all(|h| !h.healthy));
+        pool.execute(|client| client.batch_read("D100", 1, DataType::UWORD, true))?;
+        assert_eq!(pool.health().iter().filter(|h| h.healthy).count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_pool_priority() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[]));
+        let pool = ClientPool::connect("127.0.0.1", port, "Q", true, 1)?;
+        let order = std::sync::Mutex::new(Vec::new());
+        let run = |name: &'static str, priority: Priority, delay: u64| {
+            thread::sleep(Duration::from_millis(delay));
+            pool.execute_with_priority(priority, |_| {
+                order.lock().unwrap().push(name);
+                thread::sleep(Duration::from_millis(50));
+                Ok(())
+            })
+            .unwrap();
+        };
+        thread::scope(|scope| {
+            scope.spawn(|| run("bulk", Priority::Low, 0));
+            scope.spawn(|| run("historian", Priority::Low, 10));
+            scope.spawn(|| run("operator", Priority::High, 20));
+        });
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["bulk", "operator", "historian"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_pool_cancel_queued() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[]));
+        let pool = ClientPool::connect("127.0.0.1", port, "Q", true, 1)?;
+        let token = CancelToken::new();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                pool.execute(|_| {
+                    thread::sleep(Duration::from_millis(100));
+                    Ok(())
+                })
+                .unwrap();
+            });
+            let queued = scope.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                pool.execute_cancellable(Priority::Low, &token, |_| Ok(()))
+                    .map_err(|e| e.to_string())
+            });
+            thread::sleep(Duration::from_millis(30));
+            token.cancel();
+            let result = queued.join().unwrap();
+            assert_eq!(result, Err("Operation cancelled".to_string()));
+        });
+        assert!(pool.execute(|_| Ok(())).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deadline() -> Result<(), Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        // accepts but never answers
+        thread::spawn(move || {
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(50);
+        let result = client.batch_read_with_deadline("D100", 1, DataType::UWORD, true, deadline);
+        assert!(err::find::<err::DeadlineExceeded>(&*result.unwrap_err()).is_some());
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let result =
+            client.read_with_deadline(Vec::new(), Instant::now() - Duration::from_millis(1));
+        assert!(err::find::<err::DeadlineExceeded>(&*result.unwrap_err()).is_some());
+        assert_eq!(*client.deadline.lock().unwrap(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch_poll_once() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[0x34, 0x12, 0x01, 0x00]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        assert!(client.poll_once()?.is_empty());
+
+        client.watch("D100", DataType::UWORD);
+        client.watch("D200", DataType::UWORD);
+        let changed = client.poll_once()?;
+        assert_eq!(changed.len(), 2);
+        assert_eq!(changed[0].value, Some("4660".to_string()));
+        assert!(client.poll_once()?.is_empty());
+
+        client.unwatch("D200");
+        client.watch("D300", DataType::UWORD);
+        let changed = client.poll_once()?;
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].device, "D300");
+        Ok(())
+    }
+
+    #[test]
+    fn test_force_and_pulse_bits() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        client.set_write_rate_limit(Some(
+            WriteRateLimiter::new(crate::rate_limit::ThrottlePolicy::Reject)
+                .device_interval(Duration::from_secs(10)),
+        ));
+        client.force_bits("Y10", 8, true)?;
+        let started = Instant::now();
+        client.pulse_bit("Y20", Duration::from_millis(20))?;
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        let log = client.operation_log();
+        let operations: Vec<(&str, &str)> = log
+            .iter()
+            .map(|record| (record.operation, record.target.as_str()))
+            .collect();
+        assert_eq!(
+            operations,
+            vec![
+                ("batch_write", "Y10-Y17"),
+                ("batch_write", "Y20"),
+                ("pulse_bit", "Y20")
+            ]
+        );
+        assert!(client.pulse_bit("Y20", Duration::from_millis(20)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_io_checkout() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[0x10, 0x01]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        let mut tag_map = TagMap::new();
+        tag_map.insert("Conveyor run", "Y1F", DataType::BIT);
+
+        let report = IoCheckout::new()
+            .inputs("X0", 2)
+            .outputs("Y1E", 2)
+            .with_labels(&tag_map)
+            .toggle_outputs(Duration::from_millis(1))
+            .run(&client, |point| point.device == "Y1F")?;
+
+        let devices: Vec<&str> = report.points.iter().map(|p| p.device.as_str()).collect();
+        assert_eq!(devices, vec!["X0", "X1", "Y1E", "Y1F"]);
+        assert!(report.points[0].state && !report.points[1].state);
+        assert_eq!(report.count(&CheckoutResult::Read), 2);
+        assert_eq!(report.points[2].result, CheckoutResult::Rejected);
+        assert_eq!(report.points[3].result, CheckoutResult::Confirmed);
+        assert_eq!(report.failures().len(), 1);
+        assert!(report
+            .to_csv()
+            .contains("Y1F,Conveyor run,output,0,confirmed"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_redundant_failover() -> Result<(), Box<dyn Error>> {
+        // system A answers as standby, system B as control
+        let port_a = start_mock_plc(e4_response(&[0x10, 0x00, 0x01]));
+        let port_b = start_mock_plc(e4_response(&[0x01, 0x00, 0x10]));
+        let mut client = Client::new("127.0.0.1".to_string(), port_a, "Q", true);
+        client.set_redundant_target(RedundantTarget::ControlSystem);
+
+        let mut failover = RedundantFailover::new(("127.0.0.1", port_a), ("127.0.0.1", port_b));
+        let status = failover.connect(&mut client)?;
+        assert_eq!(status.system, RedundantSystem::B);
+        assert!(status.is_control);
+        assert_eq!(failover.active_endpoint(), ("127.0.0.1", port_b));
+        assert_eq!(client.port(), port_b);
+        assert_eq!(client.dest_moduleio, 0x03D0);
+        assert!(!failover.check(&mut client)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failover_endpoints() -> Result<(), Box<dyn Error>> {
+        let unreachable = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let port = start_mock_plc(e4_response(&[0x34, 0x12]));
+        let mut client = Client::new("127.0.0.1".to_string(), 0, "Q", true);
+        client.set_failover_endpoints(vec![
+            ("127.0.0.1".to_string(), unreachable),
+            ("127.0.0.1".to_string(), port),
+        ]);
+        assert_eq!(client.active_endpoint(), ("127.0.0.1", unreachable));
+        client.connect()?;
+        assert_eq!(client.active_endpoint(), ("127.0.0.1", port));
+        let mut out = [0u16; 1];
+        client.batch_read_words_into("D100", &mut out)?;
+        assert_eq!(out, [0x1234]);
+
+        // the next endpoint after the active one wraps around to the
+        // unreachable one and moves on to the reachable one again
+        client.failover()?;
+        assert_eq!(client.active_endpoint(), ("127.0.0.1", port));
+        Ok(())
+    }
+
+    #[test]
+    fn test_connect_resolves_host() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[]));
+        let mut client = Client::new("localhost".to_string(), port, "Q", true);
+        client.connect()?;
+        let addr = client.peer_addr().unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_eq!(addr.port(), port);
+
+        // IPv6 literals are taken as they are, without brackets
+        if let Ok(listener) = TcpListener::bind("[::1]:0") {
+            let port = listener.local_addr()?.port();
+            let mut client = Client::new("::1".to_string(), port, "Q", true);
+            client.connect()?;
+            assert!(client.peer_addr().unwrap().is_ipv6());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_password_unlock_on_connect() -> Result<(), Box<dyn Error>> {
+        let (port, frames) = start_capturing_plc(e4_response(&[]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        assert!(client.set_remote_password(Some("toolong")).is_err());
+        client.set_remote_password(Some("ab12"))?;
+        client.connect()?;
+        let frame = frames.recv()?;
+        assert_eq!(&frame[15..19], &[0x30, 0x16, 0x00, 0x00]);
+        assert_eq!(&frame[19..], &[0x04, 0x00, b'a', b'b', b'1', b'2']);
+        let annotated = client.annotate(&frame, FrameKind::Request).to_string();
+        assert!(annotated.ends_with("data=<redacted>"));
+
+        let port = start_mock_plc(e4_error_response(0xC200));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.set_remote_password(Some("ab12"))?;
+        let e = client.connect().unwrap_err();
+        let rejected = err::find::<err::PasswordRejected>(&*e).unwrap();
+        assert_eq!(rejected.code, 0xC200);
+        assert!(!e.to_string().contains("ab12"));
+        assert!(!client.is_connected());
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_password() -> Result<(), Box<dyn Error>> {
+        let (port, frames) = start_capturing_plc(e4_response(&[0x01, 0x00]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        assert!(client.set_file_password(Some("12345")).is_err());
+        client.set_file_password(Some("ab"))?;
+        client.connect()?;
+        assert_eq!(client.open_file(2, "LOG.CSV")?, 1);
+        let frame = frames.recv()?;
+        assert_eq!(&frame[15..19], &[0x27, 0x18, 0x00, 0x00]);
+        assert_eq!(&frame[19..23], b"ab  ");
+        assert_eq!(&frame[23..29], &[0x00, 0x00, 0x02, 0x00, 0x07, 0x00]);
+        assert_eq!(&frame[29..], b"LOG.CSV");
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_file() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc_sequence(vec![
+            e4_response(&[0x01, 0x00]),
+            e4_response(&[0x03, 0x00, b'a', b'b', b'c']),
+            e4_response(&[]),
+        ]);
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        assert_eq!(client.read_file(2, "LOG.CSV")?, b"abc");
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_log() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc_sequence(vec![
+            e4_response(&[]),
+            e4_error_response(0xC056),
+            e4_response(&[]),
+        ]);
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        client.remote_stop()?;
+        assert!(client
+            .batch_write("D100", vec![1, 2], &DataType::UWORD)
+            .is_err());
+        client.remote_run(false)?;
+
+        let log = client.operation_log();
+        let operations: Vec<&str> = log.iter().map(|record| record.operation).collect();
+        assert_eq!(operations, vec!["remote_stop", "batch_write", "remote_run"]);
+        assert_eq!(log[1].target, "D100-D101");
+        assert!(log[0].is_ok() && !log[1].is_ok() && log[2].is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_drive_status() -> Result<(), Box<dyn Error>> {
+        // SM603, SM600, SM601 as words, then SD606 and SD610 as dwords
+        let port = start_mock_plc(e4_response(&[
+            0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x10, 0x00, 0x00,
+        ]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        let status = client.drive_status()?;
+        assert!(status.card_present && status.card_usable && !status.write_protected);
+        assert_eq!(status.capacity_kb, 4 * 1024 * 1024);
+        assert_eq!(status.free_kb, 4096);
+        assert!(status.can_write(4096 * 1024));
+        assert!(!status.can_write(4096 * 1024 + 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_diagnostics() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc(e4_response(&[
+            0x01, 0x00, 0x00, 0x00, 0x32, 0x08, 0x00, 0x00, 0x02, 0x00, 0x0C, 0x00, 0xF4, 0x01,
+            0x14, 0x00, 0x00, 0x00,
+        ]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        let diagnostics = client.read_diagnostics()?;
+        assert!(diagnostics.has_error && !diagnostics.battery_low);
+        assert_eq!(diagnostics.error_code, 0x0832);
+        assert_eq!(diagnostics.switch_position, SwitchPosition::Run);
+        assert_eq!(diagnostics.operating_status, OperatingStatus::Pause);
+        assert_eq!(diagnostics.scan_time, Duration::from_micros(12_500));
+        assert_eq!(diagnostics.max_scan_time, Duration::from_millis(20));
+        Ok(())
+    }
+
+    #[test]
+    fn test_battery_and_error_status() -> Result<(), Box<dyn Error>> {
+        let port = start_mock_plc_sequence(vec![
+            e4_response(&[0x00, 0x00, 0x01, 0x00]),
+            e4_response(&[0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x07, 0x00]),
+        ]);
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        assert_eq!(client.battery_status()?, BatteryStatus::LowLatched);
+        assert_eq!(client.error_status()?, ErrorStatus::Annunciator(7));
+        Ok(())
+    }
+
+    #[test]
+    fn test_e4_serial() -> Result<(), Box<dyn Error>> {
+        let (port, frames) = start_capturing_plc(e4_response(&[0x34, 0x12]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        assert!(format!("{:?}", client).starts_with("Client { frame: \"4E\""));
+        client.set_subheader_serial(0x0102)?;
+        client.connect()?;
+        let mut out = [0u16; 1];
+        let e = client.batch_read_words_into("D100", &mut out).unwrap_err();
+        assert!(e.to_string().contains("serial number 0 does not match"));
+        assert_eq!(&frames.recv()?[..4], &[0x54, 0x00, 0x02, 0x01]);
+
+        client.set_subheader_serial(0)?;
+        client.batch_read_words_into("D100", &mut out)?;
+        assert_eq!(out, [0x1234]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_device() -> Result<(), Box<dyn Error>> {
+        let mut client = Client::new("127.0.0.1".to_string(), 0, "Q", true);
+        assert!(client.build_device_data("QV10").is_err());
+        client.register_device(
+            "QV",
+            CustomDevice {
+                binary_code: 0xE0,
+                ascii_code: "QV".to_string(),
+                base: 16,
+            },
+        );
+        assert_eq!(
+            client.build_device_data("QV10")?,
+            vec![0x10, 0x00, 0x00, 0xE0]
+        );
+        client.set_comm_type("ascii");
+        assert_eq!(client.build_device_data("QV1F")?, b"QV00001F");
+
+        let mut client = Client::new("127.0.0.1".to_string(), 0, "iQ-R", true);
+        assert_eq!(
+            client.build_device_data("D100")?,
+            vec![0x64, 0x00, 0x00, 0x00, 0xA8, 0x00]
+        );
+        client.register_device(
+            "QV",
+            CustomDevice {
+                binary_code: 0x01E0,
+                ascii_code: "QV".to_string(),
+                base: 10,
+            },
+        );
+        assert_eq!(
+            client.build_device_data("QV7")?,
+            vec![0x07, 0x00, 0x00, 0x00, 0xE0, 0x01]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_connect_via_got() -> Result<(), Box<dyn Error>> {
+        let (port, frames) = start_capturing_plc(e4_response(&[0x34, 0x12]));
+        let mut client = Client::new("192.0.2.1".to_string(), 5000, "Q", true);
+        let got = GotTransparent::new("127.0.0.1", port).network_station(2, 3);
+        client.connect_via_got(&got)?;
+        assert_eq!(client.active_endpoint(), ("127.0.0.1", port));
+        let mut out = [0u16; 1];
+        client.batch_read_words_into("D100", &mut out)?;
+        // network 2, station 3, connected CPU
+        assert_eq!(&frames.recv()?[6..11], &[0x02, 0x03, 0xFF, 0x03, 0x00]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_write() -> Result<(), Box<dyn Error>> {
+        let (port, frames) = start_capturing_plc(Vec::new());
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        assert!(client
+            .broadcast_write(0, "D100", vec![1], &DataType::UWORD)
+            .is_err());
+        client.broadcast_write(3, "D100", vec![0x1234], &DataType::UWORD)?;
+        let frame = frames.recv()?;
+        assert_eq!(&frame[6..8], &[0x03, 0xFF]);
+        assert_eq!(&frame[frame.len() - 2..], &[0x34, 0x12]);
+        assert_eq!(client.operation_log()[1].target, "D100 on network 3");
+        Ok(())
+    }
+
+    // Answers the n-th request with `response` split into two TCP segments
+    // after byte n.
+    fn start_fragmenting_plc(response: &'static [u8]) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to address");
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("Failed to accept connection");
+            stream.set_nodelay(true).unwrap();
+            let mut buffer = [0; 1024];
+            let mut split = 1;
+            while let Ok(size) = stream.read(&mut buffer) {
+                if size == 0 {
+                    break;
+                }
+                let at = split.min(response.len());
+                stream.write_all(&response[..at]).unwrap();
+                thread::sleep(Duration::from_millis(2));
+                stream.write_all(&response[at..]).unwrap();
+                split += 1;
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_recv_fragmented_responses() -> Result<(), Box<dyn Error>> {
+        for name in ["batch_read_words", "random_read"] {
+            for fixture in FIXTURES.iter().filter(|f| f.name == name) {
+                let port = start_fragmenting_plc(fixture.response);
+                let mut client = client_for(fixture, "127.0.0.1", port);
+                client.connect()?;
+                for _ in 1..fixture.response.len() {
+                    client.send(fixture.request)?;
+                    assert_frame_eq(&client.recv()?, fixture.response);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_connection_closed() -> Result<(), Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer);
+            // half a header, then close
+            stream.write_all(&[0xD4, 0x00, 0x00]).unwrap();
+        });
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        let mut out = [0u16; 1];
+        let e = client.batch_read_words_into("D100", &mut out).unwrap_err();
+        assert_eq!(err::find::<err::ConnectionClosed>(&*e).unwrap().received, 3);
+        assert!(!client.is_connected());
+        assert!(client.batch_read_words_into("D100", &mut out).is_err());
+        Ok(())
     }
 
-    fn check_command_response(&self, recv_data: &[u8]) -> Result<(), err::MCError> {
-        let response_status_index = self.device_type.get_response_status_index(self.comm_type);
-        let response_status = self
-            .decode_value(
-                &recv_data[response_status_index..response_status_index + self._wordsize],
-                &DataType::SWORD,
-                false,
-            )
-            .unwrap() as u16;
+    #[test]
+    fn test_partial_frame_timeout() -> Result<(), Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let (sender, receiver) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer);
+            // the rest of the frame comes after the client gave up
+            let response = e4_response(&[0x34, 0x12]);
+            stream.write_all(&response[..5]).unwrap();
+            let _ = receiver.recv();
+            let _ = stream.write_all(&response[5..]);
+        });
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        let deadline = Instant::now() + Duration::from_millis(200);
+        let e = client
+            .batch_read_with_deadline("D100", 1, DataType::UWORD, true, deadline)
+            .unwrap_err();
+        assert!(err::find::<err::DeadlineExceeded>(&*e).is_some());
+        assert!(err::find::<err::ShortRead>(&*e).is_none());
+        assert!(!client.is_connected());
+        sender.send(())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_information() -> Result<(), Box<dyn Error>> {
+        let mut response = e4_response(&[0x02, 0x05, 0xFF, 0x03, 0x00, 0x01, 0x04, 0x00, 0x00]);
+        LittleEndian::write_u16(&mut response[13..15], 0xC056);
+        let port = start_mock_plc(response);
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        let mut out = [0u16; 1];
+        let e = client.batch_read_words_into("D100", &mut out).unwrap_err();
+        let mc_error = err::find::<err::MCError>(&*e).unwrap();
+        assert_eq!(
+            mc_error.info(),
+            Some(&err::ErrorInformation {
+                network: 2,
+                pc: 5,
+                dest_moduleio: 0x03FF,
+                dest_modulesta: 0,
+                command: 0x0401,
+                subcommand: 0,
+            })
+        );
+        assert!(e
+            .to_string()
+            .ends_with("(network 2, station 05h, module 03FFh/0, command 0401h/0000h)"));
+
+        let port = start_mock_plc(e3_ascii_response(0xC059, "00FF03FF0004010000"));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", false);
+        client.set_comm_type("ascii");
+        client.connect()?;
+        let e = client.batch_read_words_into("D100", &mut out).unwrap_err();
+        let info = *err::find::<err::MCError>(&*e).unwrap().info().unwrap();
+        assert_eq!((info.network, info.pc, info.command), (0, 0xFF, 0x0401));
 
-        Client::check_mc_error(response_status)
+        let port = start_mock_plc(e4_error_response(0xC059));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.connect()?;
+        let e = client.batch_read_words_into("D100", &mut out).unwrap_err();
+        assert!(err::find::<err::MCError>(&*e).unwrap().info().is_none());
+        Ok(())
     }
 
-    pub fn read(&self, devices: Vec<QueryTag>) -> Result<Vec<Tag>, Box<dyn Error>> {
-        let command = commands::RANDOM_READ;
-        let subcommand = if self.plc_type == consts::IQR_SERIES {
-            subcommands::TWO
-        } else {
-            subcommands::ZERO
-        };
+    #[test]
+    fn test_transaction_ids() -> Result<(), Box<dyn Error>> {
+        let simulator = crate::simulator::Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.set_serial_per_transaction(true);
+        client.connect()?;
+        assert_eq!(client.last_transaction_id(), 0);
+        let mut out = [0u16; 1];
+        client.batch_read_words_into("D0", &mut out)?;
+        client.batch_read_words_into("D0", &mut out)?;
+        assert_eq!(client.last_transaction_id(), 2);
 
-        let mut words_count = 0;
+        simulator.inject(crate::simulator::Fault::CompletionCode(0xC056));
+        let e = client.batch_read_words_into("D0", &mut out).unwrap_err();
+        assert_eq!(
+            err::find::<err::MelsecError>(&*e).unwrap().transaction_id,
+            3
+        );
+        let stats = client.stats();
+        assert_eq!(stats.targets["D0"].last_error_transaction, Some(3));
 
-        for element in &devices {
-            let _size = element.data_type.size();
-            words_count += _size / 2;
+        // 3E frames keep network 0 and PC FFh where 4E frames have the serial
+        let (port, requests) = start_capturing_plc(vec![
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ]);
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", false);
+        client.set_serial_per_transaction(true);
+        client.connect()?;
+        for _ in 0..2 {
+            client.batch_write("D0", vec![1], &DataType::SWORD)?;
+            assert_eq!(
+                requests.recv()?[..7],
+                [0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00]
+            );
         }
+        Ok(())
+    }
 
-        let mut request_data = Vec::new();
-        request_data.extend(self.build_command_data(command, subcommand)?);
-        request_data.extend(self.encode_value(words_count as i64, DataType::BIT, false)?);
-        request_data.extend(self.encode_value(0, DataType::BIT, false)?);
-
-        for element in &devices {
-            let element_size = element.data_type.size() / 2;
-            if element_size > 1 {
-                let tag_name = &element.device;
-                let device_type = get_device_type(tag_name)?;
-                let mut device_index = get_device_index(tag_name)?;
-                for _ in 0..element_size {
-                    let temp_tag_name = format!("{}{}", device_type, device_index);
-                    request_data.extend(self.build_device_data(&temp_tag_name)?);
-                    device_index += 1;
+    #[test]
+    fn test_pymcprotocol_frames() -> Result<(), Box<dyn Error>> {
+        for fixture in crate::test_fixtures::PYMCPROTOCOL_FIXTURES {
+            let (port, requests) = start_capturing_plc(fixture.response.to_vec());
+            let mut client = Client::new("127.0.0.1".to_string(), port, "Q", fixture.use_e4);
+            client.set_comm_type(fixture.comm_type);
+            client.connect()?;
+            let values: Vec<Option<String>> = match fixture.name {
+                "batchread_wordunits" => client.batch_read("D100", 3, DataType::SWORD, true)?,
+                "batchwrite_wordunits" => {
+                    client.batch_write("D10", vec![1, -1], &DataType::SWORD)?;
+                    Vec::new()
                 }
-            } else {
-                request_data.extend(self.build_device_data(&element.device)?);
+                "batchwrite_bitunits" => {
+                    client.batch_write("M0", vec![1, 0, 1], &DataType::BIT)?;
+                    Vec::new()
+                }
+                "randomread" => client.read(
+                    [
+                        ("D1000", DataType::SWORD),
+                        ("D2000", DataType::SWORD),
+                        ("D3000", DataType::SDWORD),
+                    ]
+                    .into_iter()
+                    .map(|(device, data_type)| QueryTag {
+                        device: device.to_string(),
+                        data_type,
+                    })
+                    .collect(),
+                )?,
+                "randomwrite" => {
+                    client.write(vec![
+                        Tag::new("D1000".to_string(), Some("5".to_string()), DataType::SWORD),
+                        Tag::new(
+                            "D3000".to_string(),
+                            Some("70000".to_string()),
+                            DataType::SDWORD,
+                        ),
+                    ])?;
+                    Vec::new()
+                }
+                "remote_run" => {
+                    client.remote_run(false)?;
+                    Vec::new()
+                }
+                "remote_stop" => {
+                    client.remote_stop()?;
+                    Vec::new()
+                }
+                name => panic!("no test for pymcprotocol call {}", name),
             }
-        }
+            .into_iter()
+            .map(|tag| tag.value)
+            .collect();
+            assert_frame_eq(&requests.recv()?, fixture.request);
 
-        if words_count < 1 {
-            return Ok(Vec::new());
+            let expected: Vec<&str> = match fixture.name {
+                "batchread_wordunits" => vec!["1", "2", "3"],
+                "randomread" => vec!["10", "20", "70000"],
+                _ => Vec::new(),
+            };
+            let expected: Vec<Option<String>> =
+                expected.into_iter().map(|v| Some(v.to_string())).collect();
+            assert_eq!(values, expected, "{}", fixture.name);
         }
+        Ok(())
+    }
 
-        let send_data = self.build_send_data(&request_data)?;
-        self.send(&send_data)?;
-        let recv_data = self.recv()?;
-
-        let mut output = Vec::new();
-        self.check_command_response(&recv_data)?;
+    #[test]
+    fn test_octal_inputs() -> Result<(), Box<dyn Error>> {
+        let (port, requests) = start_capturing_plc(e3_ascii_response(0, ""));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "iQ-F", false);
+        client.set_comm_type("ascii");
+        client.set_monitoring_timer(Duration::from_secs(4))?;
+        client.connect()?;
 
-        let mut data_index = self.device_type.get_response_data_index(self.comm_type);
+        // X17 is the 16th input of an FX5 CPU and stays octal on the wire
+        client.batch_write("X17", vec![1], &DataType::BIT)?;
+        assert_eq!(
+            String::from_utf8(requests.recv()?)?,
+            "500000FF03FF000019001014010001X*00001700011"
+        );
+        assert!(client.batch_write("X18", vec![1], &DataType::BIT).is_err());
+        assert_eq!(
+            client.device_range("Y6", 3)?,
+            vec!["Y6".to_string(), "Y7".to_string(), "Y10".to_string()]
+        );
+        let client = Client::new("127.0.0.1".to_string(), 0, "Q", true);
+        assert_eq!(client.device_range("Y6", 3)?[2], "Y8");
+        // hex, and still parseable
+        assert_eq!(client.device_range("X9", 2)?[1], "X0A");
+        Ok(())
+    }
 
-        for element in devices {
-            let size = element.data_type.size();
-            let value = self.decode_value(
-                &recv_data[data_index..data_index + size as usize],
-                &DataType::BIT,
-                false,
-            )?;
-
-            output.push(Tag {
-                device: element.device,
-                value: format!("{}", value).into(),
-                data_type: element.data_type,
-            });
+    #[test]
+    fn test_long_counter_access() -> Result<(), Box<dyn Error>> {
+        let (port, requests) = start_capturing_plc(e4_response(&[0x45, 0x23, 0x01, 0x00]));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "iQ-R", true);
+        client.connect()?;
 
-            data_index += size as usize;
-        }
+        // asked for as a word, read as a dword point
+        let tags = client.read(vec![QueryTag {
+            device: "LCN10".to_string(),
+            data_type: DataType::SWORD,
+        }])?;
+        assert_eq!(
+            &requests.recv()?[15..],
+            &[0x03, 0x04, 0x02, 0x00, 0x00, 0x01, 0x0A, 0x00, 0x00, 0x00, 0x56, 0x00]
+        );
+        assert_eq!(tags[0].value.as_deref(), Some("74565"));
+        assert_eq!(tags[0].data_type, DataType::SDWORD);
 
-        Ok(output)
+        assert!(client.batch_write("LTN0", vec![1], &DataType::BIT).is_err());
+        Ok(())
     }
 
-    pub fn write(&self, devices: Vec<Tag>) -> Result<(), Box<dyn Error>> {
-        let command = commands::RANDOM_WRITE;
-        let subcommand = if self.plc_type == consts::IQR_SERIES {
-            subcommands::TWO
-        } else {
-            subcommands::ZERO
-        };
-
-        // Get the words equivalent in size
-        let mut words_count = 0;
-        for element in &devices {
-            words_count += element.data_type.size() / 2;
-        }
+    #[test]
+    fn test_extended_device_numbers() -> Result<(), Box<dyn Error>> {
+        let client = Client::new("127.0.0.1".to_string(), 0, "iQ-R", true);
+        assert_eq!(
+            client.build_device_data("D65536")?,
+            vec![0x00, 0x00, 0x01, 0x00, 0xA8, 0x00]
+        );
+        assert_eq!(
+            client.build_device_data("D4194303")?,
+            vec![0xFF, 0xFF, 0x3F, 0x00, 0xA8, 0x00]
+        );
+        assert_eq!(
+            client.device_range("D65535", 2)?,
+            vec!["D65535".to_string(), "D65536".to_string()]
+        );
 
-        let mut request_data = Vec::new();
-        request_data.extend(self.build_command_data(command, subcommand)?);
-        request_data.extend(self.encode_value(words_count as i64, DataType::BIT, false)?);
-        request_data.extend(self.encode_value(0, DataType::BIT, false)?);
-
-        for mut element in devices {
-            if element.data_type == DataType::BIT {
-                match element.value {
-                    Some(s) => {
-                        let s_vec: Vec<i64> = s
-                            .split_whitespace()
-                            .filter_map(|part| part.parse::<i64>().ok())
-                            .collect();
-                        self.batch_write(&element.device, s_vec, &element.data_type)?;
-                    }
-                    None => continue,
-                }
-                continue;
-            }
-            let element_size = element.data_type.size() / 2;
-            if (element.data_type == DataType::UWORD || element.data_type == DataType::UDWORD)
-                && element.value.clone().unwrap().parse::<i64>().unwrap() < 0
-            {
-                element.value = format!("-{}", element.value.unwrap()).into();
-            }
-            if element_size > 1 {
-                let tag_name = &element.device;
-                let device_type = get_device_type(tag_name)?;
-                let mut device_index = get_device_index(tag_name)?;
-                let _value = element.value.unwrap().parse::<i64>().unwrap();
-                let temp_tag_value = self.encode_value(_value, element.data_type, false)?;
-                let mut data_index = 0;
-                for _ in 0..element_size {
-                    let temp_tag_name = format!("{}{}", device_type, device_index);
-                    request_data.extend(self.build_device_data(&temp_tag_name)?);
-                    request_data.extend(&temp_tag_value[data_index..data_index + self._wordsize]);
-                    data_index += self._wordsize;
-                    device_index += 1;
-                }
-            } else {
-                request_data.extend(self.build_device_data(&element.device)?);
-                let _value = element.value.unwrap().parse::<i64>().unwrap();
-                request_data.extend(&self.encode_value(_value, element.data_type, false)?);
-            }
-        }
+        let client = Client::new("127.0.0.1".to_string(), 0, "Q", true);
+        assert_eq!(
+            client.build_device_data("ZR0FFFFFF")?,
+            vec![0xFF, 0xFF, 0xFF, 0xB0]
+        );
+        assert!(client.build_device_data("ZR1000000").is_err());
 
-        let send_data = self.build_send_data(&request_data)?;
-        self.send(&send_data)?;
-        let recv_data = self.recv()?;
-        self.check_command_response(&recv_data)?;
+        let mut client = Client::new("127.0.0.1".to_string(), 0, "Q", true);
+        client.set_comm_type("ascii");
+        assert_eq!(client.build_device_data("D999999")?, b"D*999999".to_vec());
+        assert!(client.build_device_data("D1000000").is_err());
 
+        let mut client = Client::new("127.0.0.1".to_string(), 0, "iQ-R", true);
+        client.set_comm_type("ascii");
+        assert_eq!(
+            client.build_device_data("D1000000")?,
+            b"D***01000000".to_vec()
+        );
         Ok(())
     }
-}
 
-impl Drop for Client {
-    fn drop(&mut self) {
-        if let Err(e) = self.close() {
-            eprintln!("Error closing connection: {:?}", e);
+    #[test]
+    fn test_raw_bytes() -> Result<(), Box<dyn Error>> {
+        let simulator = crate::simulator::Simulator::start();
+        // "ABCDE" with the low byte first, as the PLC stores strings
+        for (offset, word) in [0x4241, 0x4443, 0x0045].into_iter().enumerate() {
+            simulator.set(&format!("D{}", 10 + offset), word);
         }
-    }
-}
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
 
-impl std::fmt::Debug for Client {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Type3E")
-            .field("plc_type", &self.plc_type)
-            .field("comm_type", &self.comm_type)
-            .field("network", &self.network)
-            .field("pc", &self.pc)
-            .field("dest_moduleio", &self.dest_moduleio)
-            .field("dest_modulesta", &self.dest_modulesta)
-            .field("timer", &self.timer)
-            .field("sock_timeout", &self.sock_timeout)
-            .field("_is_connected", &self._is_connected)
-            .field("_sockbufsize", &self._sockbufsize)
-            .field("_wordsize", &self._wordsize)
-            .field("_debug", &self._debug)
-            .field("endian", &self.endian)
-            .field("host", &self.host)
-            .field("port", &self.port)
-            .finish()
+        let tags = client.batch_read("D10", 1, DataType::RAW(5), true)?;
+        assert_eq!(tags[0].raw_bytes(), Some(b"ABCDE".to_vec()));
+        assert_eq!(tags[0].to_string(), "D10, Some(\"4142434445\"), RAW(5)");
+        let tags = client.read(vec![QueryTag {
+            device: "D10".to_string(),
+            data_type: DataType::RAW(5),
+        }])?;
+        assert_eq!(tags[0].raw_bytes(), Some(b"ABCDE".to_vec()));
+        assert!(client
+            .batch_write("D10", vec![1], &DataType::RAW(2))
+            .is_err());
+        Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests_client {
-    use super::*;
-    use std::io::{Read, Write};
-    use std::net::TcpListener;
-    use std::thread;
 
-    pub fn start_mock_server(port: u16) -> std::net::SocketAddr {
-        let addr = format!("127.0.0.1:{}", port).parse().unwrap();
-        let listener = TcpListener::bind(addr).expect("Failed to bind to address");
+    #[test]
+    fn test_connect_check() -> Result<(), Box<dyn Error>> {
+        let simulator = crate::simulator::Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.set_connect_check(Some(ConnectCheck::CpuModel));
+        client.connect()?;
+        assert_eq!(client.read_cpu_model()?, ("Q03UDVCPU".to_string(), 0x0366));
+        client.set_connect_check(Some(ConnectCheck::Loopback));
+        client.connect()?;
+        assert_eq!(simulator.transactions(), 3);
 
+        // a service that answers something else than MC protocol
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
         thread::spawn(move || {
-            for stream in listener.incoming() {
-                let mut stream = stream.expect("Failed to accept connection");
-                thread::spawn(move || {
-                    let mut buffer = [0; 1024];
-                    loop {
-                        match stream.read(&mut buffer) {
-                            Ok(0) => break, // Connection closed
-                            Ok(size) => {
-                                let received = &buffer[..size];
-                                stream
-                                    .write_all(received)
-                                    .expect("Failed to write to stream");
-                            }
-                            Err(_) => break,
-                        }
-                    }
-                });
+            for mut stream in listener.incoming().flatten() {
+                let mut buffer = [0u8; 64];
+                let _ = stream.read(&mut buffer);
+                let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n");
             }
         });
-
-        addr
-    }
-
-    // Mock DeviceInfo implementations for testing
-    struct MockDeviceInfo {
-        subheader: u16,
-        subheader_serial: u16,
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.set_connect_check(Some(ConnectCheck::CpuModel));
+        let e = client.connect().unwrap_err();
+        assert!(err::find::<err::NotMcProtocol>(&*e).is_some());
+        assert!(!client.is_connected());
+        Ok(())
     }
 
-    impl DeviceInfo for MockDeviceInfo {
-        fn set_subheader_series(&mut self, subheader_serial: u16) {
-            self.subheader_serial = subheader_serial;
-        }
-
-        fn get_response_data_index(&self, _: &str) -> usize {
-            10
-        }
-        fn get_response_status_index(&self, _: &str) -> usize {
-            11
-        }
+    #[test]
+    fn test_auto_detect() -> Result<(), Box<dyn Error>> {
+        let simulator = crate::simulator::Simulator::start();
+        let client = Client::auto_detect("127.0.0.1", simulator.port(), "Q")?;
+        assert!(client.is_connected());
+        assert_eq!(client.comm_type, consts::COMMTYPE_BINARY);
+        assert_eq!(client.monitoring_timer(), Duration::from_secs(1));
+        client.batch_read_words_into("D0", &mut [0])?;
 
-        fn get_subheader(&self) -> u16 {
-            self.subheader
-        }
-        fn get_subheader_serial(&self) -> u16 {
-            self.subheader_serial
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.write_all(b"SSH-2.0-OpenSSH_9.6\r\n");
+            }
+        });
+        let e = Client::auto_detect("127.0.0.1", port, "Q")
+            .err()
+            .unwrap()
+            .to_string();
+        for probe in ["3E binary", "3E ascii", "4E binary", "4E ascii"] {
+            assert!(e.contains(probe), "{}", e);
         }
+        Ok(())
     }
 
     #[test]
-    fn test_client_new() {
-        let client = Client::new("localhost".to_string(), 8080, "Q", true);
-        assert_eq!(client.host, "localhost");
-        assert_eq!(client.port, 8080);
-        assert_eq!(client.plc_type, "Q");
-        assert!(client.use_e4);
+    fn test_plc_series_check() -> Result<(), Box<dyn Error>> {
+        let simulator = crate::simulator::Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "iQ-R", true);
+        client.set_connect_check(Some(ConnectCheck::CpuModel));
+        client.connect()?;
+        client.set_connect_check(Some(ConnectCheck::PlcSeries));
+        let e = client.connect().unwrap_err();
+        let mismatch = err::find::<err::PlcTypeMismatch>(&*e).unwrap();
+        assert_eq!(mismatch.model, "Q03UDVCPU");
+        assert_eq!(mismatch.detected, consts::Q_SERIES);
+
+        client.set_connect_check(None);
+        client.connect()?;
+        assert_eq!(client.detect_plc_type()?, Some(PlcType::Q));
+        assert_eq!(client.plc_type, consts::Q_SERIES);
+        client.set_connect_check(Some(ConnectCheck::PlcSeries));
+        client.connect()?;
+        Ok(())
     }
 
     #[test]
-    fn test_set_debug() {
-        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
-        client.set_debug(true);
-        assert!(client._debug);
+    fn test_batch_read_typed() -> Result<(), Box<dyn Error>> {
+        let simulator = crate::simulator::Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        client.batch_write("D0", vec![-2, 300], &DataType::SWORD)?;
+        client.batch_write("D10", vec![1.5f32.to_bits() as i64], &DataType::UDWORD)?;
+        client.batch_write("D20", vec![-70000], &DataType::SDWORD)?;
+
+        assert_eq!(client.batch_read_typed::<i16>("D0", 2)?, vec![-2, 300]);
+        assert_eq!(client.batch_read_typed::<u16>("D0", 1)?, vec![0xFFFE]);
+        assert_eq!(client.batch_read_typed::<f32>("D10", 1)?, vec![1.5]);
+        assert_eq!(client.batch_read_typed::<i32>("D20", 1)?, vec![-70000]);
+
+        let client = Client::new("127.0.0.1".to_string(), 0, "iQ-R", true);
+        assert!(client.batch_read_typed::<i16>("LTN0", 1).is_err());
+        Ok(())
     }
 
     #[test]
-    fn test_set_subheader_serial() {
-        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
-        client.device_type = Box::new(MockDeviceInfo {
-            subheader_serial: 0,
-            subheader: 12,
+    fn test_stale_responses_dropped() -> Result<(), Box<dyn Error>> {
+        // every answer is preceded by a duplicate of the previous one
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 1024];
+            while let Ok(size) = stream.read(&mut buffer) {
+                if size == 0 {
+                    break;
+                }
+                let serial = LittleEndian::read_u16(&buffer[2..4]);
+                let mut stale = e4_response(&[0xAD, 0xDE]);
+                LittleEndian::write_u16(&mut stale[2..4], serial.wrapping_sub(1));
+                let mut response = e4_response(&serial.to_le_bytes());
+                LittleEndian::write_u16(&mut response[2..4], serial);
+                stale.extend(response);
+                stream.write_all(&stale).unwrap();
+            }
         });
-        let result = client.set_subheader_serial(1234);
-        assert!(result.is_ok());
-        assert_eq!(client.device_type.get_subheader_serial(), 1234);
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.set_serial_per_transaction(true);
+        client.connect()?;
+        for serial in 1..=3 {
+            let mut value = [0];
+            client.batch_read_words_into("D0", &mut value)?;
+            assert_eq!(value[0], serial);
+        }
+        assert_eq!(client.stats().stale_responses, 3);
+        Ok(())
     }
 
     #[test]
-    fn test_connect() {
-        // This test requires a server running that sends data
-        let server_addr = start_mock_server(9999);
-        let port = server_addr.port();
-        let mut client = Client::new("localhost".to_string(), port, "Q", true);
-        let result = client.connect();
-        assert!(result.is_ok());
-        let data_to_send = b"Hello, server!";
-        let send_result = client.send(data_to_send);
-        assert!(send_result.is_ok());
-        let received_data = client.recv().expect("Failed to receive data");
-        assert_eq!(received_data, data_to_send);
-        let close_result = client.close();
-        assert!(close_result.is_ok());
-    }
+    fn test_strict_mode() -> Result<(), Box<dyn Error>> {
+        let simulator = crate::simulator::Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        // without strict mode the request goes out and the PLC refuses it
+        assert!(client
+            .build_command_data(commands::BATCH_READ, subcommands::TWO)
+            .is_ok());
 
-    #[test]
-    fn test_check_plc_type() {
-        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
-        let result = client.check_plc_type();
-        assert!(result.is_ok());
+        client.set_strict(true);
+        let e = client
+            .build_command_data(commands::BATCH_READ, subcommands::TWO)
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Subcommand 0002h of command 0401h (BatchRead) is not supported by Q series CPUs"
+        );
+        let e = client
+            .batch_read("LZ0", 1, DataType::UDWORD, true)
+            .unwrap_err();
+        let refused = err::find::<err::NotSupported>(&*e).unwrap();
+        assert_eq!(refused.what, "Device LZ0");
+        client.batch_read("D100", 1, DataType::UWORD, true)?;
+        assert_eq!(simulator.transactions(), 1);
 
-        client.plc_type = "InvalidType";
-        let result = client.check_plc_type();
-        assert!(result.is_err());
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "QnA", true);
+        client.set_strict(true);
+        let e = client.remote_unlock("1234").unwrap_err();
+        assert!(e.to_string().starts_with("Command 1630h"), "{}", e);
+
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "iQ-F", true);
+        client.set_strict(true);
+        assert!(client.build_device_data("X17").is_ok());
+        let e = client.build_device_data("X18").unwrap_err();
+        assert!(err::find::<err::NotSupported>(&*e).is_some());
+        Ok(())
     }
 
     #[test]
-    fn test_set_comm_type() {
-        let mut client = Client::new("localhost".to_string(), 8080, "Q", true);
-        client.set_comm_type("binary");
-        assert_eq!(client.comm_type, consts::COMMTYPE_BINARY);
-        assert_eq!(client._wordsize, 2);
-
-        client.set_comm_type("ascii");
-        assert_eq!(client.comm_type, consts::COMMTYPE_ASCII);
-        assert_eq!(client._wordsize, 4);
+    fn test_ascii_random_read_dwords() -> Result<(), Box<dyn Error>> {
+        // dword points carry 8 digits, high first; an lword is two of them
+        let payload = "FFFFFFFE5566778811223344";
+        let (port, requests) = start_capturing_plc(e3_ascii_response(0, payload));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", false);
+        client.set_comm_type(consts::COMMTYPE_ASCII);
+        client.connect()?;
+        let tags = client.read(vec![
+            QueryTag {
+                device: "D200".to_string(),
+                data_type: DataType::SDWORD,
+            },
+            QueryTag {
+                device: "D300".to_string(),
+                data_type: DataType::ULWORD,
+            },
+        ])?;
+        assert_eq!(
+            std::str::from_utf8(&requests.recv()?[22..])?,
+            "040300000003D*000200D*000300D*000302"
+        );
+        assert_eq!(tags[0].value.as_deref(), Some("-2"));
+        assert_eq!(tags[1].value, Some(0x1122334455667788i64.to_string()));
+        Ok(())
     }
+
     #[test]
-    fn test_build_send_data_binary() -> Result<(), Box<dyn Error>> {
-        let client = Client::new("localhost".to_string(), 8080, "Q", true);
-        let request_data = b"test";
-        let expected_length = 14;
-        let result = client.build_send_data(request_data)?;
-        assert_eq!(result.len(), expected_length);
+    fn test_ascii_random_write_dwords() -> Result<(), Box<dyn Error>> {
+        let (port, requests) = start_capturing_plc(e3_ascii_response(0, ""));
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", false);
+        client.set_comm_type(consts::COMMTYPE_ASCII);
+        client.connect()?;
+        client.write(vec![
+            Tag::new("D200".to_string(), Some("-2".to_string()), DataType::SDWORD),
+            Tag::new(
+                "D300".to_string(),
+                Some(0x1122334455667788i64.to_string()),
+                DataType::ULWORD,
+            ),
+        ])?;
+        assert_eq!(
+            std::str::from_utf8(&requests.recv()?[22..])?,
+            "140200000003D*000200FFFFFFFED*00030055667788D*00030211223344"
+        );
         Ok(())
     }
 
     #[test]
-    fn test_encode_value_little_endian() -> Result<(), Box<dyn Error>> {
-        let client = Client::new("localhost".to_string(), 8080, "Q", true);
-        let value = 1234;
-        let encoded = client.encode_value(value as i64, DataType::SWORD, false)?;
-        let mut expected = Vec::new();
-        expected.write_u8(value as u8)?;
-        assert_eq!(encoded, expected);
+    fn test_custom_device_random_access() -> Result<(), Box<dyn Error>> {
+        let response = e4_response(&[0x01, 0x00, 0x78, 0x56, 0x34, 0x12]);
+        let (port, requests) = start_capturing_plc(response);
+        let mut client = Client::new("127.0.0.1".to_string(), port, "Q", true);
+        client.register_device(
+            "QV",
+            CustomDevice {
+                binary_code: 0xE0,
+                ascii_code: "QV".to_string(),
+                base: 16,
+            },
+        );
+        client.connect()?;
+        let tags = client.read(vec![
+            QueryTag {
+                device: "QV1F".to_string(),
+                data_type: DataType::UWORD,
+            },
+            QueryTag {
+                device: "QV0FE".to_string(),
+                data_type: DataType::UDWORD,
+            },
+        ])?;
+        assert_eq!(
+            &requests.recv()?[15..],
+            &[0x03, 0x04, 0x00, 0x00, 0x01, 0x01, 0x1F, 0x00, 0x00, 0xE0, 0xFE, 0x00, 0x00, 0xE0]
+        );
+        assert_eq!(tags[0].value.as_deref(), Some("1"));
+        assert_eq!(tags[1].value.as_deref(), Some("305419896"));
+        assert_eq!(client.device_range("QV0FF", 2)?, vec!["QV0FF", "QV100"]);
         Ok(())
     }
 
     #[test]
-    fn test_encode_value_big_endian() -> Result<(), Box<dyn Error>> {
-        let client = Client::new("localhost".to_string(), 8080, "Q", true);
-        let value = 1234;
-        let encoded = client.encode_value(value as i64, DataType::SWORD, false)?;
-        let mut expected = Vec::new();
-        expected.write_u8(value as u8)?;
-
-        assert_eq!(encoded, expected);
+    fn test_signed_tag_values() -> Result<(), Box<dyn Error>> {
+        let client = Client::new("127.0.0.1".to_string(), 0, "Q", true);
+        let cases = [
+            (&[0xFF, 0xFF][..], DataType::SWORD, "-1"),
+            (&[0xFF, 0xFF][..], DataType::UWORD, "65535"),
+            (&[0x00, 0x80][..], DataType::SWORD, "-32768"),
+            (&[0xFE, 0xFF, 0xFF, 0xFF][..], DataType::SDWORD, "-2"),
+            (
+                &[0xFE, 0xFF, 0xFF, 0xFF][..],
+                DataType::UDWORD,
+                "4294967294",
+            ),
+            (&[0xFF; 8][..], DataType::SLWORD, "-1"),
+        ];
+        for (data, data_type, expected) in cases {
+            assert_eq!(client.decode_tag_value(data, &data_type)?, expected);
+        }
         Ok(())
     }
 }
@@ -0,0 +1,332 @@
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::cancel::CancelToken;
+use super::client::Client;
+use super::db::{consts, DataType};
+
+// iQ-R, iQ-L and iQ-F: SD210..SD216 hold year, month, day, hour, minute,
+// second and day of week in binary. Q, L and QnA: SD210..SD213 hold BCD
+// pairs, high byte first: year (last two digits) and month, day and hour,
+// minute and second, year (first two digits) and day of week.
+const CLOCK_DEVICE: &str = "SD210";
+// turning it on makes the CPU take over the clock data at the end of the scan
+const CLOCK_SET_REQUEST: &str = "SM210";
+
+fn has_bcd_clock(plc_type: &str) -> bool {
+    [consts::Q_SERIES, consts::L_SERIES, consts::QNA_SERIES].contains(&plc_type)
+}
+
+fn to_bcd(value: u8) -> u16 {
+    (((value / 10) << 4) | (value % 10)) as u16
+}
+
+// `None` for a byte that is not two BCD digits
+fn from_bcd(byte: u16) -> Option<u8> {
+    let (high, low) = ((byte >> 4 & 0xF) as u8, (byte & 0xF) as u8);
+    (high < 10 && low < 10).then_some(high * 10 + low)
+}
+
+// Calendar time of the CPU clock, in the local time of the PLC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlcClock {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl PlcClock {
+    pub fn from_unix_seconds(seconds: i64) -> Self {
+        let days = seconds.div_euclid(86400);
+        let time = seconds.rem_euclid(86400);
+        // civil_from_days of H. Hinnant's date algorithms
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        PlcClock {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time % 3600 / 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+
+    pub fn to_unix_seconds(&self) -> i64 {
+        let (year, month, day) = (self.year as i64, self.month as i64, self.day as i64);
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
+    // 0 is Sunday, as in SD216
+    pub fn day_of_week(&self) -> u8 {
+        (self.to_unix_seconds().div_euclid(86400) + 4).rem_euclid(7) as u8
+    }
+
+    fn to_bcd_words(self) -> [u16; 4] {
+        let pair = |high: u8, low: u8| to_bcd(high) << 8 | to_bcd(low);
+        [
+            pair((self.year % 100) as u8, self.month),
+            pair(self.day, self.hour),
+            pair(self.minute, self.second),
+            pair((self.year / 100) as u8, self.day_of_week()),
+        ]
+    }
+
+    fn from_bcd_words(words: &[u16; 4]) -> Option<Self> {
+        let high = |word: u16| from_bcd(word >> 8);
+        let low = |word: u16| from_bcd(word & 0xFF);
+        Some(PlcClock {
+            year: high(words[3])? as u16 * 100 + high(words[0])? as u16,
+            month: low(words[0])?,
+            day: high(words[1])?,
+            hour: low(words[1])?,
+            minute: high(words[2])?,
+            second: low(words[2])?,
+        })
+    }
+
+    fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+}
+
+impl Client {
+    /// Reads the CPU clock from SD210, in the layout of the PLC series.
+    pub fn read_clock(&self) -> Result<PlcClock, Box<dyn Error>> {
+        let clock = if has_bcd_clock(self.plc_type) {
+            let mut values = [0; 4];
+            self.batch_read_words_into(CLOCK_DEVICE, &mut values)?;
+            PlcClock::from_bcd_words(&values)
+                .ok_or_else(|| format!("PLC clock holds no valid BCD time: {:04X?}", values))?
+        } else {
+            let mut values = [0; 6];
+            self.batch_read_words_into(CLOCK_DEVICE, &mut values)?;
+            PlcClock {
+                year: values[0],
+                month: values[1] as u8,
+                day: values[2] as u8,
+                hour: values[3] as u8,
+                minute: values[4] as u8,
+                second: values[5] as u8,
+            }
+        };
+        if !clock.is_valid() {
+            return Err(format!("PLC clock holds no valid time: {:?}", clock).into());
+        }
+        Ok(clock)
+    }
+
+    /// Sets the CPU clock through SD210 and the clock set request SM210,
+    /// which is turned off first so the CPU sees it turn on.
+    pub fn write_clock(&self, clock: &PlcClock) -> Result<(), Box<dyn Error>> {
+        if !clock.is_valid() {
+            return Err(format!("Invalid clock value: {:?}", clock).into());
+        }
+        let values = if has_bcd_clock(self.plc_type) {
+            clock
+                .to_bcd_words()
+                .iter()
+                .map(|&word| word as i64)
+                .collect()
+        } else {
+            vec![
+                clock.year as i64,
+                clock.month as i64,
+                clock.day as i64,
+                clock.hour as i64,
+                clock.minute as i64,
+                clock.second as i64,
+                clock.day_of_week() as i64,
+            ]
+        };
+        self.batch_write(CLOCK_SET_REQUEST, vec![0], &DataType::BIT)?;
+        self.batch_write(CLOCK_DEVICE, values, &DataType::UWORD)?;
+        self.batch_write(CLOCK_SET_REQUEST, vec![1], &DataType::BIT)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockCheck {
+    pub plc: PlcClock,
+    // PLC clock minus host clock, whole seconds
+    pub drift: i64,
+    // the PLC clock was set to the host time
+    pub corrected: bool,
+}
+
+/// Compares the PLC clock with the host clock and optionally sets the PLC
+/// clock when they drift apart. The result of every check is kept in
+/// `ClientStats::clock_drift`.
+#[derive(Debug, Clone)]
+pub struct ClockMonitor {
+    interval: Duration,
+    // seconds the local time of the PLC is ahead of UTC
+    utc_offset: i64,
+    correct_beyond: Option<Duration>,
+}
+
+impl ClockMonitor {
+    pub fn new(interval: Duration) -> Self {
+        ClockMonitor {
+            interval,
+            utc_offset: 0,
+            correct_beyond: None,
+        }
+    }
+
+    /// The PLC clock runs in local time, the host clock is compared in UTC
+    /// shifted by `seconds`, e.g. 9 * 3600 for a PLC on JST.
+    pub fn utc_offset(mut self, seconds: i64) -> Self {
+        self.utc_offset = seconds;
+        self
+    }
+
+    /// Sets the PLC clock to the host time whenever the drift is larger
+    /// than `threshold`. Read-only clients only report the drift.
+    pub fn correct_beyond(mut self, threshold: Duration) -> Self {
+        self.correct_beyond = Some(threshold);
+        self
+    }
+
+    fn host_clock(&self) -> Result<PlcClock, Box<dyn Error>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        Ok(PlcClock::from_unix_seconds(now + self.utc_offset))
+    }
+
+    pub fn check(&self, client: &Client) -> Result<ClockCheck, Box<dyn Error>> {
+        let plc = client.read_clock()?;
+        let host = self.host_clock()?;
+        let drift = plc.to_unix_seconds() - host.to_unix_seconds();
+        client.record_clock_drift(drift);
+
+        let corrected = match self.correct_beyond {
+            Some(threshold)
+                if drift.unsigned_abs() > threshold.as_secs() && !client.is_read_only() =>
+            {
+                client.write_clock(&self.host_clock()?)?;
+                true
+            }
+            _ => false,
+        };
+        Ok(ClockCheck {
+            plc,
+            drift,
+            corrected,
+        })
+    }
+
+    /// Checks every interval until `token` is cancelled, passing each
+    /// result to `on_check`.
+    pub fn run<F>(&self, client: &Client, token: &CancelToken, mut on_check: F)
+    where
+        F: FnMut(Result<ClockCheck, Box<dyn Error>>),
+    {
+        while !token.is_cancelled() {
+            let started = Instant::now();
+            on_check(self.check(client));
+            if let Some(remaining) = self.interval.checked_sub(started.elapsed()) {
+                thread::sleep(remaining);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_clock {
+    use super::*;
+    use crate::simulator::Simulator;
+
+    #[test]
+    fn test_calendar() {
+        let clock = PlcClock::from_unix_seconds(1_709_210_096);
+        assert_eq!(
+            clock,
+            PlcClock {
+                year: 2024,
+                month: 2,
+                day: 29,
+                hour: 12,
+                minute: 34,
+                second: 56,
+            }
+        );
+        assert_eq!(clock.to_unix_seconds(), 1_709_210_096);
+        // a Thursday
+        assert_eq!(clock.day_of_week(), 4);
+        assert_eq!(PlcClock::from_unix_seconds(0).day_of_week(), 4);
+    }
+
+    #[test]
+    fn test_bcd_clock() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        // 2024-02-29 12:34:56, a Thursday
+        for (offset, value) in [0x2402, 0x2912, 0x3456, 0x2004].into_iter().enumerate() {
+            simulator.set(&format!("SD{}", 210 + offset), value);
+        }
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        let clock = client.read_clock()?;
+        assert_eq!(clock, PlcClock::from_unix_seconds(1_709_210_096));
+
+        client.write_clock(&PlcClock::from_unix_seconds(946_684_800))?;
+        let words: Vec<u16> = (210..214)
+            .map(|number| simulator.get(&format!("SD{}", number)))
+            .collect();
+        // 2000-01-01 00:00:00, a Saturday
+        assert_eq!(words, vec![0x0001, 0x0100, 0x0000, 0x2006]);
+        assert_eq!(simulator.get("SD214"), 0);
+
+        simulator.set("SD212", 0x3A00);
+        assert!(client.read_clock().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_drift_correction() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        for (offset, value) in [2020, 1, 1, 0, 0, 0].into_iter().enumerate() {
+            simulator.set(&format!("SD{}", 210 + offset), value);
+        }
+        // binary layout of the iQ series
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "iQ-F", true);
+        client.connect()?;
+
+        let monitor =
+            ClockMonitor::new(Duration::from_secs(60)).correct_beyond(Duration::from_secs(5));
+        client.set_read_only(true);
+        let check = monitor.check(&client)?;
+        assert!(check.drift < -86400 * 365);
+        assert!(!check.corrected);
+        assert_eq!(client.stats().clock_drift, Some(check.drift));
+
+        client.set_read_only(false);
+        let check = monitor.check(&client)?;
+        assert!(check.corrected);
+        assert!(simulator.get("SD210") >= 2024);
+        assert_eq!(simulator.get("SM210"), 1);
+        assert!(monitor.check(&client)?.drift.abs() <= 1);
+        Ok(())
+    }
+}
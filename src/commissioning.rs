@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+use super::client::Client;
+use super::db::DataType;
+use super::tag_map::TagMap;
+
+impl Client {
+    /// Sets `count` consecutive bits starting at `start_device` to `value`
+    /// in a single batch write.
+    ///
+    /// This is a plain device write that the PLC program can overwrite on
+    /// its next scan, not the forced input/output registration of GX Works.
+    /// The MC protocol has no command to register, cancel or list forced
+    /// devices, so those stay with the engineering tool.
+    pub fn force_bits(
+        &self,
+        start_device: &str,
+        count: usize,
+        value: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        self.batch_write(start_device, vec![value as i64; count], &DataType::BIT)
+    }
+
+    /// Writes 1 to `device`, waits `duration`, then writes 0. The reset is
+    /// retried once so a transient error does not leave the bit on; if the
+    /// retry fails too the error says so. The reset is recorded in the
+    /// operation log as "pulse_bit".
+    pub fn pulse_bit(&self, device: &str, duration: Duration) -> Result<(), Box<dyn Error>> {
+        self.batch_write(device, vec![1], &DataType::BIT)?;
+        thread::sleep(duration);
+        // the reset belongs to the pulse and is not rate limited again
+        let result = self
+            .batch_write_frame(device, vec![0], &DataType::BIT)
+            .or_else(|_| self.batch_write_frame(device, vec![0], &DataType::BIT))
+            .map_err(|e| {
+                format!(
+                    "Failed to reset {} after pulse, bit may still be on: {}",
+                    device, e
+                )
+                .into()
+            });
+        self.log_operation("pulse_bit", device, &result);
+        result
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckoutResult {
+    // state read, no toggle requested
+    Read,
+    // output toggled and the operator confirmed the field device reacted
+    Confirmed,
+    // output toggled and the operator reported no reaction
+    Rejected,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckoutPoint {
+    pub device: String,
+    pub label: Option<String>,
+    pub is_output: bool,
+    pub state: bool,
+    pub result: CheckoutResult,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheckoutReport {
+    pub points: Vec<CheckoutPoint>,
+}
+
+impl CheckoutReport {
+    pub fn count(&self, result: &CheckoutResult) -> usize {
+        self.points.iter().filter(|p| p.result == *result).count()
+    }
+
+    pub fn failures(&self) -> Vec<&CheckoutPoint> {
+        self.points
+            .iter()
+            .filter(|p| {
+                matches!(
+                    p.result,
+                    CheckoutResult::Failed(_) | CheckoutResult::Rejected
+                )
+            })
+            .collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("device,label,direction,state,result\n");
+        for point in &self.points {
+            let result = match point.result {
+                CheckoutResult::Read => "read".to_string(),
+                CheckoutResult::Confirmed => "confirmed".to_string(),
+                CheckoutResult::Rejected => "rejected".to_string(),
+                CheckoutResult::Failed(ref e) => format!("failed: {}", e.replace(',', ";")),
+            };
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                point.device,
+                point.label.as_deref().unwrap_or(""),
+                if point.is_output { "output" } else { "input" },
+                point.state as u8,
+                result
+            ));
+        }
+        csv
+    }
+}
+
+// Walks X/Y ranges for commissioning, optionally pulsing every output
+#[derive(Debug, Default)]
+pub struct IoCheckout {
+    inputs: Vec<(String, usize)>,
+    outputs: Vec<(String, usize)>,
+    labels: HashMap<String, String>,
+    pulse: Option<Duration>,
+}
+
+impl IoCheckout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inputs(mut self, start_device: &str, count: usize) -> Self {
+        self.inputs.push((start_device.to_string(), count));
+        self
+    }
+
+    pub fn outputs(mut self, start_device: &str, count: usize) -> Self {
+        self.outputs.push((start_device.to_string(), count));
+        self
+    }
+
+    pub fn with_labels(mut self, tag_map: &TagMap) -> Self {
+        for entry in tag_map.iter() {
+            self.labels.insert(entry.device.clone(), entry.name.clone());
+        }
+        self
+    }
+
+    /// Pulses each output for `duration` and asks the confirmation callback
+    /// whether the field device reacted.
+    pub fn toggle_outputs(mut self, duration: Duration) -> Self {
+        self.pulse = Some(duration);
+        self
+    }
+
+    pub fn run<F>(&self, client: &Client, mut confirm: F) -> Result<CheckoutReport, Box<dyn Error>>
+    where
+        F: FnMut(&CheckoutPoint) -> bool,
+    {
+        let mut report = CheckoutReport::default();
+        for (is_output, ranges) in [(false, &self.inputs), (true, &self.outputs)] {
+            for (start_device, count) in ranges {
+                let devices = client.device_range(start_device, *count)?;
+                let mut states = vec![false; *count];
+                client.batch_read_bits_into(start_device, &mut states)?;
+                for (device, state) in devices.into_iter().zip(states) {
+                    let mut point = CheckoutPoint {
+                        label: self.labels.get(&device).cloned(),
+                        device,
+                        is_output,
+                        state,
+                        result: CheckoutResult::Read,
+                    };
+                    if let (true, Some(duration)) = (is_output, self.pulse) {
+                        point.result = match client.pulse_bit(&point.device, duration) {
+                            Ok(()) if confirm(&point) => CheckoutResult::Confirmed,
+                            Ok(()) => CheckoutResult::Rejected,
+                            Err(e) => CheckoutResult::Failed(e.to_string()),
+                        };
+                    }
+                    report.points.push(point);
+                }
+            }
+        }
+        Ok(report)
+    }
+}
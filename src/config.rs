@@ -0,0 +1,873 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "notify")]
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::warn;
+use toml::{Table, Value};
+
+use super::cancel::CancelToken;
+use super::client::Client;
+use super::db::{consts, DataType};
+use super::env::EnvOverrides;
+use super::err::ConfigError;
+use super::poller::{Poller, PollerControl};
+use super::sink::CsvSink;
+#[cfg(feature = "sqlite")]
+use super::sink::SqliteSink;
+use super::tag::ValueFormat;
+use super::tag_map::{parse_type_name, TagMap};
+
+// Connection settings of the `[plc]` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlcConfig {
+    pub host: String,
+    pub port: u16,
+    pub plc_type: &'static str,
+    pub comm_type: &'static str,
+    pub use_e4: bool,
+    pub network: u8,
+    pub pc: u8,
+    pub dest_moduleio: u16,
+    pub dest_modulesta: u8,
+    pub monitoring_timer: Duration,
+    pub read_only: bool,
+    // further endpoints of the same PLC, tried in order
+    pub failover: Vec<(String, u16)>,
+}
+
+// One `[[groups.tags]]` entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagConfig {
+    pub name: String,
+    pub device: String,
+    pub data_type: DataType,
+    pub unit: String,
+    pub description: String,
+    pub precision: Option<u8>,
+    pub format: Option<ValueFormat>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkConfig {
+    Csv {
+        path: PathBuf,
+    },
+    #[cfg(feature = "sqlite")]
+    Sqlite {
+        path: PathBuf,
+    },
+}
+
+// One `[[groups]]` entry: tags polled together on one interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupConfig {
+    pub name: String,
+    pub interval: Duration,
+    pub tags: Vec<TagConfig>,
+    pub sinks: Vec<SinkConfig>,
+}
+
+/// Settings of a whole collector, read from a TOML file:
+///
+/// ```toml
+/// [plc]
+/// host = "192.168.1.10"
+/// port = 5007
+/// plc_type = "iQ-R"          # Q, L, QnA, iQ-L, iQ-R or iQ-F
+/// comm_type = "binary"       # or "ascii"
+/// frame = "4E"               # or "3E" (default)
+/// monitoring_timer_ms = 1000
+/// read_only = true           # refuse writes and remote operations
+/// failover = ["192.168.1.11:5007"]  # IPv6 in brackets: "[fd00::11]:5007"
+///
+/// [plc.route]                # defaults address the connected station
+/// network = 0
+/// pc = 0xFF
+/// module_io = 0x3FF
+/// module_station = 0
+///
+/// [[groups]]
+/// name = "fast"
+/// interval_ms = 100
+/// sinks = [{ type = "csv", path = "fast.csv" }]
+///
+/// [[groups.tags]]
+/// name = "line_speed"
+/// device = "D100"
+/// type = "REAL"              # GX Works type name, INT by default
+/// unit = "m/min"
+/// precision = 1
+/// ```
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub plc: PlcConfig,
+    pub groups: Vec<GroupConfig>,
+}
+
+/// A connected client and one poller per scan group, built from a `Config`.
+pub struct Collector {
+    pub client: Client,
+    // group name and its poller, in file order
+    pub groups: Vec<(String, Poller)>,
+}
+
+impl Config {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, Box<dyn Error>> {
+        let root: Table = content.parse()?;
+        let root = Section {
+            table: &root,
+            path: String::new(),
+        };
+        let plc = parse_plc(&root.section("plc")?.ok_or_else(|| root.missing("plc"))?)?;
+        let groups = root
+            .sections("groups")?
+            .iter()
+            .map(parse_group)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Config { plc, groups })
+    }
+
+    /// Layers the `MELSEC_*` variables of `env` over the `[plc]` section.
+    pub fn with_env_overrides(mut self) -> Result<Self, Box<dyn Error>> {
+        self.plc.apply_env(&EnvOverrides::from_env()?);
+        Ok(self)
+    }
+
+    /// Connects the client and sets up the pollers and their sinks.
+    pub fn build(&self) -> Result<Collector, Box<dyn Error>> {
+        let plc = &self.plc;
+        let mut client = Client::new(plc.host.clone(), plc.port, plc.plc_type, plc.use_e4);
+        client.set_comm_type(plc.comm_type);
+        client.network = plc.network;
+        client.pc = plc.pc;
+        client.dest_moduleio = plc.dest_moduleio;
+        client.dest_modulesta = plc.dest_modulesta;
+        client.set_monitoring_timer(plc.monitoring_timer)?;
+        client.set_read_only(plc.read_only);
+        if !plc.failover.is_empty() {
+            let mut endpoints = vec![(plc.host.clone(), plc.port)];
+            endpoints.extend(plc.failover.iter().cloned());
+            client.set_failover_endpoints(endpoints);
+        }
+        client.connect()?;
+
+        let mut groups = Vec::new();
+        for group in self.groups.iter() {
+            let mut poller = Poller::from_tag_map(group.tag_map(), group.interval);
+            for sink in group.sinks.iter() {
+                match sink {
+                    SinkConfig::Csv { path } => poller.add_sink(Box::new(CsvSink::create(path)?)),
+                    #[cfg(feature = "sqlite")]
+                    SinkConfig::Sqlite { path } => {
+                        poller.add_sink(Box::new(SqliteSink::open(path)?))
+                    }
+                }
+            }
+            groups.push((group.name.clone(), poller));
+        }
+        Ok(Collector { client, groups })
+    }
+}
+
+impl GroupConfig {
+    pub fn tag_map(&self) -> TagMap {
+        let mut tag_map = TagMap::new();
+        for tag in self.tags.iter() {
+            tag_map.insert(&tag.name, &tag.device, tag.data_type.clone());
+            tag_map.describe(&tag.name, &tag.unit, &tag.description, tag.precision);
+            if let Some(format) = tag.format {
+                tag_map.set_format(&tag.name, format);
+            }
+        }
+        tag_map
+    }
+}
+
+impl PlcConfig {
+    pub fn apply_env(&mut self, overrides: &EnvOverrides) {
+        if let Some(ref host) = overrides.host {
+            self.host = host.clone();
+        }
+        self.port = overrides.port.unwrap_or(self.port);
+        self.monitoring_timer = overrides.monitoring_timer.unwrap_or(self.monitoring_timer);
+        self.read_only = overrides.read_only.unwrap_or(self.read_only);
+    }
+}
+
+impl Collector {
+    pub fn controls(&self) -> Vec<(String, PollerControl)> {
+        self.groups
+            .iter()
+            .map(|(name, poller)| (name.clone(), poller.control()))
+            .collect()
+    }
+
+    /// Polls every group when it is due until `token` is cancelled, then
+    /// flushes all sinks. Groups share the one connection. A failed poll
+    /// is logged and sent to the subscribers of its group as
+    /// `PollerEvent::PollFailed`; it does not stop the other groups or the
+    /// next poll of its own group. Returns the first error of flushing.
+    pub fn run(&mut self, token: &CancelToken) -> Result<(), Box<dyn Error>> {
+        let mut due = vec![Instant::now(); self.groups.len()];
+        while !token.is_cancelled() {
+            let now = Instant::now();
+            for ((name, poller), due) in self.groups.iter_mut().zip(due.iter_mut()) {
+                if *due > now {
+                    continue;
+                }
+                let (paused, interval) = poller.state();
+                if !paused {
+                    if let Err(e) = poller.poll_once(&self.client) {
+                        warn!("Poll of group {} failed: {}", name, e);
+                        poller.record_failure(&*e);
+                    }
+                }
+                *due = (*due + interval).max(now);
+            }
+            match due.iter().min() {
+                Some(next) => thread::sleep(next.saturating_duration_since(Instant::now())),
+                None => break,
+            }
+        }
+        let mut result = Ok(());
+        for (name, poller) in self.groups.iter_mut() {
+            if let Err(e) = poller.flush() {
+                warn!("Flushing group {} failed: {}", name, e);
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReloadEvent {
+    // tag lists and intervals of all groups now follow the new file
+    Applied,
+    // nothing was changed, the running configuration stays
+    Rejected { error: String },
+}
+
+/// Applies edited configuration files to the groups of a running
+/// `Collector`. Tags and scan intervals change in place; edits to the
+/// `[plc]` section, the group list or sinks need a restart and reject the
+/// whole file, as do parse errors.
+pub struct Reloader {
+    config: Config,
+    controls: Vec<(String, PollerControl)>,
+    overrides: EnvOverrides,
+}
+
+impl Reloader {
+    /// `config` is the configuration `collector` was built from.
+    pub fn new(config: Config, collector: &Collector) -> Self {
+        Reloader {
+            config,
+            controls: collector.controls(),
+            overrides: EnvOverrides::default(),
+        }
+    }
+
+    /// Applies `overrides` to every reloaded file, as they were applied to
+    /// the running configuration.
+    pub fn with_env_overrides(mut self, overrides: EnvOverrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn reload(&mut self, content: &str) -> ReloadEvent {
+        let result = Config::parse(content).and_then(|mut config| {
+            config.plc.apply_env(&self.overrides);
+            self.apply(config).map_err(|e| e.into())
+        });
+        match result {
+            Ok(()) => ReloadEvent::Applied,
+            Err(e) => ReloadEvent::Rejected {
+                error: e.to_string(),
+            },
+        }
+    }
+
+    /// Checks the whole of `config` before changing any group.
+    pub fn apply(&mut self, config: Config) -> Result<(), ConfigError> {
+        let restart = |key: &str| ConfigError {
+            key: key.to_string(),
+            message: "changes need a restart".to_string(),
+        };
+        if config.plc != self.config.plc {
+            return Err(restart("plc"));
+        }
+        let names = |config: &Config| -> Vec<String> {
+            config.groups.iter().map(|g| g.name.clone()).collect()
+        };
+        if names(&config) != names(&self.config) {
+            return Err(restart("groups"));
+        }
+        for (index, (old, new)) in self
+            .config
+            .groups
+            .iter()
+            .zip(config.groups.iter())
+            .enumerate()
+        {
+            if old.sinks != new.sinks {
+                return Err(restart(&format!("groups[{}].sinks", index)));
+            }
+        }
+
+        for (group, (_, control)) in config.groups.iter().zip(self.controls.iter()) {
+            control.replace_tags(group.tag_map());
+            control.set_interval(group.interval);
+        }
+        self.config = config;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "notify")]
+const RELOAD_SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Reloads a configuration file whenever it is saved. Dropping the watcher
+/// stops watching.
+#[cfg(feature = "notify")]
+pub struct ConfigWatcher {
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<ReloadEvent>,
+}
+
+#[cfg(feature = "notify")]
+impl ConfigWatcher {
+    pub fn start<P: AsRef<Path>>(path: P, mut reloader: Reloader) -> Result<Self, Box<dyn Error>> {
+        use notify::{EventKind, RecursiveMode, Watcher};
+
+        let path = path.as_ref().to_path_buf();
+        let file_name = path
+            .file_name()
+            .ok_or("Config path has no file name")?
+            .to_owned();
+        // editors often save by replacing the file, so watch its directory
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let (sender, events) = mpsc::channel();
+        let (changed, changes) = mpsc::channel::<Result<(), String>>();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let _ = match event {
+                    Err(e) => changed.send(Err(e.to_string())),
+                    Ok(event)
+                        if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                            && event
+                                .paths
+                                .iter()
+                                .any(|p| p.file_name() == Some(file_name.as_os_str())) =>
+                    {
+                        changed.send(Ok(()))
+                    }
+                    Ok(_) => Ok(()),
+                };
+            })?;
+
+        // Reloads once a save has settled: writing a file raises several
+        // events and reading too early sees it truncated. Ends when the
+        // watcher is dropped.
+        let mut last_content = fs::read_to_string(&path).ok();
+        thread::spawn(move || {
+            while let Ok(change) = changes.recv() {
+                if let Err(error) = change {
+                    let _ = sender.send(ReloadEvent::Rejected { error });
+                    continue;
+                }
+                while let Ok(Ok(())) = changes.recv_timeout(RELOAD_SETTLE_TIME) {}
+                let event = match fs::read_to_string(&path) {
+                    Ok(content) if last_content.as_ref() == Some(&content) => continue,
+                    Ok(content) => {
+                        let event = reloader.reload(&content);
+                        last_content = Some(content);
+                        event
+                    }
+                    Err(e) => ReloadEvent::Rejected {
+                        error: format!("Failed to read {}: {}", path.display(), e),
+                    },
+                };
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        Ok(ConfigWatcher {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    pub fn events(&self) -> &mpsc::Receiver<ReloadEvent> {
+        &self.events
+    }
+}
+
+// A table of the file and its dotted path, for error messages
+struct Section<'a> {
+    table: &'a Table,
+    path: String,
+}
+
+impl<'a> Section<'a> {
+    fn key(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    fn missing(&self, key: &str) -> ConfigError {
+        ConfigError {
+            key: self.key(key),
+            message: "missing".to_string(),
+        }
+    }
+
+    fn invalid(&self, key: &str, message: &str) -> ConfigError {
+        ConfigError {
+            key: self.key(key),
+            message: message.to_string(),
+        }
+    }
+
+    fn str(&self, key: &str) -> Result<Option<&'a str>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(self.invalid(key, "expected a string")),
+        }
+    }
+
+    fn required_str(&self, key: &str) -> Result<&'a str, ConfigError> {
+        self.str(key)?.ok_or_else(|| self.missing(key))
+    }
+
+    fn bool(&self, key: &str) -> Result<Option<bool>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(None),
+            Some(Value::Boolean(value)) => Ok(Some(*value)),
+            Some(_) => Err(self.invalid(key, "expected true or false")),
+        }
+    }
+
+    // integer that fits in `T`
+    fn int<T: TryFrom<i64>>(&self, key: &str) -> Result<Option<T>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(None),
+            Some(Value::Integer(value)) => T::try_from(*value)
+                .map(Some)
+                .map_err(|_| self.invalid(key, &format!("{} is out of range", value))),
+            Some(_) => Err(self.invalid(key, "expected an integer")),
+        }
+    }
+
+    fn millis(&self, key: &str) -> Result<Option<Duration>, ConfigError> {
+        Ok(self.int::<u64>(key)?.map(Duration::from_millis))
+    }
+
+    fn section(&self, key: &str) -> Result<Option<Section<'a>>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(None),
+            Some(Value::Table(table)) => Ok(Some(Section {
+                table,
+                path: self.key(key),
+            })),
+            Some(_) => Err(self.invalid(key, "expected a table")),
+        }
+    }
+
+    // array of tables; a missing key is an empty array
+    fn sections(&self, key: &str) -> Result<Vec<Section<'a>>, ConfigError> {
+        let values = match self.table.get(key) {
+            None => return Ok(Vec::new()),
+            Some(Value::Array(values)) => values,
+            Some(_) => return Err(self.invalid(key, "expected an array of tables")),
+        };
+        values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| match value {
+                Value::Table(table) => Ok(Section {
+                    table,
+                    path: format!("{}[{}]", self.key(key), index),
+                }),
+                _ => Err(self.invalid(key, "expected an array of tables")),
+            })
+            .collect()
+    }
+}
+
+// "host:port", with an IPv6 address in brackets: "[fe80::1]:5007"
+fn parse_endpoint(endpoint: &str) -> Option<(String, u16)> {
+    let (host, port) = endpoint.rsplit_once(':')?;
+    let host = match host.strip_prefix('[') {
+        Some(host) => host.strip_suffix(']')?,
+        None if host.contains(':') => return None,
+        None => host,
+    };
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+fn parse_plc(plc: &Section) -> Result<PlcConfig, ConfigError> {
+    let plc_type = match plc.required_str("plc_type")? {
+        "Q" => consts::Q_SERIES,
+        "L" => consts::L_SERIES,
+        "QnA" => consts::QNA_SERIES,
+        "iQ-L" => consts::IQL_SERIES,
+        "iQ-R" => consts::IQR_SERIES,
+        "iQ-F" => consts::IQF_SERIES,
+        _ => return Err(plc.invalid("plc_type", "expected Q, L, QnA, iQ-L, iQ-R or iQ-F")),
+    };
+    let comm_type = match plc.str("comm_type")? {
+        None | Some("binary") => consts::COMMTYPE_BINARY,
+        Some("ascii") => consts::COMMTYPE_ASCII,
+        Some(_) => return Err(plc.invalid("comm_type", "expected binary or ascii")),
+    };
+    let use_e4 = match plc.str("frame")? {
+        None | Some("3E") => false,
+        Some("4E") => true,
+        Some(_) => return Err(plc.invalid("frame", "expected 3E or 4E")),
+    };
+    let failover = match plc.table.get("failover") {
+        None => Vec::new(),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| value.as_str().and_then(parse_endpoint))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| plc.invalid("failover", "expected \"host:port\" strings"))?,
+        Some(_) => return Err(plc.invalid("failover", "expected an array")),
+    };
+
+    let mut config = PlcConfig {
+        host: plc.required_str("host")?.to_string(),
+        port: plc.int("port")?.ok_or_else(|| plc.missing("port"))?,
+        plc_type,
+        comm_type,
+        use_e4,
+        network: 0,
+        pc: 0xFF,
+        dest_moduleio: 0x3FF,
+        dest_modulesta: 0,
+        monitoring_timer: plc
+            .millis("monitoring_timer_ms")?
+            .unwrap_or(Duration::from_secs(1)),
+        read_only: plc.bool("read_only")?.unwrap_or(false),
+        failover,
+    };
+    if let Some(route) = plc.section("route")? {
+        config.network = route.int("network")?.unwrap_or(config.network);
+        config.pc = route.int("pc")?.unwrap_or(config.pc);
+        config.dest_moduleio = route.int("module_io")?.unwrap_or(config.dest_moduleio);
+        config.dest_modulesta = route
+            .int("module_station")?
+            .unwrap_or(config.dest_modulesta);
+    }
+    Ok(config)
+}
+
+fn parse_group(group: &Section) -> Result<GroupConfig, ConfigError> {
+    let interval = group
+        .millis("interval_ms")?
+        .ok_or_else(|| group.missing("interval_ms"))?;
+    if interval.is_zero() {
+        return Err(group.invalid("interval_ms", "must be greater than 0"));
+    }
+
+    let mut tags = Vec::new();
+    for tag in group.sections("tags")?.iter() {
+        let data_type = match tag.str("type")? {
+            None => DataType::SWORD,
+            Some(name) => parse_type_name(name)
+                .ok_or_else(|| tag.invalid("type", &format!("unknown type {}", name)))?,
+        };
+        let format = match tag.str("format")? {
+            None => None,
+            Some(format) => Some(
+                ValueFormat::parse(format)
+                    .ok_or_else(|| tag.invalid("format", "expected dec, hex, bin or float:N"))?,
+            ),
+        };
+        let device = tag.required_str("device")?.to_uppercase();
+        tags.push(TagConfig {
+            name: tag.str("name")?.unwrap_or(&device).to_string(),
+            device,
+            data_type,
+            unit: tag.str("unit")?.unwrap_or_default().to_string(),
+            description: tag.str("description")?.unwrap_or_default().to_string(),
+            precision: tag.int("precision")?,
+            format,
+        });
+    }
+
+    let mut sinks = Vec::new();
+    for sink in group.sections("sinks")?.iter() {
+        let path = PathBuf::from(sink.required_str("path")?);
+        sinks.push(match sink.required_str("type")? {
+            "csv" => SinkConfig::Csv { path },
+            #[cfg(feature = "sqlite")]
+            "sqlite" => SinkConfig::Sqlite { path },
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => return Err(sink.invalid("type", "sqlite needs the sqlite feature")),
+            _ => return Err(sink.invalid("type", "expected csv or sqlite")),
+        });
+    }
+
+    Ok(GroupConfig {
+        name: group.required_str("name")?.to_string(),
+        interval,
+        tags,
+        sinks,
+    })
+}
+
+#[cfg(test)]
+mod tests_config {
+    use super::*;
+    use crate::err;
+    use crate::poller::PollerEvent;
+    use crate::simulator::Simulator;
+
+    const CONFIG: &str = r#"
+[plc]
+host = "127.0.0.1"
+port = 5007
+plc_type = "Q"
+frame = "4E"
+failover = ["127.0.0.2:5008"]
+
+[plc.route]
+module_io = 0x3E0
+
+[[groups]]
+name = "fast"
+interval_ms = 10
+
+[[groups.tags]]
+name = "line_speed"
+device = "d100"
+type = "REAL"
+unit = "m/min"
+precision = 1
+
+[[groups.tags]]
+device = "M0"
+type = "BIT"
+
+[[groups]]
+name = "slow"
+interval_ms = 1000
+sinks = [{ type = "csv", path = "slow.csv" }]
+"#;
+
+    #[test]
+    fn test_parse() -> Result<(), Box<dyn Error>> {
+        let config = Config::parse(CONFIG)?;
+        assert_eq!(config.plc.plc_type, "Q");
+        assert!(config.plc.use_e4);
+        assert_eq!(config.plc.comm_type, "binary");
+        assert_eq!(config.plc.dest_moduleio, 0x3E0);
+        assert_eq!(config.plc.pc, 0xFF);
+        assert_eq!(config.plc.failover, vec![("127.0.0.2".to_string(), 5008)]);
+        let content = CONFIG.replace("127.0.0.2:5008", "[::1]:5008");
+        assert_eq!(
+            Config::parse(&content)?.plc.failover,
+            vec![("::1".to_string(), 5008)]
+        );
+        assert_eq!(config.groups.len(), 2);
+        let fast = &config.groups[0];
+        assert_eq!(fast.interval, Duration::from_millis(10));
+        assert_eq!(fast.tags[0].device, "D100");
+        assert!(matches!(fast.tags[0].data_type, DataType::FLOAT));
+        assert_eq!(fast.tags[0].precision, Some(1));
+        assert_eq!(fast.tags[1].name, "M0");
+        assert_eq!(
+            config.groups[1].sinks,
+            vec![SinkConfig::Csv {
+                path: PathBuf::from("slow.csv")
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_overrides() -> Result<(), Box<dyn Error>> {
+        let mut config = Config::parse(CONFIG)?;
+        assert!(!config.plc.read_only);
+        let overrides = EnvOverrides::from_lookup(|name| match name {
+            "MELSEC_PORT" => Some("6000".to_string()),
+            "MELSEC_READ_ONLY" => Some("1".to_string()),
+            _ => None,
+        })?;
+        config.plc.apply_env(&overrides);
+        assert_eq!(config.plc.host, "127.0.0.1");
+        assert_eq!(config.plc.port, 6000);
+        assert!(config.plc.read_only);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_setting() {
+        let content = CONFIG.replace("interval_ms = 1000", "interval_ms = \"1s\"");
+        let e = Config::parse(&content).unwrap_err();
+        let e = err::find::<ConfigError>(&*e).unwrap();
+        assert_eq!(e.key, "groups[1].interval_ms");
+
+        let content = CONFIG.replace("127.0.0.2:5008", "::1:5008");
+        let e = Config::parse(&content).unwrap_err();
+        assert!(e.to_string().contains("plc.failover"));
+
+        let content = CONFIG.replace("port = 5007", "port = 70000");
+        let e = Config::parse(&content).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Invalid config setting plc.port: 70000 is out of range"
+        );
+    }
+
+    #[test]
+    fn test_build_and_run() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        simulator.set("D10", 42);
+        let content = format!(
+            "[plc]\nhost = \"127.0.0.1\"\nport = {}\nplc_type = \"Q\"\nframe = \"4E\"\n\n\
+             [[groups]]\nname = \"fast\"\ninterval_ms = 5\n\
+             tags = [{{ name = \"level\", device = \"D10\" }}]\n",
+            simulator.port()
+        );
+        let mut collector = Config::parse(&content)?.build()?;
+        assert_eq!(collector.groups[0].0, "fast");
+        let tags = collector.groups[0].1.poll_once(&collector.client)?;
+        assert_eq!(tags[0].value.as_deref(), Some("42"));
+
+        let token = CancelToken::new();
+        token.cancel();
+        collector.run(&token)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_past_failed_poll() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let content = format!(
+            "[plc]\nhost = \"127.0.0.1\"\nport = {}\nplc_type = \"Q\"\n\n\
+             [[groups]]\nname = \"a\"\ninterval_ms = 5\n\
+             tags = [{{ name = \"level\", device = \"D10\" }}]\n\n\
+             [[groups]]\nname = \"b\"\ninterval_ms = 5\n\
+             tags = [{{ name = \"speed\", device = \"D20\" }}]\n",
+            simulator.port()
+        );
+        let mut collector = Config::parse(&content)?.build()?;
+        let events = collector.groups[0].1.control().subscribe();
+
+        // the first poll of group a is refused
+        simulator.inject(crate::simulator::Fault::CompletionCode(0xC051));
+        let token = CancelToken::new();
+        let canceller = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            canceller.cancel();
+        });
+        collector.run(&token)?;
+
+        match events.try_recv()? {
+            PollerEvent::PollFailed(error) => assert!(error.contains("0xC051")),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert!(events.try_recv().is_err());
+        assert!(simulator.transactions() > 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let content = format!(
+            "[plc]\nhost = \"127.0.0.1\"\nport = {}\nplc_type = \"Q\"\n\n\
+             [[groups]]\nname = \"fast\"\ninterval_ms = 5\n\
+             tags = [{{ name = \"level\", device = \"D10\" }}]\n",
+            simulator.port()
+        );
+        let config = Config::parse(&content)?;
+        let collector = config.build()?;
+        let control = collector.controls().remove(0).1;
+        let events = control.subscribe();
+        let mut reloader = Reloader::new(config, &collector);
+
+        let edited = content.replace(
+            "device = \"D10\" }",
+            "device = \"D10\" }, { name = \"flow\", device = \"D11\", unit = \"l/min\" }",
+        );
+        let edited = edited.replace("interval_ms = 5", "interval_ms = 20");
+        assert_eq!(reloader.reload(&edited), ReloadEvent::Applied);
+        assert_eq!(control.tags().len(), 2);
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![
+                PollerEvent::TagAdded("D11".to_string()),
+                PollerEvent::IntervalChanged(Duration::from_millis(20)),
+            ]
+        );
+
+        // rejected files leave the running groups alone
+        let event = reloader.reload(&edited.replace("device = \"D11\"", "device = 11"));
+        assert!(matches!(event, ReloadEvent::Rejected { .. }));
+        let event = reloader.reload(&edited.replace("plc_type = \"Q\"", "plc_type = \"L\""));
+        assert_eq!(
+            event,
+            ReloadEvent::Rejected {
+                error: "Invalid config setting plc: changes need a restart".to_string()
+            }
+        );
+        assert_eq!(control.tags().len(), 2);
+        assert_eq!(reloader.config().groups[0].tags[1].unit, "l/min");
+        Ok(())
+    }
+
+    #[cfg(feature = "notify")]
+    #[test]
+    fn test_watcher() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let path = std::env::temp_dir().join(format!("rs-melsec-{}.toml", simulator.port()));
+        let content = format!(
+            "[plc]\nhost = \"127.0.0.1\"\nport = {}\nplc_type = \"Q\"\n\n\
+             [[groups]]\nname = \"fast\"\ninterval_ms = 5\n\
+             tags = [{{ device = \"D10\" }}]\n",
+            simulator.port()
+        );
+        fs::write(&path, &content)?;
+        let config = Config::from_path(&path)?;
+        let collector = config.build()?;
+        let watcher = ConfigWatcher::start(&path, Reloader::new(config, &collector))?;
+
+        fs::write(
+            &path,
+            content.replace("interval_ms = 5", "interval_ms = 50"),
+        )?;
+        let event = watcher.events().recv_timeout(Duration::from_secs(5));
+        fs::remove_file(&path)?;
+        assert_eq!(event?, ReloadEvent::Applied);
+        assert_eq!(collector.groups[0].1.state().1, Duration::from_millis(50));
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::error::Error;
+
+use super::client::Client;
+use super::db::DataType;
+use super::tag::QueryTag;
+
+// Accumulates a wrapping 16/32-bit counter device into a monotonic total
+#[derive(Debug)]
+pub struct Totalizer {
+    device: String,
+    data_type: DataType,
+    last_raw: Option<u64>,
+    total: u64,
+}
+
+impl Totalizer {
+    /// `data_type` must be `UWORD` or `UDWORD`, the width the counter wraps at.
+    pub fn new(device: &str, data_type: DataType) -> Result<Self, Box<dyn Error>> {
+        match data_type {
+            DataType::UWORD | DataType::UDWORD => Ok(Self {
+                device: device.to_string(),
+                data_type,
+                last_raw: None,
+                total: 0,
+            }),
+            _ => Err(format!("Unsupported counter data type {:?}", data_type).into()),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn reset(&mut self) {
+        self.last_raw = None;
+        self.total = 0;
+    }
+
+    /// Feeds a raw counter value and returns the accumulated total. The
+    /// first value only sets the baseline; a smaller value than the previous
+    /// one is treated as a single rollover.
+    pub fn update(&mut self, raw: i64) -> u64 {
+        let modulus = 1u64 << (self.data_type.size() as u32 * 8);
+        let raw = raw as u64 % modulus;
+        if let Some(last_raw) = self.last_raw {
+            self.total += (raw + modulus - last_raw) % modulus;
+        }
+        self.last_raw = Some(raw);
+        self.total
+    }
+
+    pub fn poll(&mut self, client: &Client) -> Result<u64, Box<dyn Error>> {
+        let tags = client.read(vec![QueryTag {
+            device: self.device.clone(),
+            data_type: self.data_type.clone(),
+        }])?;
+        let raw = tags
+            .first()
+            .and_then(|tag| tag.value.as_ref())
+            .ok_or_else(|| format!("No value read for {}", self.device))?
+            .parse::<i64>()?;
+        Ok(self.update(raw))
+    }
+}
+
+#[cfg(test)]
+mod tests_counter {
+    use super::*;
+
+    #[test]
+    fn test_rollover() -> Result<(), Box<dyn Error>> {
+        let mut counter = Totalizer::new("D100", DataType::UWORD)?;
+        assert_eq!(counter.update(65530), 0);
+        assert_eq!(counter.update(65535), 5);
+        assert_eq!(counter.update(4), 10);
+        assert_eq!(counter.update(4), 10);
+
+        let mut counter = Totalizer::new("D200", DataType::UDWORD)?;
+        counter.update(u32::MAX as i64);
+        assert_eq!(counter.update(1), 2);
+
+        assert!(Totalizer::new("D300", DataType::FLOAT).is_err());
+        Ok(())
+    }
+}
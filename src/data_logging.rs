@@ -0,0 +1,135 @@
+use std::error::Error;
+
+use super::client::Client;
+
+pub const SD_CARD_DRIVE: u16 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggingColumn {
+    pub name: String,
+    // as written by the CPU, e.g. "SHORT[DEC.0]", "FLOAT[DEC.2]"
+    pub data_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggingRecord {
+    // "YYYY/MM/DD hh:mm:ss.sss", sorts chronologically as a string
+    pub timestamp: String,
+    pub index: Option<u64>,
+    pub values: Vec<String>,
+}
+
+/// Contents of a data logging CSV file: the file information and comment
+/// rows, a data type row starting with `DATETIME`, a data name row and one
+/// row per sample.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DataLoggingFile {
+    pub columns: Vec<LoggingColumn>,
+    pub records: Vec<LoggingRecord>,
+}
+
+impl DataLoggingFile {
+    pub fn parse(contents: &str) -> Result<Self, Box<dyn Error>> {
+        let mut lines = contents
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .skip_while(|line| !line.starts_with("DATETIME"));
+        let types = lines.next().ok_or("No data type row in logging file")?;
+        let names = lines.next().ok_or("No data name row in logging file")?;
+        let types: Vec<&str> = types.split(',').collect();
+        let names: Vec<&str> = names.split(',').collect();
+        // DATETIME and the optional INDEX column precede the logged data
+        let has_index = types.get(1).is_some_and(|t| t.starts_with("INDEX"));
+        let skip = if has_index { 2 } else { 1 };
+
+        let columns = types
+            .iter()
+            .zip(names.iter())
+            .skip(skip)
+            .map(|(data_type, name)| LoggingColumn {
+                name: name.to_string(),
+                data_type: data_type.to_string(),
+            })
+            .collect();
+
+        let mut records = Vec::new();
+        for (line_number, line) in lines.enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').collect();
+            let index = if has_index {
+                let index = fields
+                    .get(1)
+                    .ok_or_else(|| format!("Logging record {} has no index", line_number + 1))?;
+                Some(index.parse::<u64>().map_err(|e| {
+                    format!(
+                        "Invalid index {:?} in record {}: {}",
+                        index,
+                        line_number + 1,
+                        e
+                    )
+                })?)
+            } else {
+                None
+            };
+            records.push(LoggingRecord {
+                timestamp: fields[0].to_string(),
+                index,
+                values: fields.iter().skip(skip).map(|v| v.to_string()).collect(),
+            });
+        }
+        Ok(Self { columns, records })
+    }
+
+    /// Records logged after `timestamp`, for filling a gap in a historian.
+    pub fn records_after(&self, timestamp: &str) -> Vec<&LoggingRecord> {
+        self.records
+            .iter()
+            .filter(|record| record.timestamp.as_str() > timestamp)
+            .collect()
+    }
+}
+
+impl Client {
+    /// Reads and parses a data logging file from the SD card, e.g.
+    /// `LOGGING/LOG01/00000001/LOG01_00000001.CSV`.
+    pub fn read_data_logging(&self, path: &str) -> Result<DataLoggingFile, Box<dyn Error>> {
+        let contents = self.read_file(SD_CARD_DRIVE, path)?;
+        DataLoggingFile::parse(&self.text_encoding().decode_lossy(&contents))
+    }
+}
+
+#[cfg(test)]
+mod tests_data_logging {
+    use super::*;
+
+    const LOGGING_CSV: &str = "[LOGGING],1,\"LOG01\"\r\n\
+        [COMMENT],line 1 temperatures\r\n\
+        DATETIME[YYYY/MM/DD hh:mm:ss.sss],INDEX,SHORT[DEC.0],FLOAT[DEC.2]\r\n\
+        TIME,INDEX,D100,D200\r\n\
+        2024/05/01 08:00:00.000,1,21,20.50\r\n\
+        2024/05/01 08:00:01.000,2,22,20.75\r\n";
+
+    #[test]
+    fn test_parse_logging_file() -> Result<(), Box<dyn Error>> {
+        let file = DataLoggingFile::parse(LOGGING_CSV)?;
+        assert_eq!(
+            file.columns[1],
+            LoggingColumn {
+                name: "D200".to_string(),
+                data_type: "FLOAT[DEC.2]".to_string(),
+            }
+        );
+        assert_eq!(file.records.len(), 2);
+        assert_eq!(file.records[1].index, Some(2));
+        assert_eq!(file.records[1].values, vec!["22", "20.75"]);
+
+        let missed = file.records_after("2024/05/01 08:00:00.000");
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].timestamp, "2024/05/01 08:00:01.000");
+
+        assert!(DataLoggingFile::parse("[LOGGING],1\r\n").is_err());
+        Ok(())
+    }
+}
@@ -7,16 +7,20 @@ pub mod consts {
     pub const QNA_SERIES: &str = "QnA";
     pub const IQL_SERIES: &str = "iQ-L";
     pub const IQR_SERIES: &str = "iQ-R";
+    // FX5 CPUs, X and Y numbered in octal
+    pub const IQF_SERIES: &str = "iQ-F";
 
     // communication type
     pub const COMMTYPE_BINARY: &str = "binary";
     pub const COMMTYPE_ASCII: &str = "ascii";
+}
 
-    // endian types
-    pub const ENDIAN_NATIVE: char = '=';
-    pub const ENDIAN_LITTLE: char = '<';
-    pub const ENDIAN_BIG: char = '>';
-    pub const ENDIAN_NETWORK: char = '!';
+// Byte order of the data values carried in the request/response payload.
+// The frame header, command and device fields are always little-endian.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Endian {
+    Little,
+    Big,
 }
 
 // Commands
@@ -25,6 +29,7 @@ pub mod commands {
     pub const BATCH_WRITE: u16 = 0x1401;
     pub const RANDOM_READ: u16 = 0x0403;
     pub const RANDOM_WRITE: u16 = 0x1402;
+    pub const MULTI_BLOCK_READ: u16 = 0x0406;
     pub const MONITOR_REG: u16 = 0x0801;
     pub const MONITOR: u16 = 0x0802;
     pub const REMOTE_RUN: u16 = 0x1001;
@@ -37,6 +42,63 @@ pub mod commands {
     pub const ERROR_LED_OFF: u16 = 0x1617;
     pub const READ_CPU_MODEL: u16 = 0x0101;
     pub const LOOPBACK_TEST: u16 = 0x0619;
+    pub const FILE_OPEN: u16 = 0x1827;
+    pub const FILE_READ: u16 = 0x1828;
+    pub const FILE_CLOSE: u16 = 0x182A;
+    pub const LABEL_ARRAY_READ: u16 = 0x041A;
+    pub const LABEL_ARRAY_WRITE: u16 = 0x141A;
+    pub const LABEL_RANDOM_READ: u16 = 0x041C;
+    pub const LABEL_RANDOM_WRITE: u16 = 0x141B;
+
+    // Commands that change device values or the CPU state, refused by a
+    // read-only client
+    pub fn is_write(command: u16) -> bool {
+        matches!(
+            command,
+            BATCH_WRITE
+                | RANDOM_WRITE
+                | LABEL_ARRAY_WRITE
+                | LABEL_RANDOM_WRITE
+                | REMOTE_RUN
+                | REMOTE_STOP
+                | REMOTE_PAUSE
+                | REMOTE_LATCH_CLEAR
+                | REMOTE_RESET
+                | ERROR_LED_OFF
+        )
+    }
+
+    // Name used in error messages, e.g. "BatchRead"
+    pub fn name(command: u16) -> Option<&'static str> {
+        let name = match command {
+            BATCH_READ => "BatchRead",
+            BATCH_WRITE => "BatchWrite",
+            RANDOM_READ => "RandomRead",
+            RANDOM_WRITE => "RandomWrite",
+            MULTI_BLOCK_READ => "MultiBlockRead",
+            MONITOR_REG => "MonitorRegister",
+            MONITOR => "Monitor",
+            REMOTE_RUN => "RemoteRun",
+            REMOTE_STOP => "RemoteStop",
+            REMOTE_PAUSE => "RemotePause",
+            REMOTE_LATCH_CLEAR => "RemoteLatchClear",
+            REMOTE_RESET => "RemoteReset",
+            REMOTE_UNLOCK => "RemoteUnlock",
+            REMOTE_LOCK => "RemoteLock",
+            ERROR_LED_OFF => "ErrorLedOff",
+            READ_CPU_MODEL => "ReadCpuModel",
+            LOOPBACK_TEST => "LoopbackTest",
+            FILE_OPEN => "FileOpen",
+            FILE_READ => "FileRead",
+            FILE_CLOSE => "FileClose",
+            LABEL_ARRAY_READ => "LabelArrayRead",
+            LABEL_ARRAY_WRITE => "LabelArrayWrite",
+            LABEL_RANDOM_READ => "LabelRandomRead",
+            LABEL_RANDOM_WRITE => "LabelRandomWrite",
+            _ => return None,
+        };
+        Some(name)
+    }
 }
 
 // SubCommands
@@ -48,6 +110,7 @@ pub mod subcommands {
     pub const FIVE: u16 = 0x0005;
     pub const A: u16 = 0x000A;
     pub const F: u16 = 0x000F;
+    pub const FILE_IQR: u16 = 0x0040;
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -61,17 +124,28 @@ pub enum DataType {
     DOUBLE,
     SLWORD,
     ULWORD,
+    // user-defined packing of `size` bytes, decoded by a decoder registered
+    // on the client under `name`
+    CUSTOM { name: String, size: i8 },
+    // `len` bytes returned as they are, e.g. a barcode buffer; an odd length
+    // takes up the whole last word
+    RAW(usize),
 }
 
+pub type Decoder = Box<dyn Fn(&[u8]) -> Result<String, Box<dyn Error>> + Send + Sync>;
+
 impl DataType {
-    pub fn size(&self) -> i8 {
+    pub fn size(&self) -> usize {
         match self {
             DataType::BIT | DataType::SWORD | DataType::UWORD => 2,
             DataType::SDWORD | DataType::UDWORD | DataType::FLOAT => 4,
             DataType::DOUBLE | DataType::SLWORD | DataType::ULWORD => 8,
+            DataType::CUSTOM { size, .. } => *size as usize,
+            DataType::RAW(len) => len.div_ceil(2) * 2,
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "b" => Some(DataType::BIT),
@@ -98,8 +172,207 @@ impl DataType {
             DataType::DOUBLE => "d",
             DataType::SLWORD => "q",
             DataType::ULWORD => "Q",
+            DataType::CUSTOM { name, .. } => name,
+            DataType::RAW(_) => "raw",
+        }
+    }
+}
+
+/// Rust type `Client::batch_read_typed` returns values of `DATA_TYPE` as.
+pub trait PlcValue: Sized {
+    const DATA_TYPE: DataType;
+    // `bits` holds the value in its low `DATA_TYPE.size()` bytes
+    fn from_bits(bits: u64) -> Self;
+}
+
+impl PlcValue for i16 {
+    const DATA_TYPE: DataType = DataType::SWORD;
+    fn from_bits(bits: u64) -> Self {
+        bits as u16 as i16
+    }
+}
+
+impl PlcValue for u16 {
+    const DATA_TYPE: DataType = DataType::UWORD;
+    fn from_bits(bits: u64) -> Self {
+        bits as u16
+    }
+}
+
+impl PlcValue for i32 {
+    const DATA_TYPE: DataType = DataType::SDWORD;
+    fn from_bits(bits: u64) -> Self {
+        bits as u32 as i32
+    }
+}
+
+impl PlcValue for u32 {
+    const DATA_TYPE: DataType = DataType::UDWORD;
+    fn from_bits(bits: u64) -> Self {
+        bits as u32
+    }
+}
+
+impl PlcValue for i64 {
+    const DATA_TYPE: DataType = DataType::SLWORD;
+    fn from_bits(bits: u64) -> Self {
+        bits as i64
+    }
+}
+
+impl PlcValue for u64 {
+    const DATA_TYPE: DataType = DataType::ULWORD;
+    fn from_bits(bits: u64) -> Self {
+        bits
+    }
+}
+
+impl PlcValue for f32 {
+    const DATA_TYPE: DataType = DataType::FLOAT;
+    fn from_bits(bits: u64) -> Self {
+        f32::from_bits(bits as u32)
+    }
+}
+
+impl PlcValue for f64 {
+    const DATA_TYPE: DataType = DataType::DOUBLE;
+    fn from_bits(bits: u64) -> Self {
+        f64::from_bits(bits)
+    }
+}
+
+// Device outside the built-in table, e.g. of a third-party SLMP server,
+// registered on the client under its name
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomDevice {
+    // one byte before iQ-R, two bytes on iQ-R
+    pub binary_code: u16,
+    // padded with '*' like the built-in codes
+    pub ascii_code: String,
+    // 10 or 16, the base of the device number
+    pub base: u32,
+}
+
+// CPU series, for checking up front what a PLC can be asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlcType {
+    Q,
+    L,
+    QnA,
+    IqL,
+    IqR,
+    IqF,
+}
+
+impl PlcType {
+    pub const ALL: [PlcType; 6] = [
+        PlcType::Q,
+        PlcType::L,
+        PlcType::QnA,
+        PlcType::IqL,
+        PlcType::IqR,
+        PlcType::IqF,
+    ];
+
+    // Takes the `consts` names used by `Client::new`, e.g. "iQ-R"
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(plc_type: &str) -> Option<Self> {
+        PlcType::ALL
+            .into_iter()
+            .find(|series| series.as_str() == plc_type)
+    }
+
+    /// Series of a model name returned by the CPU model read, e.g. "R04CPU",
+    /// "Q03UDECPU", "L26CPU-BT" or "FX5U-32MR/ES".
+    pub fn from_cpu_model(model: &str) -> Option<Self> {
+        let model = model.trim().to_uppercase();
+        if model.starts_with("FX5") {
+            return Some(PlcType::IqF);
+        }
+        let mut chars = model.chars();
+        let prefix = chars.next()?;
+        let rest = chars.as_str();
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return None;
+        }
+        let after_digits = &rest[digits..];
+        match prefix {
+            'R' => Some(PlcType::IqR),
+            'L' if after_digits.starts_with("HCPU") => Some(PlcType::IqL),
+            'L' => Some(PlcType::L),
+            // QnA models are Q2ACPU, Q4ARCPU, Q2ASHCPU and the like
+            'Q' if after_digits.starts_with('A') => Some(PlcType::QnA),
+            'Q' => Some(PlcType::Q),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlcType::Q => consts::Q_SERIES,
+            PlcType::L => consts::L_SERIES,
+            PlcType::QnA => consts::QNA_SERIES,
+            PlcType::IqL => consts::IQL_SERIES,
+            PlcType::IqR => consts::IQR_SERIES,
+            PlcType::IqF => consts::IQF_SERIES,
+        }
+    }
+
+    /// Whether the CPUs of this series accept `command` over the built-in
+    /// Ethernet port. Unknown command codes are not supported.
+    pub fn supports(&self, command: u16) -> bool {
+        use commands::*;
+        match command {
+            BATCH_READ | BATCH_WRITE | RANDOM_READ | RANDOM_WRITE | MULTI_BLOCK_READ
+            | MONITOR_REG | MONITOR | REMOTE_RUN | REMOTE_STOP | REMOTE_PAUSE
+            | REMOTE_LATCH_CLEAR | REMOTE_RESET | READ_CPU_MODEL | LOOPBACK_TEST => true,
+            // remote password and error LED commands came with the Q series
+            REMOTE_UNLOCK | REMOTE_LOCK | ERROR_LED_OFF => *self != PlcType::QnA,
+            // so did file access, which FX5 CPUs do not have over SLMP
+            FILE_OPEN | FILE_READ | FILE_CLOSE => !matches!(self, PlcType::QnA | PlcType::IqF),
+            // labels only exist on iQ CPUs
+            LABEL_ARRAY_READ | LABEL_ARRAY_WRITE | LABEL_RANDOM_READ | LABEL_RANDOM_WRITE => {
+                matches!(self, PlcType::IqL | PlcType::IqR)
+            }
+            _ => false,
         }
     }
+
+    /// Whether the CPUs of this series accept `subcommand`. The device
+    /// extension subcommands 0002h/0003h with 4-byte device numbers are
+    /// iQ-R/iQ-L only.
+    pub fn supports_subcommand(&self, subcommand: u16) -> bool {
+        match subcommand {
+            subcommands::TWO | subcommands::THREE => matches!(self, PlcType::IqL | PlcType::IqR),
+            _ => true,
+        }
+    }
+
+    /// Whether `device` (e.g. "D100", "LTN0") names a device of this series
+    /// with a valid device number.
+    pub fn supports_device(&self, device: &str) -> bool {
+        let plc_type = self.as_str();
+        let letters = device
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(device.len());
+        // hex device numbers may start with a letter, e.g. XA, BFF or SWFF,
+        // so the letters are tried as a device name from the longest down
+        (1..=letters).rev().any(|split| {
+            let (name, number) = device.split_at(split);
+            let base = DeviceConstants::get_ascii_device_code(plc_type, name)
+                .map(|(_, base)| base)
+                .or_else(|_| {
+                    DeviceConstants::get_binary_device_code(plc_type, name).map(|(_, base)| base)
+                });
+            match base {
+                Ok(base) => !number.is_empty() && u32::from_str_radix(number, base).is_ok(),
+                Err(_) => false,
+            }
+        })
+    }
 }
 
 pub struct DeviceConstants;
@@ -145,6 +418,12 @@ impl DeviceConstants {
     pub const LZ_DEVICE: u8 = 0x62;
     pub const RD_DEVICE: u8 = 0x2C;
 
+    // Devices of every series, by the names `get_binary_device_code` takes
+    pub const DEVICE_NAMES: [&'static str; 26] = [
+        "SM", "SD", "X", "Y", "M", "L", "F", "V", "B", "D", "W", "TS", "TC", "TN", "SS", "SC",
+        "SN", "CS", "CC", "CN", "SB", "SW", "DX", "DY", "R", "ZR",
+    ];
+
     pub const BIT_DEVICE: &'static str = "bit";
     pub const WORD_DEVICE: &'static str = "word";
     pub const DWORD_DEVICE: &'static str = "dword";
@@ -157,6 +436,8 @@ impl DeviceConstants {
         match device_name {
             "SM" => Ok((DeviceConstants::SM_DEVICE, 10)),
             "SD" => Ok((DeviceConstants::SD_DEVICE, 10)),
+            "X" if plc_type == consts::IQF_SERIES => Ok((DeviceConstants::X_DEVICE, 8)),
+            "Y" if plc_type == consts::IQF_SERIES => Ok((DeviceConstants::Y_DEVICE, 8)),
             "X" => Ok((DeviceConstants::X_DEVICE, 16)),
             "Y" => Ok((DeviceConstants::Y_DEVICE, 16)),
             "M" => Ok((DeviceConstants::M_DEVICE, 10)),
@@ -181,17 +462,17 @@ impl DeviceConstants {
             "DY" => Ok((DeviceConstants::DY_DEVICE, 16)),
             "R" => Ok((DeviceConstants::R_DEVICE, 10)),
             "ZR" => Ok((DeviceConstants::ZR_DEVICE, 16)),
-            "LTS" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::LTS_DEVICE, 10)),
-            "LTC" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::LTC_DEVICE, 10)),
-            "LTN" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::LTN_DEVICE, 10)),
-            "LSTS" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::LSTS_DEVICE, 10)),
-            "LSTC" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::LSTC_DEVICE, 10)),
-            "LSTN" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::LSTN_DEVICE, 10)),
-            "LCS" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::LCS_DEVICE, 10)),
-            "LCC" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::LCC_DEVICE, 10)),
-            "LCN" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::LCN_DEVICE, 10)),
-            "LZ" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::LZ_DEVICE, 10)),
-            "RD" if plc_type == "iQR_SERIES" => Ok((DeviceConstants::RD_DEVICE, 10)),
+            "LTS" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::LTS_DEVICE, 10)),
+            "LTC" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::LTC_DEVICE, 10)),
+            "LTN" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::LTN_DEVICE, 10)),
+            "LSTS" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::LSTS_DEVICE, 10)),
+            "LSTC" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::LSTC_DEVICE, 10)),
+            "LSTN" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::LSTN_DEVICE, 10)),
+            "LCS" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::LCS_DEVICE, 10)),
+            "LCC" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::LCC_DEVICE, 10)),
+            "LCN" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::LCN_DEVICE, 10)),
+            "LZ" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::LZ_DEVICE, 10)),
+            "RD" if plc_type == consts::IQR_SERIES => Ok((DeviceConstants::RD_DEVICE, 10)),
             _ => Err(format!(
                 "failed to get binary device code for device: {}",
                 device_name,
@@ -208,10 +489,10 @@ impl DeviceConstants {
         let padded_name = format!("{:*<width$}", device_name, width = padding);
 
         match device_name {
-            "SM" | "SD" | "X" | "Y" | "M" | "L" | "F" | "V" | "B" | "D" | "W" | "TS" | "TC"
-            | "TN" | "CS" | "CC" | "CN" | "SB" | "SW" | "DX" | "DY" | "R" | "ZR" => {
-                Ok((padded_name, 16))
-            }
+            "X" | "Y" if plc_type == consts::IQF_SERIES => Ok((padded_name, 8)),
+            "X" | "Y" | "B" | "W" | "SB" | "SW" | "DX" | "DY" | "ZR" => Ok((padded_name, 16)),
+            "SM" | "SD" | "M" | "L" | "F" | "V" | "D" | "TS" | "TC" | "TN" | "CS" | "CC" | "CN"
+            | "R" => Ok((padded_name, 10)),
             "STS" if plc_type == consts::IQR_SERIES => {
                 Ok((format!("{:*<width$}", "STS", width = padding), 10))
             }
@@ -224,16 +505,17 @@ impl DeviceConstants {
                 Ok((format!("{:*<width$}", "STN", width = padding), 10))
             }
             "STN" => Ok((format!("{:*<width$}", "SN", width = padding), 10)),
-            "LTS" if plc_type == "iQR_SERIES" => Ok((padded_name, 10)),
-            "LTC" if plc_type == "iQR_SERIES" => Ok((padded_name, 10)),
-            "LTN" if plc_type == "iQR_SERIES" => Ok((padded_name, 10)),
-            "LSTS" if plc_type == "iQR_SERIES" => Ok((padded_name, 10)),
-            "LSTN" if plc_type == "iQR_SERIES" => Ok((padded_name, 10)),
-            "LCS" if plc_type == "iQR_SERIES" => Ok((padded_name, 10)),
-            "LCC" if plc_type == "iQR_SERIES" => Ok((padded_name, 10)),
-            "LCN" if plc_type == "iQR_SERIES" => Ok((padded_name, 10)),
-            "LZ" if plc_type == "iQR_SERIES" => Ok((padded_name, 10)),
-            "RD" if plc_type == "iQR_SERIES" => Ok((padded_name, 10)),
+            "LTS" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
+            "LTC" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
+            "LTN" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
+            "LSTS" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
+            "LSTC" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
+            "LSTN" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
+            "LCS" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
+            "LCC" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
+            "LCN" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
+            "LZ" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
+            "RD" if plc_type == consts::IQR_SERIES => Ok((padded_name, 10)),
             _ => Err(format!(
                 "failed to get ascii device code  for device: {}",
                 device_name,
@@ -247,16 +529,16 @@ impl DeviceConstants {
         device_name: &str,
     ) -> Result<&'static str, Box<dyn Error>> {
         match device_name {
-            "SM" | "X" | "Y" | "M" | "L" | "F" | "V" | "B" | "TS" | "TC" | "STS" | "STC" | "CS"
-            | "CC" | "SB" | "DX" | "DY" => Ok(DeviceConstants::BIT_DEVICE),
-            "SD" | "D" | "W" | "TN" | "STN" | "CN" | "SW" | "R" | "ZR" => {
+            "SM" | "X" | "Y" | "M" | "L" | "F" | "V" | "B" | "TS" | "TC" | "STS" | "SS" | "STC"
+            | "SC" | "CS" | "CC" | "SB" | "DX" | "DY" => Ok(DeviceConstants::BIT_DEVICE),
+            "SD" | "D" | "W" | "TN" | "STN" | "SN" | "CN" | "SW" | "R" | "ZR" => {
                 Ok(DeviceConstants::WORD_DEVICE)
             }
-            "LSTN" | "LCN" | "LZ" => match plc_type {
+            "LTN" | "LSTN" | "LCN" | "LZ" => match plc_type {
                 consts::IQR_SERIES => Ok(DeviceConstants::DWORD_DEVICE),
                 _ => Err(format!("Unsupported PLC type: {}", plc_type).into()),
             },
-            "LST" | "LTC" | "LTN" | "LSTS" | "LCS" | "LCC" => match plc_type {
+            "LTS" | "LTC" | "LSTS" | "LSTC" | "LCS" | "LCC" => match plc_type {
                 consts::IQR_SERIES => Ok(DeviceConstants::BIT_DEVICE),
                 _ => Err(format!("Unsupported PLC type: {}", plc_type).into()),
             },
@@ -272,3 +554,60 @@ impl DeviceConstants {
         }
     }
 }
+
+#[cfg(test)]
+mod tests_db {
+    use super::*;
+
+    #[test]
+    fn test_support_matrix() {
+        assert_eq!(PlcType::from_str("iQ-R"), Some(PlcType::IqR));
+        assert_eq!(PlcType::from_str("FX"), None);
+        assert!(PlcType::IqR.supports(commands::LABEL_ARRAY_READ));
+        assert!(!PlcType::Q.supports(commands::LABEL_ARRAY_READ));
+        assert!(!PlcType::QnA.supports(commands::REMOTE_UNLOCK));
+        assert!(PlcType::Q.supports(commands::MULTI_BLOCK_READ));
+        assert!(!PlcType::Q.supports(0x9999));
+        assert!(!PlcType::Q.supports_subcommand(subcommands::TWO));
+        assert!(PlcType::IqR.supports_subcommand(subcommands::THREE));
+
+        assert!(PlcType::Q.supports_device("D100"));
+        assert!(PlcType::Q.supports_device("X1F"));
+        assert!(PlcType::Q.supports_device("XA"));
+        assert!(PlcType::Q.supports_device("BFF"));
+        assert!(PlcType::Q.supports_device("SWFF"));
+        assert!(PlcType::Q.supports_device("DXA0"));
+        assert!(!PlcType::Q.supports_device("DA"));
+        assert!(!PlcType::Q.supports_device("D1F"));
+        assert!(!PlcType::Q.supports_device("D"));
+        assert!(!PlcType::Q.supports_device("LTN0"));
+        assert!(PlcType::IqR.supports_device("LTN0"));
+        assert!(PlcType::IqR.supports_device("STS10"));
+        assert!(!PlcType::IqR.supports_device("QQ1"));
+        assert!(PlcType::IqF.supports_device("X17"));
+        assert!(!PlcType::IqF.supports_device("X18"));
+        assert!(!PlcType::IqF.supports_device("X1F"));
+
+        for plc_type in PlcType::ALL {
+            for name in DeviceConstants::DEVICE_NAMES {
+                let plc_type = plc_type.as_str();
+                assert!(DeviceConstants::get_binary_device_code(plc_type, name).is_ok());
+                assert!(DeviceConstants::get_device_type(plc_type, name).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_cpu_model() {
+        assert_eq!(PlcType::from_cpu_model("R04CPU"), Some(PlcType::IqR));
+        assert_eq!(PlcType::from_cpu_model("R08ENCPU  "), Some(PlcType::IqR));
+        assert_eq!(PlcType::from_cpu_model("Q03UDECPU"), Some(PlcType::Q));
+        assert_eq!(PlcType::from_cpu_model("Q00JCPU"), Some(PlcType::Q));
+        assert_eq!(PlcType::from_cpu_model("Q2ASHCPU"), Some(PlcType::QnA));
+        assert_eq!(PlcType::from_cpu_model("L26CPU-BT"), Some(PlcType::L));
+        assert_eq!(PlcType::from_cpu_model("L08HCPU"), Some(PlcType::IqL));
+        assert_eq!(PlcType::from_cpu_model("FX5U-32MR/ES"), Some(PlcType::IqF));
+        assert_eq!(PlcType::from_cpu_model("FX3U"), None);
+        assert_eq!(PlcType::from_cpu_model("RJ71EN71"), None);
+    }
+}
@@ -1,10 +1,14 @@
 use super::db::consts;
 
-pub trait DeviceInfo {
+pub trait DeviceInfo: Send + Sync {
     fn get_response_data_index(&self, comm_type: &str) -> usize;
     fn get_response_status_index(&self, comm_type: &str) -> usize;
     fn get_subheader(&self) -> u16;
     fn get_subheader_serial(&self) -> u16;
+    // monitoring timer upper bound, in 250ms units
+    fn get_max_monitoring_timer(&self) -> u16 {
+        0xFFFF
+    }
     fn set_subheader_series(&mut self, subheader_serial: u16) {
         println!(
             "not need to set subheader_series {} for E3",
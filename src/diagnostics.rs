@@ -0,0 +1,209 @@
+use std::error::Error;
+use std::time::Duration;
+
+use super::client::Client;
+use super::db::DataType;
+use super::tag::QueryTag;
+
+// SD memory card (drive 2) special relays and registers
+const SM_CARD_USABLE: &str = "SM600";
+const SM_CARD_PROTECTED: &str = "SM601";
+const SM_CARD_PRESENT: &str = "SM603";
+// capacity and free space in K bytes, 32-bit
+const SD_CARD_CAPACITY: &str = "SD606";
+const SD_CARD_FREE: &str = "SD610";
+
+// CPU health special relays and registers
+const SM_DIAGNOSTIC_ERROR: &str = "SM0";
+const SM_BATTERY_LOW: &str = "SM52";
+const SD_DIAGNOSTIC_ERROR: &str = "SD0";
+const SD_SWITCH_STATE: &str = "SD200";
+const SD_OPERATING_STATUS: &str = "SD203";
+const SM_BATTERY_LOW_LATCH: &str = "SM51";
+const SM_ANNUNCIATOR: &str = "SM62";
+const SD_ANNUNCIATOR: &str = "SD62";
+// scan times as milliseconds followed by the microseconds remainder
+const SD_SCAN_TIME: &str = "SD520";
+const SD_SCAN_TIME_US: &str = "SD521";
+const SD_MAX_SCAN_TIME: &str = "SD526";
+const SD_MAX_SCAN_TIME_US: &str = "SD527";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchPosition {
+    Run,
+    Stop,
+    Other(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperatingStatus {
+    Run,
+    Stop,
+    Pause,
+    Other(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatteryStatus {
+    Normal,
+    // the battery voltage is low right now
+    Low,
+    // the voltage recovered but was low since the last reset of SM51
+    LowLatched,
+}
+
+// What the ERROR/USER LEDs show
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorStatus {
+    Normal,
+    // self-diagnostic error with its code
+    Error(u16),
+    // annunciator (F device) turned on by the program, with its number
+    Annunciator(u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics {
+    pub has_error: bool,
+    // latest self-diagnostic error code, 0 if none
+    pub error_code: u16,
+    pub battery_low: bool,
+    pub switch_position: SwitchPosition,
+    pub operating_status: OperatingStatus,
+    pub scan_time: Duration,
+    pub max_scan_time: Duration,
+}
+
+fn scan_time(ms: i64, us: i64) -> Duration {
+    Duration::from_millis(ms as u64) + Duration::from_micros(us as u64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriveStatus {
+    pub card_present: bool,
+    pub card_usable: bool,
+    pub write_protected: bool,
+    pub capacity_kb: u32,
+    pub free_kb: u32,
+}
+
+impl DriveStatus {
+    pub fn used_kb(&self) -> u32 {
+        self.capacity_kb.saturating_sub(self.free_kb)
+    }
+
+    /// True if a file of `bytes` bytes can be written to the card now.
+    pub fn can_write(&self, bytes: u64) -> bool {
+        self.card_present
+            && self.card_usable
+            && !self.write_protected
+            && bytes.div_ceil(1024) <= self.free_kb as u64
+    }
+}
+
+impl Client {
+    // reads `devices` in one random read and returns their values in order
+    fn read_special(&self, devices: &[(&str, DataType)]) -> Result<Vec<i64>, Box<dyn Error>> {
+        let tags = self.read(
+            devices
+                .iter()
+                .map(|(device, data_type)| QueryTag {
+                    device: device.to_string(),
+                    data_type: data_type.clone(),
+                })
+                .collect(),
+        )?;
+        tags.iter()
+            .map(|tag| {
+                let value = tag.value.as_deref().unwrap_or_default();
+                value.parse::<i64>().map_err(|e| {
+                    format!("Invalid value {:?} of {}: {}", value, tag.device, e).into()
+                })
+            })
+            .collect()
+    }
+
+    /// Reads the error flag and code, battery state, RUN/STOP switch,
+    /// operating status and scan times in a single random read.
+    pub fn read_diagnostics(&self) -> Result<Diagnostics, Box<dyn Error>> {
+        let values = self.read_special(&[
+            (SM_DIAGNOSTIC_ERROR, DataType::BIT),
+            (SM_BATTERY_LOW, DataType::BIT),
+            (SD_DIAGNOSTIC_ERROR, DataType::UWORD),
+            (SD_SWITCH_STATE, DataType::UWORD),
+            (SD_OPERATING_STATUS, DataType::UWORD),
+            (SD_SCAN_TIME, DataType::UWORD),
+            (SD_SCAN_TIME_US, DataType::UWORD),
+            (SD_MAX_SCAN_TIME, DataType::UWORD),
+            (SD_MAX_SCAN_TIME_US, DataType::UWORD),
+        ])?;
+        // the low nibble holds the switch, the status register more flags above it
+        let switch_position = match values[3] & 0xF {
+            0 => SwitchPosition::Run,
+            1 => SwitchPosition::Stop,
+            other => SwitchPosition::Other(other as u16),
+        };
+        let operating_status = match values[4] & 0xF {
+            0 => OperatingStatus::Run,
+            1 => OperatingStatus::Stop,
+            2 => OperatingStatus::Pause,
+            other => OperatingStatus::Other(other as u16),
+        };
+        Ok(Diagnostics {
+            has_error: values[0] != 0,
+            error_code: values[2] as u16,
+            battery_low: values[1] != 0,
+            switch_position,
+            operating_status,
+            scan_time: scan_time(values[5], values[6]),
+            max_scan_time: scan_time(values[7], values[8]),
+        })
+    }
+
+    pub fn battery_status(&self) -> Result<BatteryStatus, Box<dyn Error>> {
+        let values = self.read_special(&[
+            (SM_BATTERY_LOW, DataType::BIT),
+            (SM_BATTERY_LOW_LATCH, DataType::BIT),
+        ])?;
+        Ok(match (values[0] != 0, values[1] != 0) {
+            (true, _) => BatteryStatus::Low,
+            (false, true) => BatteryStatus::LowLatched,
+            (false, false) => BatteryStatus::Normal,
+        })
+    }
+
+    /// Self-diagnostic errors take precedence over annunciators.
+    pub fn error_status(&self) -> Result<ErrorStatus, Box<dyn Error>> {
+        let values = self.read_special(&[
+            (SM_DIAGNOSTIC_ERROR, DataType::BIT),
+            (SD_DIAGNOSTIC_ERROR, DataType::UWORD),
+            (SM_ANNUNCIATOR, DataType::BIT),
+            (SD_ANNUNCIATOR, DataType::UWORD),
+        ])?;
+        Ok(if values[0] != 0 {
+            ErrorStatus::Error(values[1] as u16)
+        } else if values[2] != 0 {
+            ErrorStatus::Annunciator(values[3] as u16)
+        } else {
+            ErrorStatus::Normal
+        })
+    }
+
+    /// Presence, state and free space of the SD memory card (drive 2).
+    pub fn drive_status(&self) -> Result<DriveStatus, Box<dyn Error>> {
+        let values = self.read_special(&[
+            (SM_CARD_PRESENT, DataType::BIT),
+            (SM_CARD_USABLE, DataType::BIT),
+            (SM_CARD_PROTECTED, DataType::BIT),
+            (SD_CARD_CAPACITY, DataType::UDWORD),
+            (SD_CARD_FREE, DataType::UDWORD),
+        ])?;
+        Ok(DriveStatus {
+            card_present: values[0] != 0,
+            card_usable: values[1] != 0,
+            write_protected: values[2] != 0,
+            capacity_kb: values[3] as u32,
+            free_kb: values[4] as u32,
+        })
+    }
+}
@@ -0,0 +1,158 @@
+use std::error::Error;
+use std::time::Duration;
+
+use super::client::Client;
+use super::err::ConfigError;
+
+pub const HOST: &str = "MELSEC_HOST";
+pub const PORT: &str = "MELSEC_PORT";
+pub const MONITORING_TIMER_MS: &str = "MELSEC_MONITORING_TIMER_MS";
+// 1/true or 0/false
+pub const READ_ONLY: &str = "MELSEC_READ_ONLY";
+
+/// Connection settings taken from environment variables, to parameterize
+/// one build or config file per site. Unset variables leave the setting
+/// as it is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnvOverrides {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub monitoring_timer: Option<Duration>,
+    pub read_only: Option<bool>,
+}
+
+impl EnvOverrides {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Reads the variables through `lookup` instead of the process
+    /// environment. Empty values count as unset.
+    pub fn from_lookup<F>(lookup: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let var = |name: &str| lookup(name).filter(|value| !value.trim().is_empty());
+        let invalid = |name: &str, message: &str| ConfigError {
+            key: name.to_string(),
+            message: message.to_string(),
+        };
+
+        let port = match var(PORT) {
+            Some(port) => Some(
+                port.trim()
+                    .parse()
+                    .map_err(|_| invalid(PORT, "expected a port number"))?,
+            ),
+            None => None,
+        };
+        let monitoring_timer = match var(MONITORING_TIMER_MS) {
+            Some(millis) => {
+                Some(Duration::from_millis(millis.trim().parse().map_err(
+                    |_| invalid(MONITORING_TIMER_MS, "expected milliseconds"),
+                )?))
+            }
+            None => None,
+        };
+        let read_only = match var(READ_ONLY).as_deref().map(str::trim) {
+            Some("1") | Some("true") => Some(true),
+            Some("0") | Some("false") => Some(false),
+            Some(_) => return Err(invalid(READ_ONLY, "expected 1, 0, true or false")),
+            None => None,
+        };
+        Ok(EnvOverrides {
+            host: var(HOST).map(|host| host.trim().to_string()),
+            port,
+            monitoring_timer,
+            read_only,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Applies the overrides to a client built in code. A new host or port
+    /// closes the current connection, see `Client::set_endpoint`.
+    pub fn apply(&self, client: &mut Client) -> Result<(), Box<dyn Error>> {
+        if self.host.is_some() || self.port.is_some() {
+            let host = self
+                .host
+                .clone()
+                .unwrap_or_else(|| client.host().to_string());
+            let port = self.port.unwrap_or(client.port());
+            client.set_endpoint(&host, port);
+        }
+        if let Some(monitoring_timer) = self.monitoring_timer {
+            client.set_monitoring_timer(monitoring_timer)?;
+        }
+        if let Some(read_only) = self.read_only {
+            client.set_read_only(read_only);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_env {
+    use super::*;
+    use crate::db::DataType;
+    use crate::err;
+    use crate::simulator::Simulator;
+
+    fn lookup<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn test_from_lookup() -> Result<(), Box<dyn Error>> {
+        assert!(EnvOverrides::from_lookup(lookup(&[]))?.is_empty());
+        let overrides = EnvOverrides::from_lookup(lookup(&[
+            (HOST, "10.0.1.5"),
+            (PORT, " 5007"),
+            (MONITORING_TIMER_MS, "2500"),
+            (READ_ONLY, "true"),
+        ]))?;
+        assert_eq!(overrides.host.as_deref(), Some("10.0.1.5"));
+        assert_eq!(overrides.port, Some(5007));
+        assert_eq!(
+            overrides.monitoring_timer,
+            Some(Duration::from_millis(2500))
+        );
+        assert_eq!(overrides.read_only, Some(true));
+
+        let e = EnvOverrides::from_lookup(lookup(&[(PORT, "http")])).unwrap_err();
+        assert_eq!(e.key, PORT);
+        assert!(EnvOverrides::from_lookup(lookup(&[(READ_ONLY, "yes")])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_client() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("10.0.0.1".to_string(), 1, "Q", true);
+        let port = simulator.port().to_string();
+        EnvOverrides::from_lookup(lookup(&[
+            (HOST, "127.0.0.1"),
+            (PORT, &port),
+            (READ_ONLY, "1"),
+        ]))?
+        .apply(&mut client)?;
+        client.connect()?;
+        assert!(client.is_read_only());
+
+        let e = client
+            .batch_write("D0", vec![1], &DataType::SWORD)
+            .unwrap_err();
+        assert!(err::find::<err::ReadOnly>(&*e).is_some());
+        assert!(client.remote_stop().is_err());
+        assert_eq!(simulator.transactions(), 0);
+        client.batch_read("D0", 1, DataType::SWORD, true)?;
+        assert_eq!(simulator.transactions(), 1);
+        Ok(())
+    }
+}
@@ -1,45 +1,839 @@
+use std::error::Error;
 use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+
+use super::db::commands;
+use super::retry::TRANSIENT_CODES;
+
+// Finds the first error of type T in the source chain of `error`,
+// starting with `error` itself
+pub fn find<'a, T: Error + 'static>(mut error: &'a (dyn Error + 'static)) -> Option<&'a T> {
+    loop {
+        if let Some(found) = error.downcast_ref::<T>() {
+            return Some(found);
+        }
+        error = error.source()?;
+    }
+}
+
+// Error information section of an error response: the station that
+// detected the error and the request it refused
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorInformation {
+    pub network: u8,
+    pub pc: u8,
+    pub dest_moduleio: u16,
+    pub dest_modulesta: u8,
+    pub command: u16,
+    pub subcommand: u16,
+}
 
 #[derive(Debug)]
 pub struct MCError {
-    error_code: String,
+    code: u16,
+    info: Option<ErrorInformation>,
+}
+
+// Groups of completion codes, for deciding what to do about an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MCErrorKind {
+    // malformed request data or wrong communication data code
+    InvalidRequest,
+    // too many read or write points
+    PointCountOutOfRange,
+    // write to a RUN-state CPU with online change disabled
+    OnlineChangeDisabled,
+    // device address beyond the configured device range
+    AddressOutOfRange,
+    UnsupportedCommand,
+    DeviceNotAccessible,
+    NoMonitorRegistration,
+    // the CPU cannot execute the request right now
+    CannotExecute,
+    RemotePassword,
+    Unknown,
 }
 
 impl MCError {
     pub fn new(error_code: u16) -> MCError {
         Self {
-            error_code: format!("0x{:04x}", error_code),
+            code: error_code,
+            info: None,
         }
     }
+    pub fn with_info(mut self, info: ErrorInformation) -> MCError {
+        self.info = Some(info);
+        self
+    }
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+    pub fn info(&self) -> Option<&ErrorInformation> {
+        self.info.as_ref()
+    }
+    pub fn kind(&self) -> MCErrorKind {
+        match self.code {
+            0x0050 | 0xC058 | 0xC05C | 0xC060 | 0xC061 | 0xC06F | 0xC0B5 => {
+                MCErrorKind::InvalidRequest
+            }
+            0x0051..=0x0054 => MCErrorKind::PointCountOutOfRange,
+            0x0055 => MCErrorKind::OnlineChangeDisabled,
+            0xC056 => MCErrorKind::AddressOutOfRange,
+            0xC059 => MCErrorKind::UnsupportedCommand,
+            0xC05B | 0xC070 => MCErrorKind::DeviceNotAccessible,
+            0xC05D => MCErrorKind::NoMonitorRegistration,
+            0xC05F => MCErrorKind::CannotExecute,
+            0xC200 | 0xC201 | 0xC204 | 0xC810 => MCErrorKind::RemotePassword,
+            _ => MCErrorKind::Unknown,
+        }
+    }
+    /// Whether the same request may succeed when sent again, i.e. the code
+    /// is one of `retry::TRANSIENT_CODES`.
+    pub fn is_transient(&self) -> bool {
+        TRANSIENT_CODES.contains(&self.code)
+    }
+    /// Description in the language set with `set_locale`.
     pub fn description(&self) -> String {
-        match self.error_code.as_str() {
-            "0x0050" => "0x0050: When \"Communication Data Code\" is set to ASCII Code, ASCII code data that cannot be converted to binary were received.".to_string(),
-            "0x0051" | "0x0052" | "0x0053" | "0x0054" => "0x0051-0x0054: The number of read or write points is outside the allowable range.".to_string(),
-            "0x0055" => "0x0055: Although online change is disabled, the connected device requested the RUN-state CPU module for data writing.".to_string(),
-            "0xC056" => "0xC056: The read or write request exceeds the maximum address.".to_string(),
-            "0xC058" => "0xC058: The request data length after ASCII-to-binary conversion does not match the data size of the character area (a part of text data).".to_string(),
-            "0xC059" => "0xC059: The command and/or subcommand are specified incorrectly. The CPU module does not support the command and/or subcommand.".to_string(),
-            "0xC05B" => "0xC05B: The CPU module cannot read data from or write data to the specified device.".to_string(),
-            "0xC05C" => "0xC05C: The request data is incorrect. (e.g. reading or writing data in units of bits from or to a word device)".to_string(),
-            "0xC05D" => "0xC05D: No monitor registration.".to_string(),
-            "0xC05F" => "0xC05F: The request cannot be executed to the CPU module.".to_string(),
-            "0xC060" => "0xC060: The request data is incorrect. (ex. incorrect specification of data for bit devices)".to_string(),
-            "0xC061" => "0xC061: The request data length does not match the number of data in the character area (a part of text data).".to_string(),
-            "0xC06F" => "0xC06F: The CPU module received a request message in ASCII format when \"Communication Data Code is set to Binary Code, or received it in binary format when the setting is set to ASCII Code. (This error code is only registered to the error history, and no abnormal response is returned.)".to_string(),
-            "0xC070" => "0xC070: The device memory extension cannot be specified for the target station.".to_string(),
-            "0xC0B5" => "0xC0B5: The CPU module cannot handle the data specified.".to_string(),
-            "0xC200" => "0xC200: The remote password is incorrect.".to_string(),
-            "0xC201" => "0xC201: The port used for communication is locked with the remote password. Or, because of the remote password lock status with \"Communication Data Code\" set to ASCII Code, the subcommand and later part cannot be converted to a binary code.".to_string(),
-            "0xC204" => "0xC204: The connected device is different from the one that requested for unlock processing of the remote password.".to_string(),
-            _ => format!("{}: Unknown error code.", self.error_code),
+        self.description_in(locale())
+    }
+    pub fn description_in(&self, locale: Locale) -> String {
+        let table = match locale {
+            Locale::English => descriptions::ENGLISH,
+            Locale::Japanese => descriptions::JAPANESE,
+        };
+        match table
+            .iter()
+            .find(|(first, last, _)| (*first..=*last).contains(&self.code))
+        {
+            Some((first, last, text)) if first != last => {
+                format!("0x{:04X}-0x{:04X}: {}", first, last, text)
+            }
+            Some((_, _, text)) => format!("0x{:04X}: {}", self.code, text),
+            None => format!("0x{:04X}: {}", self.code, descriptions::unknown(locale)),
+        }
+    }
+    /// What to check for a completion code that usually points to a setup
+    /// mistake, in the language set with `set_locale`.
+    pub fn hint(&self) -> Option<&'static str> {
+        self.hint_in(locale())
+    }
+    pub fn hint_in(&self, locale: Locale) -> Option<&'static str> {
+        let table = match locale {
+            Locale::English => hints::ENGLISH,
+            Locale::Japanese => hints::JAPANESE,
+        };
+        table
+            .iter()
+            .find(|(codes, _)| codes.contains(&self.code))
+            .map(|(_, hint)| *hint)
+    }
+}
+
+// Language of MC error descriptions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    English,
+    Japanese,
+}
+
+static LOCALE: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the language of `MCError` descriptions for the whole process.
+pub fn set_locale(locale: Locale) {
+    LOCALE.store(locale as u8, Ordering::Relaxed);
+}
+
+pub fn locale() -> Locale {
+    match LOCALE.load(Ordering::Relaxed) {
+        1 => Locale::Japanese,
+        _ => Locale::English,
+    }
+}
+
+// Completion code descriptions per locale: first code, last code, text
+mod descriptions {
+    use super::Locale;
+
+    pub(super) const ENGLISH: &[(u16, u16, &str)] = &[
+        (0x0050, 0x0050, "When \"Communication Data Code\" is set to ASCII Code, ASCII code data that cannot be converted to binary were received."),
+        (0x0051, 0x0054, "The number of read or write points is outside the allowable range."),
+        (0x0055, 0x0055, "Although online change is disabled, the connected device requested the RUN-state CPU module for data writing."),
+        (0xC056, 0xC056, "The read or write request exceeds the maximum address."),
+        (0xC058, 0xC058, "The request data length after ASCII-to-binary conversion does not match the data size of the character area (a part of text data)."),
+        (0xC059, 0xC059, "The command and/or subcommand are specified incorrectly. The CPU module does not support the command and/or subcommand."),
+        (0xC05B, 0xC05B, "The CPU module cannot read data from or write data to the specified device."),
+        (0xC05C, 0xC05C, "The request data is incorrect. (e.g. reading or writing data in units of bits from or to a word device)"),
+        (0xC05D, 0xC05D, "No monitor registration."),
+        (0xC05F, 0xC05F, "The request cannot be executed to the CPU module."),
+        (0xC060, 0xC060, "The request data is incorrect. (ex. incorrect specification of data for bit devices)"),
+        (0xC061, 0xC061, "The request data length does not match the number of data in the character area (a part of text data)."),
+        (0xC06F, 0xC06F, "The CPU module received a request message in ASCII format when \"Communication Data Code is set to Binary Code, or received it in binary format when the setting is set to ASCII Code. (This error code is only registered to the error history, and no abnormal response is returned.)"),
+        (0xC070, 0xC070, "The device memory extension cannot be specified for the target station."),
+        (0xC0B5, 0xC0B5, "The CPU module cannot handle the data specified."),
+        (0xC200, 0xC200, "The remote password is incorrect."),
+        (0xC201, 0xC201, "The port used for communication is locked with the remote password. Or, because of the remote password lock status with \"Communication Data Code\" set to ASCII Code, the subcommand and later part cannot be converted to a binary code."),
+        (0xC204, 0xC204, "The connected device is different from the one that requested for unlock processing of the remote password."),
+        (0xC810, 0xC810, "Remote password authentication failed."),
+    ];
+
+    pub(super) const JAPANESE: &[(u16, u16, &str)] = &[
+        (0x0050, 0x0050, "交信データコードがASCIIコードに設定されているときに、バイナリに変換できないASCIIコードのデータを受信しました。"),
+        (0x0051, 0x0054, "読出し/書込み点数が許容範囲外です。"),
+        (0x0055, 0x0055, "RUN中書込みが禁止されているときに、RUN中のCPUユニットへデータの書込みが要求されました。"),
+        (0xC056, 0xC056, "最大アドレスを超えた読出し/書込み要求です。"),
+        (0xC058, 0xC058, "ASCII-バイナリ変換後の要求データ長が、キャラクタ部(テキストの一部)のデータ数と合いません。"),
+        (0xC059, 0xC059, "コマンド、サブコマンドの指定に誤りがあります。またはCPUユニットで使用できないコマンド、サブコマンドです。"),
+        (0xC05B, 0xC05B, "指定デバイスに対してCPUユニットが読出し/書込みできません。"),
+        (0xC05C, 0xC05C, "要求内容に誤りがあります。(ワードデバイスに対するビット単位の読出し/書込みなど)"),
+        (0xC05D, 0xC05D, "モニタ登録されていません。"),
+        (0xC05F, 0xC05F, "CPUユニットに対して実行できない要求です。"),
+        (0xC060, 0xC060, "要求内容に誤りがあります。(ビットデバイスに対するデータの指定誤りなど)"),
+        (0xC061, 0xC061, "要求データ長がキャラクタ部(テキストの一部)のデータ数と合いません。"),
+        (0xC06F, 0xC06F, "交信データコードがバイナリ設定のときにASCIIの要求電文を、またはASCII設定のときにバイナリの要求電文を受信しました。(エラー履歴にのみ登録され、異常応答は返りません)"),
+        (0xC070, 0xC070, "対象局に対してデバイスメモリの拡張指定ができません。"),
+        (0xC0B5, 0xC0B5, "CPUユニットで扱えないデータが指定されました。"),
+        (0xC200, 0xC200, "リモートパスワードが誤っています。"),
+        (0xC201, 0xC201, "交信に使用したポートがリモートパスワードでロックされています。または交信データコードがASCIIコードのとき、リモートパスワードのロック状態のためサブコマンド以降をバイナリに変換できません。"),
+        (0xC204, 0xC204, "リモートパスワードのアンロック処理を要求した相手機器と異なります。"),
+        (0xC810, 0xC810, "リモートパスワードの認証に失敗しました。"),
+    ];
+
+    pub(super) fn unknown(locale: Locale) -> &'static str {
+        match locale {
+            Locale::English => "Unknown error code.",
+            Locale::Japanese => "不明なエラーコードです。",
         }
     }
 }
 
 impl fmt::Display for MCError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.description())
+        write!(f, "{}", self.description())?;
+        if let Some(info) = self.info {
+            write!(
+                f,
+                " (network {}, station {:02X}h, module {:04X}h/{}, command {:04X}h/{:04X}h)",
+                info.network,
+                info.pc,
+                info.dest_moduleio,
+                info.dest_modulesta,
+                info.command,
+                info.subcommand
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for MCError {}
+
+// A write was refused by the client's write rate limiter
+#[derive(Debug)]
+pub struct Throttled {
+    pub device: Option<String>,
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.device {
+            Some(ref device) => write!(
+                f,
+                "Write to {} throttled, retry after {:?}",
+                device, self.retry_after
+            ),
+            None => write!(f, "Write throttled, retry after {:?}", self.retry_after),
+        }
+    }
+}
+
+impl Error for Throttled {}
+
+// The operation was aborted through its CancelToken
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Operation cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+// The operation could not complete before its deadline
+#[derive(Debug)]
+pub struct DeadlineExceeded;
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Deadline exceeded")
+    }
+}
+
+impl Error for DeadlineExceeded {}
+
+// A read response carried fewer points than were requested
+#[derive(Debug)]
+pub struct ShortRead {
+    pub requested: usize,
+    pub received: usize,
+}
+
+impl fmt::Display for ShortRead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Short read: {} points requested, {} received",
+            self.requested, self.received
+        )
+    }
+}
+
+impl Error for ShortRead {}
+
+// Some frames of a read sent as several frames failed. `failed` names the
+// device and size of each failed chunk; the error of the first one is the
+// source.
+#[derive(Debug)]
+pub struct PartialFailure {
+    pub chunks: usize,
+    pub failed: Vec<String>,
+    pub source: Box<dyn Error>,
+}
+
+impl fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} frames failed ({}): {}",
+            self.failed.len(),
+            self.chunks,
+            self.failed.join(", "),
+            self.source
+        )
+    }
+}
+
+impl Error for PartialFailure {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+// A request or response is larger than a limit set with `Client::set_limits`
+#[derive(Debug)]
+pub struct LimitExceeded {
+    pub limit: &'static str,
+    pub max: usize,
+    pub requested: usize,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} exceed the limit of {}",
+            self.requested, self.limit, self.max
+        )
+    }
+}
+
+impl Error for LimitExceeded {}
+
+// The PLC refused the remote password. Carries the completion code only,
+// never the password itself.
+#[derive(Debug)]
+pub struct PasswordRejected {
+    pub code: u16,
+}
+
+impl fmt::Display for PasswordRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Remote password rejected by the PLC (0x{:04X})",
+            self.code
+        )
+    }
+}
+
+impl Error for PasswordRejected {}
+
+// The peer accepted the TCP connection but did not answer the connect
+// check in MC protocol, e.g. another service or a mismatched frame type
+#[derive(Debug)]
+pub struct NotMcProtocol {
+    pub endpoint: String,
+    pub check: &'static str,
+    pub source: Box<dyn Error>,
+}
+
+impl fmt::Display for NotMcProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} does not answer the {} in MC protocol, check the port number and the frame type and data code settings: {}",
+            self.endpoint, self.check, self.source
+        )
+    }
+}
+
+impl Error for NotMcProtocol {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+// The CPU model read on connect names a CPU of another series than the
+// PLC type the client was created with
+#[derive(Debug)]
+pub struct PlcTypeMismatch {
+    pub configured: String,
+    pub model: String,
+    pub detected: &'static str,
+}
+
+impl fmt::Display for PlcTypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PLC type {} is configured, but the CPU is a {} of the {} series",
+            self.configured, self.model, self.detected
+        )
+    }
+}
+
+impl Error for PlcTypeMismatch {}
+
+// A remote operation or write was refused because an interlock condition
+// does not hold
+#[derive(Debug)]
+pub struct InterlockNotSatisfied {
+    pub device: String,
+    pub required: i64,
+    pub actual: i64,
+}
+
+impl fmt::Display for InterlockNotSatisfied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Interlock not satisfied: {} is {}, {} required",
+            self.device, self.actual, self.required
+        )
+    }
+}
+
+impl Error for InterlockNotSatisfied {}
+
+// The peer closed the connection, `received` bytes into a response
+#[derive(Debug)]
+pub struct ConnectionClosed {
+    pub received: usize,
+}
+
+impl fmt::Display for ConnectionClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Connection closed by peer after {} response bytes",
+            self.received
+        )
+    }
+}
+
+impl Error for ConnectionClosed {}
+
+// A read-modify-write found another value than it wrote when reading
+// the device back, so the PLC program changed it in the meantime
+#[derive(Debug)]
+pub struct Conflict {
+    pub device: String,
+    pub written: u16,
+    pub actual: u16,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Conflict on {}: wrote {}, read back {}",
+            self.device, self.written, self.actual
+        )
+    }
+}
+
+impl Error for Conflict {}
+
+// The PLC program did not set the acknowledge device of a handshake in time
+#[derive(Debug)]
+pub struct AckTimeout {
+    pub device: String,
+    pub waited: Duration,
+}
+
+impl fmt::Display for AckTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No acknowledge on {} after {:?}",
+            self.device, self.waited
+        )
+    }
+}
+
+impl Error for AckTimeout {}
+
+// A `ClientHandle` request did not fit in the request queue
+#[derive(Debug)]
+pub struct QueueFull {
+    pub capacity: usize,
+}
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Request queue is full ({} requests waiting)",
+            self.capacity
+        )
     }
 }
 
-impl std::error::Error for MCError {}
+impl Error for QueueFull {}
+
+// The thread behind a `ClientHandle` is gone, e.g. after a request panicked
+#[derive(Debug)]
+pub struct WorkerStopped;
+
+impl fmt::Display for WorkerStopped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Client thread has stopped")
+    }
+}
+
+impl Error for WorkerStopped {}
+
+// A strict client refused a command, subcommand or device that the CPUs
+// of its series do not have, see `Client::set_strict`
+#[derive(Debug)]
+pub struct NotSupported {
+    pub plc_type: &'static str,
+    // e.g. "Command 1630h (RemoteUnlock)" or "Device LZ0"
+    pub what: String,
+}
+
+impl fmt::Display for NotSupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is not supported by {} series CPUs",
+            self.what, self.plc_type
+        )
+    }
+}
+
+impl Error for NotSupported {}
+
+// A write command was refused because the client is read-only
+#[derive(Debug)]
+pub struct ReadOnly {
+    pub command: u16,
+}
+
+impl fmt::Display for ReadOnly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match commands::name(self.command) {
+            Some(name) => write!(f, "{} refused, the client is read-only", name),
+            None => write!(
+                f,
+                "Command 0x{:04X} refused, the client is read-only",
+                self.command
+            ),
+        }
+    }
+}
+
+impl Error for ReadOnly {}
+
+// A setting of a configuration file is missing or has the wrong value
+#[derive(Debug)]
+pub struct ConfigError {
+    // dotted path of the setting, e.g. `groups[0].interval_ms`
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid config setting {}: {}", self.key, self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+// An error of a request run on the thread behind a `ClientHandle`. The
+// original error cannot cross threads, so what callers act on is copied.
+#[derive(Debug, Clone)]
+pub struct WorkerError {
+    pub message: String,
+    // MC completion code anywhere in the source chain
+    pub mc_code: Option<u16>,
+    pub transient: bool,
+}
+
+impl WorkerError {
+    pub fn from_error(error: &(dyn Error + 'static)) -> WorkerError {
+        Self {
+            message: error.to_string(),
+            mc_code: find::<MCError>(error).map(MCError::code),
+            transient: is_transient(error),
+        }
+    }
+}
+
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for WorkerError {}
+
+/// Whether retrying the failed operation may succeed: IO timeouts, dropped
+/// connections, throttled writes and transient MC completion codes anywhere
+/// in the source chain. Deadlines and cancellations are final.
+pub fn is_transient(error: &(dyn Error + 'static)) -> bool {
+    let mut error = Some(error);
+    while let Some(e) = error {
+        if let Some(mc_error) = e.downcast_ref::<MCError>() {
+            return mc_error.is_transient();
+        }
+        if let Some(io_error) = e.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io_error.kind(),
+                std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::WouldBlock
+                    | std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::UnexpectedEof
+                    | std::io::ErrorKind::NotConnected
+            );
+        }
+        if let Some(worker_error) = e.downcast_ref::<WorkerError>() {
+            return worker_error.transient;
+        }
+        if e.is::<ConnectionClosed>() || e.is::<Throttled>() || e.is::<QueueFull>() {
+            return true;
+        }
+        if e.is::<DeadlineExceeded>() || e.is::<Cancelled>() {
+            return false;
+        }
+        error = e.source();
+    }
+    false
+}
+
+// A request exchange failed. Names the command, the devices it accessed,
+// the PLC host and the transaction; the underlying error is its source.
+#[derive(Debug)]
+pub struct MelsecError {
+    pub command: u16,
+    pub target: String,
+    pub host: String,
+    pub transaction_id: u64,
+    source: Box<dyn Error>,
+}
+
+impl MelsecError {
+    pub fn new(
+        command: u16,
+        target: &str,
+        host: &str,
+        transaction_id: u64,
+        source: Box<dyn Error>,
+    ) -> MelsecError {
+        Self {
+            command,
+            target: target.to_string(),
+            host: host.to_string(),
+            transaction_id,
+            source,
+        }
+    }
+    pub fn mc_error(&self) -> Option<&MCError> {
+        find::<MCError>(&*self.source)
+    }
+    pub fn is_transient(&self) -> bool {
+        is_transient(&*self.source)
+    }
+}
+
+impl fmt::Display for MelsecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match commands::name(self.command) {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "Command 0x{:04X}", self.command)?,
+        }
+        write!(
+            f,
+            " {} on {} failed (#{}): {}",
+            self.target, self.host, self.transaction_id, self.source
+        )
+    }
+}
+
+impl Error for MelsecError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+// Hints for frequent setup mistakes per locale: completion codes, hint
+mod hints {
+    pub(super) const ENGLISH: &[(&[u16], &str)] = &[
+        (&[0x0050, 0xC06F], "Check that the communication data code (binary or ASCII) of the Ethernet port matches the client's comm_type."),
+        (&[0xC059], "Check frame type 3E vs 4E and binary vs ASCII code settings of the Ethernet module, and that the PLC type matches the CPU series."),
+        (&[0x0051, 0x0052, 0x0053, 0x0054], "Read or write fewer points per request, e.g. 960 words for a batch access."),
+        (&[0x0055], "Enable online change (write during RUN) in the Ethernet port settings or put the CPU into STOP."),
+        (&[0xC056], "Check the device range in the CPU parameters; the device number is beyond it."),
+        (&[0xC05B], "The device type cannot be accessed over MC protocol on this CPU series; check the PLC type."),
+        (&[0xC05C], "Use word access for word devices; bit access only works on bit devices."),
+        (&[0xC200, 0xC810], "Check the remote password set in the CPU parameters."),
+        (&[0xC201], "Unlock the port with Client::remote_unlock before sending other requests."),
+    ];
+
+    pub(super) const JAPANESE: &[(&[u16], &str)] = &[
+        (&[0x0050, 0xC06F], "Ethernetポートの交信データコード(バイナリ/ASCII)とクライアントのcomm_typeが一致しているか確認してください。"),
+        (&[0xC059], "Ethernetユニットのフレーム(3E/4E)と交信データコード(バイナリ/ASCII)の設定、およびPLC種別がCPUシリーズと一致しているか確認してください。"),
+        (&[0x0051, 0x0052, 0x0053, 0x0054], "1回の要求の点数を減らしてください(一括アクセスは960ワードまで)。"),
+        (&[0x0055], "EthernetポートのRUN中書込みを許可するか、CPUをSTOPにしてください。"),
+        (&[0xC056], "CPUパラメータのデバイス点数を確認してください。デバイス番号が範囲外です。"),
+        (&[0xC05B], "このCPUシリーズではMCプロトコルでアクセスできないデバイスです。PLC種別を確認してください。"),
+        (&[0xC05C], "ワードデバイスにはワード単位でアクセスしてください。ビット単位のアクセスはビットデバイスのみです。"),
+        (&[0xC200, 0xC810], "CPUパラメータに設定したリモートパスワードを確認してください。"),
+        (&[0xC201], "他の要求の前にClient::remote_unlockでポートのロックを解除してください。"),
+    ];
+}
+
+#[cfg(test)]
+mod tests_err {
+    use super::*;
+
+    #[test]
+    fn test_mc_error() {
+        let error = MCError::new(0xC056);
+        assert_eq!(error.code(), 0xC056);
+        assert_eq!(error.kind(), MCErrorKind::AddressOutOfRange);
+        assert!(error
+            .to_string()
+            .starts_with("0xC056: The read or write request"));
+        assert_eq!(
+            MCError::new(0x0052).kind(),
+            MCErrorKind::PointCountOutOfRange
+        );
+        assert_eq!(MCError::new(0xC200).kind(), MCErrorKind::RemotePassword);
+        let unknown = MCError::new(0xCEEE);
+        assert_eq!(unknown.kind(), MCErrorKind::Unknown);
+        assert_eq!(unknown.to_string(), "0xCEEE: Unknown error code.");
+        assert_eq!(
+            MCError::new(0x0053).description(),
+            "0x0051-0x0054: The number of read or write points is outside the allowable range."
+        );
+    }
+
+    #[test]
+    fn test_locale() {
+        let error = MCError::new(0xC200);
+        assert_eq!(
+            error.description_in(Locale::Japanese),
+            "0xC200: リモートパスワードが誤っています。"
+        );
+        assert_eq!(
+            MCError::new(0xCEEE).description_in(Locale::Japanese),
+            "0xCEEE: 不明なエラーコードです。"
+        );
+        // every code has a description in every locale
+        for (english, japanese) in descriptions::ENGLISH.iter().zip(descriptions::JAPANESE) {
+            assert_eq!((english.0, english.1), (japanese.0, japanese.1));
+        }
+        assert_eq!(descriptions::ENGLISH.len(), descriptions::JAPANESE.len());
+        // and so does every code with a hint
+        for (codes, _) in hints::ENGLISH {
+            for code in codes.iter() {
+                assert!(!MCError::new(*code)
+                    .description_in(Locale::English)
+                    .contains("Unknown"));
+            }
+        }
+        assert_eq!(
+            MCError::new(0xC810).description_in(Locale::Japanese),
+            "0xC810: リモートパスワードの認証に失敗しました。"
+        );
+    }
+
+    #[test]
+    fn test_hint() {
+        let error = MCError::new(0xC059);
+        assert!(error
+            .hint_in(Locale::English)
+            .unwrap()
+            .starts_with("Check frame type 3E vs 4E"));
+        assert!(error.hint_in(Locale::Japanese).is_some());
+        assert!(MCError::new(0xC05F).hint_in(Locale::English).is_none());
+        for (english, japanese) in hints::ENGLISH.iter().zip(hints::JAPANESE) {
+            assert_eq!(english.0, japanese.0);
+        }
+        assert_eq!(hints::ENGLISH.len(), hints::JAPANESE.len());
+    }
+
+    #[test]
+    fn test_melsec_error_context() {
+        let error: Box<dyn Error> = Box::new(MelsecError::new(
+            commands::BATCH_READ,
+            "D5000-D5099",
+            "10.0.1.5",
+            7,
+            Box::new(MCError::new(0xC056)),
+        ));
+        assert!(error
+            .to_string()
+            .starts_with("BatchRead D5000-D5099 on 10.0.1.5 failed (#7): 0xC056: "));
+        assert_eq!(find::<MCError>(&*error).unwrap().code(), 0xC056);
+        assert!(find::<ShortRead>(&*error).is_none());
+
+        let error = MelsecError::new(0x9999, "D0", "plc", 1, Box::new(Cancelled));
+        assert_eq!(
+            error.to_string(),
+            "Command 0x9999 D0 on plc failed (#1): Operation cancelled"
+        );
+        assert!(error.mc_error().is_none());
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let wrap =
+            |source: Box<dyn Error>| MelsecError::new(commands::BATCH_READ, "D0", "plc", 1, source);
+        assert!(wrap(Box::new(MCError::new(0xC05F))).is_transient());
+        assert!(!wrap(Box::new(MCError::new(0xC056))).is_transient());
+        let reset = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        assert!(wrap(Box::new(reset)).is_transient());
+        let timeout = std::io::Error::new(std::io::ErrorKind::TimedOut, "timeout");
+        assert!(wrap(Box::new(timeout)).is_transient());
+        let denied = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        assert!(!wrap(Box::new(denied)).is_transient());
+        assert!(wrap(Box::new(ConnectionClosed { received: 0 })).is_transient());
+        assert!(!wrap(Box::new(DeadlineExceeded)).is_transient());
+        assert!(!wrap("Invalid device".into()).is_transient());
+        assert!(is_transient(&Throttled {
+            device: None,
+            retry_after: Duration::from_millis(1),
+        }));
+    }
+}
@@ -0,0 +1,157 @@
+use std::error::Error;
+
+use super::client::Client;
+
+/// Reads a sequence-of-events ring buffer the PLC program writes: records
+/// of `record_words` words in `capacity` slots starting at the buffer
+/// device, and a word holding the slot the next record goes to. Each poll
+/// reads only the records written since the last one, oldest first.
+///
+/// The PLC must not write more than `capacity` records between two polls,
+/// older ones are overwritten before they can be read.
+#[derive(Debug, Clone)]
+pub struct EventRing {
+    pointer_device: String,
+    buffer_device: String,
+    capacity: usize,
+    record_words: usize,
+    // slot read up to, `None` before the first poll
+    next_slot: Option<usize>,
+    received: u64,
+}
+
+impl EventRing {
+    pub fn new(
+        pointer_device: &str,
+        buffer_device: &str,
+        capacity: usize,
+        record_words: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        if capacity == 0 || record_words == 0 {
+            return Err("Event ring needs at least one slot of at least one word".into());
+        }
+        Ok(EventRing {
+            pointer_device: pointer_device.to_string(),
+            buffer_device: buffer_device.to_string(),
+            capacity,
+            record_words,
+            next_slot: None,
+            received: 0,
+        })
+    }
+
+    /// Starts reading at `slot` instead of at the write pointer found by
+    /// the first poll, e.g. to pick up where a previous run stopped.
+    pub fn resume_at(mut self, slot: usize) -> Self {
+        self.next_slot = Some(slot % self.capacity);
+        self
+    }
+
+    /// Slot the next poll starts reading at.
+    pub fn next_slot(&self) -> Option<usize> {
+        self.next_slot
+    }
+
+    /// Records delivered so far.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Reads the new records and decodes each with `decode`. The first poll
+    /// only takes note of the write pointer, unless `resume_at` was used.
+    pub fn poll<T, F>(&mut self, client: &Client, mut decode: F) -> Result<Vec<T>, Box<dyn Error>>
+    where
+        F: FnMut(&[u16]) -> Result<T, Box<dyn Error>>,
+    {
+        let mut pointer = [0];
+        client.batch_read_words_into(&self.pointer_device, &mut pointer)?;
+        let pointer = pointer[0] as usize;
+        if pointer >= self.capacity {
+            return Err(format!(
+                "Write pointer {} = {} is outside the {} slots of the ring",
+                self.pointer_device, pointer, self.capacity
+            )
+            .into());
+        }
+        let next_slot = match self.next_slot {
+            Some(slot) => slot,
+            None => {
+                self.next_slot = Some(pointer);
+                return Ok(Vec::new());
+            }
+        };
+
+        // up to the end of the buffer first when the pointer wrapped around
+        let ranges = if pointer >= next_slot {
+            vec![(next_slot, pointer)]
+        } else {
+            vec![(next_slot, self.capacity), (0, pointer)]
+        };
+        let mut words = Vec::new();
+        for (start, end) in ranges {
+            words.extend(self.read_slots(client, start, end)?);
+        }
+        let events = words
+            .chunks_exact(self.record_words)
+            .map(&mut decode)
+            .collect::<Result<Vec<T>, _>>()?;
+        self.next_slot = Some(pointer);
+        self.received += events.len() as u64;
+        Ok(events)
+    }
+
+    fn read_slots(
+        &self,
+        client: &Client,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<u16>, Box<dyn Error>> {
+        let device = client.offset_device(&self.buffer_device, start * self.record_words)?;
+        client
+            .read_words_partial(&device, (end - start) * self.record_words)?
+            .into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests_event_ring {
+    use super::*;
+    use crate::simulator::Simulator;
+
+    // two words per record: event code and value
+    fn write_record(simulator: &Simulator, slot: u16, code: u16, value: u16) {
+        simulator.set(&format!("D{}", 100 + slot * 2), code);
+        simulator.set(&format!("D{}", 101 + slot * 2), value);
+    }
+
+    #[test]
+    fn test_poll() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        let decode = |record: &[u16]| Ok((record[0], record[1]));
+
+        let mut ring = EventRing::new("D0", "D100", 4, 2)?;
+        simulator.set("D0", 1);
+        assert!(ring.poll(&client, decode)?.is_empty());
+        assert_eq!(ring.next_slot(), Some(1));
+
+        write_record(&simulator, 1, 10, 1);
+        write_record(&simulator, 2, 11, 2);
+        simulator.set("D0", 3);
+        assert_eq!(ring.poll(&client, decode)?, vec![(10, 1), (11, 2)]);
+        assert!(ring.poll(&client, decode)?.is_empty());
+
+        // wraps around the end of the buffer
+        write_record(&simulator, 3, 12, 3);
+        write_record(&simulator, 0, 13, 4);
+        write_record(&simulator, 1, 14, 5);
+        simulator.set("D0", 2);
+        assert_eq!(ring.poll(&client, decode)?, vec![(12, 3), (13, 4), (14, 5)]);
+        assert_eq!(ring.received(), 5);
+
+        simulator.set("D0", 4);
+        assert!(ring.poll(&client, decode).is_err());
+        Ok(())
+    }
+}
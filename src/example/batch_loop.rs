@@ -0,0 +1,29 @@
+use rs_melsec::client::Client;
+use rs_melsec::db::DataType;
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+// Reads D0-D9 once a second and reconnects whenever a read fails.
+// MELSEC_LOOPS limits the number of reads (default 10).
+pub fn run(client: &mut Client) -> Result<(), Box<dyn Error>> {
+    let loops = std::env::var("MELSEC_LOOPS")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(10);
+    for _ in 0..loops {
+        match client.batch_read("D0", 10, DataType::SWORD, true) {
+            Ok(tags) => {
+                let values: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+                println!("{}", values.join(" "));
+            }
+            Err(e) => {
+                eprintln!("read failed, reconnecting: {}", e);
+                client.close()?;
+                client.connect()?;
+            }
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+    Ok(())
+}
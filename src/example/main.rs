@@ -1,26 +1,77 @@
+mod batch_loop;
+mod mixed_read;
+mod poll_csv;
+mod remote;
+
 use rs_melsec::client::Client;
 use rs_melsec::db::DataType;
-use rs_melsec::tag::QueryTag;
+use rs_melsec::tag::{QueryTag, Tag, ValueFormat};
 use std::env;
+use std::error::Error;
 
+// Usage: example <host> [port] [read|batch-loop|mixed|remote|poll-csv]
+// MELSEC_PLC sets the PLC type (default iQ-R), MELSEC_ASCII=1 the ASCII mode,
+// MELSEC_FORMAT the value format of printed tags (dec, hex, bin, float:N).
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let host = args.get(1).expect("failed to get host");
+    let host = args
+        .get(1)
+        .cloned()
+        .or_else(|| env::var("MELSEC_HOST").ok())
+        .expect("failed to get host");
     let default_port = 6000;
     let num_port = args
         .get(2)
+        .cloned()
+        .or_else(|| env::var("MELSEC_PORT").ok())
         .and_then(|s| s.parse::<u16>().ok())
-        .or(Some(default_port))
-        .unwrap();
+        .unwrap_or(default_port);
+    let example = args.get(3).map(String::as_str).unwrap_or("read");
+
+    let plc_type = match env::var("MELSEC_PLC").as_deref() {
+        Ok("Q") => "Q",
+        Ok("L") => "L",
+        Ok("QnA") => "QnA",
+        Ok("iQ-L") => "iQ-L",
+        Ok("iQ-F") => "iQ-F",
+        _ => "iQ-R",
+    };
+    let mut client = Client::new(host, num_port, plc_type, true);
+    if env::var("MELSEC_ASCII").is_ok_and(|v| v == "1") {
+        client.set_comm_type("ascii");
+    }
+
+    let result = client.connect().and_then(|_| match example {
+        "read" => read(&client),
+        "batch-loop" => batch_loop::run(&mut client),
+        "mixed" => mixed_read::run(&client),
+        "remote" => remote::run(&client),
+        "poll-csv" => poll_csv::run(&client),
+        other => Err(format!("Unknown example: {}", other).into()),
+    });
+    if let Err(e) = result {
+        eprintln!("{} failed: {}", example, e);
+        std::process::exit(1);
+    }
+}
 
-    let mut tags = Vec::new();
-    tags.push(QueryTag {
+fn read(client: &Client) -> Result<(), Box<dyn Error>> {
+    let tags = vec![QueryTag {
         device: "M8304".to_string(),
         data_type: DataType::BIT,
-    });
-    let client = Client::new(host.to_string(), num_port, "iQ-R", true);
-    let result = client.read(tags).expect("failed to read data");
-    for tag in result {
-        println!("{}", tag);
+    }];
+    for tag in client.read(tags)? {
+        print_tag(tag);
     }
+    Ok(())
+}
+
+// Prints `tag` in the MELSEC_FORMAT value format, or the default format
+// of its device kind
+pub fn print_tag(tag: Tag) {
+    let format = env::var("MELSEC_FORMAT")
+        .ok()
+        .and_then(|format| ValueFormat::parse(&format))
+        .unwrap_or_else(|| ValueFormat::for_device(&tag.device));
+    println!("{}", tag.with_format(format));
 }
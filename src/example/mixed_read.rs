@@ -0,0 +1,24 @@
+use rs_melsec::client::Client;
+use rs_melsec::db::DataType;
+use rs_melsec::tag::QueryTag;
+use std::error::Error;
+
+// One random read of bit, word, dword and float devices
+pub fn run(client: &Client) -> Result<(), Box<dyn Error>> {
+    let tags = vec![
+        ("M100", DataType::BIT),
+        ("D100", DataType::SWORD),
+        ("D200", DataType::UDWORD),
+        ("D300", DataType::FLOAT),
+    ]
+    .into_iter()
+    .map(|(device, data_type)| QueryTag {
+        device: device.to_string(),
+        data_type,
+    })
+    .collect();
+    for tag in client.read(tags)? {
+        crate::print_tag(tag);
+    }
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use rs_melsec::cancel::CancelToken;
+use rs_melsec::client::Client;
+use rs_melsec::db::DataType;
+use rs_melsec::poller::Poller;
+use rs_melsec::sink::CsvSink;
+use rs_melsec::tag::QueryTag;
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+// Polls D0 and D1 every 500ms for ten seconds into MELSEC_CSV (default poll.csv)
+pub fn run(client: &Client) -> Result<(), Box<dyn Error>> {
+    let path = std::env::var("MELSEC_CSV").unwrap_or_else(|_| "poll.csv".to_string());
+    let tags = ["D0", "D1"]
+        .iter()
+        .map(|device| QueryTag {
+            device: device.to_string(),
+            data_type: DataType::SWORD,
+        })
+        .collect();
+    let mut poller = Poller::new(tags, Duration::from_millis(500));
+    poller.add_sink(Box::new(CsvSink::create(&path)?));
+
+    let token = CancelToken::new();
+    let stopper = token.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_secs(10));
+        stopper.cancel();
+    });
+    poller.run(client, &token)?;
+    println!("wrote {}", path);
+    Ok(())
+}
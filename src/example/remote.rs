@@ -0,0 +1,17 @@
+use rs_melsec::client::Client;
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+// Stops the CPU, waits five seconds and sets it back to RUN
+pub fn run(client: &Client) -> Result<(), Box<dyn Error>> {
+    client.remote_stop()?;
+    println!("CPU stopped");
+    thread::sleep(Duration::from_secs(5));
+    client.remote_run(false)?;
+    println!("CPU running");
+    for record in client.operation_log() {
+        println!("{} {}", record.operation, record.is_ok());
+    }
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::error::Error;
+
+use super::client::Client;
+use super::db::{commands, consts, subcommands, DataType};
+
+// bytes per file read request
+const FILE_READ_CHUNK: usize = 1920;
+const OPEN_MODE_READ: u64 = 0x0000;
+const CLOSE_NORMAL: u64 = 0x0000;
+
+impl Client {
+    fn file_subcommand(&self) -> u16 {
+        if self.plc_type == consts::IQR_SERIES {
+            subcommands::FILE_IQR
+        } else {
+            subcommands::ZERO
+        }
+    }
+
+    // Q/L take a fixed 4 character keyword, iQ-R a length prefixed password
+    fn push_file_password(&self, buffer: &mut Vec<u8>) {
+        let password = self.file_password().unwrap_or("");
+        if self.plc_type == consts::IQR_SERIES {
+            self.push_header_value(buffer, password.len() as u64, 2);
+            buffer.extend_from_slice(password.as_bytes());
+        } else {
+            buffer.extend_from_slice(format!("{:<4}", password).as_bytes());
+        }
+    }
+
+    // iQ-R file names are UTF-16, older series take the client's text
+    // encoding (usually Shift-JIS)
+    fn encode_file_name(&self, name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut buffer = Vec::new();
+        if self.plc_type == consts::IQR_SERIES {
+            let units: Vec<u16> = name.encode_utf16().collect();
+            self.push_header_value(&mut buffer, units.len() as u64, 2);
+            for unit in units {
+                self.push_header_value(&mut buffer, unit as u64, 2);
+            }
+        } else {
+            let encoded = self.text_encoding().encode(name)?;
+            self.push_header_value(&mut buffer, encoded.len() as u64, 2);
+            buffer.extend_from_slice(&encoded);
+        }
+        Ok(buffer)
+    }
+
+    fn file_request(
+        &self,
+        command: u16,
+        name: &str,
+        fill: impl FnOnce(&mut Vec<u8>),
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut request_data = self.build_command_data(command, self.file_subcommand())?;
+        fill(&mut request_data);
+        let send_data = self.build_send_data(&request_data)?;
+        self.exchange(&send_data, command, name)
+    }
+
+    /// Opens `name` on `drive` (0 program memory, 2 SD card, ...) for
+    /// reading and returns the file pointer number.
+    pub fn open_file(&self, drive: u16, name: &str) -> Result<u16, Box<dyn Error>> {
+        let file_name = self.encode_file_name(name)?;
+        let recv_data = self.file_request(commands::FILE_OPEN, name, |request_data| {
+            self.push_file_password(request_data);
+            self.push_header_value(request_data, OPEN_MODE_READ, 2);
+            self.push_header_value(request_data, drive as u64, 2);
+            request_data.extend_from_slice(&file_name);
+        })?;
+        let handle = self.response_payload(&recv_data, self.header_width(2))?;
+        Ok(self.decode_header_value(handle, &DataType::UWORD)? as u16)
+    }
+
+    /// Reads up to `length` bytes at `offset` of an open file.
+    pub fn read_file_chunk(
+        &self,
+        handle: u16,
+        offset: u32,
+        length: usize,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let length = length.min(FILE_READ_CHUNK);
+        let recv_data = self.file_request(commands::FILE_READ, "file", |request_data| {
+            self.push_header_value(request_data, handle as u64, 2);
+            self.push_header_value(request_data, offset as u64, 4);
+            self.push_header_value(request_data, length as u64, 2);
+        })?;
+        let count = self.response_payload(&recv_data, self.header_width(2))?;
+        let count = self.decode_header_value(count, &DataType::UWORD)? as usize;
+        let data = self.response_payload(&recv_data, self.header_width(2 + count))?;
+        let data = &data[self.header_width(2)..];
+        if self.comm_type == consts::COMMTYPE_BINARY {
+            Ok(data.to_vec())
+        } else {
+            Ok(hex::decode(data)?)
+        }
+    }
+
+    pub fn close_file(&self, handle: u16) -> Result<(), Box<dyn Error>> {
+        self.file_request(commands::FILE_CLOSE, "file", |request_data| {
+            self.push_header_value(request_data, handle as u64, 2);
+            self.push_header_value(request_data, CLOSE_NORMAL, 2);
+        })?;
+        Ok(())
+    }
+
+    /// Reads a whole file. The file is closed again even if a read fails.
+    pub fn read_file(&self, drive: u16, name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let handle = self.open_file(drive, name)?;
+        let mut contents = Vec::new();
+        let result = loop {
+            match self.read_file_chunk(handle, contents.len() as u32, FILE_READ_CHUNK) {
+                Ok(chunk) => {
+                    let is_last = chunk.len() < FILE_READ_CHUNK;
+                    contents.extend_from_slice(&chunk);
+                    if is_last {
+                        break Ok(());
+                    }
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        let closed = self.close_file(handle);
+        result?;
+        closed?;
+        Ok(contents)
+    }
+
+    // width on the wire of a `bytes` wide binary field
+    pub(crate) fn header_width(&self, bytes: usize) -> usize {
+        if self.comm_type == consts::COMMTYPE_BINARY {
+            bytes
+        } else {
+            bytes * 2
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::error::Error;
+
+use super::client::{format_device, Client, MAX_BATCH_WORDS};
+use super::db::{DataType, DeviceConstants};
+use super::partial::PartialRead;
+
+// R0..R32767 of one block
+pub const FILE_REGISTER_BLOCK_SIZE: u32 = 32768;
+
+/// ZR device of R`offset` in file register block `block`. ZR numbers the
+/// file registers of all blocks consecutively, so no block switching with
+/// RSET is needed to reach them.
+pub fn file_register_device(
+    plc_type: &str,
+    block: u32,
+    offset: u32,
+) -> Result<String, Box<dyn Error>> {
+    if offset >= FILE_REGISTER_BLOCK_SIZE {
+        return Err(format!(
+            "File register R{} is outside a block of {} registers",
+            offset, FILE_REGISTER_BLOCK_SIZE
+        )
+        .into());
+    }
+    let (_, base) = DeviceConstants::get_binary_device_code(plc_type, "ZR")?;
+    let number = block as i64 * FILE_REGISTER_BLOCK_SIZE as i64 + offset as i64;
+    Ok(format_device("ZR", number, base))
+}
+
+impl Client {
+    /// Reads `count` file registers from R`offset` of block `block` on,
+    /// continuing into the following blocks as needed. If a frame fails
+    /// the error is a `PartialFailure` naming the failed ranges.
+    pub fn read_file_registers(
+        &self,
+        block: u32,
+        offset: u32,
+        count: usize,
+    ) -> Result<Vec<u16>, Box<dyn Error>> {
+        self.read_file_registers_partial(block, offset, count)?
+            .into_result()
+    }
+
+    /// Same as `read_file_registers`, but reads every frame even if an
+    /// earlier one failed and reports the failed ones, see `PartialRead`.
+    pub fn read_file_registers_partial(
+        &self,
+        block: u32,
+        offset: u32,
+        count: usize,
+    ) -> Result<PartialRead, Box<dyn Error>> {
+        let device = file_register_device(self.plc_type, block, offset)?;
+        self.read_words_partial(&device, count)
+    }
+
+    /// Writes `values` to the file registers from R`offset` of block
+    /// `block` on, continuing into the following blocks as needed.
+    pub fn write_file_registers(
+        &self,
+        block: u32,
+        offset: u32,
+        values: &[u16],
+    ) -> Result<(), Box<dyn Error>> {
+        for (index, chunk) in values.chunks(MAX_BATCH_WORDS).enumerate() {
+            let device = self.file_register_at(block, offset, index * MAX_BATCH_WORDS)?;
+            let chunk = chunk.iter().map(|value| *value as i64).collect();
+            self.batch_write(&device, chunk, &DataType::UWORD)?;
+        }
+        Ok(())
+    }
+
+    // ZR device `skip` registers after R`offset` of block `block`
+    fn file_register_at(
+        &self,
+        block: u32,
+        offset: u32,
+        skip: usize,
+    ) -> Result<String, Box<dyn Error>> {
+        let linear = block as u64 * FILE_REGISTER_BLOCK_SIZE as u64 + offset as u64 + skip as u64;
+        let size = FILE_REGISTER_BLOCK_SIZE as u64;
+        file_register_device(
+            self.plc_type,
+            (linear / size) as u32,
+            (linear % size) as u32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests_file_register {
+    use super::*;
+    use crate::simulator::Simulator;
+
+    #[test]
+    fn test_across_blocks() -> Result<(), Box<dyn Error>> {
+        assert_eq!(file_register_device("Q", 1, 0)?, "ZR8000");
+        assert!(file_register_device("Q", 0, 32768).is_err());
+
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+
+        // the last register of block 2 and the first two of block 3
+        client.write_file_registers(2, 32767, &[7, 8, 9])?;
+        assert_eq!(simulator.get("ZR17FFF"), 7);
+        assert_eq!(simulator.get("ZR18001"), 9);
+        assert_eq!(client.read_file_registers(3, 0, 2)?, vec![8, 9]);
+
+        let values: Vec<u16> = (0..1000).collect();
+        client.write_file_registers(0, 32000, &values)?;
+        assert_eq!(client.read_file_registers(0, 32000, 1000)?, values);
+        assert_eq!(simulator.get("ZR8000"), 768);
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+
+use super::db::{commands, consts};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    Request,
+    Response,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameField {
+    pub name: &'static str,
+    pub value: String,
+}
+
+/// Field by field breakdown of an MC frame, e.g.
+/// `subheader=0x5000, net=00, pc=FF, io=03FF, station=00, len=000C, ...`.
+/// Numeric fields are shown in hex with the most significant digit first.
+/// A truncated frame ends with the fields that were complete.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotatedFrame {
+    pub fields: Vec<FrameField>,
+}
+
+impl AnnotatedFrame {
+    pub fn parse(frame: &[u8], kind: FrameKind, use_e4: bool, comm_type: &str) -> Self {
+        let is_ascii = comm_type == consts::COMMTYPE_ASCII;
+        let mut layout = vec![("subheader", 2)];
+        if use_e4 {
+            layout.extend([("serial", 2), ("reserved", 2)]);
+        }
+        layout.extend([("net", 1), ("pc", 1), ("io", 2), ("station", 1), ("len", 2)]);
+        match kind {
+            FrameKind::Request => layout.extend([("timer", 2), ("cmd", 2), ("sub", 2)]),
+            FrameKind::Response => layout.push(("end", 2)),
+        }
+
+        let mut fields = Vec::new();
+        let mut offset = 0;
+        for (name, width) in layout {
+            let width = if is_ascii { width * 2 } else { width };
+            let raw = match frame.get(offset..offset + width) {
+                Some(raw) => raw,
+                None => break,
+            };
+            let value = if is_ascii {
+                String::from_utf8_lossy(raw).to_string()
+            } else if name == "subheader" {
+                // the only big-endian field of a binary frame
+                raw.iter().map(|b| format!("{:02X}", b)).collect()
+            } else {
+                raw.iter().rev().map(|b| format!("{:02X}", b)).collect()
+            };
+            let value = match name {
+                "subheader" => format!("0x{}", value),
+                _ => value,
+            };
+            fields.push(FrameField { name, value });
+            offset += width;
+        }
+
+        if let Some(data) = frame.get(offset..).filter(|data| !data.is_empty()) {
+            let is_password = kind == FrameKind::Request
+                && fields.iter().any(|field| {
+                    field.name == "cmd"
+                        && [commands::REMOTE_UNLOCK, commands::REMOTE_LOCK]
+                            .iter()
+                            .any(|command| field.value == format!("{:04X}", command))
+                });
+            let value = if is_password {
+                // remote unlock/lock carry the password in clear text
+                "<redacted>".to_string()
+            } else if is_ascii {
+                String::from_utf8_lossy(data).to_string()
+            } else {
+                data.iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            fields.push(FrameField {
+                name: "data",
+                value,
+            });
+        }
+        Self { fields }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| field.value.as_str())
+    }
+}
+
+impl fmt::Display for AnnotatedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, field) in self.fields.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}", field.name, field.value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_frame {
+    use super::*;
+    use crate::test_fixtures::fixture;
+
+    #[test]
+    fn test_annotate_frames() {
+        let read = fixture("batch_read_words", false, consts::COMMTYPE_BINARY).unwrap();
+        let request = AnnotatedFrame::parse(
+            read.request,
+            FrameKind::Request,
+            false,
+            consts::COMMTYPE_BINARY,
+        );
+        assert_eq!(
+            request.to_string(),
+            "subheader=0x5000, net=00, pc=FF, io=03FF, station=00, len=000C, timer=0010, \
+             cmd=0401, sub=0000, data=64 00 00 A8 03 00"
+        );
+        let response = AnnotatedFrame::parse(
+            &read.response[..13],
+            FrameKind::Response,
+            false,
+            consts::COMMTYPE_BINARY,
+        );
+        assert_eq!(response.get("end"), Some("0000"));
+        assert_eq!(response.get("data"), Some("95 19"));
+
+        let read = fixture("batch_read_words", true, consts::COMMTYPE_ASCII).unwrap();
+        let request = AnnotatedFrame::parse(
+            read.request,
+            FrameKind::Request,
+            true,
+            consts::COMMTYPE_ASCII,
+        );
+        assert_eq!(request.get("subheader"), Some("0x5400"));
+        assert_eq!(request.get("serial"), Some("0000"));
+        assert_eq!(request.get("cmd"), Some("0401"));
+        assert_eq!(request.get("data"), Some("D*0001000003"));
+
+        let truncated =
+            AnnotatedFrame::parse(&[0x50, 0x00, 0x00], FrameKind::Request, false, "binary");
+        assert_eq!(truncated.to_string(), "subheader=0x5000, net=00");
+    }
+}
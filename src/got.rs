@@ -0,0 +1,60 @@
+use std::error::Error;
+
+use super::client::Client;
+
+/// Route to a PLC through the Ethernet transparent function of a GOT. The
+/// client connects to the GOT, which forwards MC frames to the CPU it is
+/// connected to, or on through the network to another station.
+#[derive(Debug, Clone)]
+pub struct GotTransparent {
+    host: String,
+    port: u16,
+    network: u8,
+    pc: u8,
+    dest_moduleio: u16,
+    dest_modulesta: u8,
+}
+
+impl GotTransparent {
+    /// Reaches the CPU connected to the GOT at `host`:`port`, the port
+    /// configured for transparent communication in the GOT.
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            network: 0,
+            pc: 0xFF,
+            dest_moduleio: 0x03FF,
+            dest_modulesta: 0,
+        }
+    }
+
+    /// Routes on to station `pc` of network `network` behind that CPU.
+    pub fn network_station(mut self, network: u8, pc: u8) -> Self {
+        self.network = network;
+        self.pc = pc;
+        self
+    }
+
+    /// Addresses a module, e.g. another CPU of a multiple CPU system (0x03E1..).
+    pub fn target_module(mut self, dest_moduleio: u16, dest_modulesta: u8) -> Self {
+        self.dest_moduleio = dest_moduleio;
+        self.dest_modulesta = dest_modulesta;
+        self
+    }
+
+    pub fn apply(&self, client: &mut Client) {
+        client.set_endpoint(&self.host, self.port);
+        client.network = self.network;
+        client.pc = self.pc;
+        client.dest_moduleio = self.dest_moduleio;
+        client.dest_modulesta = self.dest_modulesta;
+    }
+}
+
+impl Client {
+    pub fn connect_via_got(&mut self, got: &GotTransparent) -> Result<(), Box<dyn Error>> {
+        got.apply(self);
+        self.connect()
+    }
+}
@@ -0,0 +1,552 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+use super::client::Client;
+use super::db::DataType;
+use super::err::{QueueFull, WorkerError, WorkerStopped};
+use super::stats::QueueStats;
+use super::tag::{QueryTag, Tag};
+
+// requests waiting for the client thread when `spawn` is used
+const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+// Called with the client to run the request, or with None when the
+// request was dropped from the queue or the client thread stopped
+type Job = Box<dyn FnOnce(Option<&mut Client>) + Send>;
+
+// What a request does when the queue of a `ClientHandle` is full
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    // fail the new request with `QueueFull`
+    Reject,
+    // fail the oldest waiting request with `QueueFull` to make room
+    DropOldest,
+    // wait until the client thread takes a request off the queue
+    Block,
+}
+
+type Reply<T> = mpsc::SyncSender<Result<T, WorkerError>>;
+
+// Values of a queued batch write and everyone waiting for it. Writes to
+// the same range while it is queued replace the values and wait as well.
+struct PendingWrite {
+    values: Vec<i64>,
+    waiters: Vec<Reply<()>>,
+}
+
+struct QueueState {
+    jobs: VecDeque<Job>,
+    // keyed by device, point count and data type
+    pending_writes: HashMap<String, PendingWrite>,
+    closed: bool,
+    stats: QueueStats,
+}
+
+struct Queue {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    coalesce_writes: AtomicBool,
+    // signalled when a job is queued or the queue is closed
+    job_ready: Condvar,
+    // signalled when the client thread takes a job
+    space_ready: Condvar,
+}
+
+impl Queue {
+    fn push(&self, job: Job) -> Result<(), Box<dyn Error>> {
+        let state = self.state.lock().unwrap();
+        let (state, dropped) = self.make_room(state)?;
+        self.push_job(state, job, dropped);
+        Ok(())
+    }
+
+    // Merges the write into a queued write of the same range, or queues a
+    // new one built by `job`.
+    fn push_write(
+        &self,
+        key: String,
+        values: Vec<i64>,
+        reply: Reply<()>,
+        job: impl FnOnce() -> Job,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(pending) = state.pending_writes.get_mut(&key) {
+            pending.values = values;
+            pending.waiters.push(reply);
+            state.stats.coalesced += 1;
+            return Ok(());
+        }
+        let (mut state, dropped) = self.make_room(state)?;
+        let waiters = vec![reply];
+        state
+            .pending_writes
+            .insert(key, PendingWrite { values, waiters });
+        self.push_job(state, job(), dropped);
+        Ok(())
+    }
+
+    // Applies the overflow policy until a job fits. Dropped jobs are
+    // returned to be failed once the lock is released.
+    fn make_room<'a>(
+        &self,
+        mut state: MutexGuard<'a, QueueState>,
+    ) -> Result<(MutexGuard<'a, QueueState>, Vec<Job>), Box<dyn Error>> {
+        let mut dropped = Vec::new();
+        while state.jobs.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::Reject => {
+                    state.stats.rejected += 1;
+                    return Err(Box::new(QueueFull {
+                        capacity: self.capacity,
+                    }));
+                }
+                OverflowPolicy::DropOldest => {
+                    if let Some(oldest) = state.jobs.pop_front() {
+                        state.stats.dropped += 1;
+                        dropped.push(oldest);
+                    }
+                }
+                OverflowPolicy::Block => state = self.space_ready.wait(state).unwrap(),
+            }
+        }
+        if state.closed {
+            return Err(Box::new(WorkerStopped));
+        }
+        Ok((state, dropped))
+    }
+
+    fn push_job(&self, mut state: MutexGuard<QueueState>, job: Job, dropped: Vec<Job>) {
+        state.jobs.push_back(job);
+        state.stats.depth = state.jobs.len();
+        state.stats.max_depth = state.stats.max_depth.max(state.jobs.len());
+        self.job_ready.notify_one();
+        drop(state);
+        for job in dropped {
+            job(None);
+        }
+    }
+
+    // next job, or None once the queue is closed and drained
+    fn pop(&self) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                state.stats.depth = state.jobs.len();
+                self.space_ready.notify_one();
+                return Some(job);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.job_ready.wait(state).unwrap();
+        }
+    }
+
+    // Error for a job called with None
+    fn dropped_error(&self) -> WorkerError {
+        if self.state.lock().unwrap().closed {
+            WorkerError::from_error(&WorkerStopped)
+        } else {
+            WorkerError::from_error(&QueueFull {
+                capacity: self.capacity,
+            })
+        }
+    }
+
+    // Refuses further requests and fails those still queued
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        let jobs = std::mem::take(&mut state.jobs);
+        state.stats.depth = 0;
+        drop(state);
+        self.job_ready.notify_all();
+        self.space_ready.notify_all();
+        for job in jobs {
+            job(None);
+        }
+    }
+}
+
+// Closes the queue when the last handle is dropped
+struct Owner {
+    queue: Arc<Queue>,
+}
+
+impl Drop for Owner {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().closed = true;
+        self.queue.job_ready.notify_all();
+    }
+}
+
+// Closes the queue when the client thread exits, also by a panicking job,
+// so callers fail instead of waiting for a thread that is gone
+struct Worker {
+    queue: Arc<Queue>,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+/// Cheap, cloneable access to a client owned by a background thread. Every
+/// request is queued to that thread, so the socket is never shared and
+/// callers on any thread can use the handle without locking. The thread
+/// closes the connection and exits once the last handle is dropped.
+#[derive(Clone)]
+pub struct ClientHandle {
+    owner: Arc<Owner>,
+}
+
+impl Client {
+    /// Moves the client to a background thread and returns a handle to it.
+    /// Up to 1024 requests wait for the thread; further ones block.
+    pub fn spawn(self) -> ClientHandle {
+        self.spawn_with_queue(DEFAULT_QUEUE_CAPACITY, OverflowPolicy::Block)
+    }
+
+    /// Same as `spawn`, with at most `capacity` waiting requests and
+    /// `policy` deciding what happens to a request that does not fit, so a
+    /// stalled PLC cannot make the queue grow without bound.
+    pub fn spawn_with_queue(mut self, capacity: usize, policy: OverflowPolicy) -> ClientHandle {
+        let queue = Arc::new(Queue {
+            state: Mutex::new(QueueState {
+                jobs: VecDeque::new(),
+                pending_writes: HashMap::new(),
+                closed: false,
+                stats: QueueStats::default(),
+            }),
+            capacity: capacity.max(1),
+            policy,
+            coalesce_writes: AtomicBool::new(false),
+            job_ready: Condvar::new(),
+            space_ready: Condvar::new(),
+        });
+        let worker = Worker {
+            queue: Arc::clone(&queue),
+        };
+        thread::spawn(move || {
+            while let Some(job) = worker.queue.pop() {
+                job(Some(&mut self));
+            }
+            // every handle is gone, nothing can report a failure any more
+            let _ = self.close();
+        });
+        ClientHandle {
+            owner: Arc::new(Owner { queue }),
+        }
+    }
+}
+
+impl ClientHandle {
+    /// Runs `request` on the client thread and waits for its result. Errors
+    /// cross the thread as `WorkerError`, which keeps the message, the MC
+    /// completion code and whether the error is transient.
+    pub fn execute<T, F>(&self, request: F) -> Result<T, Box<dyn Error>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Client) -> Result<T, Box<dyn Error>> + Send + 'static,
+    {
+        let queue = Arc::clone(&self.owner.queue);
+        let (reply, result) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move |client| {
+            let result = match client {
+                Some(client) => request(client).map_err(|e| WorkerError::from_error(&*e)),
+                None => Err(queue.dropped_error()),
+            };
+            // the caller may have given up waiting
+            let _ = reply.send(result);
+        });
+        self.owner.queue.push(job)?;
+        wait_for_reply(result)
+    }
+
+    /// While enabled, a `batch_write` to a range that already has a write
+    /// waiting in the queue replaces the values of that write instead of
+    /// queueing another one, e.g. for setpoints changed faster than the
+    /// PLC answers. Every merged caller gets the result of the one write.
+    pub fn set_write_coalescing(&self, enable: bool) {
+        self.owner
+            .queue
+            .coalesce_writes
+            .store(enable, Ordering::SeqCst);
+    }
+
+    /// Current and highest queue depth and the requests lost to overflow.
+    pub fn queue_stats(&self) -> QueueStats {
+        self.owner.queue.state.lock().unwrap().stats.clone()
+    }
+
+    pub fn batch_read(
+        &self,
+        ref_device: &str,
+        read_size: usize,
+        data_type: DataType,
+    ) -> Result<Vec<Tag>, Box<dyn Error>> {
+        let ref_device = ref_device.to_string();
+        self.execute(move |client| client.batch_read(&ref_device, read_size, data_type, true))
+    }
+
+    pub fn batch_write(
+        &self,
+        ref_device: &str,
+        values: Vec<i64>,
+        data_type: DataType,
+    ) -> Result<(), Box<dyn Error>> {
+        let ref_device = ref_device.to_string();
+        let queue = &self.owner.queue;
+        if !queue.coalesce_writes.load(Ordering::SeqCst) {
+            return self.execute(move |client| client.batch_write(&ref_device, values, &data_type));
+        }
+
+        let key = format!(
+            "{}/{}/{}",
+            ref_device,
+            values.len(),
+            data_type.to_struct_type()
+        );
+        let (reply, result) = mpsc::sync_channel(1);
+        let job_queue = Arc::clone(queue);
+        let job_key = key.clone();
+        queue.push_write(key, values, reply, move || {
+            Box::new(move |client| {
+                let pending = job_queue
+                    .state
+                    .lock()
+                    .unwrap()
+                    .pending_writes
+                    .remove(&job_key);
+                let Some(pending) = pending else {
+                    return;
+                };
+                let result = match client {
+                    Some(client) => client
+                        .batch_write(&ref_device, pending.values, &data_type)
+                        .map_err(|e| WorkerError::from_error(&*e)),
+                    None => Err(job_queue.dropped_error()),
+                };
+                for waiter in pending.waiters {
+                    let _ = waiter.send(result.clone());
+                }
+            })
+        })?;
+        wait_for_reply(result)
+    }
+
+    pub fn read(&self, devices: Vec<QueryTag>) -> Result<Vec<Tag>, Box<dyn Error>> {
+        self.execute(move |client| client.read(devices))
+    }
+
+    pub fn write(&self, devices: Vec<Tag>) -> Result<(), Box<dyn Error>> {
+        self.execute(move |client| client.write(devices))
+    }
+}
+
+fn wait_for_reply<T>(result: mpsc::Receiver<Result<T, WorkerError>>) -> Result<T, Box<dyn Error>> {
+    match result.recv() {
+        Ok(result) => result.map_err(|e| e.into()),
+        Err(_) => Err(Box::new(WorkerStopped)),
+    }
+}
+
+#[cfg(test)]
+mod tests_handle {
+    use super::*;
+    use crate::simulator::{Fault, Simulator};
+
+    #[test]
+    fn test_client_handle() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        let handle = client.spawn();
+
+        let workers: Vec<_> = (0..4)
+            .map(|worker| {
+                let handle = handle.clone();
+                thread::spawn(move || -> Result<(), String> {
+                    let device = format!("D{}", worker * 10);
+                    handle
+                        .batch_write(&device, vec![worker], DataType::SWORD)
+                        .map_err(|e| e.to_string())?;
+                    let tags = handle
+                        .batch_read(&device, 1, DataType::SWORD)
+                        .map_err(|e| e.to_string())?;
+                    assert_eq!(tags[0].value, Some(worker.to_string()));
+                    Ok(())
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap()?;
+        }
+        assert_eq!(simulator.get("D30"), 3);
+
+        simulator.inject(Fault::CompletionCode(0xC05F));
+        let e = handle.batch_read("D0", 1, DataType::SWORD).unwrap_err();
+        let worker_error = e.downcast_ref::<WorkerError>().unwrap();
+        assert_eq!(worker_error.mc_code, Some(0xC05F));
+        assert!(worker_error.transient);
+        Ok(())
+    }
+
+    #[test]
+    fn test_queue_overflow() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        for policy in [OverflowPolicy::Reject, OverflowPolicy::DropOldest] {
+            let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+            client.connect()?;
+            let handle = client.spawn_with_queue(1, policy);
+            let read = |handle: &ClientHandle| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    handle
+                        .batch_read("D0", 1, DataType::SWORD)
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
+                })
+            };
+
+            // hold the client thread until the queue is full
+            let (release, blocked) = mpsc::channel::<()>();
+            let busy = {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    handle
+                        .execute(move |_| Ok(blocked.recv()?))
+                        .map_err(|e| e.to_string())
+                })
+            };
+            while handle.queue_stats().depth > 0 || handle.queue_stats().max_depth == 0 {
+                thread::yield_now();
+            }
+            let queued = read(&handle);
+            while handle.queue_stats().depth == 0 {
+                thread::yield_now();
+            }
+
+            if policy == OverflowPolicy::Reject {
+                let e = handle.batch_read("D0", 1, DataType::SWORD).unwrap_err();
+                assert!(e.is::<QueueFull>());
+                assert_eq!(handle.queue_stats().rejected, 1);
+                release.send(())?;
+                assert!(queued.join().unwrap().is_ok());
+            } else {
+                let overflow = read(&handle);
+                let e = queued.join().unwrap().unwrap_err();
+                assert!(e.contains("queue is full"));
+                assert_eq!(handle.queue_stats().dropped, 1);
+                release.send(())?;
+                assert!(overflow.join().unwrap().is_ok());
+            }
+            assert_eq!(handle.queue_stats().max_depth, 1);
+            busy.join().unwrap()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_coalescing() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        let handle = client.spawn();
+        handle.set_write_coalescing(true);
+
+        // hold the client thread while the setpoint changes
+        let (release, blocked) = mpsc::channel::<()>();
+        let busy = {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                handle
+                    .execute(move |_| Ok(blocked.recv()?))
+                    .map_err(|e| e.to_string())
+            })
+        };
+        while handle.queue_stats().max_depth == 0 || handle.queue_stats().depth > 0 {
+            thread::yield_now();
+        }
+        let writers: Vec<_> = (1..=5)
+            .map(|value| {
+                let writer_handle = handle.clone();
+                let writer = thread::spawn(move || {
+                    writer_handle
+                        .batch_write("D100", vec![value], DataType::SWORD)
+                        .map_err(|e| e.to_string())
+                });
+                // keep the writes in order
+                while handle.queue_stats().depth == 0
+                    || handle.queue_stats().coalesced < value as u64 - 1
+                {
+                    thread::yield_now();
+                }
+                writer
+            })
+            .collect();
+        let transactions = simulator.transactions();
+        release.send(())?;
+        busy.join().unwrap()?;
+        for writer in writers {
+            writer.join().unwrap()?;
+        }
+        assert_eq!(simulator.transactions(), transactions + 1);
+        assert_eq!(simulator.get("D100"), 5);
+        assert_eq!(handle.queue_stats().max_depth, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_panicking_request() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        let handle = client.spawn_with_queue(4, OverflowPolicy::Block);
+
+        // a read queued behind the panicking request fails with it
+        let (release, blocked) = mpsc::channel::<()>();
+        let panicking = {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                handle
+                    .execute(move |_| -> Result<(), Box<dyn Error>> {
+                        let _ = blocked.recv();
+                        panic!("request panicked")
+                    })
+                    .map_err(|e| e.to_string())
+            })
+        };
+        while handle.queue_stats().max_depth == 0 || handle.queue_stats().depth > 0 {
+            thread::yield_now();
+        }
+        let queued = {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                handle
+                    .batch_read("D0", 1, DataType::SWORD)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            })
+        };
+        while handle.queue_stats().depth == 0 {
+            thread::yield_now();
+        }
+        release.send(())?;
+        assert!(panicking
+            .join()
+            .unwrap()
+            .unwrap_err()
+            .contains("has stopped"));
+        assert!(queued.join().unwrap().unwrap_err().contains("has stopped"));
+
+        let e = handle.batch_read("D0", 1, DataType::SWORD).unwrap_err();
+        assert!(e.is::<WorkerStopped>());
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::client::Client;
+use super::db::DataType;
+use super::err::AckTimeout;
+
+/// Data-valid/acknowledge handshake for handing a block of data to the PLC
+/// program: write the data, set `trigger_device`, wait until the program
+/// sets `ack_device`, then reset `trigger_device` again.
+#[derive(Debug, Clone)]
+pub struct Handshake {
+    trigger_device: String,
+    ack_device: String,
+    timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl Handshake {
+    pub fn new(trigger_device: &str, ack_device: &str, timeout: Duration) -> Self {
+        Self {
+            trigger_device: trigger_device.to_string(),
+            ack_device: ack_device.to_string(),
+            timeout,
+            poll_interval: Duration::from_millis(20),
+        }
+    }
+
+    /// How often the acknowledge bit is read while waiting.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Runs the handshake for `values` written from `data_device` on. The
+    /// trigger bit is reset whether or not the acknowledge arrived, so a
+    /// failed handshake can be repeated as is.
+    pub fn write(
+        &self,
+        client: &Client,
+        data_device: &str,
+        values: Vec<i64>,
+        data_type: &DataType,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.is_acknowledged(client)? {
+            return Err(
+                format!("{} is still set from a previous handshake", self.ack_device).into(),
+            );
+        }
+        client.batch_write(data_device, values, data_type)?;
+        client.batch_write(&self.trigger_device, vec![1], &DataType::BIT)?;
+        let result = self.wait_for_ack(client);
+        let reset = client.batch_write(&self.trigger_device, vec![0], &DataType::BIT);
+        result.and(reset)
+    }
+
+    fn wait_for_ack(&self, client: &Client) -> Result<(), Box<dyn Error>> {
+        let started = Instant::now();
+        loop {
+            if self.is_acknowledged(client)? {
+                return Ok(());
+            }
+            if started.elapsed() >= self.timeout {
+                return Err(Box::new(AckTimeout {
+                    device: self.ack_device.clone(),
+                    waited: started.elapsed(),
+                }));
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+
+    fn is_acknowledged(&self, client: &Client) -> Result<bool, Box<dyn Error>> {
+        let mut ack = [false];
+        client.batch_read_bits_into(&self.ack_device, &mut ack)?;
+        Ok(ack[0])
+    }
+}
+
+#[cfg(test)]
+mod tests_handshake {
+    use super::*;
+    use crate::simulator::Simulator;
+
+    #[test]
+    fn test_handshake() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        let handshake = Handshake::new("M100", "M101", Duration::from_secs(2))
+            .poll_interval(Duration::from_millis(5));
+
+        // PLC program: copy D10 to D20 and acknowledge once triggered
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while simulator.get("M100") == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                simulator.set("D20", simulator.get("D10"));
+                simulator.set("M101", 1);
+            });
+            handshake.write(&client, "D10", vec![1234, 5678], &DataType::UWORD)
+        })?;
+        assert_eq!(simulator.get("D20"), 1234);
+        assert_eq!(simulator.get("M100"), 0);
+
+        // the acknowledge is still set, the next handshake must not start
+        assert!(handshake
+            .write(&client, "D10", vec![1], &DataType::UWORD)
+            .is_err());
+        assert_eq!(simulator.get("D10"), 1234);
+
+        simulator.set("M101", 0);
+        let handshake = Handshake::new("M100", "M101", Duration::from_millis(30))
+            .poll_interval(Duration::from_millis(5));
+        let e = handshake
+            .write(&client, "D10", vec![1], &DataType::UWORD)
+            .unwrap_err();
+        assert_eq!(e.downcast_ref::<AckTimeout>().unwrap().device, "M101");
+        assert_eq!(simulator.get("M100"), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::cancel::CancelToken;
+use super::client::Client;
+use super::db::DataType;
+use super::tag::QueryTag;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeartbeatMode {
+    // flip a bit device every beat
+    Toggle,
+    // add one to a word device every beat, wrapping at 0xFFFF
+    Increment,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeartbeatEvent {
+    // the response device has not changed for longer than the timeout
+    Lost { silent_for: Duration },
+    // the response device changed again after a loss
+    Restored,
+    // the host side write or response read failed
+    CommunicationError(String),
+}
+
+/// Host-PLC watchdog. Every beat writes the next value to `write_device` and
+/// reads `response_device`, which the PLC program is expected to change in
+/// reaction (echo the counter, toggle its own bit, ...).
+#[derive(Debug)]
+pub struct Heartbeat {
+    write_device: String,
+    response_device: String,
+    mode: HeartbeatMode,
+    interval: Duration,
+    timeout: Duration,
+    value: i64,
+    last_response: Option<String>,
+    last_change: Instant,
+    is_lost: bool,
+}
+
+impl Heartbeat {
+    pub fn new(
+        write_device: &str,
+        response_device: &str,
+        mode: HeartbeatMode,
+        interval: Duration,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            write_device: write_device.to_string(),
+            response_device: response_device.to_string(),
+            mode,
+            interval,
+            timeout,
+            value: 0,
+            last_response: None,
+            last_change: Instant::now(),
+            is_lost: false,
+        }
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.is_lost
+    }
+
+    fn data_type(&self) -> DataType {
+        match self.mode {
+            HeartbeatMode::Toggle => DataType::BIT,
+            HeartbeatMode::Increment => DataType::UWORD,
+        }
+    }
+
+    /// Performs one write/read exchange and returns an event if the
+    /// connection state changed.
+    pub fn beat(&mut self, client: &Client) -> Option<HeartbeatEvent> {
+        self.value = match self.mode {
+            HeartbeatMode::Toggle => 1 - self.value,
+            HeartbeatMode::Increment => (self.value + 1) & 0xFFFF,
+        };
+        if let Err(e) = client.batch_write(&self.write_device, vec![self.value], &self.data_type())
+        {
+            return Some(HeartbeatEvent::CommunicationError(e.to_string()));
+        }
+        let response = match client.read(vec![QueryTag {
+            device: self.response_device.clone(),
+            data_type: self.data_type(),
+        }]) {
+            Ok(tags) => tags.into_iter().next().and_then(|tag| tag.value),
+            Err(e) => return Some(HeartbeatEvent::CommunicationError(e.to_string())),
+        };
+        self.observe(response, Instant::now())
+    }
+
+    fn observe(&mut self, response: Option<String>, now: Instant) -> Option<HeartbeatEvent> {
+        if response != self.last_response {
+            self.last_response = response;
+            self.last_change = now;
+            if self.is_lost {
+                self.is_lost = false;
+                return Some(HeartbeatEvent::Restored);
+            }
+            return None;
+        }
+        let silent_for = now.duration_since(self.last_change);
+        if !self.is_lost && silent_for > self.timeout {
+            self.is_lost = true;
+            return Some(HeartbeatEvent::Lost { silent_for });
+        }
+        None
+    }
+
+    /// Beats every interval until `token` is cancelled, passing events to `on_event`.
+    pub fn run<F>(&mut self, client: &Client, token: &CancelToken, mut on_event: F)
+    where
+        F: FnMut(HeartbeatEvent),
+    {
+        self.last_change = Instant::now();
+        while !token.is_cancelled() {
+            let started = Instant::now();
+            if let Some(event) = self.beat(client) {
+                on_event(event);
+            }
+            if let Some(remaining) = self.interval.checked_sub(started.elapsed()) {
+                thread::sleep(remaining);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_heartbeat {
+    use super::*;
+
+    #[test]
+    fn test_lost_and_restored() {
+        let mut heartbeat = Heartbeat::new(
+            "D8000",
+            "D8001",
+            HeartbeatMode::Increment,
+            Duration::from_millis(100),
+            Duration::from_millis(300),
+        );
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        heartbeat.last_change = t0;
+
+        assert_eq!(heartbeat.observe(Some("1".to_string()), at(100)), None);
+        assert_eq!(heartbeat.observe(Some("1".to_string()), at(300)), None);
+        assert_eq!(
+            heartbeat.observe(Some("1".to_string()), at(500)),
+            Some(HeartbeatEvent::Lost {
+                silent_for: Duration::from_millis(400)
+            })
+        );
+        assert!(heartbeat.is_lost());
+        assert_eq!(heartbeat.observe(Some("1".to_string()), at(600)), None);
+        assert_eq!(
+            heartbeat.observe(Some("2".to_string()), at(700)),
+            Some(HeartbeatEvent::Restored)
+        );
+        assert!(!heartbeat.is_lost());
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use super::client::Client;
+use super::db::DataType;
+use super::err::InterlockNotSatisfied;
+use super::tag::QueryTag;
+
+// Device value that has to hold before a guarded operation is sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interlock {
+    pub device: String,
+    pub data_type: DataType,
+    pub value: i64,
+}
+
+/// Conditions read and verified before `remote_stop`, `remote_reset` and
+/// `batch_write`, `broadcast_write` or `write` calls covering a guarded
+/// device, e.g. that the
+/// line is stopped before the CPU is. The operation is refused with
+/// `InterlockNotSatisfied` if any of them does not hold.
+#[derive(Debug, Clone, Default)]
+pub struct Interlocks {
+    conditions: Vec<Interlock>,
+    guarded_devices: HashSet<String>,
+}
+
+impl Interlocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires `device`, read as `data_type`, to hold `value`.
+    pub fn require(mut self, device: &str, data_type: DataType, value: i64) -> Self {
+        self.conditions.push(Interlock {
+            device: device.to_string(),
+            data_type,
+            value,
+        });
+        self
+    }
+
+    /// Checks the conditions before writes that include `device`. Devices
+    /// are compared by point, so "X00A" guards X0A and "D0100" guards D100.
+    pub fn guard_device(mut self, device: &str) -> Self {
+        self.guarded_devices.insert(device.to_string());
+        self
+    }
+
+    pub fn conditions(&self) -> &[Interlock] {
+        &self.conditions
+    }
+
+    // `devices` are named as `Client::device_range` names them
+    fn guards(&self, client: &Client, devices: &[String]) -> bool {
+        self.guarded_devices.iter().any(|guarded| {
+            let guarded = client
+                .offset_device(guarded, 0)
+                .unwrap_or_else(|_| guarded.clone());
+            devices.contains(&guarded)
+        })
+    }
+}
+
+impl Client {
+    // Reads all conditions in one random read and fails on the first one
+    // that does not hold
+    pub(crate) fn check_interlocks(&self) -> Result<(), Box<dyn Error>> {
+        let interlocks = match self.interlocks() {
+            Some(interlocks) if !interlocks.conditions.is_empty() => interlocks,
+            _ => return Ok(()),
+        };
+        let tags = self.read(
+            interlocks
+                .conditions
+                .iter()
+                .map(|condition| QueryTag {
+                    device: condition.device.clone(),
+                    data_type: condition.data_type.clone(),
+                })
+                .collect(),
+        )?;
+        for (condition, tag) in interlocks.conditions.iter().zip(tags) {
+            let actual = tag.value.as_deref().unwrap_or_default();
+            let actual = actual
+                .parse::<i64>()
+                .map_err(|e| format!("Invalid value {:?} of {}: {}", actual, tag.device, e))?;
+            if actual != condition.value {
+                return Err(Box::new(InterlockNotSatisfied {
+                    device: condition.device.clone(),
+                    required: condition.value,
+                    actual,
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    // Checks the conditions if a write covers a guarded device. `ranges`
+    // are the start device and number of points of each written value.
+    pub(crate) fn check_write_interlocks(
+        &self,
+        ranges: &[(&str, usize)],
+    ) -> Result<(), Box<dyn Error>> {
+        let interlocks = match self.interlocks() {
+            Some(interlocks) => interlocks,
+            None => return Ok(()),
+        };
+        for (ref_device, points) in ranges {
+            if interlocks.guards(self, &self.device_range(ref_device, *points)?) {
+                return self.check_interlocks();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_interlock {
+    use super::*;
+    use crate::err;
+    use crate::simulator::Simulator;
+    use crate::tag::Tag;
+
+    #[test]
+    fn test_interlocks() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        // M0: line running, D0: operating mode 1 = maintenance
+        simulator.set("M0", 1);
+        client.set_interlocks(Some(
+            Interlocks::new()
+                .require("M0", DataType::BIT, 0)
+                .require("D0", DataType::UWORD, 1)
+                .guard_device("D0101")
+                .guard_device("X00A"),
+        ));
+
+        let e = client.remote_stop().unwrap_err();
+        let refused = err::find::<InterlockNotSatisfied>(&*e).unwrap();
+        assert_eq!((refused.device.as_str(), refused.actual), ("M0", 1));
+        let e = client
+            .batch_write("D100", vec![1, 2], &DataType::SWORD)
+            .unwrap_err();
+        assert!(err::find::<InterlockNotSatisfied>(&*e).is_some());
+        assert_eq!(simulator.get("D100"), 0);
+        // unguarded devices are written without checking
+        client.batch_write("D102", vec![3], &DataType::SWORD)?;
+        assert_eq!(simulator.get("D102"), 3);
+        let e = client
+            .batch_write("X8", vec![1, 1, 1], &DataType::BIT)
+            .unwrap_err();
+        assert!(err::find::<InterlockNotSatisfied>(&*e).is_some());
+        let transactions = simulator.transactions();
+        let e = client
+            .broadcast_write(1, "D101", vec![1], &DataType::SWORD)
+            .unwrap_err();
+        assert!(err::find::<InterlockNotSatisfied>(&*e).is_some());
+        // only the conditions were read
+        assert_eq!(simulator.transactions(), transactions + 1);
+
+        simulator.set("M0", 0);
+        simulator.set("D0", 1);
+        client.write(vec![Tag::new(
+            "D101".to_string(),
+            Some("7".to_string()),
+            DataType::SWORD,
+        )])?;
+        assert_eq!(simulator.get("D101"), 7);
+        Ok(())
+    }
+}
@@ -1,5 +1,48 @@
+pub mod alarm;
+pub mod cache;
+pub mod cancel;
 pub mod client;
+pub mod clock;
+pub mod commissioning;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod counter;
+pub mod data_logging;
 pub mod db;
 pub(crate) mod device_info;
-pub(crate) mod err;
+pub mod diagnostics;
+pub mod env;
+pub mod err;
+pub mod event_ring;
+pub mod file;
+pub mod file_register;
+pub mod frame;
+pub mod got;
+pub mod handle;
+pub mod handshake;
+pub mod heartbeat;
+pub mod interlock;
+pub mod limits;
+pub mod modify;
+pub mod operation_log;
+pub mod partial;
+pub mod poller;
+pub mod pool;
+pub mod rate_limit;
+pub mod redundant;
+pub mod remote;
+pub mod retry;
+pub mod session;
+#[cfg(any(test, feature = "test-fixtures"))]
+pub mod simulator;
+pub mod sink;
+pub mod snapshot;
+pub mod sockopt;
+pub mod sparkplug;
+pub mod stats;
 pub mod tag;
+pub mod tag_map;
+#[cfg(any(test, feature = "test-fixtures"))]
+pub mod test_fixtures;
+pub mod text;
+pub mod transaction;
@@ -0,0 +1,123 @@
+use super::err::LimitExceeded;
+
+/// Bounds on what a client allocates for a single request and for its
+/// cache, for gateways with little memory. Requests over a limit fail with
+/// `LimitExceeded` before anything is sent or allocated. All limits are
+/// off by default.
+///
+/// The request queue of a `ClientHandle` is bounded separately, see
+/// `ClientHandle::spawn_with_queue`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceLimits {
+    max_tags_per_request: Option<usize>,
+    max_response_bytes: Option<usize>,
+    max_cached_tags: Option<usize>,
+}
+
+impl ResourceLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Values of one batch read or write, or devices of one random read or
+    /// write.
+    pub fn max_tags_per_request(mut self, max: usize) -> Self {
+        self.max_tags_per_request = Some(max);
+        self
+    }
+
+    /// Bytes of one response frame, checked against the length announced
+    /// in its header before the data is read.
+    pub fn max_response_bytes(mut self, max: usize) -> Self {
+        self.max_response_bytes = Some(max);
+        self
+    }
+
+    /// Devices kept by the tag cache. Once full, the device updated least
+    /// recently makes room for a new one.
+    pub fn max_cached_tags(mut self, max: usize) -> Self {
+        self.max_cached_tags = Some(max);
+        self
+    }
+
+    pub fn cached_tags(&self) -> Option<usize> {
+        self.max_cached_tags
+    }
+
+    pub(crate) fn check_tags(&self, requested: usize) -> Result<(), LimitExceeded> {
+        check("tags per request", self.max_tags_per_request, requested)
+    }
+
+    pub(crate) fn check_response(&self, bytes: usize) -> Result<(), LimitExceeded> {
+        check("response bytes", self.max_response_bytes, bytes)
+    }
+}
+
+fn check(limit: &'static str, max: Option<usize>, requested: usize) -> Result<(), LimitExceeded> {
+    match max {
+        Some(max) if requested > max => Err(LimitExceeded {
+            limit,
+            max,
+            requested,
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests_limits {
+    use super::*;
+    use crate::client::Client;
+    use crate::db::DataType;
+    use crate::err;
+    use crate::simulator::Simulator;
+    use crate::tag::QueryTag;
+    use std::error::Error;
+    use std::time::Duration;
+
+    fn query(device: &str) -> QueryTag {
+        QueryTag {
+            device: device.to_string(),
+            data_type: DataType::SWORD,
+        }
+    }
+
+    #[test]
+    fn test_limits() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        client.enable_cache(Duration::from_secs(60));
+        client.set_limits(
+            ResourceLimits::new()
+                .max_tags_per_request(3)
+                .max_response_bytes(100)
+                .max_cached_tags(2),
+        );
+
+        let e = client
+            .batch_read("D0", 4, DataType::SWORD, true)
+            .unwrap_err();
+        let exceeded = err::find::<LimitExceeded>(&*e).unwrap();
+        assert_eq!((exceeded.max, exceeded.requested), (3, 4));
+        assert!(client
+            .read(vec![query("D0"), query("D5"), query("D9"), query("D12")])
+            .is_err());
+        assert!(client
+            .batch_write("D0", vec![1, 2, 3, 4], &DataType::SWORD)
+            .is_err());
+        assert_eq!(simulator.transactions(), 0);
+
+        // only the two devices read last stay cached
+        client.batch_read("D0", 3, DataType::SWORD, true)?;
+        assert!(client.get_cached("D0").is_none());
+        assert!(client.get_cached("D2").is_some());
+
+        client.set_limits(ResourceLimits::new().max_response_bytes(60));
+        let mut buf = [0u8; 200];
+        let e = client.batch_read_into("D0", 50, &mut buf).unwrap_err();
+        assert!(err::find::<LimitExceeded>(&*e).is_some());
+        assert!(!client.is_connected());
+        Ok(())
+    }
+}
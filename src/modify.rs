@@ -0,0 +1,79 @@
+use std::error::Error;
+
+use super::client::Client;
+use super::db::DataType;
+use super::err::Conflict;
+
+impl Client {
+    /// Reads the word at `device`, writes `modify(value)` back and returns
+    /// the written value. Anything the PLC program writes to the device in
+    /// between is overwritten; use `modify_word_checked` to notice that.
+    pub fn modify_word<F>(&self, device: &str, modify: F) -> Result<u16, Box<dyn Error>>
+    where
+        F: FnOnce(u16) -> u16,
+    {
+        self.read_modify_write(device, modify, false)
+    }
+
+    /// Same as `modify_word`, then reads the word again and returns
+    /// `Conflict` when it no longer holds the written value.
+    pub fn modify_word_checked<F>(&self, device: &str, modify: F) -> Result<u16, Box<dyn Error>>
+    where
+        F: FnOnce(u16) -> u16,
+    {
+        self.read_modify_write(device, modify, true)
+    }
+
+    fn read_modify_write<F>(
+        &self,
+        device: &str,
+        modify: F,
+        verify: bool,
+    ) -> Result<u16, Box<dyn Error>>
+    where
+        F: FnOnce(u16) -> u16,
+    {
+        let mut word = [0u16];
+        self.batch_read_words_into(device, &mut word)?;
+        let written = modify(word[0]);
+        self.batch_write(device, vec![written as i64], &DataType::UWORD)?;
+        if verify {
+            self.batch_read_words_into(device, &mut word)?;
+            if word[0] != written {
+                return Err(Box::new(Conflict {
+                    device: device.to_string(),
+                    written,
+                    actual: word[0],
+                }));
+            }
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests_modify {
+    use super::*;
+    use crate::simulator::{Generator, Simulator};
+
+    #[test]
+    fn test_modify_word() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        simulator.set("D100", 100);
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        assert_eq!(client.modify_word("D100", |value| value + 5)?, 105);
+        assert_eq!(simulator.get("D100"), 105);
+
+        // the PLC program counts D100 up before every frame, so the read
+        // sees 100 and the check after writing 200 sees 201
+        simulator.set("D100", 99);
+        simulator.generate("D100", Generator::Ramp { step: 1, max: 1000 })?;
+        let e = client
+            .modify_word_checked("D100", |value| value * 2)
+            .unwrap_err();
+        let conflict = e.downcast_ref::<Conflict>().unwrap();
+        assert_eq!((conflict.written, conflict.actual), (200, 201));
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_CAPACITY: usize = 1000;
+
+// One remote control or write action as sent to the PLC
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperationRecord {
+    pub timestamp: SystemTime,
+    // "remote_run", "remote_stop", "remote_reset", "batch_write", "write", ...
+    pub operation: &'static str,
+    // device range, device list or empty for CPU wide operations
+    pub target: String,
+    pub error: Option<String>,
+}
+
+impl OperationRecord {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Audit trail of the operations that change PLC state. Keeps the latest
+/// records in memory and, with a file attached, appends every record to
+/// it as `timestamp,operation,target,outcome` (timestamp in unix ms).
+#[derive(Debug)]
+pub struct OperationLog {
+    records: VecDeque<OperationRecord>,
+    capacity: usize,
+    file: Option<File>,
+}
+
+impl Default for OperationLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl OperationLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::new(),
+            capacity,
+            file: None,
+        }
+    }
+
+    /// Appends to `path`, writing the header line if the file is new.
+    pub fn attach_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn Error>> {
+        let is_new = !path.as_ref().exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if is_new {
+            writeln!(file, "timestamp,operation,target,outcome")?;
+        }
+        self.file = Some(file);
+        Ok(())
+    }
+
+    pub fn records(&self) -> Vec<OperationRecord> {
+        self.records.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub(crate) fn record(&mut self, record: OperationRecord) {
+        if let Some(ref mut file) = self.file {
+            let timestamp = record
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis())
+                .unwrap_or(0);
+            let outcome = match record.error {
+                Some(ref e) => format!("failed: {}", e.replace([',', '\n'], ";")),
+                None => "ok".to_string(),
+            };
+            // a full disk must not fail the operation that was already sent
+            let _ = writeln!(
+                file,
+                "{},{},{},{}",
+                timestamp, record.operation, record.target, outcome
+            );
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+}
+
+#[cfg(test)]
+mod tests_operation_log {
+    use super::*;
+
+    fn record(operation: &'static str, error: Option<&str>) -> OperationRecord {
+        OperationRecord {
+            timestamp: UNIX_EPOCH,
+            operation,
+            target: "D100-D101".to_string(),
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_capacity_and_file() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("operation_log_{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut log = OperationLog::new(2);
+        log.attach_file(&path)?;
+        log.record(record("remote_stop", None));
+        log.record(record("batch_write", Some("0xc056, out of range")));
+        log.record(record("remote_run", None));
+
+        let records = log.records();
+        assert_eq!(records.len(), 2);
+        assert!(!records[0].is_ok());
+        assert_eq!(records[1].operation, "remote_run");
+
+        let contents = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "timestamp,operation,target,outcome");
+        assert_eq!(
+            lines[2],
+            "0,batch_write,D100-D101,failed: 0xc056; out of range"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,369 @@
+use std::error::Error;
+
+use super::client::{get_device_type, Client, MAX_BATCH_WORDS};
+use super::db::DeviceConstants;
+use super::err::PartialFailure;
+use super::snapshot::{DeviceImage, MAX_BLOCKS, MAX_BLOCK_POINTS};
+
+// A frame of a multi-frame read that failed: `points` words read from
+// `device`, at `offset` in the values of the read
+#[derive(Debug)]
+pub struct FailedChunk {
+    pub device: String,
+    pub offset: usize,
+    pub points: usize,
+    pub error: Box<dyn Error>,
+}
+
+/// Words of a read split into several frames. A failed frame does not stop
+/// the others; its words stay zero and it is listed in `failed` with the
+/// device and size needed to read it again, see `retry`.
+///
+/// Block reads split into several frames report in the same way through
+/// `PartialSnapshot`, and writes sent as several frames per item through
+/// `Client::write_transaction` and `Client::retry_failed_writes`.
+#[derive(Debug)]
+pub struct PartialRead {
+    values: Vec<u16>,
+    chunks: usize,
+    failed: Vec<FailedChunk>,
+}
+
+impl PartialRead {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// All words of the read, zero where a chunk failed.
+    pub fn values(&self) -> &[u16] {
+        &self.values
+    }
+
+    /// Word `index` of the read, `None` if its chunk failed.
+    pub fn get(&self, index: usize) -> Option<u16> {
+        let failed = self
+            .failed
+            .iter()
+            .any(|chunk| (chunk.offset..chunk.offset + chunk.points).contains(&index));
+        if failed {
+            return None;
+        }
+        self.values.get(index).copied()
+    }
+
+    pub fn failed(&self) -> &[FailedChunk] {
+        &self.failed
+    }
+
+    /// Frames the read was split into.
+    pub fn chunks(&self) -> usize {
+        self.chunks
+    }
+
+    /// Reads the failed chunks again, keeping those that fail again.
+    /// Returns whether the read is complete now.
+    pub fn retry(&mut self, client: &Client) -> bool {
+        for chunk in std::mem::take(&mut self.failed) {
+            let out = &mut self.values[chunk.offset..chunk.offset + chunk.points];
+            if let Err(error) = client.batch_read_words_into(&chunk.device, out) {
+                self.failed.push(FailedChunk { error, ..chunk });
+            }
+        }
+        self.is_complete()
+    }
+
+    /// The values if every chunk was read, otherwise a `PartialFailure`
+    /// with the error of the first failed chunk as its source.
+    pub fn into_result(mut self) -> Result<Vec<u16>, Box<dyn Error>> {
+        if self.failed.is_empty() {
+            return Ok(self.values);
+        }
+        let devices = self
+            .failed
+            .iter()
+            .map(|chunk| format!("{}x{}", chunk.device, chunk.points))
+            .collect();
+        let first = self.failed.swap_remove(0);
+        Err(Box::new(PartialFailure {
+            chunks: self.chunks,
+            failed: devices,
+            source: first.error,
+        }))
+    }
+}
+
+// A frame of a multi-frame block read that failed, with the `(start device,
+// words)` ranges it carried
+#[derive(Debug)]
+pub struct FailedBlocks {
+    pub ranges: Vec<(String, usize)>,
+    pub error: Box<dyn Error>,
+}
+
+/// Ranges of a block read split into several frames, see
+/// `Client::snapshot_partial`. Devices of a failed frame are missing from
+/// the image until `retry` reads them.
+#[derive(Debug)]
+pub struct PartialSnapshot {
+    image: DeviceImage,
+    chunks: usize,
+    failed: Vec<FailedBlocks>,
+}
+
+impl PartialSnapshot {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Values of the frames read so far.
+    pub fn image(&self) -> &DeviceImage {
+        &self.image
+    }
+
+    pub fn failed(&self) -> &[FailedBlocks] {
+        &self.failed
+    }
+
+    /// Frames the read was split into.
+    pub fn chunks(&self) -> usize {
+        self.chunks
+    }
+
+    /// Reads the failed frames again, keeping those that fail again.
+    /// Returns whether the read is complete now.
+    pub fn retry(&mut self, client: &Client) -> bool {
+        for frame in std::mem::take(&mut self.failed) {
+            let ranges: Vec<(&str, usize)> = frame
+                .ranges
+                .iter()
+                .map(|(device, words)| (device.as_str(), *words))
+                .collect();
+            match client.snapshot(&ranges) {
+                Ok(image) => self.image.extend(image),
+                Err(error) => self.failed.push(FailedBlocks { error, ..frame }),
+            }
+        }
+        self.is_complete()
+    }
+
+    /// The image if every frame was read, otherwise a `PartialFailure`
+    /// with the error of the first failed frame as its source.
+    pub fn into_result(mut self) -> Result<DeviceImage, Box<dyn Error>> {
+        if self.failed.is_empty() {
+            return Ok(self.image);
+        }
+        let ranges = self
+            .failed
+            .iter()
+            .flat_map(|frame| frame.ranges.iter())
+            .map(|(device, words)| format!("{}x{}", device, words))
+            .collect();
+        let first = self.failed.swap_remove(0);
+        Err(Box::new(PartialFailure {
+            chunks: self.chunks,
+            failed: ranges,
+            source: first.error,
+        }))
+    }
+}
+
+impl Client {
+    /// Reads `count` words from `device` on in frames of at most 960 words,
+    /// sending every frame even if an earlier one failed. Only an invalid
+    /// device fails the read as a whole.
+    pub fn read_words_partial(
+        &self,
+        device: &str,
+        count: usize,
+    ) -> Result<PartialRead, Box<dyn Error>> {
+        let mut read = PartialRead {
+            values: vec![0; count],
+            chunks: count.div_ceil(MAX_BATCH_WORDS),
+            failed: Vec::new(),
+        };
+        let mut devices = Vec::with_capacity(read.chunks);
+        for index in 0..read.chunks {
+            devices.push(self.offset_device(device, index * MAX_BATCH_WORDS)?);
+        }
+        for (index, (chunk, device)) in read
+            .values
+            .chunks_mut(MAX_BATCH_WORDS)
+            .zip(devices)
+            .enumerate()
+        {
+            if let Err(error) = self.batch_read_words_into(&device, chunk) {
+                read.failed.push(FailedChunk {
+                    device,
+                    offset: index * MAX_BATCH_WORDS,
+                    points: chunk.len(),
+                    error,
+                });
+            }
+        }
+        Ok(read)
+    }
+
+    /// Same as `snapshot`, but takes any number of ranges of any length:
+    /// they are packed into as few multiple block batch reads as the
+    /// limits of one frame allow, splitting long ranges, and every frame
+    /// is sent even if an earlier one failed. Ranges read in different
+    /// frames may come from different PLC scans.
+    pub fn snapshot_partial(
+        &self,
+        ranges: &[(&str, usize)],
+    ) -> Result<PartialSnapshot, Box<dyn Error>> {
+        let mut frames: Vec<Vec<(String, usize)>> = Vec::new();
+        let mut frame_words = 0;
+        for &(device, words) in ranges {
+            let device_type = get_device_type(device)?;
+            let points_per_word = match self.device_kind(&device_type)? {
+                DeviceConstants::BIT_DEVICE => 16,
+                _ => 1,
+            };
+            let mut done = 0;
+            while done < words {
+                let full = frames.last().is_none_or(|frame| {
+                    frame.len() == MAX_BLOCKS || frame_words == MAX_BLOCK_POINTS
+                });
+                if full {
+                    frames.push(Vec::new());
+                    frame_words = 0;
+                }
+                let piece = (words - done).min(MAX_BLOCK_POINTS - frame_words);
+                let start = self.offset_device(device, done * points_per_word)?;
+                frames.last_mut().unwrap().push((start, piece));
+                frame_words += piece;
+                done += piece;
+            }
+        }
+
+        let mut snapshot = PartialSnapshot {
+            image: DeviceImage::default(),
+            chunks: frames.len(),
+            failed: Vec::new(),
+        };
+        for ranges in frames {
+            let blocks: Vec<(&str, usize)> = ranges
+                .iter()
+                .map(|(device, words)| (device.as_str(), *words))
+                .collect();
+            match self.snapshot(&blocks) {
+                Ok(image) => snapshot.image.extend(image),
+                Err(error) => snapshot.failed.push(FailedBlocks { ranges, error }),
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests_partial {
+    use super::*;
+    use crate::db::DataType;
+    use crate::err;
+    use crate::simulator::{Fault, Simulator};
+    use crate::transaction::WriteStatus;
+    use std::time::Duration;
+
+    #[test]
+    fn test_retry_failed_chunk() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        simulator.set("D0", 1);
+        simulator.set("D960", 2);
+        simulator.set("D1999", 3);
+
+        // the second of three frames is refused
+        simulator.inject(Fault::Delay(Duration::ZERO));
+        simulator.inject(Fault::CompletionCode(0xC051));
+        let mut read = client.read_words_partial("D0", 2000)?;
+        assert_eq!(read.chunks(), 3);
+        assert!(!read.is_complete());
+        assert_eq!(read.failed().len(), 1);
+        let failed = &read.failed()[0];
+        assert_eq!(
+            (failed.device.as_str(), failed.offset, failed.points),
+            ("D960", 960, 960)
+        );
+        assert_eq!(read.get(0), Some(1));
+        assert_eq!(read.get(960), None);
+        assert_eq!(read.get(1999), Some(3));
+
+        let transactions = simulator.transactions();
+        assert!(read.retry(&client));
+        assert_eq!(simulator.transactions(), transactions + 1);
+        let values = read.into_result()?;
+        assert_eq!((values[0], values[960], values[1999]), (1, 2, 3));
+
+        simulator.inject(Fault::CompletionCode(0xC051));
+        let e = client
+            .read_words_partial("D0", 100)?
+            .into_result()
+            .unwrap_err();
+        let failure = err::find::<PartialFailure>(&*e).unwrap();
+        assert_eq!(failure.failed, vec!["D0x100"]);
+        assert!(err::find::<err::MCError>(&*e).is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_failed_blocks() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        simulator.set("D0", 1);
+        simulator.set("D999", 2);
+        simulator.set("M20", 1);
+
+        // D0x960 in the first frame, D960x40 and M0x2 in the refused second
+        simulator.inject(Fault::Delay(Duration::ZERO));
+        simulator.inject(Fault::CompletionCode(0xC051));
+        let mut snapshot = client.snapshot_partial(&[("D0", 1000), ("M0", 2)])?;
+        assert_eq!(snapshot.chunks(), 2);
+        let failed: Vec<(&str, usize)> = snapshot.failed()[0]
+            .ranges
+            .iter()
+            .map(|(device, words)| (device.as_str(), *words))
+            .collect();
+        assert_eq!(failed, vec![("D960", 40), ("M0", 2)]);
+        assert_eq!(snapshot.image().get_u16("D0"), Some(1));
+        assert_eq!(snapshot.image().get_u16("D999"), None);
+        assert_eq!(snapshot.image().get_bit("M20"), None);
+
+        assert!(snapshot.retry(&client));
+        let image = snapshot.into_result()?;
+        assert_eq!(image.get_u16("D999"), Some(2));
+        assert_eq!(image.get_bit("M20"), Some(true));
+
+        // long bit ranges continue 16 bits per word further on
+        let snapshot = client.snapshot_partial(&[("M0", 961)])?;
+        assert_eq!(snapshot.failed().len(), 0);
+        assert_eq!(snapshot.image().get_bit("M15360"), Some(false));
+        assert_eq!(snapshot.image().get_bit("M15376"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_failed_writes() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+
+        // the first frame, the word write, is refused
+        simulator.inject(Fault::CompletionCode(0xC051));
+        let mut results = client
+            .write_transaction()
+            .write("D100", 5, DataType::SWORD)
+            .write("M10", 1, DataType::BIT)
+            .commit();
+        assert!(matches!(results[0].status, WriteStatus::Failed(_)));
+        assert!(results[1].is_success());
+
+        let transactions = simulator.transactions();
+        assert!(client.retry_failed_writes(&mut results));
+        assert_eq!(simulator.transactions(), transactions + 1);
+        assert_eq!((simulator.get("D100"), simulator.get("M10")), (5, 1));
+        Ok(())
+    }
+}
@@ -0,0 +1,416 @@
+use std::error::Error;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::cancel::CancelToken;
+use super::client::Client;
+use super::sink::Sink;
+use super::tag::{QueryTag, Tag};
+use super::tag_map::TagMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PollerEvent {
+    Paused,
+    Resumed,
+    // device of the added or removed tag
+    TagAdded(String),
+    TagRemoved(String),
+    IntervalChanged(Duration),
+    // a poll finished this long after the next one was due
+    Overrun(Duration),
+    // a poll run by a `Collector` failed with this error; polling goes on
+    PollFailed(String),
+}
+
+// The part of a poller that can be changed while it runs
+struct Schedule {
+    tags: Vec<QueryTag>,
+    interval: Duration,
+    paused: bool,
+    tag_map: Option<Arc<TagMap>>,
+    subscribers: Vec<mpsc::Sender<PollerEvent>>,
+    overruns: u64,
+}
+
+impl Schedule {
+    fn emit(&mut self, event: PollerEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+/// Changes the tags, interval and paused state of a running `Poller`.
+/// Changes apply from the next poll on. Clones control the same poller.
+#[derive(Clone)]
+pub struct PollerControl {
+    schedule: Arc<Mutex<Schedule>>,
+}
+
+impl PollerControl {
+    /// Polls stop until `resume`; the sinks are still flushed on exit.
+    pub fn pause(&self) {
+        let mut schedule = self.schedule.lock().unwrap();
+        if !schedule.paused {
+            schedule.paused = true;
+            schedule.emit(PollerEvent::Paused);
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut schedule = self.schedule.lock().unwrap();
+        if schedule.paused {
+            schedule.paused = false;
+            schedule.emit(PollerEvent::Resumed);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.schedule.lock().unwrap().paused
+    }
+
+    pub fn tags(&self) -> Vec<QueryTag> {
+        self.schedule.lock().unwrap().tags.clone()
+    }
+
+    /// Returns false if a tag of the same device is already polled.
+    pub fn add_tag(&self, tag: QueryTag) -> bool {
+        let mut schedule = self.schedule.lock().unwrap();
+        if schedule.tags.iter().any(|t| t.device == tag.device) {
+            return false;
+        }
+        let device = tag.device.clone();
+        schedule.tags.push(tag);
+        schedule.emit(PollerEvent::TagAdded(device));
+        true
+    }
+
+    /// Returns false if no tag of `device` is polled.
+    pub fn remove_tag(&self, device: &str) -> bool {
+        let mut schedule = self.schedule.lock().unwrap();
+        let count = schedule.tags.len();
+        schedule.tags.retain(|t| t.device != device);
+        if schedule.tags.len() == count {
+            return false;
+        }
+        schedule.emit(PollerEvent::TagRemoved(device.to_string()));
+        true
+    }
+
+    /// Swaps in the tags of `tag_map` and its metadata in one step, so no
+    /// poll sees a mix of the old and new list. Emits an event for every
+    /// device removed or added.
+    pub fn replace_tags(&self, tag_map: TagMap) {
+        let tags = tag_map_tags(&tag_map);
+        let mut schedule = self.schedule.lock().unwrap();
+        let old = std::mem::replace(&mut schedule.tags, tags);
+        schedule.tag_map = Some(Arc::new(tag_map));
+        let removed: Vec<String> = old
+            .iter()
+            .filter(|tag| !schedule.tags.iter().any(|t| t.device == tag.device))
+            .map(|tag| tag.device.clone())
+            .collect();
+        let added: Vec<String> = schedule
+            .tags
+            .iter()
+            .filter(|tag| !old.iter().any(|t| t.device == tag.device))
+            .map(|tag| tag.device.clone())
+            .collect();
+        for device in removed {
+            schedule.emit(PollerEvent::TagRemoved(device));
+        }
+        for device in added {
+            schedule.emit(PollerEvent::TagAdded(device));
+        }
+    }
+
+    pub fn set_interval(&self, interval: Duration) {
+        let mut schedule = self.schedule.lock().unwrap();
+        if schedule.interval != interval {
+            schedule.interval = interval;
+            schedule.emit(PollerEvent::IntervalChanged(interval));
+        }
+    }
+
+    /// Polls that finished after the next one was due, see `Poller::run`.
+    pub fn overruns(&self) -> u64 {
+        self.schedule.lock().unwrap().overruns
+    }
+
+    /// Receives every change made from now on, through any control.
+    pub fn subscribe(&self) -> mpsc::Receiver<PollerEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.schedule.lock().unwrap().subscribers.push(sender);
+        receiver
+    }
+}
+
+// Reads a tag list on an interval and hands each batch to the sinks
+pub struct Poller {
+    schedule: Arc<Mutex<Schedule>>,
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Poller {
+    pub fn new(tags: Vec<QueryTag>, interval: Duration) -> Self {
+        Self {
+            schedule: Arc::new(Mutex::new(Schedule {
+                tags,
+                interval,
+                paused: false,
+                tag_map: None,
+                subscribers: Vec::new(),
+                overruns: 0,
+            })),
+            sinks: Vec::new(),
+        }
+    }
+
+    /// Polls the tags of `tag_map` and passes its metadata on to the sinks.
+    pub fn from_tag_map(tag_map: TagMap, interval: Duration) -> Self {
+        let poller = Self::new(tag_map_tags(&tag_map), interval);
+        poller.schedule.lock().unwrap().tag_map = Some(Arc::new(tag_map));
+        poller
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn Sink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn control(&self) -> PollerControl {
+        PollerControl {
+            schedule: Arc::clone(&self.schedule),
+        }
+    }
+
+    /// Reads the current tag list, even while paused. An empty list is not
+    /// sent to the PLC.
+    pub fn poll_once(&mut self, client: &Client) -> Result<Vec<Tag>, Box<dyn Error>> {
+        let (query, tag_map) = {
+            let schedule = self.schedule.lock().unwrap();
+            (schedule.tags.clone(), schedule.tag_map.clone())
+        };
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut tags = client.read(query)?;
+        if let Some(tag_map) = tag_map {
+            tag_map.annotate(&mut tags);
+        }
+        for sink in self.sinks.iter_mut() {
+            sink.record(&tags)?;
+        }
+        Ok(tags)
+    }
+
+    /// Polls until `token` is cancelled, flushing the sinks before returning.
+    /// Polls start on a fixed grid of the interval, so a slow poll delays
+    /// only itself and the rate holds on average. A poll that runs past
+    /// the next start is reported as `PollerEvent::Overrun`; the poll that
+    /// is due then starts at once, and only starts that passed entirely
+    /// while it ran are skipped rather than made up in a burst.
+    pub fn run(&mut self, client: &Client, token: &CancelToken) -> Result<(), Box<dyn Error>> {
+        let mut interval = self.state().1;
+        let mut next_start = Instant::now();
+        while !token.is_cancelled() {
+            let (paused, current_interval) = self.state();
+            if current_interval != interval {
+                interval = current_interval;
+                next_start = Instant::now();
+            }
+            if !paused {
+                self.poll_once(client)?;
+            }
+            next_start += interval;
+            let now = Instant::now();
+            if interval.is_zero() {
+                next_start = now;
+            } else if now > next_start {
+                let late = now - next_start;
+                self.record_overrun(late);
+                let missed = late.as_nanos() / interval.as_nanos();
+                next_start += interval * missed as u32;
+            }
+            thread::sleep(next_start.saturating_duration_since(now));
+        }
+        self.flush()
+    }
+
+    #[cfg(feature = "config")]
+    pub(crate) fn record_failure(&self, error: &dyn Error) {
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.emit(PollerEvent::PollFailed(error.to_string()));
+    }
+
+    fn record_overrun(&self, late: Duration) {
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.overruns += 1;
+        schedule.emit(PollerEvent::Overrun(late));
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        for sink in self.sinks.iter_mut() {
+            sink.flush()?;
+        }
+        Ok(())
+    }
+
+    // paused flag and interval as currently set through the controls
+    pub(crate) fn state(&self) -> (bool, Duration) {
+        let schedule = self.schedule.lock().unwrap();
+        (schedule.paused, schedule.interval)
+    }
+}
+
+// tags of a map in name order
+fn tag_map_tags(tag_map: &TagMap) -> Vec<QueryTag> {
+    let mut names: Vec<&str> = tag_map.iter().map(|entry| entry.name.as_str()).collect();
+    names.sort();
+    names
+        .into_iter()
+        .filter_map(|name| tag_map.query_tag(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests_poller {
+    use super::*;
+    use crate::db::DataType;
+    use crate::simulator::{Fault, Simulator};
+
+    fn query(device: &str) -> QueryTag {
+        QueryTag {
+            device: device.to_string(),
+            data_type: DataType::SWORD,
+        }
+    }
+
+    #[test]
+    fn test_runtime_changes() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        simulator.set("D10", 7);
+        simulator.set("D11", 8);
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+
+        let mut poller = Poller::new(vec![query("D10")], Duration::from_millis(5));
+        let control = poller.control();
+        let events = control.subscribe();
+
+        assert!(control.add_tag(query("D11")));
+        assert!(!control.add_tag(query("D11")));
+        let tags = poller.poll_once(&client)?;
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[1].value.as_deref(), Some("8"));
+
+        assert!(control.remove_tag("D10"));
+        assert!(!control.remove_tag("D10"));
+        assert_eq!(poller.poll_once(&client)?.len(), 1);
+        control.remove_tag("D11");
+        assert!(poller.poll_once(&client)?.is_empty());
+
+        control.pause();
+        control.pause();
+        control.set_interval(Duration::from_millis(10));
+        control.resume();
+        let received: Vec<PollerEvent> = events.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                PollerEvent::TagAdded("D11".to_string()),
+                PollerEvent::TagRemoved("D10".to_string()),
+                PollerEvent::TagRemoved("D11".to_string()),
+                PollerEvent::Paused,
+                PollerEvent::IntervalChanged(Duration::from_millis(10)),
+                PollerEvent::Resumed,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_paused_run() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+
+        let mut poller = Poller::new(vec![query("D10")], Duration::from_millis(5));
+        let control = poller.control();
+        control.pause();
+        let token = CancelToken::new();
+        let transactions = simulator.transactions();
+        thread::scope(|scope| {
+            let canceller = token.clone();
+            scope.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                canceller.cancel();
+            });
+            poller.run(&client, &token)
+        })?;
+        assert_eq!(simulator.transactions(), transactions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_rate() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        // the second poll takes longer than two intervals
+        simulator.inject(Fault::Delay(Duration::ZERO));
+        simulator.inject(Fault::Delay(Duration::from_millis(70)));
+
+        let mut poller = Poller::new(vec![query("D10")], Duration::from_millis(30));
+        let control = poller.control();
+        let events = control.subscribe();
+        let token = CancelToken::new();
+        thread::scope(|scope| {
+            let canceller = token.clone();
+            scope.spawn(move || {
+                thread::sleep(Duration::from_millis(290));
+                canceller.cancel();
+            });
+            poller.run(&client, &token)
+        })?;
+
+        assert!(control.overruns() >= 1);
+        let late = events
+            .try_iter()
+            .find_map(|event| match event {
+                PollerEvent::Overrun(late) => Some(late),
+                _ => None,
+            })
+            .unwrap();
+        assert!(late >= Duration::from_millis(40));
+        // starts at 0, 30, at once at 100 after the overrun, then 120 .. 270
+        let polls = simulator.transactions();
+        assert!((6..=10).contains(&polls), "{} polls", polls);
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_overrun() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        // the first poll ends 30 ms after the second one was due
+        simulator.inject(Fault::Delay(Duration::from_millis(130)));
+
+        let mut poller = Poller::new(vec![query("D10")], Duration::from_millis(100));
+        let control = poller.control();
+        let token = CancelToken::new();
+        thread::scope(|scope| {
+            let canceller = token.clone();
+            scope.spawn(move || {
+                thread::sleep(Duration::from_millis(180));
+                canceller.cancel();
+            });
+            poller.run(&client, &token)
+        })?;
+
+        // the second poll was not skipped to 200 ms
+        assert_eq!(control.overruns(), 1);
+        assert_eq!(simulator.transactions(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use super::cancel::CancelToken;
+use super::client::Client;
+use super::err::Cancelled;
+
+// how often a queued request re-checks its cancel token
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+// consecutive failures after which a connection is taken out of rotation
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionHealth {
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    pub total_requests: u64,
+    pub total_failures: u64,
+    pub last_error: Option<String>,
+}
+
+// Lanes for requests waiting on a free connection. A request only starts
+// when no request of a higher priority is waiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    // operator-initiated writes
+    High = 0,
+    // HMI refreshes
+    Normal = 1,
+    // bulk scans and historian reads
+    Low = 2,
+}
+
+struct Lanes {
+    waiting: [usize; 3],
+    available: usize,
+}
+
+struct Connection {
+    client: Mutex<Client>,
+    health: Mutex<ConnectionHealth>,
+}
+
+// Several connections to the same CPU, used round-robin
+pub struct ClientPool {
+    connections: Vec<Connection>,
+    next: AtomicUsize,
+    lanes: Mutex<Lanes>,
+    lane_ready: Condvar,
+}
+
+impl ClientPool {
+    /// Wraps already configured clients. Each one is connected if it is not yet.
+    pub fn new(clients: Vec<Client>) -> Result<Self, Box<dyn Error>> {
+        if clients.is_empty() {
+            return Err("Client pool needs at least one connection".into());
+        }
+        let mut connections = Vec::new();
+        for mut client in clients {
+            if !client.is_connected() {
+                client.connect()?;
+            }
+            connections.push(Connection {
+                client: Mutex::new(client),
+                health: Mutex::new(ConnectionHealth {
+                    healthy: true,
+                    ..Default::default()
+                }),
+            });
+        }
+        Ok(Self {
+            lanes: Mutex::new(Lanes {
+                waiting: [0; 3],
+                available: connections.len(),
+            }),
+            lane_ready: Condvar::new(),
+            connections,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn connect(
+        host: &str,
+        port: u16,
+        plc_type: &'static str,
+        use_e4: bool,
+        size: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let clients = (0..size)
+            .map(|_| Client::new(host.to_string(), port, plc_type, use_e4))
+            .collect();
+        Self::new(clients)
+    }
+
+    pub fn len(&self) -> usize {
+        self.connections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+
+    pub fn health(&self) -> Vec<ConnectionHealth> {
+        self.connections
+            .iter()
+            .map(|connection| connection.health.lock().unwrap().clone())
+            .collect()
+    }
+
+    pub fn execute<T, F>(&self, operation: F) -> Result<T, Box<dyn Error>>
+    where
+        F: FnMut(&mut Client) -> Result<T, Box<dyn Error>>,
+    {
+        self.execute_with_priority(Priority::Normal, operation)
+    }
+
+    /// Runs `operation` on the next healthy connection once one is free and
+    /// no higher priority request is waiting. Unhealthy connections are only
+    /// used, with a reconnect attempt, when no healthy one is left.
+    pub fn execute_with_priority<T, F>(
+        &self,
+        priority: Priority,
+        operation: F,
+    ) -> Result<T, Box<dyn Error>>
+    where
+        F: FnMut(&mut Client) -> Result<T, Box<dyn Error>>,
+    {
+        self.acquire_lane(priority, None)?;
+        let result = self.run_on_connection(operation);
+        self.release_lane();
+        result
+    }
+
+    /// Like `execute_with_priority`, but gives up with `Cancelled` if `token`
+    /// is cancelled while the request is still queued. The operation itself
+    /// can check the token between frames of a multi-frame transfer.
+    pub fn execute_cancellable<T, F>(
+        &self,
+        priority: Priority,
+        token: &CancelToken,
+        operation: F,
+    ) -> Result<T, Box<dyn Error>>
+    where
+        F: FnMut(&mut Client) -> Result<T, Box<dyn Error>>,
+    {
+        self.acquire_lane(priority, Some(token))?;
+        let result = match token.check() {
+            Ok(()) => self.run_on_connection(operation),
+            Err(e) => Err(e.into()),
+        };
+        self.release_lane();
+        result
+    }
+
+    fn acquire_lane(
+        &self,
+        priority: Priority,
+        token: Option<&CancelToken>,
+    ) -> Result<(), Cancelled> {
+        let lane = priority as usize;
+        let mut lanes = self.lanes.lock().unwrap();
+        lanes.waiting[lane] += 1;
+        while lanes.available == 0 || lanes.waiting[..lane].iter().any(|w| *w > 0) {
+            if let Some(Err(e)) = token.map(|token| token.check()) {
+                lanes.waiting[lane] -= 1;
+                drop(lanes);
+                self.lane_ready.notify_all();
+                return Err(e);
+            }
+            lanes = self
+                .lane_ready
+                .wait_timeout(lanes, CANCEL_POLL_INTERVAL)
+                .unwrap()
+                .0;
+        }
+        lanes.waiting[lane] -= 1;
+        lanes.available -= 1;
+        Ok(())
+    }
+
+    fn release_lane(&self) {
+        self.lanes.lock().unwrap().available += 1;
+        self.lane_ready.notify_all();
+    }
+
+    fn run_on_connection<T, F>(&self, mut operation: F) -> Result<T, Box<dyn Error>>
+    where
+        F: FnMut(&mut Client) -> Result<T, Box<dyn Error>>,
+    {
+        let count = self.connections.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let order: Vec<usize> = (0..count).map(|i| (start + i) % count).collect();
+        let healthy: Vec<usize> = order
+            .iter()
+            .copied()
+            .filter(|index| self.connections[*index].health.lock().unwrap().healthy)
+            .collect();
+
+        let (index, mut client) = match healthy.iter().find_map(|index| {
+            match self.connections[*index].client.try_lock() {
+                Ok(client) => Some((*index, client)),
+                Err(_) => None,
+            }
+        }) {
+            Some(found) => found,
+            None => {
+                let index = healthy.first().copied().unwrap_or(order[0]);
+                (index, self.connections[index].client.lock().unwrap())
+            }
+        };
+
+        let connection = &self.connections[index];
+        if !connection.health.lock().unwrap().healthy {
+            client.close()?;
+            client.connect()?;
+        } else if !client.is_connected() {
+            // closed by the peer during an earlier request
+            client.connect()?;
+        }
+
+        let result = operation(&mut client);
+        let mut health = connection.health.lock().unwrap();
+        health.total_requests += 1;
+        match result {
+            Ok(_) => {
+                health.healthy = true;
+                health.consecutive_failures = 0;
+            }
+            Err(ref e) => {
+                health.total_failures += 1;
+                health.consecutive_failures += 1;
+                health.last_error = Some(e.to_string());
+                if health.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+                    health.healthy = false;
+                }
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::err::Throttled;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThrottlePolicy {
+    // fail immediately with `Throttled`
+    Reject,
+    // block until the write is allowed, failing if that takes longer than `max_wait`
+    Wait { max_wait: Duration },
+}
+
+// Minimum intervals between writes, overall and to the same device
+#[derive(Debug)]
+pub struct WriteRateLimiter {
+    global_interval: Option<Duration>,
+    device_interval: Option<Duration>,
+    policy: ThrottlePolicy,
+    last_write: Mutex<Option<Instant>>,
+    last_device_write: Mutex<HashMap<String, Instant>>,
+}
+
+impl WriteRateLimiter {
+    pub fn new(policy: ThrottlePolicy) -> Self {
+        Self {
+            global_interval: None,
+            device_interval: None,
+            policy,
+            last_write: Mutex::new(None),
+            last_device_write: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn global_interval(mut self, interval: Duration) -> Self {
+        self.global_interval = Some(interval);
+        self
+    }
+
+    pub fn device_interval(mut self, interval: Duration) -> Self {
+        self.device_interval = Some(interval);
+        self
+    }
+
+    pub fn acquire(&self, devices: &[&str]) -> Result<(), Throttled> {
+        let mut last_write = self.last_write.lock().unwrap();
+        let mut last_device_write = self.last_device_write.lock().unwrap();
+
+        let mut wait = Duration::ZERO;
+        let mut blocking_device = None;
+        if let (Some(interval), Some(last)) = (self.global_interval, *last_write) {
+            wait = interval.saturating_sub(last.elapsed());
+        }
+        if let Some(interval) = self.device_interval {
+            for device in devices {
+                if let Some(last) = last_device_write.get(*device) {
+                    let device_wait = interval.saturating_sub(last.elapsed());
+                    if device_wait > wait {
+                        wait = device_wait;
+                        blocking_device = Some(device.to_string());
+                    }
+                }
+            }
+        }
+
+        if !wait.is_zero() {
+            match self.policy {
+                ThrottlePolicy::Wait { max_wait } if wait <= max_wait => thread::sleep(wait),
+                _ => {
+                    return Err(Throttled {
+                        device: blocking_device,
+                        retry_after: wait,
+                    })
+                }
+            }
+        }
+
+        let now = Instant::now();
+        *last_write = Some(now);
+        for device in devices {
+            last_device_write.insert(device.to_string(), now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_rate_limit {
+    use super::*;
+
+    #[test]
+    fn test_reject() {
+        let limiter =
+            WriteRateLimiter::new(ThrottlePolicy::Reject).device_interval(Duration::from_secs(10));
+        assert!(limiter.acquire(&["D100"]).is_ok());
+        assert!(limiter.acquire(&["D101"]).is_ok());
+        let throttled = limiter.acquire(&["D101", "D102"]).unwrap_err();
+        assert_eq!(throttled.device, Some("D101".to_string()));
+        assert!(throttled.retry_after > Duration::from_secs(9));
+    }
+
+    #[test]
+    fn test_wait() {
+        let limiter = WriteRateLimiter::new(ThrottlePolicy::Wait {
+            max_wait: Duration::from_millis(100),
+        })
+        .global_interval(Duration::from_millis(20));
+        let start = Instant::now();
+        assert!(limiter.acquire(&["D100"]).is_ok());
+        assert!(limiter.acquire(&["D200"]).is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let limiter = WriteRateLimiter::new(ThrottlePolicy::Wait {
+            max_wait: Duration::from_millis(10),
+        })
+        .global_interval(Duration::from_secs(1));
+        assert!(limiter.acquire(&["D100"]).is_ok());
+        let throttled = limiter.acquire(&["D200"]).unwrap_err();
+        assert_eq!(throttled.device, None);
+    }
+}
@@ -0,0 +1,160 @@
+use std::error::Error;
+
+use super::client::Client;
+
+// Request destination I/O numbers of a redundant Q/R system
+const IO_CONTROL_SYSTEM: u16 = 0x03D0;
+const IO_STANDBY_SYSTEM: u16 = 0x03D1;
+const IO_SYSTEM_A: u16 = 0x03D2;
+const IO_SYSTEM_B: u16 = 0x03D3;
+const IO_CONNECTED_CPU: u16 = 0x03FF;
+
+// SM1511 system A, SM1512 system B, SM1515 control system, SM1516 standby system
+const STATUS_RELAYS: &str = "SM1511";
+const STATUS_RELAY_COUNT: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedundantTarget {
+    // the CPU the request arrives at, whatever its role
+    Connected,
+    ControlSystem,
+    StandbySystem,
+    SystemA,
+    SystemB,
+}
+
+impl RedundantTarget {
+    pub fn io_number(&self) -> u16 {
+        match self {
+            RedundantTarget::Connected => IO_CONNECTED_CPU,
+            RedundantTarget::ControlSystem => IO_CONTROL_SYSTEM,
+            RedundantTarget::StandbySystem => IO_STANDBY_SYSTEM,
+            RedundantTarget::SystemA => IO_SYSTEM_A,
+            RedundantTarget::SystemB => IO_SYSTEM_B,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedundantSystem {
+    A,
+    B,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RedundantStatus {
+    pub system: RedundantSystem,
+    pub is_control: bool,
+}
+
+impl RedundantStatus {
+    fn from_relays(relays: &[bool]) -> Result<Self, Box<dyn Error>> {
+        let system = match (relays[0], relays[1]) {
+            (true, false) => RedundantSystem::A,
+            (false, true) => RedundantSystem::B,
+            _ => return Err("CPU is not part of a redundant system".into()),
+        };
+        Ok(Self {
+            system,
+            is_control: relays[4] && !relays[5],
+        })
+    }
+}
+
+impl Client {
+    /// Addresses the following requests to a CPU of a redundant system
+    /// relative to the connected one.
+    pub fn set_redundant_target(&mut self, target: RedundantTarget) {
+        self.dest_moduleio = target.io_number();
+    }
+
+    /// Reads which system the addressed CPU is and whether it currently
+    /// is the control system.
+    pub fn redundant_status(&self) -> Result<RedundantStatus, Box<dyn Error>> {
+        let mut relays = [false; STATUS_RELAY_COUNT];
+        self.batch_read_bits_into(STATUS_RELAYS, &mut relays)?;
+        RedundantStatus::from_relays(&relays)
+    }
+}
+
+/// Keeps a client connected to the control system of a redundant pair.
+/// Each `check` reads the connected CPU's status and reconnects to the
+/// other CPU when the connected one became standby or stopped answering.
+#[derive(Debug, Clone)]
+pub struct RedundantFailover {
+    endpoints: [(String, u16); 2],
+    active: usize,
+    last_status: Option<RedundantStatus>,
+}
+
+impl RedundantFailover {
+    pub fn new(system_a: (&str, u16), system_b: (&str, u16)) -> Self {
+        Self {
+            endpoints: [
+                (system_a.0.to_string(), system_a.1),
+                (system_b.0.to_string(), system_b.1),
+            ],
+            active: 0,
+            last_status: None,
+        }
+    }
+
+    pub fn active_endpoint(&self) -> (&str, u16) {
+        let (host, port) = &self.endpoints[self.active];
+        (host, *port)
+    }
+
+    pub fn last_status(&self) -> Option<RedundantStatus> {
+        self.last_status
+    }
+
+    /// Connects the client to whichever CPU is the control system.
+    pub fn connect(&mut self, client: &mut Client) -> Result<RedundantStatus, Box<dyn Error>> {
+        let mut last_error = None;
+        for _ in 0..self.endpoints.len() {
+            match self.try_endpoint(client) {
+                Ok(status) if status.is_control => return Ok(status),
+                Ok(_) => last_error = Some("Connected CPU is the standby system".into()),
+                Err(e) => last_error = Some(e),
+            }
+            self.active = 1 - self.active;
+        }
+        Err(last_error.unwrap_or_else(|| "No control system found".into()))
+    }
+
+    /// Returns true if the client was moved to the other CPU.
+    pub fn check(&mut self, client: &mut Client) -> Result<bool, Box<dyn Error>> {
+        match connected_status(client) {
+            Ok(status) if status.is_control => {
+                self.last_status = Some(status);
+                Ok(false)
+            }
+            _ => {
+                let previous = self.active;
+                self.active = 1 - self.active;
+                self.connect(client)?;
+                Ok(self.active != previous)
+            }
+        }
+    }
+
+    fn try_endpoint(&mut self, client: &mut Client) -> Result<RedundantStatus, Box<dyn Error>> {
+        let (host, port) = self.endpoints[self.active].clone();
+        if !client.is_connected() || client.host() != host || client.port() != port {
+            client.set_endpoint(&host, port);
+            client.connect()?;
+        }
+        let status = connected_status(client)?;
+        self.last_status = Some(status);
+        Ok(status)
+    }
+}
+
+// status of the CPU the socket is connected to, regardless of the request target
+fn connected_status(client: &mut Client) -> Result<RedundantStatus, Box<dyn Error>> {
+    let dest_moduleio = client.dest_moduleio;
+    client.set_redundant_target(RedundantTarget::Connected);
+    let status = client.redundant_status();
+    client.dest_moduleio = dest_moduleio;
+    status
+}
@@ -0,0 +1,219 @@
+use std::error::Error;
+use std::time::Duration;
+
+use super::client::Client;
+use log::warn;
+
+use super::db::{commands, subcommands, DataType, PlcType};
+use super::err;
+
+// remote RUN: execute even if another device holds a remote STOP/PAUSE
+const RUN_FORCED: u64 = 0x0003;
+const RUN_NOT_FORCED: u64 = 0x0001;
+// remote RUN: leave the device memory as it is
+const CLEAR_NONE: u64 = 0x00;
+const REMOTE_FIXED: u64 = 0x0001;
+// model name field of the CPU model read response, padded with spaces
+const CPU_MODEL_NAME_LENGTH: usize = 16;
+// loopback data has to be made of '0'-'9' and 'A'-'F'
+const LOOPBACK_DATA: &[u8] = b"5A5A";
+// kept short so a probe in the wrong format gives up quickly
+const PROBE_MONITORING_TIMER: Duration = Duration::from_millis(250);
+// (use_e4, comm_type) in the order `auto_detect` tries them
+const PROBES: [(bool, &str); 4] = [
+    (false, "binary"),
+    (false, "ascii"),
+    (true, "binary"),
+    (true, "ascii"),
+];
+
+/// Request sent by `connect()` to make sure the peer speaks MC protocol
+/// with the configured frame type and data code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectCheck {
+    // loopback test (0619h), answered by the Ethernet port itself
+    Loopback,
+    // CPU model read (0101h), warns if the model is of another series than
+    // the PLC type
+    CpuModel,
+    // CPU model read, fails with `err::PlcTypeMismatch` if the model is of
+    // another series than the PLC type
+    PlcSeries,
+}
+
+impl ConnectCheck {
+    fn name(&self) -> &'static str {
+        match self {
+            ConnectCheck::Loopback => "loopback test",
+            ConnectCheck::CpuModel | ConnectCheck::PlcSeries => "CPU model read",
+        }
+    }
+}
+
+impl Client {
+    /// Connects with each frame type and data code in turn, 3E binary, 3E
+    /// ASCII, 4E binary and 4E ASCII, until the PLC answers a CPU model
+    /// read, and returns that connected client. 1E frames are not tried,
+    /// the client does not speak them.
+    pub fn auto_detect(
+        host: &str,
+        port: u16,
+        plc_type: &'static str,
+    ) -> Result<Client, Box<dyn Error>> {
+        let mut failures = Vec::new();
+        for (use_e4, comm_type) in PROBES {
+            let mut client = Client::new(host.to_string(), port, plc_type, use_e4);
+            client.set_comm_type(comm_type);
+            let monitoring_timer = client.monitoring_timer();
+            client.set_monitoring_timer(PROBE_MONITORING_TIMER)?;
+            client.set_connect_check(Some(ConnectCheck::CpuModel));
+            match client.connect() {
+                Ok(()) => {
+                    client.set_connect_check(None);
+                    client.set_monitoring_timer(monitoring_timer)?;
+                    return Ok(client);
+                }
+                Err(e) => failures.push(format!(
+                    "{} {}: {}",
+                    if use_e4 { "4E" } else { "3E" },
+                    comm_type,
+                    e
+                )),
+            }
+        }
+        Err(format!(
+            "No MC protocol answer from {}:{} ({})",
+            host,
+            port,
+            failures.join("; ")
+        )
+        .into())
+    }
+
+    pub fn remote_run(&self, force: bool) -> Result<(), Box<dyn Error>> {
+        let result = self.remote_command(commands::REMOTE_RUN, |client, request_data| {
+            let mode = if force { RUN_FORCED } else { RUN_NOT_FORCED };
+            client.push_header_value(request_data, mode, 2);
+            client.push_header_value(request_data, CLEAR_NONE, 1);
+            client.push_header_value(request_data, 0, 1);
+        });
+        self.log_operation("remote_run", "", &result);
+        result
+    }
+
+    pub fn remote_stop(&self) -> Result<(), Box<dyn Error>> {
+        let result = self.check_interlocks().and_then(|_| {
+            self.remote_command(commands::REMOTE_STOP, |client, request_data| {
+                client.push_header_value(request_data, REMOTE_FIXED, 2);
+            })
+        });
+        self.log_operation("remote_stop", "", &result);
+        result
+    }
+
+    pub fn remote_reset(&self) -> Result<(), Box<dyn Error>> {
+        let result = self.check_interlocks().and_then(|_| {
+            self.remote_command(commands::REMOTE_RESET, |client, request_data| {
+                client.push_header_value(request_data, REMOTE_FIXED, 2);
+            })
+        });
+        self.log_operation("remote_reset", "", &result);
+        result
+    }
+
+    /// Model name and model code of the CPU, e.g. ("Q03UDVCPU", 0x0366).
+    pub fn read_cpu_model(&self) -> Result<(String, u16), Box<dyn Error>> {
+        let request_data = self.build_command_data(commands::READ_CPU_MODEL, subcommands::ZERO)?;
+        let send_data = self.build_send_data(&request_data)?;
+        let recv_data = self.exchange(&send_data, commands::READ_CPU_MODEL, "cpu")?;
+        // the name is ASCII text in both modes, the code a header value
+        let code_width = self.header_width(2);
+        let payload = self.response_payload(&recv_data, CPU_MODEL_NAME_LENGTH + code_width)?;
+        let name = String::from_utf8_lossy(&payload[..CPU_MODEL_NAME_LENGTH])
+            .trim_end()
+            .to_string();
+        let code = self.decode_header_value(&payload[CPU_MODEL_NAME_LENGTH..], &DataType::UWORD)?;
+        Ok((name, code as u16))
+    }
+
+    /// Sends a fixed pattern the connected station has to echo back.
+    pub fn loopback_test(&self) -> Result<(), Box<dyn Error>> {
+        let mut request_data =
+            self.build_command_data(commands::LOOPBACK_TEST, subcommands::ZERO)?;
+        self.push_header_value(&mut request_data, LOOPBACK_DATA.len() as u64, 2);
+        request_data.extend_from_slice(LOOPBACK_DATA);
+        let send_data = self.build_send_data(&request_data)?;
+        let recv_data = self.exchange(&send_data, commands::LOOPBACK_TEST, "loopback")?;
+        let length_width = self.header_width(2);
+        let payload = self.response_payload(&recv_data, length_width + LOOPBACK_DATA.len())?;
+        if &payload[length_width..] != LOOPBACK_DATA {
+            return Err(format!(
+                "Loopback test answered {:?} instead of {:?}",
+                String::from_utf8_lossy(&payload[length_width..]),
+                String::from_utf8_lossy(LOOPBACK_DATA)
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    // An MC completion code still proves the peer speaks MC protocol, e.g.
+    // a CPU that does not support the command; anything else does not.
+    pub(crate) fn run_connect_check(&self, check: ConnectCheck) -> Result<(), Box<dyn Error>> {
+        let result = match check {
+            ConnectCheck::Loopback => self.loopback_test().map(|_| None),
+            ConnectCheck::CpuModel | ConnectCheck::PlcSeries => {
+                self.read_cpu_model().map(|(model, _)| Some(model))
+            }
+        };
+        let model = match result {
+            Ok(model) => model,
+            Err(e) if err::find::<err::MCError>(&*e).is_some() => None,
+            Err(e) => {
+                return Err(Box::new(err::NotMcProtocol {
+                    endpoint: format!("{}:{}", self.host(), self.port()),
+                    check: check.name(),
+                    source: e,
+                }))
+            }
+        };
+        let detected = model
+            .as_deref()
+            .and_then(PlcType::from_cpu_model)
+            .filter(|series| series.as_str() != self.plc_type);
+        if let (Some(model), Some(detected)) = (model, detected) {
+            let mismatch = err::PlcTypeMismatch {
+                configured: self.plc_type.to_string(),
+                model,
+                detected: detected.as_str(),
+            };
+            if check == ConnectCheck::PlcSeries {
+                return Err(Box::new(mismatch));
+            }
+            warn!("{}", mismatch);
+        }
+        Ok(())
+    }
+
+    /// Reads the CPU model and switches the PLC type to its series. Models
+    /// of unknown series leave the PLC type as it is and return `None`.
+    pub fn detect_plc_type(&mut self) -> Result<Option<PlcType>, Box<dyn Error>> {
+        let (model, _) = self.read_cpu_model()?;
+        let series = PlcType::from_cpu_model(&model);
+        if let Some(series) = series {
+            self.plc_type = series.as_str();
+        }
+        Ok(series)
+    }
+
+    fn remote_command<F>(&self, command: u16, fill: F) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&Client, &mut Vec<u8>),
+    {
+        let mut request_data = self.build_command_data(command, subcommands::ZERO)?;
+        fill(self, &mut request_data);
+        let send_data = self.build_send_data(&request_data)?;
+        self.exchange(&send_data, command, "cpu")?;
+        Ok(())
+    }
+}
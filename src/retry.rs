@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// MC completion codes that are transient unless stated otherwise: the CPU
+/// could not execute the request right now. `MCError::is_transient` and a new
+/// `RetryPolicy` both use this list.
+pub const TRANSIENT_CODES: &[u16] = &[0xC05F];
+
+// Which MC completion codes are retried, how often and how long to wait in
+// between. Codes not registered as transient are returned to the caller at once.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    transient_codes: HashSet<u16>,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Retries up to `max_retries` times, doubling the wait after every
+    /// attempt starting from `initial_backoff`. The codes in
+    /// `TRANSIENT_CODES` are retried; more can be added with `transient`.
+    pub fn new(max_retries: u32, initial_backoff: Duration) -> Self {
+        Self {
+            transient_codes: TRANSIENT_CODES.iter().copied().collect(),
+            max_retries,
+            initial_backoff,
+            max_backoff: initial_backoff * 16,
+        }
+    }
+
+    pub fn transient(mut self, code: u16) -> Self {
+        self.transient_codes.insert(code);
+        self
+    }
+
+    pub fn transient_codes(mut self, codes: &[u16]) -> Self {
+        self.transient_codes.extend(codes);
+        self
+    }
+
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn is_transient(&self, code: u16) -> bool {
+        self.transient_codes.contains(&code)
+    }
+
+    /// Wait before retry number `attempt` (starting at 0), or `None` once
+    /// the retries are used up.
+    pub fn backoff(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        Some(
+            self.initial_backoff
+                .saturating_mul(factor)
+                .min(self.max_backoff),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests_retry {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new(4, Duration::from_millis(10))
+            .max_backoff(Duration::from_millis(50))
+            .transient_codes(&[0xC051]);
+        assert!(policy.is_transient(0xC05F));
+        assert!(policy.is_transient(0xC051));
+        assert!(!policy.is_transient(0xC056));
+        for code in TRANSIENT_CODES {
+            assert!(crate::err::MCError::new(*code).is_transient());
+        }
+        assert_eq!(policy.backoff(0), Some(Duration::from_millis(10)));
+        assert_eq!(policy.backoff(1), Some(Duration::from_millis(20)));
+        assert_eq!(policy.backoff(2), Some(Duration::from_millis(40)));
+        assert_eq!(policy.backoff(3), Some(Duration::from_millis(50)));
+        assert_eq!(policy.backoff(4), None);
+    }
+}
@@ -0,0 +1,270 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use super::client::Client;
+use super::db::DataType;
+use super::tag::QueryTag;
+
+const HEADER: &str = "# rs-melsec session";
+
+// Watched device and its value at the last `poll_once`, `None` before the
+// first one
+#[derive(Debug, Clone)]
+pub struct WatchEntry {
+    pub tag: QueryTag,
+    pub last_value: Option<Option<String>>,
+}
+
+/// Logical session state of a client that is not derived from the PLC: the
+/// request route, the watch list with the values last seen by `poll_once`,
+/// and named tag groups, e.g. the tags of each `Poller`. Saved to a text
+/// file so a restarted collector picks up where it stopped; `poll_once`
+/// then reports only what changed while it was down.
+///
+/// The client keeps no monitor registration (0801h) between requests, so
+/// there is none to save or re-register.
+#[derive(Debug, Clone, Default)]
+pub struct SessionState {
+    pub network: u8,
+    pub pc: u8,
+    pub dest_moduleio: u16,
+    pub dest_modulesta: u8,
+    pub watch_list: Vec<WatchEntry>,
+    pub groups: Vec<(String, Vec<QueryTag>)>,
+}
+
+impl SessionState {
+    /// Sets the tags of group `name`, replacing an earlier group of that name.
+    pub fn set_group(&mut self, name: &str, tags: Vec<QueryTag>) {
+        match self.groups.iter_mut().find(|(group, _)| group == name) {
+            Some((_, group_tags)) => *group_tags = tags,
+            None => self.groups.push((name.to_string(), tags)),
+        }
+    }
+
+    pub fn group(&self, name: &str) -> Option<&[QueryTag]> {
+        self.groups
+            .iter()
+            .find(|(group, _)| group == name)
+            .map(|(_, tags)| tags.as_slice())
+    }
+
+    /// One record per line: `route`, then a `watch` line per watched device
+    /// and a `group` line per grouped tag. Group names and devices must not
+    /// contain whitespace.
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{}\nroute {} {:02X} {:04X} {:02X}\n",
+            HEADER, self.network, self.pc, self.dest_moduleio, self.dest_modulesta
+        );
+        for entry in &self.watch_list {
+            text += &format!(
+                "watch {} {}",
+                entry.tag.device,
+                type_code(&entry.tag.data_type)
+            );
+            match &entry.last_value {
+                Some(Some(value)) => text += &format!(" = {}", value),
+                Some(None) => text += " =",
+                None => {}
+            }
+            text.push('\n');
+        }
+        for (name, tags) in &self.groups {
+            for tag in tags {
+                text += &format!(
+                    "group {} {} {}\n",
+                    name,
+                    tag.device,
+                    type_code(&tag.data_type)
+                );
+            }
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut state = SessionState::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("Invalid session line {}: {}", index + 1, line);
+            let (record, value) = match line.split_once(" = ") {
+                Some((record, value)) => (record, Some(Some(value.to_string()))),
+                None => match line.strip_suffix(" =") {
+                    Some(record) => (record, Some(None)),
+                    None => (line, None),
+                },
+            };
+            let fields: Vec<&str> = record.split_whitespace().collect();
+            match fields.as_slice() {
+                ["route", network, pc, moduleio, modulesta] => {
+                    state.network = network.parse().map_err(|_| invalid())?;
+                    state.pc = u8::from_str_radix(pc, 16).map_err(|_| invalid())?;
+                    state.dest_moduleio =
+                        u16::from_str_radix(moduleio, 16).map_err(|_| invalid())?;
+                    state.dest_modulesta =
+                        u8::from_str_radix(modulesta, 16).map_err(|_| invalid())?;
+                }
+                ["watch", device, code] => state.watch_list.push(WatchEntry {
+                    tag: QueryTag {
+                        device: device.to_string(),
+                        data_type: parse_type_code(code).ok_or_else(invalid)?,
+                    },
+                    last_value: value,
+                }),
+                ["group", name, device, code] => {
+                    let tag = QueryTag {
+                        device: device.to_string(),
+                        data_type: parse_type_code(code).ok_or_else(invalid)?,
+                    };
+                    match state.groups.iter_mut().find(|(group, _)| group == name) {
+                        Some((_, tags)) => tags.push(tag),
+                        None => state.groups.push((name.to_string(), vec![tag])),
+                    }
+                }
+                _ => return Err(invalid().into()),
+            }
+        }
+        Ok(state)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        // written next to the target and renamed, so a crash mid-write
+        // leaves the previous session intact
+        let path = path.as_ref();
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, self.to_text())?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+// struct codes of `DataType::to_struct_type`, plus the sized types
+fn type_code(data_type: &DataType) -> String {
+    match data_type {
+        DataType::RAW(len) => format!("raw:{}", len),
+        DataType::CUSTOM { name, size } => format!("custom:{}:{}", size, name),
+        data_type => data_type.to_struct_type().to_string(),
+    }
+}
+
+fn parse_type_code(code: &str) -> Option<DataType> {
+    if let Some(len) = code.strip_prefix("raw:") {
+        return len.parse().ok().map(DataType::RAW);
+    }
+    if let Some(custom) = code.strip_prefix("custom:") {
+        let (size, name) = custom.split_once(':')?;
+        return Some(DataType::CUSTOM {
+            name: name.to_string(),
+            size: size.parse().ok()?,
+        });
+    }
+    DataType::from_str(code)
+}
+
+impl Client {
+    /// Captures the route and watch list. Tag groups are added by the
+    /// caller with `SessionState::set_group`.
+    pub fn session_state(&self) -> SessionState {
+        SessionState {
+            network: self.network,
+            pc: self.pc,
+            dest_moduleio: self.dest_moduleio,
+            dest_modulesta: self.dest_modulesta,
+            watch_list: self
+                .watch_list()
+                .iter()
+                .map(|tag| WatchEntry {
+                    tag: tag.clone(),
+                    last_value: self.watch_value(&tag.device),
+                })
+                .collect(),
+            groups: Vec::new(),
+        }
+    }
+
+    /// Restores the route and replaces the watch list, including the values
+    /// last seen by `poll_once`.
+    pub fn restore_session(&mut self, state: &SessionState) {
+        self.network = state.network;
+        self.pc = state.pc;
+        self.dest_moduleio = state.dest_moduleio;
+        self.dest_modulesta = state.dest_modulesta;
+        let devices: Vec<String> = self
+            .watch_list()
+            .iter()
+            .map(|tag| tag.device.clone())
+            .collect();
+        for device in devices {
+            self.unwatch(&device);
+        }
+        for entry in &state.watch_list {
+            self.watch(&entry.tag.device, entry.tag.data_type.clone());
+            if let Some(value) = &entry.last_value {
+                self.set_watch_value(&entry.tag.device, value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_session {
+    use super::*;
+    use crate::simulator::Simulator;
+
+    #[test]
+    fn test_save_and_restore() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        simulator.set("D100", 5);
+        simulator.set("D101", 6);
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        client.dest_moduleio = 0x3E0;
+        client.watch("D100", DataType::UWORD);
+        client.watch("D101", DataType::SWORD);
+        assert_eq!(client.poll_once()?.len(), 2);
+
+        let mut state = client.session_state();
+        state.set_group(
+            "line1",
+            vec![
+                QueryTag {
+                    device: "D200".to_string(),
+                    data_type: DataType::FLOAT,
+                },
+                QueryTag {
+                    device: "D210".to_string(),
+                    data_type: DataType::RAW(5),
+                },
+            ],
+        );
+        let path = std::env::temp_dir().join(format!("melsec-session-{}", simulator.port()));
+        state.save(&path)?;
+        let state = SessionState::load(&path)?;
+        std::fs::remove_file(&path)?;
+
+        // a restarted client only reports what changed while it was down
+        simulator.set("D101", 7);
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.restore_session(&state);
+        client.connect()?;
+        assert_eq!(client.dest_moduleio, 0x3E0);
+        assert_eq!(client.watch_list().len(), 2);
+        let changed = client.poll_once()?;
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].device, "D101");
+
+        let group = state.group("line1").unwrap();
+        assert_eq!(group[1].data_type, DataType::RAW(5));
+        assert!(SessionState::parse("watch D100").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,1067 @@
+//! In-process MC protocol server for integration and stress tests, enabled
+//! with the `test-fixtures` feature. It answers binary 3E and 4E frames of a
+//! Q series CPU: batch and random read/write of bit and word devices, backed
+//! by a shared device memory, the CPU model read and the loopback test.
+//! Other commands get completion code 0xC059, and requests whose data is
+//! shorter than their counts announce get 0xC061.
+//! Faults queued with `inject` are applied to the following responses in
+//! order, one per response, to test error handling deterministically.
+//! Every point written by a request is journaled, so tests can check what
+//! a high level operation wrote with `history` and `diff`.
+//! For end-to-end tests of applications it can also be scripted: devices
+//! driven by a `Generator` as if by a PLC program, `ErrorRule`s refusing
+//! requests for certain commands or devices, and a latency added to every
+//! response.
+//! The device memory can be saved to and loaded from an image file, and
+//! with `persist` is kept in one across runs like latched devices.
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use byteorder::{ByteOrder, LittleEndian};
+use log::warn;
+
+use super::client::{format_device, get_device_number, get_device_type};
+use super::db::{commands, consts, DeviceConstants};
+
+const UNSUPPORTED_COMMAND: u16 = 0xC059;
+const REQUEST_LENGTH_ERROR: u16 = 0xC061;
+const IMAGE_HEADER: &str = "# rs-melsec simulator image";
+// how often a persisted image is saved while requests write to the memory
+const IMAGE_SAVE_INTERVAL: Duration = Duration::from_millis(500);
+const CPU_MODEL: (&[u8; 16], u16) = (b"Q03UDVCPU       ", 0x0366);
+
+// device code and number -> value, one entry per bit or word point
+type Memory = HashMap<(u8, u32), u16>;
+
+// One bit or word point written by a request, in the order written.
+// `frame` numbers the answered frames from 1, as counted by `transactions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteRecord {
+    pub frame: usize,
+    pub device: String,
+    pub old: u16,
+    pub new: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    // no answer at all, the connection stays open
+    DropResponse,
+    // answer after waiting
+    Delay(Duration),
+    // cut this many bytes off the response data, with a matching length field
+    Truncate(usize),
+    // answer with this completion code and no data instead
+    CompletionCode(u16),
+    // send the first bytes, pause, then the rest in a second segment
+    Split(usize),
+}
+
+/// Value a device takes on its own, advanced once before every request
+/// frame as if by the PLC program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Generator {
+    // adds `step` every frame, wrapping to 0 after `max`
+    Ramp { step: u16, max: u16 },
+    // alternates between 0 and 1
+    Toggle,
+    // pseudo-random values from `min` to `max`, the same for every run
+    Noise { min: u16, max: u16 },
+}
+
+impl Generator {
+    fn next(&self, value: u16, seed: &mut u64) -> u16 {
+        match *self {
+            Generator::Ramp { step, max } => match value.checked_add(step) {
+                Some(next) if next <= max => next,
+                _ => 0,
+            },
+            Generator::Toggle => (value == 0) as u16,
+            Generator::Noise { min, max } => {
+                // xorshift64
+                *seed ^= *seed << 13;
+                *seed ^= *seed >> 7;
+                *seed ^= *seed << 17;
+                let span = max.saturating_sub(min) as u64 + 1;
+                min + (*seed % span) as u16
+            }
+        }
+    }
+}
+
+/// Answers requests that match with `code` instead of executing them, e.g.
+/// `ErrorRule::new(0xC056).from_device("W4000")?` for a device range the
+/// PLC does not have. Without conditions every request matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorRule {
+    code: u16,
+    command: Option<u16>,
+    // device code, first and last number
+    devices: Option<(u8, u32, u32)>,
+}
+
+impl ErrorRule {
+    pub fn new(code: u16) -> Self {
+        Self {
+            code,
+            command: None,
+            devices: None,
+        }
+    }
+
+    /// Only requests of `command`, see `db::commands`.
+    pub fn command(mut self, command: u16) -> Self {
+        self.command = Some(command);
+        self
+    }
+
+    /// Only requests accessing `device` or a later point of its type.
+    pub fn from_device(mut self, device: &str) -> Result<Self, Box<dyn Error>> {
+        let (code, number) = device_key(device)?;
+        self.devices = Some((code, number, u32::MAX));
+        Ok(self)
+    }
+
+    /// Only requests accessing a point from `first` to `last`.
+    pub fn devices(mut self, first: &str, last: &str) -> Result<Self, Box<dyn Error>> {
+        let (code, first_number) = device_key(first)?;
+        let (last_code, last_number) = device_key(last)?;
+        if last_code != code {
+            return Err(format!("{} and {} are different devices", first, last).into());
+        }
+        self.devices = Some((code, first_number, last_number));
+        Ok(self)
+    }
+
+    fn matches(&self, command: u16, accessed: &[((u8, u32), u32)]) -> bool {
+        if self
+            .command
+            .is_some_and(|rule_command| rule_command != command)
+        {
+            return false;
+        }
+        match self.devices {
+            Some((code, first, last)) => accessed.iter().any(|&((device_code, start), points)| {
+                device_code == code && start <= last && start + points.max(1) > first
+            }),
+            None => true,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    memory: Mutex<Memory>,
+    transactions: AtomicUsize,
+    faults: Mutex<VecDeque<Fault>>,
+    history: Mutex<Vec<WriteRecord>>,
+    generators: Mutex<Vec<((u8, u32), Generator)>>,
+    noise_seed: Mutex<u64>,
+    rules: Mutex<Vec<ErrorRule>>,
+    latency: Mutex<Duration>,
+    image_file: Mutex<Option<PathBuf>>,
+    // a request wrote to the memory since the image was last saved
+    image_dirty: AtomicBool,
+    stopped: AtomicBool,
+}
+
+pub struct Simulator {
+    port: u16,
+    state: Arc<State>,
+}
+
+impl Simulator {
+    /// Listens on a free port of 127.0.0.1, serving every connection on its
+    /// own thread.
+    pub fn start() -> Self {
+        Self::bind("127.0.0.1:0").expect("Failed to bind simulator")
+    }
+
+    /// Same as `start`, listening on `addr`, e.g. "0.0.0.0:6000" to serve
+    /// other hosts.
+    pub fn bind(addr: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let port = listener.local_addr()?.port();
+        let state = Arc::new(State::default());
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = Arc::clone(&shared);
+                thread::spawn(move || serve(stream, &state));
+            }
+        });
+        Ok(Self { port, state })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Number of frames answered so far, over all connections.
+    pub fn transactions(&self) -> usize {
+        self.state.transactions.load(Ordering::SeqCst)
+    }
+
+    /// Number of bit and word points that were ever written.
+    pub fn memory_size(&self) -> usize {
+        self.state.memory.lock().unwrap().len()
+    }
+
+    pub fn inject(&self, fault: Fault) {
+        self.state.faults.lock().unwrap().push_back(fault);
+    }
+
+    /// Drives `device` with `generator`, replacing an earlier one.
+    pub fn generate(&self, device: &str, generator: Generator) -> Result<(), Box<dyn Error>> {
+        let key = device_key(device)?;
+        let mut generators = self.state.generators.lock().unwrap();
+        generators.retain(|(generated, _)| *generated != key);
+        generators.push((key, generator));
+        Ok(())
+    }
+
+    pub fn stop_generators(&self) {
+        self.state.generators.lock().unwrap().clear();
+    }
+
+    /// Adds `rule`. Rules are checked in the order added, the first match
+    /// answers the request.
+    pub fn add_error_rule(&self, rule: ErrorRule) {
+        self.state.rules.lock().unwrap().push(rule);
+    }
+
+    pub fn clear_error_rules(&self) {
+        self.state.rules.lock().unwrap().clear();
+    }
+
+    /// Writes the device memory to `path`, one `device value` line per
+    /// point that was ever set or written.
+    pub fn save_image<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        write_image(path.as_ref(), &self.state.memory.lock().unwrap())
+    }
+
+    /// Replaces the device memory with the image in `path`.
+    pub fn load_image<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let memory = parse_image(&fs::read_to_string(path)?)?;
+        *self.state.memory.lock().unwrap() = memory;
+        Ok(())
+    }
+
+    /// Loads the image in `path`, or creates it, and saves the memory to it
+    /// within half a second of a request writing to it and when the
+    /// simulator is dropped, so the next run starts with the values this
+    /// one left.
+    pub fn persist<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        if path.exists() {
+            self.load_image(path)?;
+        } else {
+            self.save_image(path)?;
+        }
+        let previous = self
+            .state
+            .image_file
+            .lock()
+            .unwrap()
+            .replace(path.to_path_buf());
+        if previous.is_none() {
+            let state = Arc::clone(&self.state);
+            thread::spawn(move || {
+                while !state.stopped.load(Ordering::SeqCst) {
+                    thread::sleep(IMAGE_SAVE_INTERVAL);
+                    save_if_dirty(&state);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Waits `latency` before every response, on top of any `Fault::Delay`.
+    pub fn set_latency(&self, latency: Duration) {
+        *self.state.latency.lock().unwrap() = latency;
+    }
+
+    pub fn get(&self, device: &str) -> u16 {
+        let key = device_key(device).expect("valid device");
+        *self.state.memory.lock().unwrap().get(&key).unwrap_or(&0)
+    }
+
+    pub fn set(&self, device: &str, value: u16) {
+        let key = device_key(device).expect("valid device");
+        self.state.memory.lock().unwrap().insert(key, value);
+    }
+
+    /// Points written by requests so far, oldest first. Values set with
+    /// `set` are not included.
+    pub fn history(&self) -> Vec<WriteRecord> {
+        self.state.history.lock().unwrap().clone()
+    }
+
+    pub fn clear_history(&self) {
+        self.state.history.lock().unwrap().clear();
+    }
+
+    /// Net effect of the journaled writes: each point whose value now
+    /// differs from before its first write, with the old and the new
+    /// value, ordered by device code and number.
+    pub fn diff(&self) -> Vec<(String, u16, u16)> {
+        let history = self.state.history.lock().unwrap();
+        let mut first: Vec<&WriteRecord> = Vec::new();
+        let mut last: HashMap<&str, u16> = HashMap::new();
+        for record in history.iter() {
+            if last.insert(&record.device, record.new).is_none() {
+                first.push(record);
+            }
+        }
+        first.sort_by_key(|record| device_key(&record.device).ok());
+        first
+            .into_iter()
+            .map(|record| {
+                (
+                    record.device.clone(),
+                    record.old,
+                    last[record.device.as_str()],
+                )
+            })
+            .filter(|(_, old, new)| old != new)
+            .collect()
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::SeqCst);
+        save_if_dirty(&self.state);
+    }
+}
+
+// Saves the memory to the persisted image if a request wrote to it. The
+// memory is copied so requests are not held up while the file is written.
+fn save_if_dirty(state: &State) {
+    if !state.image_dirty.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let Some(path) = state.image_file.lock().unwrap().clone() else {
+        return;
+    };
+    let memory = state.memory.lock().unwrap().clone();
+    if let Err(e) = write_image(&path, &memory) {
+        warn!("Error saving simulator image {}: {}", path.display(), e);
+        // try again on the next tick
+        state.image_dirty.store(true, Ordering::SeqCst);
+    }
+}
+
+// written next to the target and renamed, so a crash mid-write leaves the
+// previous image intact
+fn write_image(path: &Path, memory: &Memory) -> Result<(), Box<dyn Error>> {
+    let mut points: Vec<_> = memory.iter().collect();
+    points.sort();
+    let mut text = format!("{}\n", IMAGE_HEADER);
+    for (key, value) in points {
+        text += &format!("{} {}\n", device_name(*key), value);
+    }
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, text)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn parse_image(text: &str) -> Result<Memory, Box<dyn Error>> {
+    let mut memory = Memory::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || format!("Invalid image line {}: {}", index + 1, line);
+        let (device, value) = line.split_once(' ').ok_or_else(invalid)?;
+        let key = device_key(device).map_err(|_| invalid())?;
+        memory.insert(key, value.trim().parse().map_err(|_| invalid())?);
+    }
+    Ok(memory)
+}
+
+// Whether `code` is a Q series bit device, by the device table
+fn is_bit_code(code: u8) -> bool {
+    static CODES: OnceLock<Vec<u8>> = OnceLock::new();
+    CODES
+        .get_or_init(|| {
+            DeviceConstants::DEVICE_NAMES
+                .into_iter()
+                .filter(|name| {
+                    DeviceConstants::get_device_type(consts::Q_SERIES, name)
+                        .is_ok_and(|kind| kind == DeviceConstants::BIT_DEVICE)
+                })
+                .filter_map(|name| {
+                    DeviceConstants::get_binary_device_code(consts::Q_SERIES, name).ok()
+                })
+                .map(|(code, _)| code)
+                .collect()
+        })
+        .contains(&code)
+}
+
+// name of a journaled point, e.g. "D100" or "X1F"
+fn device_name((code, number): (u8, u32)) -> String {
+    for name in DeviceConstants::DEVICE_NAMES {
+        if let Ok((device_code, base)) =
+            DeviceConstants::get_binary_device_code(consts::Q_SERIES, name)
+        {
+            if device_code == code {
+                return format_device(name, number as i64, base);
+            }
+        }
+    }
+    format!("0x{:02X}:{}", code, number)
+}
+
+fn device_key(device: &str) -> Result<(u8, u32), String> {
+    let device_type = get_device_type(device)?;
+    let (code, base) = DeviceConstants::get_binary_device_code(consts::Q_SERIES, &device_type)
+        .map_err(|e| e.to_string())?;
+    Ok((code, get_device_number(device, base)? as u32))
+}
+
+fn serve(mut stream: TcpStream, state: &State) {
+    while let Some(request) = read_frame(&mut stream) {
+        let fault = state.faults.lock().unwrap().pop_front();
+        let frame = state.transactions.fetch_add(1, Ordering::SeqCst) + 1;
+        advance_generators(state);
+        let response = respond(&request, state, frame, fault.as_ref());
+        let latency = *state.latency.lock().unwrap();
+        if !latency.is_zero() {
+            thread::sleep(latency);
+        }
+        let written = match fault {
+            Some(Fault::DropResponse) => Ok(()),
+            Some(Fault::Delay(delay)) => {
+                thread::sleep(delay);
+                stream.write_all(&response)
+            }
+            Some(Fault::Split(at)) => {
+                let at = at.min(response.len());
+                stream
+                    .write_all(&response[..at])
+                    .and_then(|_| stream.flush())
+                    .and_then(|_| {
+                        thread::sleep(Duration::from_millis(20));
+                        stream.write_all(&response[at..])
+                    })
+            }
+            _ => stream.write_all(&response),
+        };
+        if written.is_err() {
+            break;
+        }
+    }
+}
+
+// one request frame, or None once the connection is closed
+fn read_frame(stream: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut frame = vec![0u8; 2];
+    stream.read_exact(&mut frame).ok()?;
+    let header_length = if frame[0] == 0x54 { 13 } else { 9 };
+    frame.resize(header_length, 0);
+    stream.read_exact(&mut frame[2..]).ok()?;
+    let length = LittleEndian::read_u16(&frame[header_length - 2..]) as usize;
+    frame.resize(header_length + length, 0);
+    stream.read_exact(&mut frame[header_length..]).ok()?;
+    Some(frame)
+}
+
+fn respond(request: &[u8], state: &State, frame: usize, fault: Option<&Fault>) -> Vec<u8> {
+    let is_e4 = request[0] == 0x54;
+    let route = if is_e4 {
+        &request[6..11]
+    } else {
+        &request[2..7]
+    };
+    // after the route: length, monitoring timer, command, subcommand, data
+    let body = &request[if is_e4 { 13 } else { 9 }..];
+    let command = body.get(2..4).map_or(0, LittleEndian::read_u16);
+    let subcommand = body.get(4..6).map_or(0, LittleEndian::read_u16);
+    let request_data = body.get(6..).unwrap_or_default();
+    let complete = body.len() >= 6
+        && request_data_length(command, subcommand, request_data)
+            .is_some_and(|length| request_data.len() >= length);
+    let (status, mut data) = if !complete {
+        (REQUEST_LENGTH_ERROR, Vec::new())
+    } else {
+        let rules = state.rules.lock().unwrap();
+        let rule = if rules.is_empty() {
+            None
+        } else {
+            let accessed = accessed_points(command, subcommand, request_data);
+            rules
+                .iter()
+                .find(|rule| rule.matches(command, &accessed))
+                .map(|rule| rule.code)
+        };
+        drop(rules);
+        match rule {
+            Some(code) => (code, Vec::new()),
+            None => execute(command, subcommand, request_data, state, frame),
+        }
+    };
+    let status = match fault {
+        Some(Fault::CompletionCode(code)) => {
+            data.clear();
+            *code
+        }
+        Some(Fault::Truncate(bytes)) => {
+            data.truncate(data.len().saturating_sub(*bytes));
+            status
+        }
+        _ => status,
+    };
+
+    let mut response = if is_e4 {
+        vec![0xD4, 0x00, request[2], request[3], 0x00, 0x00]
+    } else {
+        vec![0xD0, 0x00]
+    };
+    response.extend_from_slice(route);
+    response.extend_from_slice(&(data.len() as u16 + 2).to_le_bytes());
+    response.extend_from_slice(&status.to_le_bytes());
+    response.extend_from_slice(&data);
+    response
+}
+
+// Bytes of request data a command needs by the counts at its start, `None`
+// if the counts themselves are cut off. Checked before anything is
+// executed, so a short request cannot panic with the memory locked.
+fn request_data_length(command: u16, subcommand: u16, data: &[u8]) -> Option<usize> {
+    let byte = |at: usize| data.get(at).map(|&byte| byte as usize);
+    let word = |at: usize| {
+        data.get(at..at + 2)
+            .map(|word| LittleEndian::read_u16(word) as usize)
+    };
+    Some(match (command, subcommand) {
+        (commands::BATCH_READ, _) => 6,
+        (commands::BATCH_WRITE, 0x0001) => 6 + word(4)?.div_ceil(2),
+        (commands::BATCH_WRITE, _) => 6 + word(4)? * 2,
+        (commands::RANDOM_READ, _) => 2 + (byte(0)? + byte(1)?) * 4,
+        (commands::RANDOM_WRITE, _) => 2 + byte(0)? * 6 + byte(1)? * 8,
+        (commands::MULTI_BLOCK_READ, _) => 2 + (byte(0)? + byte(1)?) * 6,
+        (commands::LOOPBACK_TEST, _) => 2 + word(0)?,
+        _ => 0,
+    })
+}
+
+fn device_at(data: &[u8]) -> (u8, u32) {
+    (data[3], LittleEndian::read_u24(&data[..3]))
+}
+
+fn advance_generators(state: &State) {
+    let generators = state.generators.lock().unwrap();
+    if generators.is_empty() {
+        return;
+    }
+    let mut memory = state.memory.lock().unwrap();
+    let mut seed = state.noise_seed.lock().unwrap();
+    if *seed == 0 {
+        *seed = 0x2545_F491_4F6C_DD1D;
+    }
+    for (key, generator) in generators.iter() {
+        let value = *memory.get(key).unwrap_or(&0);
+        memory.insert(*key, generator.next(value, &mut seed));
+    }
+}
+
+// Start point and number of points of every device range a request
+// accesses, for matching error rules. Word access to a bit device covers
+// 16 points per word. Empty if the request data is cut short.
+fn accessed_points(command: u16, subcommand: u16, data: &[u8]) -> Vec<((u8, u32), u32)> {
+    let device = |at: usize| data.get(at..at + 4).map(device_at);
+    let count = |at: usize| {
+        data.get(at..at + 2)
+            .map(|count| LittleEndian::read_u16(count) as u32)
+    };
+    let points = |(code, number): (u8, u32), words: u32| {
+        if is_bit_code(code) {
+            ((code, number), words * 16)
+        } else {
+            ((code, number), words)
+        }
+    };
+    let accessed = match (command, subcommand) {
+        (commands::BATCH_READ | commands::BATCH_WRITE, 0x0001) => {
+            device(0).zip(count(4)).map(|range| vec![range])
+        }
+        (commands::BATCH_READ | commands::BATCH_WRITE, 0x0000) => device(0)
+            .zip(count(4))
+            .map(|(start, words)| vec![points(start, words)]),
+        (commands::RANDOM_READ | commands::RANDOM_WRITE, 0x0000) => {
+            let (words, dwords) = (
+                data.first().copied().unwrap_or(0) as usize,
+                data.get(1).copied().unwrap_or(0) as usize,
+            );
+            // random write entries carry the value after the device
+            let (word_entry, dword_entry) = if command == commands::RANDOM_WRITE {
+                (6, 8)
+            } else {
+                (4, 4)
+            };
+            let dword_start = 2 + words * word_entry;
+            (0..words)
+                .map(|index| device(2 + index * word_entry).map(|start| points(start, 1)))
+                .chain((0..dwords).map(|index| {
+                    device(dword_start + index * dword_entry).map(|start| points(start, 2))
+                }))
+                .collect()
+        }
+        (commands::MULTI_BLOCK_READ, 0x0000) => {
+            let blocks = data.first().copied().unwrap_or(0) as usize
+                + data.get(1).copied().unwrap_or(0) as usize;
+            (0..blocks)
+                .map(|index| {
+                    let block = 2 + index * 6;
+                    device(block)
+                        .zip(count(block + 4))
+                        .map(|(start, words)| points(start, words))
+                })
+                .collect()
+        }
+        _ => None,
+    };
+    accessed.unwrap_or_default()
+}
+
+// Device memory of one request, journaling every point it writes
+struct Access<'a> {
+    memory: &'a mut Memory,
+    history: &'a mut Vec<WriteRecord>,
+    frame: usize,
+    written: bool,
+}
+
+impl Access<'_> {
+    fn get(&self, key: &(u8, u32)) -> Option<&u16> {
+        self.memory.get(key)
+    }
+
+    fn insert(&mut self, key: (u8, u32), value: u16) {
+        let old = self.memory.insert(key, value).unwrap_or(0);
+        self.written = true;
+        self.history.push(WriteRecord {
+            frame: self.frame,
+            device: device_name(key),
+            old,
+            new: value,
+        });
+    }
+}
+
+// 16 points starting at `start` for word access to a bit device
+fn read_word(memory: &Access, (code, number): (u8, u32)) -> u16 {
+    if is_bit_code(code) {
+        (0..16).fold(0, |word, bit| {
+            let point = *memory.get(&(code, number + bit)).unwrap_or(&0) & 1;
+            word | point << bit
+        })
+    } else {
+        *memory.get(&(code, number)).unwrap_or(&0)
+    }
+}
+
+fn write_word(memory: &mut Access, (code, number): (u8, u32), value: u16) {
+    if is_bit_code(code) {
+        for bit in 0..16 {
+            memory.insert((code, number + bit), value >> bit & 1);
+        }
+    } else {
+        memory.insert((code, number), value);
+    }
+}
+
+fn execute(
+    command: u16,
+    subcommand: u16,
+    data: &[u8],
+    state: &State,
+    frame: usize,
+) -> (u16, Vec<u8>) {
+    let mut memory = state.memory.lock().unwrap();
+    let mut history = state.history.lock().unwrap();
+    let mut memory = Access {
+        memory: &mut memory,
+        history: &mut history,
+        frame,
+        written: false,
+    };
+    let mut out = Vec::new();
+    match (command, subcommand) {
+        (commands::BATCH_READ, 0x0000) => {
+            let (code, start) = device_at(data);
+            let count = LittleEndian::read_u16(&data[4..6]) as u32;
+            for offset in 0..count {
+                let word = read_word(&memory, (code, start + offset));
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        (commands::BATCH_READ, 0x0001) => {
+            let (code, start) = device_at(data);
+            let count = LittleEndian::read_u16(&data[4..6]) as u32;
+            out.resize(count.div_ceil(2) as usize, 0);
+            for offset in 0..count {
+                let bit = *memory.get(&(code, start + offset)).unwrap_or(&0) as u8 & 1;
+                out[offset as usize / 2] |= if offset % 2 == 0 { bit << 4 } else { bit };
+            }
+        }
+        (commands::BATCH_WRITE, 0x0000) => {
+            let (code, start) = device_at(data);
+            let count = LittleEndian::read_u16(&data[4..6]) as u32;
+            for offset in 0..count {
+                let index = 6 + offset as usize * 2;
+                let value = LittleEndian::read_u16(&data[index..index + 2]);
+                write_word(&mut memory, (code, start + offset), value);
+            }
+        }
+        (commands::BATCH_WRITE, 0x0001) => {
+            let (code, start) = device_at(data);
+            let count = LittleEndian::read_u16(&data[4..6]) as u32;
+            for offset in 0..count {
+                let byte = data[6 + offset as usize / 2];
+                let bit = if offset % 2 == 0 { byte >> 4 } else { byte } & 1;
+                memory.insert((code, start + offset), bit as u16);
+            }
+        }
+        (commands::RANDOM_READ, 0x0000) => {
+            let (words, dwords) = (data[0] as usize, data[1] as usize);
+            for index in 0..words {
+                let device = device_at(&data[2 + index * 4..]);
+                out.extend_from_slice(&read_word(&memory, device).to_le_bytes());
+            }
+            for index in 0..dwords {
+                let (code, number) = device_at(&data[2 + (words + index) * 4..]);
+                out.extend_from_slice(&read_word(&memory, (code, number)).to_le_bytes());
+                let high = read_word(&memory, (code, number + 1));
+                out.extend_from_slice(&high.to_le_bytes());
+            }
+        }
+        (commands::MULTI_BLOCK_READ, 0x0000) => {
+            // word blocks first, then bit blocks, both answered in words
+            let blocks = data[0] as usize + data[1] as usize;
+            for index in 0..blocks {
+                let block = &data[2 + index * 6..];
+                let (code, start) = device_at(block);
+                let count = LittleEndian::read_u16(&block[4..6]) as u32;
+                for offset in 0..count {
+                    let number = if is_bit_code(code) {
+                        start + offset * 16
+                    } else {
+                        start + offset
+                    };
+                    out.extend_from_slice(&read_word(&memory, (code, number)).to_le_bytes());
+                }
+            }
+        }
+        (commands::RANDOM_WRITE, 0x0000) => {
+            let (words, dwords) = (data[0] as usize, data[1] as usize);
+            let mut index = 2;
+            for _ in 0..words {
+                let device = device_at(&data[index..]);
+                write_word(
+                    &mut memory,
+                    device,
+                    LittleEndian::read_u16(&data[index + 4..]),
+                );
+                index += 6;
+            }
+            for _ in 0..dwords {
+                let (code, number) = device_at(&data[index..]);
+                let value = LittleEndian::read_u32(&data[index + 4..]);
+                write_word(&mut memory, (code, number), value as u16);
+                write_word(&mut memory, (code, number + 1), (value >> 16) as u16);
+                index += 8;
+            }
+        }
+        (commands::READ_CPU_MODEL, 0x0000) => {
+            out.extend_from_slice(CPU_MODEL.0);
+            out.extend_from_slice(&CPU_MODEL.1.to_le_bytes());
+        }
+        (commands::LOOPBACK_TEST, 0x0000) => {
+            let length = LittleEndian::read_u16(&data[..2]) as usize;
+            out.extend_from_slice(&data[..2 + length]);
+        }
+        _ => return (UNSUPPORTED_COMMAND, out),
+    }
+    if memory.written {
+        state.image_dirty.store(true, Ordering::SeqCst);
+    }
+    (0, out)
+}
+
+#[cfg(test)]
+mod tests_simulator {
+    use super::*;
+    use crate::client::Client;
+    use crate::db::DataType;
+    use crate::tag::{QueryTag, Tag};
+    use std::error::Error;
+
+    #[test]
+    fn test_read_write_round_trip() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        simulator.set("M3", 1);
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", false);
+        client.connect()?;
+
+        client.batch_write("D100", vec![7, -2], &DataType::SWORD)?;
+        assert_eq!(simulator.get("D101"), 0xFFFE);
+        client.write(vec![Tag::new(
+            "D200".to_string(),
+            Some("70000".to_string()),
+            DataType::UDWORD,
+        )])?;
+        let tags = client.read(vec![
+            QueryTag {
+                device: "D200".to_string(),
+                data_type: DataType::UDWORD,
+            },
+            QueryTag {
+                device: "M3".to_string(),
+                data_type: DataType::BIT,
+            },
+        ])?;
+        assert_eq!(tags[0].value.as_deref(), Some("70000"));
+        assert_eq!(tags[1].value.as_deref(), Some("1"));
+
+        let mut bits = [false; 3];
+        client.batch_read_bits_into("M2", &mut bits)?;
+        assert_eq!(bits, [false, true, false]);
+        assert_eq!(simulator.transactions(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fault_injection() -> Result<(), Box<dyn Error>> {
+        use crate::err::{find, DeadlineExceeded, MCError, ShortRead};
+        use crate::retry::RetryPolicy;
+        use std::time::Instant;
+
+        let simulator = Simulator::start();
+        simulator.set("D10", 42);
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        let mut out = [0u16; 2];
+
+        simulator.inject(Fault::CompletionCode(0xC05F));
+        let e = client.batch_read_words_into("D10", &mut out).unwrap_err();
+        assert_eq!(find::<MCError>(&*e).unwrap().code(), 0xC05F);
+
+        client.set_retry_policy(Some(
+            RetryPolicy::new(1, Duration::from_millis(1)).transient(0xC05F),
+        ));
+        simulator.inject(Fault::CompletionCode(0xC05F));
+        client.batch_read_words_into("D10", &mut out)?;
+        assert_eq!(out, [42, 0]);
+
+        simulator.inject(Fault::Truncate(2));
+        let e = client
+            .batch_read("D10", 2, DataType::UWORD, true)
+            .unwrap_err();
+        assert!(find::<ShortRead>(&*e).is_some());
+
+        for at in [1, 9, 14, 16] {
+            simulator.inject(Fault::Split(at));
+            client.batch_read_words_into("D10", &mut out)?;
+            assert_eq!(out, [42, 0]);
+        }
+
+        simulator.inject(Fault::DropResponse);
+        let deadline = Instant::now() + Duration::from_millis(200);
+        let e = client
+            .batch_read_with_deadline("D10", 1, DataType::UWORD, true, deadline)
+            .unwrap_err();
+        assert!(find::<DeadlineExceeded>(&*e).is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_history() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        simulator.set("D101", 5);
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+
+        // a small recipe download: parameters, a dword setpoint, a start bit
+        client.batch_write("D100", vec![1, 5, 3], &DataType::SWORD)?;
+        client.write(vec![Tag::new(
+            "D200".to_string(),
+            Some("70000".to_string()),
+            DataType::UDWORD,
+        )])?;
+        client.batch_write("M10", vec![1], &DataType::BIT)?;
+        client.batch_write("D102", vec![0], &DataType::SWORD)?;
+
+        let history = simulator.history();
+        assert_eq!(history.len(), 7);
+        assert_eq!(
+            history[1],
+            WriteRecord {
+                frame: 1,
+                device: "D101".to_string(),
+                old: 5,
+                new: 5,
+            }
+        );
+        assert_eq!(history[5].device, "M10");
+        // D101 kept its value and D102 was written back to 0
+        assert_eq!(
+            simulator.diff(),
+            vec![
+                ("M10".to_string(), 0, 1),
+                ("D100".to_string(), 0, 1),
+                ("D200".to_string(), 0, 0x1170),
+                ("D201".to_string(), 0, 1),
+            ]
+        );
+        simulator.clear_history();
+        assert!(simulator.diff().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scripted_behaviors() -> Result<(), Box<dyn Error>> {
+        use crate::err::{find, MCError};
+        use std::time::Instant;
+
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        simulator.generate("D0", Generator::Ramp { step: 10, max: 25 })?;
+        simulator.generate("M0", Generator::Toggle)?;
+        simulator.generate("D1", Generator::Noise { min: 100, max: 110 })?;
+        assert!(simulator.generate("Q0", Generator::Toggle).is_err());
+
+        let mut ramp = Vec::new();
+        for _ in 0..4 {
+            let mut words = [0u16; 2];
+            client.batch_read_words_into("D0", &mut words)?;
+            ramp.push(words[0]);
+            assert!((100..=110).contains(&words[1]));
+        }
+        assert_eq!(ramp, vec![10, 20, 0, 10]);
+        let mut bits = [false];
+        client.batch_read_bits_into("M0", &mut bits)?;
+        let toggled = bits[0];
+        client.batch_read_bits_into("M0", &mut bits)?;
+        assert_ne!(bits[0], toggled);
+        simulator.stop_generators();
+
+        simulator.add_error_rule(ErrorRule::new(0xC056).from_device("W4000")?);
+        simulator.add_error_rule(
+            ErrorRule::new(0xC05C)
+                .command(commands::BATCH_WRITE)
+                .devices("D100", "D109")?,
+        );
+        let mut words = [0u16; 16];
+        let e = client
+            .batch_read_words_into("W3FF8", &mut words)
+            .unwrap_err();
+        assert_eq!(find::<MCError>(&*e).unwrap().code(), 0xC056);
+        client.batch_read_words_into("W3FE0", &mut words)?;
+        let e = client
+            .batch_write("D95", vec![1; 6], &DataType::SWORD)
+            .unwrap_err();
+        assert_eq!(find::<MCError>(&*e).unwrap().code(), 0xC05C);
+        assert_eq!(simulator.get("D95"), 0);
+        client.batch_read_words_into("D100", &mut words)?;
+        simulator.clear_error_rules();
+        client.batch_write("D95", vec![1; 6], &DataType::SWORD)?;
+        assert!(ErrorRule::new(0xC056).devices("D0", "W10").is_err());
+        assert!(accessed_points(commands::RANDOM_WRITE, 0x0000, &[2, 0, 0x64]).is_empty());
+        assert!(is_bit_code(DeviceConstants::SS_DEVICE));
+        assert!(!is_bit_code(DeviceConstants::SN_DEVICE));
+
+        simulator.set_latency(Duration::from_millis(50));
+        let started = Instant::now();
+        client.batch_read_words_into("D100", &mut words)?;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        Ok(())
+    }
+
+    #[test]
+    fn test_persistent_image() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("melsec-image-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let simulator = Simulator::start();
+            simulator.persist(&path)?;
+            let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+            client.connect()?;
+            client.batch_write("D100", vec![7, -1], &DataType::SWORD)?;
+            client.batch_write("X1F", vec![1], &DataType::BIT)?;
+            // saved in the background while running
+            let started = std::time::Instant::now();
+            while !fs::read_to_string(&path)?.contains("X1F 1\n") {
+                assert!(started.elapsed() < IMAGE_SAVE_INTERVAL * 10);
+                thread::sleep(Duration::from_millis(10));
+            }
+            client.batch_write("D102", vec![5], &DataType::SWORD)?;
+        }
+        // and when dropped
+        let text = fs::read_to_string(&path)?;
+        assert!(text.contains("D102 5\n"), "{}", text);
+
+        // the next run starts where the last one stopped
+        let simulator = Simulator::start();
+        simulator.persist(&path)?;
+        assert_eq!(simulator.get("D101"), 0xFFFF);
+        assert_eq!(simulator.get("X1F"), 1);
+
+        simulator.set("D100", 3);
+        simulator.save_image(&path)?;
+        let other = Simulator::start();
+        other.load_image(&path)?;
+        assert_eq!(other.get("D100"), 3);
+        fs::remove_file(&path)?;
+
+        assert!(parse_image("D100 x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_requests() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut stream = TcpStream::connect(("127.0.0.1", simulator.port()))?;
+        let mut exchange = |body: &[u8]| -> Result<u16, Box<dyn Error>> {
+            let mut request = vec![0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00];
+            request.extend_from_slice(&(body.len() as u16).to_le_bytes());
+            request.extend_from_slice(body);
+            stream.write_all(&request)?;
+            let mut header = [0u8; 9];
+            stream.read_exact(&mut header)?;
+            let mut rest = vec![0u8; LittleEndian::read_u16(&header[7..]) as usize];
+            stream.read_exact(&mut rest)?;
+            Ok(LittleEndian::read_u16(&rest))
+        };
+
+        // no command, then a batch write of 65535 words carrying one
+        assert_eq!(exchange(&[0x10, 0x00])?, REQUEST_LENGTH_ERROR);
+        let write = [
+            0x10, 0x00, 0x01, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0xA8, 0xFF, 0xFF, 0x01, 0x00,
+        ];
+        assert_eq!(exchange(&write)?, REQUEST_LENGTH_ERROR);
+        let random_read = [0x10, 0x00, 0x03, 0x04, 0x00, 0x00, 0x02];
+        assert_eq!(exchange(&random_read)?, REQUEST_LENGTH_ERROR);
+
+        // the memory is still usable
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", false);
+        client.connect()?;
+        client.batch_write("D0", vec![1], &DataType::SWORD)?;
+        assert_eq!(simulator.get("D0"), 1);
+        Ok(())
+    }
+}
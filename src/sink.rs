@@ -0,0 +1,230 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::tag::Tag;
+
+// Destination for batches of polled tags
+pub trait Sink {
+    fn record(&mut self, batch: &[Tag]) -> Result<(), Box<dyn Error>>;
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+fn timestamp_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+// One row per tag: timestamp (unix ms), device, value, data type and the
+// name, unit and description of the tag if it has metadata
+pub struct CsvSink<W: Write> {
+    writer: W,
+}
+
+impl CsvSink<BufWriter<File>> {
+    /// Appends to `path`, writing the header line if the file is new.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let is_new = !path.as_ref().exists();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut sink = CsvSink {
+            writer: BufWriter::new(file),
+        };
+        if is_new {
+            sink.write_header()?;
+        }
+        Ok(sink)
+    }
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> Result<Self, Box<dyn Error>> {
+        let mut sink = CsvSink { writer };
+        sink.write_header()?;
+        Ok(sink)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_header(&mut self) -> Result<(), Box<dyn Error>> {
+        writeln!(
+            self.writer,
+            "timestamp,device,value,data_type,name,unit,description"
+        )?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for CsvSink<W> {
+    fn record(&mut self, batch: &[Tag]) -> Result<(), Box<dyn Error>> {
+        let timestamp = timestamp_millis();
+        for tag in batch {
+            let metadata = tag.metadata.clone().unwrap_or_default();
+            writeln!(
+                self.writer,
+                "{},{},{},{},{},{},{}",
+                timestamp,
+                tag.device,
+                tag.formatted_value().unwrap_or_default(),
+                tag.data_type.to_struct_type(),
+                csv_field(&metadata.name),
+                csv_field(&metadata.unit),
+                csv_field(&metadata.description)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// quotes a field containing the delimiter, quotes or line breaks
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub struct SqliteSink {
+    connection: rusqlite::Connection,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteSink {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::from_connection(rusqlite::Connection::open(path)?)
+    }
+
+    pub fn from_connection(connection: rusqlite::Connection) -> Result<Self, Box<dyn Error>> {
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS tags (
+                timestamp INTEGER NOT NULL,
+                device TEXT NOT NULL,
+                value TEXT,
+                data_type TEXT NOT NULL,
+                name TEXT,
+                unit TEXT,
+                description TEXT
+            )",
+            (),
+        )?;
+        // tables created before the metadata columns existed
+        for column in ["name", "unit", "description"] {
+            if connection
+                .prepare(&format!("SELECT {} FROM tags", column))
+                .is_err()
+            {
+                connection.execute(&format!("ALTER TABLE tags ADD COLUMN {} TEXT", column), ())?;
+            }
+        }
+        Ok(Self { connection })
+    }
+
+    pub fn connection(&self) -> &rusqlite::Connection {
+        &self.connection
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Sink for SqliteSink {
+    fn record(&mut self, batch: &[Tag]) -> Result<(), Box<dyn Error>> {
+        let timestamp = timestamp_millis() as i64;
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO tags (timestamp, device, value, data_type, name, unit, description) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for tag in batch {
+                let metadata = tag.metadata.as_ref();
+                statement.execute((
+                    timestamp,
+                    &tag.device,
+                    tag.formatted_value(),
+                    tag.data_type.to_struct_type(),
+                    metadata.map(|metadata| &metadata.name),
+                    metadata.map(|metadata| &metadata.unit),
+                    metadata.map(|metadata| &metadata.description),
+                ))?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_sink {
+    use super::*;
+    use crate::db::DataType;
+    use crate::tag::TagMetadata;
+
+    #[test]
+    fn test_csv_sink() -> Result<(), Box<dyn Error>> {
+        let mut sink = CsvSink::new(Vec::new())?;
+        sink.record(&[
+            Tag::new("D100".to_string(), Some("12".to_string()), DataType::SWORD),
+            Tag::new("M0".to_string(), None, DataType::BIT),
+        ])?;
+        let output = String::from_utf8(sink.into_inner())?;
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "timestamp,device,value,data_type,name,unit,description"
+        );
+        assert!(lines[1].ends_with(",D100,12,h,,,"));
+        assert!(lines[2].ends_with(",M0,,b,,,"));
+
+        let mut sink = CsvSink::new(Vec::new())?;
+        let metadata = TagMetadata {
+            name: "LineSpeed".to_string(),
+            unit: "m/min".to_string(),
+            description: "Line speed, conveyor 1".to_string(),
+            precision: Some(1),
+        };
+        sink.record(&[Tag::new(
+            "D200".to_string(),
+            Some("12.345".to_string()),
+            DataType::FLOAT,
+        )
+        .with_metadata(metadata)])?;
+        let output = String::from_utf8(sink.into_inner())?;
+        assert!(output
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with(",D200,12.3,f,LineSpeed,m/min,\"Line speed, conveyor 1\""));
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_sink() -> Result<(), Box<dyn Error>> {
+        let mut sink = SqliteSink::from_connection(rusqlite::Connection::open_in_memory()?)?;
+        sink.record(&[Tag::new(
+            "D100".to_string(),
+            Some("12".to_string()),
+            DataType::SWORD,
+        )])?;
+        let value: String = sink.connection().query_row(
+            "SELECT value FROM tags WHERE device = 'D100'",
+            (),
+            |row| row.get(0),
+        )?;
+        assert_eq!(value, "12");
+        Ok(())
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use super::client::{get_device_number, get_device_type, Client};
+use super::db::{commands, consts, subcommands, DataType, DeviceConstants};
+use super::tag::Tag;
+
+// multiple block batch read limits of the Q/L series
+pub(crate) const MAX_BLOCKS: usize = 120;
+pub(crate) const MAX_BLOCK_POINTS: usize = 960;
+
+// A device whose value differs between two snapshots. `None` means the
+// device is missing from that snapshot or could not be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceChange {
+    pub device: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Device values captured at one point in time, e.g. the tags of a few
+/// batch reads, kept in the order they were added.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    devices: Vec<String>,
+    values: HashMap<String, Option<String>>,
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_tags(tags: &[Tag]) -> Self {
+        let mut snapshot = Self::new();
+        snapshot.extend(tags);
+        snapshot
+    }
+
+    /// Adds the values of `tags`, replacing earlier values of the same devices.
+    pub fn extend(&mut self, tags: &[Tag]) {
+        for tag in tags {
+            if self
+                .values
+                .insert(tag.device.clone(), tag.value.clone())
+                .is_none()
+            {
+                self.devices.push(tag.device.clone());
+            }
+        }
+    }
+
+    pub fn get(&self, device: &str) -> Option<&str> {
+        self.values.get(device)?.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Devices whose value changed from `self` to `newer`, in the order of
+    /// `self` followed by devices only `newer` has.
+    pub fn diff(&self, newer: &Snapshot) -> Vec<DeviceChange> {
+        self.diff_filtered(newer, |_| true)
+    }
+
+    /// Same as `diff`, limited to the devices `filter` accepts, e.g.
+    /// `|device| device.starts_with('D')`.
+    pub fn diff_filtered<F>(&self, newer: &Snapshot, filter: F) -> Vec<DeviceChange>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let only_newer = newer
+            .devices
+            .iter()
+            .filter(|device| !self.values.contains_key(*device));
+        self.devices
+            .iter()
+            .chain(only_newer)
+            .filter(|device| filter(device))
+            .filter_map(|device| {
+                let old = self.get(device);
+                let new = newer.get(device);
+                (old != new).then(|| DeviceChange {
+                    device: device.clone(),
+                    old: old.map(str::to_string),
+                    new: new.map(str::to_string),
+                })
+            })
+            .collect()
+    }
+}
+
+// One range of a `DeviceImage`. Bit device ranges hold 16 bits per word.
+#[derive(Debug, Clone)]
+struct Block {
+    device_type: String,
+    base: u32,
+    start: i32,
+    is_bit: bool,
+    words: Vec<u16>,
+}
+
+/// Values of several device ranges taken with one multiple block batch
+/// read, so all of them come from the same PLC scan. The typed accessors
+/// return `None` for devices outside the ranges that were read.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceImage {
+    blocks: Vec<Block>,
+}
+
+impl DeviceImage {
+    // adds the ranges of `other`, read in another frame
+    pub(crate) fn extend(&mut self, other: DeviceImage) {
+        self.blocks.extend(other.blocks);
+    }
+
+    // block holding `device` and the offset of the device in it, in points
+    fn locate(&self, device: &str) -> Option<(&Block, usize)> {
+        let device_type = get_device_type(device).ok()?;
+        self.blocks.iter().find_map(|block| {
+            if block.device_type != device_type {
+                return None;
+            }
+            let number = get_device_number(device, block.base).ok()?;
+            let offset = usize::try_from(number - block.start).ok()?;
+            let points = block.words.len() * if block.is_bit { 16 } else { 1 };
+            (offset < points).then_some((block, offset))
+        })
+    }
+
+    fn word_at(&self, device: &str, index: usize) -> Option<u16> {
+        let (block, offset) = self.locate(device)?;
+        if block.is_bit && offset % 16 != 0 {
+            return None;
+        }
+        let offset = if block.is_bit { offset / 16 } else { offset };
+        block.words.get(offset + index).copied()
+    }
+
+    pub fn get_bit(&self, device: &str) -> Option<bool> {
+        let (block, offset) = self.locate(device)?;
+        if !block.is_bit {
+            return None;
+        }
+        Some(block.words[offset / 16] >> (offset % 16) & 1 == 1)
+    }
+
+    pub fn get_u16(&self, device: &str) -> Option<u16> {
+        self.word_at(device, 0)
+    }
+
+    pub fn get_i16(&self, device: &str) -> Option<i16> {
+        self.get_u16(device).map(|value| value as i16)
+    }
+
+    /// Two consecutive words, the first one being the low word.
+    pub fn get_u32(&self, device: &str) -> Option<u32> {
+        let low = self.word_at(device, 0)?;
+        let high = self.word_at(device, 1)?;
+        Some((high as u32) << 16 | low as u32)
+    }
+
+    pub fn get_i32(&self, device: &str) -> Option<i32> {
+        self.get_u32(device).map(|value| value as i32)
+    }
+
+    pub fn get_f32(&self, device: &str) -> Option<f32> {
+        self.get_u32(device).map(f32::from_bits)
+    }
+}
+
+impl Client {
+    /// Reads every `(start device, words)` range in one request and returns
+    /// the values as a `DeviceImage`. For bit devices the count is in words
+    /// of 16 bits, e.g. `("M0", 2)` covers M0 to M31.
+    pub fn snapshot(&self, ranges: &[(&str, usize)]) -> Result<DeviceImage, Box<dyn Error>> {
+        let points: usize = ranges.iter().map(|(_, words)| words).sum();
+        if ranges.is_empty() || ranges.len() > MAX_BLOCKS || points > MAX_BLOCK_POINTS {
+            return Err(format!(
+                "A snapshot takes 1 to {} ranges of {} words in total, got {} ranges of {} words",
+                MAX_BLOCKS,
+                MAX_BLOCK_POINTS,
+                ranges.len(),
+                points
+            )
+            .into());
+        }
+
+        let mut word_blocks = Vec::new();
+        let mut bit_blocks = Vec::new();
+        for &(device, words) in ranges {
+            let device_type = get_device_type(device)?;
+            let base = self.device_base(&device_type)?;
+            let is_bit = self.device_kind(&device_type)? == DeviceConstants::BIT_DEVICE;
+            let block = Block {
+                start: get_device_number(device, base)?,
+                device_type,
+                base,
+                is_bit,
+                words: vec![0; words],
+            };
+            let encoded = (self.build_device_data(device)?, words);
+            if is_bit {
+                bit_blocks.push((block, encoded));
+            } else {
+                word_blocks.push((block, encoded));
+            }
+        }
+
+        let subcommand = if self.plc_type == consts::IQR_SERIES {
+            subcommands::TWO
+        } else {
+            subcommands::ZERO
+        };
+        let mut request_data = self.build_command_data(commands::MULTI_BLOCK_READ, subcommand)?;
+        self.push_header_value(&mut request_data, word_blocks.len() as u64, 1);
+        self.push_header_value(&mut request_data, bit_blocks.len() as u64, 1);
+        for (_, (device_data, words)) in word_blocks.iter().chain(bit_blocks.iter()) {
+            request_data.extend_from_slice(device_data);
+            self.push_header_value(&mut request_data, *words as u64, 2);
+        }
+        let send_data = self.build_send_data(&request_data)?;
+        let target = ranges
+            .iter()
+            .map(|(device, words)| format!("{}x{}", device, words))
+            .collect::<Vec<_>>()
+            .join(",");
+        let recv_data = self.exchange(&send_data, commands::MULTI_BLOCK_READ, &target)?;
+
+        let word_width = self.header_width(2);
+        let payload = self.response_payload(&recv_data, points * word_width)?;
+        let mut chunks = payload.chunks_exact(word_width);
+        let mut blocks = Vec::with_capacity(ranges.len());
+        for (mut block, _) in word_blocks.into_iter().chain(bit_blocks) {
+            for word in block.words.iter_mut() {
+                let chunk = chunks.next().ok_or("Snapshot response too short")?;
+                *word = self.decode_word_value(chunk, &DataType::UWORD)? as u16;
+            }
+            blocks.push(block);
+        }
+        Ok(DeviceImage { blocks })
+    }
+}
+
+#[cfg(test)]
+mod tests_snapshot {
+    use super::*;
+    use crate::db::DataType;
+
+    fn tag(device: &str, value: Option<&str>) -> Tag {
+        Tag::new(
+            device.to_string(),
+            value.map(str::to_string),
+            DataType::SWORD,
+        )
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = Snapshot::from_tags(&[
+            tag("D0", Some("1")),
+            tag("D1", Some("2")),
+            tag("M0", Some("0")),
+            tag("D2", Some("3")),
+        ]);
+        let after = Snapshot::from_tags(&[
+            tag("D0", Some("1")),
+            tag("D1", Some("5")),
+            tag("M0", Some("1")),
+            tag("D2", None),
+            tag("D3", Some("9")),
+        ]);
+        assert_eq!(before.len(), 4);
+        let changes = before.diff(&after);
+        let devices: Vec<&str> = changes.iter().map(|c| c.device.as_str()).collect();
+        assert_eq!(devices, ["D1", "M0", "D2", "D3"]);
+        assert_eq!(
+            changes[0],
+            DeviceChange {
+                device: "D1".to_string(),
+                old: Some("2".to_string()),
+                new: Some("5".to_string()),
+            }
+        );
+        assert_eq!(changes[2].new, None);
+        assert_eq!(changes[3].old, None);
+
+        let changes = before.diff_filtered(&after, |device| device.starts_with('M'));
+        assert_eq!(changes.len(), 1);
+        assert!(after.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn test_device_image() -> Result<(), Box<dyn Error>> {
+        use crate::simulator::Simulator;
+
+        let simulator = Simulator::start();
+        simulator.set("D100", 0xFFFE);
+        simulator.set("D105", 1234);
+        simulator.set("D200", 0x0000);
+        simulator.set("D201", 0x3FC0);
+        simulator.set("M12", 1);
+        simulator.set("M17", 1);
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+
+        let image = client.snapshot(&[("D100", 10), ("M0", 2), ("D200", 2)])?;
+        assert_eq!(simulator.transactions(), 1);
+        assert_eq!(image.get_u16("D105"), Some(1234));
+        assert_eq!(image.get_i16("D100"), Some(-2));
+        assert_eq!(image.get_f32("D200"), Some(1.5));
+        assert_eq!(image.get_bit("M12"), Some(true));
+        assert_eq!(image.get_bit("M13"), Some(false));
+        assert_eq!(image.get_bit("M17"), Some(true));
+        assert_eq!(image.get_u16("M16"), Some(0x0002));
+        assert_eq!(image.get_u16("D110"), None);
+        assert_eq!(image.get_u32("D109"), None);
+        assert_eq!(image.get_bit("M32"), None);
+        assert_eq!(image.get_bit("D100"), None);
+
+        assert!(client.snapshot(&[("D0", 961)]).is_err());
+        Ok(())
+    }
+}
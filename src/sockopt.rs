@@ -0,0 +1,130 @@
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+
+/// TCP options applied when the client connects. Options a platform does
+/// not have fail with `io::ErrorKind::Unsupported` when set, e.g. binding
+/// to a network interface anywhere but Linux.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SocketOptions {
+    nodelay: bool,
+    keepalive: Option<Duration>,
+    bind_device: Option<String>,
+}
+
+impl SocketOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends small request frames at once instead of waiting to batch them.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Probes an idle connection after `idle`, so a PLC that disappeared
+    /// without closing the connection is noticed between requests.
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Connects through network interface `interface` only, e.g. the
+    /// port facing the control network on a gateway with several.
+    pub fn bind_device(mut self, interface: &str) -> Self {
+        self.bind_device = Some(interface.to_string());
+        self
+    }
+
+    /// Fails with `Unsupported` if an option cannot be set on this platform.
+    pub fn check_supported(&self) -> io::Result<()> {
+        if self.bind_device.is_some() && !BIND_DEVICE_SUPPORTED {
+            return Err(unsupported("binding to a network interface"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const BIND_DEVICE_SUPPORTED: bool = true;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const BIND_DEVICE_SUPPORTED: bool = false;
+
+fn unsupported(option: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} is not supported on {}", option, std::env::consts::OS),
+    )
+}
+
+// Connects to `addr` with `options` applied. Options that must be set
+// before connecting take a socket built by hand, otherwise std connects.
+pub(crate) fn connect(addr: SocketAddr, options: &SocketOptions) -> io::Result<TcpStream> {
+    let stream = match options.bind_device {
+        Some(ref interface) => {
+            let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+            bind_device(&socket, interface)?;
+            socket.connect(&addr.into())?;
+            socket.into()
+        }
+        None => TcpStream::connect(addr)?,
+    };
+    apply(&stream, options)?;
+    Ok(stream)
+}
+
+// Options that can be set on a connected stream
+fn apply(stream: &TcpStream, options: &SocketOptions) -> io::Result<()> {
+    if options.nodelay {
+        stream.set_nodelay(true)?;
+    }
+    if let Some(idle) = options.keepalive {
+        SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+    }
+    Ok(())
+}
+
+// Read and write timeout of the stream, `None` blocks indefinitely
+pub(crate) fn set_timeout(stream: &TcpStream, timeout: Option<Duration>) -> io::Result<()> {
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(socket: &Socket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_device(_socket: &Socket, _interface: &str) -> io::Result<()> {
+    Err(unsupported("binding to a network interface"))
+}
+
+#[cfg(test)]
+mod tests_sockopt {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_apply_options() -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let options = SocketOptions::new()
+            .nodelay(true)
+            .keepalive(Duration::from_secs(30));
+        options.check_supported()?;
+        let stream = connect(listener.local_addr()?, &options)?;
+        assert!(stream.nodelay()?);
+        assert!(SockRef::from(&stream).keepalive()?);
+
+        let options = SocketOptions::new().bind_device("lo");
+        let result = options.check_supported();
+        assert_eq!(result.is_ok(), BIND_DEVICE_SUPPORTED);
+        if let Err(e) = result {
+            assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+        }
+        Ok(())
+    }
+}
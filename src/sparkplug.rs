@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::db::DataType;
+use super::tag::Tag;
+use super::tag_map::TagMap;
+
+// Sparkplug B metric data types
+pub mod datatypes {
+    pub const INT16: u32 = 2;
+    pub const INT32: u32 = 3;
+    pub const INT64: u32 = 4;
+    pub const UINT16: u32 = 6;
+    pub const UINT32: u32 = 7;
+    pub const UINT64: u32 = 8;
+    pub const FLOAT: u32 = 9;
+    pub const DOUBLE: u32 = 10;
+    pub const BOOLEAN: u32 = 11;
+    pub const STRING: u32 = 12;
+}
+
+pub fn sparkplug_datatype(data_type: &DataType) -> u32 {
+    match data_type {
+        DataType::BIT => datatypes::BOOLEAN,
+        DataType::SWORD => datatypes::INT16,
+        DataType::UWORD => datatypes::UINT16,
+        DataType::SDWORD => datatypes::INT32,
+        DataType::UDWORD => datatypes::UINT32,
+        DataType::FLOAT => datatypes::FLOAT,
+        DataType::DOUBLE => datatypes::DOUBLE,
+        DataType::SLWORD => datatypes::INT64,
+        DataType::ULWORD => datatypes::UINT64,
+        DataType::CUSTOM { .. } | DataType::RAW(_) => datatypes::STRING,
+    }
+}
+
+/// Encodes tag batches as Sparkplug B protobuf payloads. Metrics are named
+/// after the `TagMap` entry of their device when one exists; NBIRTH assigns
+/// an alias per device, starting at 1, that NDATA payloads then use instead
+/// of the name.
+#[derive(Debug, Default)]
+pub struct SparkplugEncoder {
+    seq: u8,
+    bd_seq: u64,
+    aliases: HashMap<String, u64>,
+    names: HashMap<String, String>,
+}
+
+impl SparkplugEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tag_map(mut self, tag_map: &TagMap) -> Self {
+        for entry in tag_map.iter() {
+            self.names.insert(entry.device.clone(), entry.name.clone());
+        }
+        self
+    }
+
+    /// NBIRTH: resets the sequence number and declares every metric with
+    /// name, alias and data type.
+    pub fn birth(&mut self, tags: &[Tag]) -> Vec<u8> {
+        self.seq = 0;
+        self.aliases.clear();
+        let timestamp = timestamp_millis();
+
+        let mut payload = Vec::new();
+        write_varint_field(&mut payload, 1, timestamp);
+
+        let mut bd_seq = Vec::new();
+        write_string_field(&mut bd_seq, 1, "bdSeq");
+        write_varint_field(&mut bd_seq, 3, timestamp);
+        write_varint_field(&mut bd_seq, 4, datatypes::INT64 as u64);
+        write_varint_field(&mut bd_seq, 11, self.bd_seq);
+        write_bytes_field(&mut payload, 2, &bd_seq);
+
+        for tag in tags {
+            let alias = self.aliases.len() as u64 + 1;
+            self.aliases.insert(tag.device.clone(), alias);
+            let name = self.names.get(&tag.device).unwrap_or(&tag.device);
+            let metric = encode_metric(Some(name), Some(alias), timestamp, tag);
+            write_bytes_field(&mut payload, 2, &metric);
+        }
+        self.write_seq(&mut payload);
+        self.bd_seq = self.bd_seq.wrapping_add(1);
+        payload
+    }
+
+    /// NDATA: metrics by alias only. Devices not declared in the last
+    /// NBIRTH are sent by name without an alias.
+    pub fn data(&mut self, tags: &[Tag]) -> Vec<u8> {
+        let timestamp = timestamp_millis();
+        let mut payload = Vec::new();
+        write_varint_field(&mut payload, 1, timestamp);
+        for tag in tags {
+            let metric = match self.aliases.get(&tag.device) {
+                Some(alias) => encode_metric(None, Some(*alias), timestamp, tag),
+                None => {
+                    let name = self.names.get(&tag.device).unwrap_or(&tag.device);
+                    encode_metric(Some(name), None, timestamp, tag)
+                }
+            };
+            write_bytes_field(&mut payload, 2, &metric);
+        }
+        self.write_seq(&mut payload);
+        payload
+    }
+
+    fn write_seq(&mut self, payload: &mut Vec<u8>) {
+        write_varint_field(payload, 3, self.seq as u64);
+        self.seq = self.seq.wrapping_add(1);
+    }
+}
+
+fn timestamp_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn encode_metric(name: Option<&str>, alias: Option<u64>, timestamp: u64, tag: &Tag) -> Vec<u8> {
+    let mut metric = Vec::new();
+    if let Some(name) = name {
+        write_string_field(&mut metric, 1, name);
+    }
+    if let Some(alias) = alias {
+        write_varint_field(&mut metric, 2, alias);
+    }
+    write_varint_field(&mut metric, 3, timestamp);
+    let datatype = sparkplug_datatype(&tag.data_type);
+    write_varint_field(&mut metric, 4, datatype as u64);
+
+    let value = match tag.value {
+        Some(ref value) => value,
+        None => {
+            write_varint_field(&mut metric, 7, 1);
+            return metric;
+        }
+    };
+    let written = match datatype {
+        datatypes::BOOLEAN => value
+            .parse::<i64>()
+            .map(|v| write_varint_field(&mut metric, 14, (v != 0) as u64))
+            .is_ok(),
+        datatypes::INT16 | datatypes::INT32 | datatypes::UINT16 | datatypes::UINT32 => value
+            .parse::<i64>()
+            .map(|v| write_varint_field(&mut metric, 10, v as u32 as u64))
+            .is_ok(),
+        datatypes::INT64 | datatypes::UINT64 => value
+            .parse::<i128>()
+            .map(|v| write_varint_field(&mut metric, 11, v as u64))
+            .is_ok(),
+        datatypes::FLOAT => value
+            .parse::<f32>()
+            .map(|v| {
+                write_key(&mut metric, 12, 5);
+                metric.extend_from_slice(&v.to_le_bytes());
+            })
+            .is_ok(),
+        datatypes::DOUBLE => value
+            .parse::<f64>()
+            .map(|v| {
+                write_key(&mut metric, 13, 1);
+                metric.extend_from_slice(&v.to_le_bytes());
+            })
+            .is_ok(),
+        _ => {
+            write_string_field(&mut metric, 15, value);
+            true
+        }
+    };
+    if !written {
+        write_varint_field(&mut metric, 7, 1);
+    }
+    metric
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn write_key(buffer: &mut Vec<u8>, field: u32, wire_type: u8) {
+    write_varint(buffer, ((field as u64) << 3) | wire_type as u64);
+}
+
+fn write_varint_field(buffer: &mut Vec<u8>, field: u32, value: u64) {
+    write_key(buffer, field, 0);
+    write_varint(buffer, value);
+}
+
+fn write_bytes_field(buffer: &mut Vec<u8>, field: u32, value: &[u8]) {
+    write_key(buffer, field, 2);
+    write_varint(buffer, value.len() as u64);
+    buffer.extend_from_slice(value);
+}
+
+fn write_string_field(buffer: &mut Vec<u8>, field: u32, value: &str) {
+    write_bytes_field(buffer, field, value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests_sparkplug {
+    use super::*;
+
+    #[test]
+    fn test_encode_metric() {
+        let tag = Tag::new("D100".to_string(), Some("-2".to_string()), DataType::SWORD);
+        let metric = encode_metric(Some("speed"), Some(1), 0, &tag);
+        assert_eq!(
+            metric,
+            vec![
+                0x0A, 5, b's', b'p', b'e', b'e', b'd', // name
+                0x10, 1, // alias
+                0x18, 0, // timestamp
+                0x20, 2, // datatype Int16
+                0x50, 0xFE, 0xFF, 0xFF, 0xFF, 0x0F, // int_value
+            ]
+        );
+
+        let tag = Tag::new("M0".to_string(), None, DataType::BIT);
+        let metric = encode_metric(None, Some(3), 0, &tag);
+        assert_eq!(metric, vec![0x10, 3, 0x18, 0, 0x20, 11, 0x38, 1]);
+
+        let metric = encode_metric(Some("M0"), None, 0, &tag);
+        assert_eq!(
+            metric,
+            vec![0x0A, 2, b'M', b'0', 0x18, 0, 0x20, 11, 0x38, 1]
+        );
+    }
+
+    #[test]
+    fn test_birth_and_data_sequence() {
+        let mut tag_map = TagMap::new();
+        tag_map.insert("speed", "D100", DataType::UWORD);
+        let mut encoder = SparkplugEncoder::new().with_tag_map(&tag_map);
+        let tags = vec![Tag::new(
+            "D100".to_string(),
+            Some("7".to_string()),
+            DataType::UWORD,
+        )];
+
+        let birth = encoder.birth(&tags);
+        assert!(birth.windows(5).any(|w| w == b"speed"));
+        assert_eq!(&birth[birth.len() - 2..], &[0x18, 0]);
+
+        // the first declared metric has alias 1, alias 0 is never used
+        assert!(birth.windows(3).any(|w| w == [0x10, 1, 0x18]));
+
+        let data = encoder.data(&tags);
+        assert!(!data.windows(5).any(|w| w == b"speed"));
+        assert!(data.windows(3).any(|w| w == [0x10, 1, 0x18]));
+        assert_eq!(&data[data.len() - 2..], &[0x18, 1]);
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Outcome counters of the transactions touching one device or device range
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TargetStats {
+    pub transactions: u64,
+    pub failures: u64,
+    // MC completion code of the last failure, `None` for transport errors
+    pub last_error_code: Option<u16>,
+    pub last_error: Option<String>,
+    // transaction ID of the last failure
+    pub last_error_transaction: Option<u64>,
+}
+
+// Request queue of a `ClientHandle`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueueStats {
+    // requests waiting for the client thread
+    pub depth: usize,
+    pub max_depth: usize,
+    // requests refused by `OverflowPolicy::Reject`
+    pub rejected: u64,
+    // requests failed by `OverflowPolicy::DropOldest`
+    pub dropped: u64,
+    // writes merged into a queued write of the same range
+    pub coalesced: u64,
+}
+
+// Counters of the request/response round trips of one client
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientStats {
+    pub transactions: u64,
+    pub failures: u64,
+    // round trips longer than the slow transaction threshold
+    pub slow_transactions: u64,
+    pub total_rtt: Duration,
+    pub max_rtt: Duration,
+    // keyed by device range ("W2000-W2100") or random access device list
+    pub targets: HashMap<String, TargetStats>,
+    // PLC clock minus host clock in seconds at the last `ClockMonitor` check
+    pub clock_drift: Option<i64>,
+    // late or duplicated responses to earlier requests that were dropped
+    pub stale_responses: u64,
+}
+
+impl ClientStats {
+    pub(crate) fn record(
+        &mut self,
+        id: u64,
+        target: &str,
+        rtt: Duration,
+        status: Result<u16, String>,
+        is_slow: bool,
+    ) {
+        self.transactions += 1;
+        if is_slow {
+            self.slow_transactions += 1;
+        }
+        self.total_rtt += rtt;
+        self.max_rtt = self.max_rtt.max(rtt);
+
+        let target = self.targets.entry(target.to_string()).or_default();
+        target.transactions += 1;
+        let (code, error) = match status {
+            Ok(0) => return,
+            Ok(code) => (Some(code), format!("0x{:04X}", code)),
+            Err(e) => (None, e),
+        };
+        self.failures += 1;
+        target.failures += 1;
+        target.last_error_code = code;
+        target.last_error = Some(error);
+        target.last_error_transaction = Some(id);
+    }
+
+    pub fn average_rtt(&self) -> Option<Duration> {
+        match self.transactions {
+            0 => None,
+            count => Some(self.total_rtt / count as u32),
+        }
+    }
+
+    /// Targets that failed at least once, most failures first.
+    pub fn error_heat_map(&self) -> Vec<(&str, &TargetStats)> {
+        let mut targets: Vec<(&str, &TargetStats)> = self
+            .targets
+            .iter()
+            .filter(|(_, stats)| stats.failures > 0)
+            .map(|(target, stats)| (target.as_str(), stats))
+            .collect();
+        targets.sort_by(|a, b| b.1.failures.cmp(&a.1.failures).then(a.0.cmp(b.0)));
+        targets
+    }
+}
@@ -1,3 +1,4 @@
+use super::client::get_device_type;
 use super::db::DataType;
 use std::fmt;
 use std::option::Option;
@@ -7,9 +8,122 @@ pub struct Tag {
     pub device: String,
     pub value: Option<String>,
     pub data_type: DataType,
+    // set from a `TagMap` entry, see `TagMap::annotate`
+    pub metadata: Option<TagMetadata>,
+    // how `Display` shows the value
+    pub format: ValueFormat,
 }
 
-#[derive(Debug)]
+/// Display format of numeric values. Values that are not numbers, e.g.
+/// undecoded raw bytes, are shown as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ValueFormat {
+    #[default]
+    Decimal,
+    // GX Works monitor style, e.g. "1A2BH", padded to the data type width
+    Hex,
+    // groups of four bits, most significant first
+    Binary,
+    Float {
+        precision: u8,
+    },
+}
+
+impl ValueFormat {
+    /// Hex for link relays and link registers (B, W, SB, SW), which GX
+    /// Works numbers and monitors in hex, decimal for everything else.
+    pub fn for_device(device: &str) -> Self {
+        match get_device_type(device).as_deref() {
+            Ok("B") | Ok("W") | Ok("SB") | Ok("SW") => ValueFormat::Hex,
+            _ => ValueFormat::Decimal,
+        }
+    }
+
+    /// Parses "dec", "hex", "bin" or "float:N" with N decimal places.
+    pub fn parse(format: &str) -> Option<Self> {
+        match format {
+            "dec" => Some(ValueFormat::Decimal),
+            "hex" => Some(ValueFormat::Hex),
+            "bin" => Some(ValueFormat::Binary),
+            _ => {
+                let precision = format.strip_prefix("float:")?.parse().ok()?;
+                Some(ValueFormat::Float { precision })
+            }
+        }
+    }
+
+    pub fn apply(&self, value: &str, data_type: &DataType) -> String {
+        let bits = match data_type {
+            // hex bytes already, not a number
+            DataType::RAW(_) => return value.to_string(),
+            DataType::BIT => 1,
+            data_type => data_type.size().max(1) as u32 * 8,
+        };
+        let mask = u64::MAX >> (64 - bits.min(64));
+        match self {
+            ValueFormat::Decimal => value.to_string(),
+            ValueFormat::Float { precision } => match value.parse::<f64>() {
+                Ok(number) => format!("{:.*}", *precision as usize, number),
+                Err(_) => value.to_string(),
+            },
+            ValueFormat::Hex => match integer_bits(value) {
+                Some(number) => {
+                    format!(
+                        "{:0width$X}H",
+                        number & mask,
+                        width = bits.div_ceil(4) as usize
+                    )
+                }
+                None => value.to_string(),
+            },
+            ValueFormat::Binary => match integer_bits(value) {
+                Some(number) => {
+                    let digits = format!("{:0width$b}", number & mask, width = bits as usize);
+                    let groups: Vec<&str> = digits
+                        .as_bytes()
+                        .rchunks(4)
+                        .rev()
+                        .map(|group| std::str::from_utf8(group).unwrap_or_default())
+                        .collect();
+                    groups.join(" ")
+                }
+                None => value.to_string(),
+            },
+        }
+    }
+}
+
+// The name `ValueFormat::parse` accepts, e.g. "hex" or "float:2"
+impl fmt::Display for ValueFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueFormat::Decimal => write!(f, "dec"),
+            ValueFormat::Hex => write!(f, "hex"),
+            ValueFormat::Binary => write!(f, "bin"),
+            ValueFormat::Float { precision } => write!(f, "float:{}", precision),
+        }
+    }
+}
+
+// two's complement bits of a decimal integer value
+fn integer_bits(value: &str) -> Option<u64> {
+    value
+        .parse::<u64>()
+        .ok()
+        .or_else(|| value.parse::<i64>().ok().map(|number| number as u64))
+}
+
+// Descriptive data of a tag that the PLC does not know about
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TagMetadata {
+    pub name: String,
+    pub unit: String,
+    pub description: String,
+    // decimal places of the value in exported data
+    pub precision: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
 pub struct QueryTag {
     pub device: String,
     pub data_type: DataType,
@@ -21,16 +135,90 @@ impl Tag {
             device,
             value,
             data_type,
+            metadata: None,
+            format: ValueFormat::default(),
+        }
+    }
+
+    pub fn with_format(mut self, format: ValueFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: TagMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// The value rounded to the precision of the metadata, if any.
+    pub fn formatted_value(&self) -> Option<String> {
+        let value = self.value.as_ref()?;
+        let precision = self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.precision);
+        match (precision, value.parse::<f64>()) {
+            (Some(precision), Ok(number)) => Some(format!("{:.*}", precision as usize, number)),
+            _ => Some(value.clone()),
         }
     }
 
     pub fn is_success(&self) -> bool {
         self.value.is_some()
     }
+
+    /// Bytes of a value read with `decode` set to false or as `DataType::RAW`.
+    pub fn raw_bytes(&self) -> Option<Vec<u8>> {
+        self.value
+            .as_ref()
+            .and_then(|value| hex::decode(value).ok())
+    }
 }
 
 impl fmt::Display for Tag {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}, {:?}, {:?}", self.device, self.value, self.data_type)
+        let value = self
+            .value
+            .as_ref()
+            .map(|value| self.format.apply(value, &self.data_type));
+        write!(f, "{}, {:?}, {:?}", self.device, value, self.data_type)
+    }
+}
+
+#[cfg(test)]
+mod tests_tag {
+    use super::*;
+
+    #[test]
+    fn test_value_format() {
+        assert_eq!(ValueFormat::Hex.apply("6699", &DataType::UWORD), "1A2BH");
+        assert_eq!(ValueFormat::Hex.apply("-1", &DataType::SWORD), "FFFFH");
+        assert_eq!(ValueFormat::Hex.apply("1", &DataType::UDWORD), "00000001H");
+        assert_eq!(
+            ValueFormat::Binary.apply("-32767", &DataType::SWORD),
+            "1000 0000 0000 0001"
+        );
+        assert_eq!(ValueFormat::Binary.apply("1", &DataType::BIT), "1");
+        assert_eq!(
+            ValueFormat::Float { precision: 2 }.apply("1.005e1", &DataType::FLOAT),
+            "10.05"
+        );
+        assert_eq!(ValueFormat::Hex.apply("ff80", &DataType::UWORD), "ff80");
+        assert_eq!(ValueFormat::for_device("W1F"), ValueFormat::Hex);
+        assert_eq!(ValueFormat::for_device("SB0"), ValueFormat::Hex);
+        assert_eq!(ValueFormat::for_device("D100"), ValueFormat::Decimal);
+        assert_eq!(
+            ValueFormat::parse("float:3"),
+            Some(ValueFormat::Float { precision: 3 })
+        );
+        for format in ["dec", "hex", "bin", "float:3"] {
+            assert_eq!(ValueFormat::parse(format).unwrap().to_string(), format);
+        }
+        assert_eq!(ValueFormat::parse("oct"), None);
+
+        let tag = Tag::new("D0".to_string(), Some("10".to_string()), DataType::SWORD);
+        assert_eq!(tag.to_string(), "D0, Some(\"10\"), SWORD");
+        let tag = tag.with_format(ValueFormat::Binary);
+        assert_eq!(tag.to_string(), "D0, Some(\"0000 0000 0000 1010\"), SWORD");
     }
 }
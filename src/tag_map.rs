@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+
+use super::client::get_device_type;
+use super::db::{DataType, DeviceConstants};
+use super::tag::{QueryTag, Tag, TagMetadata, ValueFormat};
+
+#[derive(Debug, Clone)]
+pub struct TagEntry {
+    pub name: String,
+    pub device: String,
+    pub data_type: DataType,
+    // doubles as the description of the tag
+    pub comment: String,
+    pub unit: String,
+    pub precision: Option<u8>,
+    pub format: ValueFormat,
+}
+
+impl TagEntry {
+    pub fn metadata(&self) -> TagMetadata {
+        TagMetadata {
+            name: self.name.clone(),
+            unit: self.unit.clone(),
+            description: self.comment.clone(),
+            precision: self.precision,
+        }
+    }
+}
+
+// Symbolic names mapped to PLC devices
+#[derive(Debug, Default)]
+pub struct TagMap {
+    entries: HashMap<String, TagEntry>,
+}
+
+impl TagMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, device: &str, data_type: DataType) {
+        self.entries.insert(
+            name.to_string(),
+            TagEntry {
+                name: name.to_string(),
+                device: device.to_string(),
+                data_type,
+                comment: String::new(),
+                unit: String::new(),
+                precision: None,
+                format: ValueFormat::default(),
+            },
+        );
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TagEntry> {
+        self.entries.get(name)
+    }
+
+    /// Sets unit, description and decimal precision of an entry. Returns
+    /// false if there is no entry `name`.
+    pub fn describe(
+        &mut self,
+        name: &str,
+        unit: &str,
+        description: &str,
+        precision: Option<u8>,
+    ) -> bool {
+        match self.entries.get_mut(name) {
+            Some(entry) => {
+                entry.unit = unit.to_string();
+                entry.comment = description.to_string();
+                entry.precision = precision;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the display format of an entry. Returns false if there is no
+    /// entry `name`.
+    pub fn set_format(&mut self, name: &str, format: ValueFormat) -> bool {
+        match self.entries.get_mut(name) {
+            Some(entry) => {
+                entry.format = format;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Attaches the metadata and display format of the matching entry to
+    /// every tag read from a mapped device.
+    pub fn annotate(&self, tags: &mut [Tag]) {
+        for tag in tags.iter_mut() {
+            if let Some(entry) = self.find_by_device(&tag.device) {
+                tag.metadata = Some(entry.metadata());
+                tag.format = entry.format;
+            }
+        }
+    }
+
+    pub fn find_by_device(&self, device: &str) -> Option<&TagEntry> {
+        self.entries.values().find(|entry| entry.device == device)
+    }
+
+    pub fn query_tag(&self, name: &str) -> Option<QueryTag> {
+        self.get(name).map(|entry| QueryTag {
+            device: entry.device.clone(),
+            data_type: entry.data_type.clone(),
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TagEntry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Exports the map as a JSON address space, one variable node per tag
+    /// sorted by name, so external servers can generate their nodes from it.
+    /// Nodes carry the unit, decimal precision (null if unset) and display
+    /// format ("dec", "hex", "bin" or "float:N") of their tag.
+    pub fn to_address_space_json(&self) -> String {
+        let mut entries: Vec<&TagEntry> = self.entries.values().collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let nodes: Vec<String> = entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{{\"node_id\":{},\"browse_name\":{},\"device\":{},\"data_type\":{},\"description\":{},\"unit\":{},\"precision\":{},\"format\":{}}}",
+                    json_string(&format!("ns=1;s={}", entry.name)),
+                    json_string(&entry.name),
+                    json_string(&entry.device),
+                    json_string(opcua_data_type(&entry.data_type)),
+                    json_string(&entry.comment),
+                    json_string(&entry.unit),
+                    entry
+                        .precision
+                        .map_or("null".to_string(), |precision| precision.to_string()),
+                    json_string(&entry.format.to_string()),
+                )
+            })
+            .collect();
+        format!("{{\"nodes\":[{}]}}", nodes.join(","))
+    }
+
+    /// Builds a map from a GX Works2/3 device comment CSV export. Comma and
+    /// tab separated files are accepted and rows that do not start with a
+    /// known device (title and header lines) are skipped.
+    ///
+    /// The comment becomes the tag name. A type marker in the comment, e.g.
+    /// `Line speed [REAL]`, sets the data type; otherwise it follows the
+    /// device kind. Rows with an empty or duplicate comment are named after
+    /// their device.
+    pub fn from_gx_works_csv<R: Read>(
+        mut reader: R,
+        plc_type: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+
+        let mut tag_map = TagMap::new();
+        for line in content.lines() {
+            let line = line.trim_start_matches('\u{feff}');
+            let delimiter = if line.contains('\t') { '\t' } else { ',' };
+            let fields = split_csv_line(line, delimiter);
+            let device = match fields.first() {
+                Some(device) => device.trim().to_uppercase(),
+                None => continue,
+            };
+            let device_kind = match get_device_type(&device) {
+                Ok(name)
+                    if device.len() > name.len()
+                        && device[name.len()..].chars().all(|c| c.is_ascii_hexdigit()) =>
+                {
+                    match DeviceConstants::get_device_type(plc_type, &name) {
+                        Ok(kind) => kind,
+                        Err(_) => continue,
+                    }
+                }
+                _ => continue,
+            };
+
+            let comment = fields.get(1).map(|s| s.trim()).unwrap_or("").to_string();
+            let (label, data_type) = parse_comment(&comment, device_kind);
+            let name = if label.is_empty() || tag_map.entries.contains_key(&label) {
+                device.clone()
+            } else {
+                label
+            };
+            let format = ValueFormat::for_device(&device);
+            tag_map.entries.insert(
+                name.clone(),
+                TagEntry {
+                    name,
+                    device,
+                    data_type,
+                    comment,
+                    unit: String::new(),
+                    precision: None,
+                    format,
+                },
+            );
+        }
+        Ok(tag_map)
+    }
+}
+
+fn opcua_data_type(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::BIT => "Boolean",
+        DataType::SWORD => "Int16",
+        DataType::UWORD => "UInt16",
+        DataType::SDWORD => "Int32",
+        DataType::UDWORD => "UInt32",
+        DataType::FLOAT => "Float",
+        DataType::DOUBLE => "Double",
+        DataType::SLWORD => "Int64",
+        DataType::ULWORD => "UInt64",
+        DataType::CUSTOM { .. } | DataType::RAW(_) => "String",
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut output = String::with_capacity(value.len() + 2);
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
+
+fn split_csv_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            c if c == delimiter && !in_quotes => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+// GX Works type names (INT, REAL, ...) and the word/bit aliases
+pub(crate) fn parse_type_name(name: &str) -> Option<DataType> {
+    match name.trim().to_uppercase().as_str() {
+        "BOOL" | "BIT" => Some(DataType::BIT),
+        "INT" | "WORD" => Some(DataType::SWORD),
+        "UINT" => Some(DataType::UWORD),
+        "DINT" => Some(DataType::SDWORD),
+        "UDINT" | "DWORD" => Some(DataType::UDWORD),
+        "REAL" | "FLOAT" => Some(DataType::FLOAT),
+        "LREAL" | "DOUBLE" => Some(DataType::DOUBLE),
+        "LINT" => Some(DataType::SLWORD),
+        "ULINT" => Some(DataType::ULWORD),
+        _ => None,
+    }
+}
+
+// Splits a trailing `[TYPE]` marker off the comment, falling back to the
+// natural type of the device kind.
+fn parse_comment(comment: &str, device_kind: &str) -> (String, DataType) {
+    let default_type = match device_kind {
+        DeviceConstants::BIT_DEVICE => DataType::BIT,
+        DeviceConstants::DWORD_DEVICE => DataType::SDWORD,
+        _ => DataType::SWORD,
+    };
+    if let (Some(start), true) = (comment.rfind('['), comment.ends_with(']')) {
+        let marker = &comment[start + 1..comment.len() - 1];
+        if let Some(data_type) = parse_type_name(marker) {
+            return (comment[..start].trim().to_string(), data_type);
+        }
+    }
+    (comment.to_string(), default_type)
+}
+
+#[cfg(test)]
+mod tests_tag_map {
+    use super::*;
+
+    #[test]
+    fn test_from_gx_works_csv() -> Result<(), Box<dyn Error>> {
+        let csv = "\u{feff}Line1 comments\n\
+                   Device Name,Comment\n\
+                   M100,Start button\n\
+                   D200,\"Line speed [REAL]\"\n\
+                   D202,\"Batch \"\"count\"\" [UDINT]\"\n\
+                   X1F,Start button\n\
+                   W10,\n";
+        let tag_map = TagMap::from_gx_works_csv(csv.as_bytes(), "Q")?;
+        assert_eq!(tag_map.len(), 5);
+
+        let start = tag_map.get("Start button").unwrap();
+        assert_eq!(start.device, "M100");
+        assert_eq!(start.data_type, DataType::BIT);
+
+        let speed = tag_map.get("Line speed").unwrap();
+        assert_eq!(speed.device, "D200");
+        assert_eq!(speed.data_type, DataType::FLOAT);
+        assert_eq!(speed.comment, "Line speed [REAL]");
+
+        let count = tag_map.get("Batch \"count\"").unwrap();
+        assert_eq!(count.data_type, DataType::UDWORD);
+
+        assert_eq!(tag_map.get("X1F").unwrap().data_type, DataType::BIT);
+        assert_eq!(tag_map.get("W10").unwrap().data_type, DataType::SWORD);
+        assert_eq!(tag_map.query_tag("Line speed").unwrap().device, "D200");
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_gx_works_csv_tab_separated() -> Result<(), Box<dyn Error>> {
+        let csv = "Device\tComment\nD0\tSetpoint [INT]\n";
+        let tag_map = TagMap::from_gx_works_csv(csv.as_bytes(), "iQ-R")?;
+        assert_eq!(tag_map.len(), 1);
+        assert_eq!(tag_map.find_by_device("D0").unwrap().name, "Setpoint");
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_address_space_json() {
+        let mut tag_map = TagMap::new();
+        tag_map.insert("speed", "D200", DataType::FLOAT);
+        tag_map.insert("run \"A\"", "M0", DataType::BIT);
+        tag_map.describe("speed", "m/min", "Line speed", Some(1));
+        tag_map.set_format("speed", ValueFormat::Float { precision: 1 });
+        assert_eq!(
+            tag_map.to_address_space_json(),
+            "{\"nodes\":[\
+             {\"node_id\":\"ns=1;s=run \\\"A\\\"\",\"browse_name\":\"run \\\"A\\\"\",\"device\":\"M0\",\"data_type\":\"Boolean\",\"description\":\"\",\"unit\":\"\",\"precision\":null,\"format\":\"dec\"},\
+             {\"node_id\":\"ns=1;s=speed\",\"browse_name\":\"speed\",\"device\":\"D200\",\"data_type\":\"Float\",\"description\":\"Line speed\",\"unit\":\"m/min\",\"precision\":1,\"format\":\"float:1\"}\
+             ]}"
+        );
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut tag_map = TagMap::new();
+        tag_map.insert("speed", "D200", DataType::FLOAT);
+        assert!(tag_map.describe("speed", "m/min", "Line speed", Some(2)));
+        assert!(!tag_map.describe("missing", "", "", None));
+
+        let mut tags = vec![
+            Tag::new("D200".to_string(), Some("1.5".to_string()), DataType::FLOAT),
+            Tag::new("D300".to_string(), Some("7".to_string()), DataType::SWORD),
+        ];
+        tag_map.annotate(&mut tags);
+        let metadata = tags[0].metadata.as_ref().unwrap();
+        assert_eq!(metadata.name, "speed");
+        assert_eq!(metadata.unit, "m/min");
+        assert_eq!(metadata.description, "Line speed");
+        assert_eq!(tags[0].formatted_value(), Some("1.50".to_string()));
+        assert!(tags[1].metadata.is_none());
+        assert_eq!(tags[1].formatted_value(), Some("7".to_string()));
+
+        tag_map.insert("status", "W10", DataType::UWORD);
+        assert!(tag_map.set_format("status", ValueFormat::Hex));
+        let mut tags = vec![Tag::new(
+            "W10".to_string(),
+            Some("255".to_string()),
+            DataType::UWORD,
+        )];
+        tag_map.annotate(&mut tags);
+        assert_eq!(tags[0].to_string(), "W10, Some(\"00FFH\"), UWORD");
+    }
+}
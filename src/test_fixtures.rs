@@ -0,0 +1,918 @@
+//! Canonical MC protocol frames for conformance tests, enabled with the
+//! `test-fixtures` feature. Every command the client sends is listed for 3E
+//! and 4E frames in binary and ASCII, laid out as the request and response
+//! data of the command are described in the MELSEC Communication Protocol
+//! Reference Manual for the Q/L series. The requests are built with the
+//! settings of the manual examples: network 0, PC FFh, module I/O 03FFh,
+//! station 0, monitoring timer 10h (4 s) and, for 4E, serial number 0. Use
+//! `client_for` to get a client configured the same way.
+//!
+//! The monitor and label access commands and remote PAUSE, latch clear and
+//! error LED off have no fixtures, as no client call sends them.
+
+use std::time::Duration;
+
+use super::client::Client;
+use super::db::consts;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fixture {
+    pub name: &'static str,
+    pub use_e4: bool,
+    pub comm_type: &'static str,
+    pub request: &'static [u8],
+    pub response: &'static [u8],
+}
+
+pub const FIXTURES: &[Fixture] = &[
+    // D100 to D102 read as words: 1995h, 1202h, 1130h
+    Fixture {
+        name: "batch_read_words",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x10, 0x00, 0x01, 0x04, 0x00,
+            0x00, 0x64, 0x00, 0x00, 0xA8, 0x03, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00, 0x00, 0x95, 0x19, 0x02,
+            0x12, 0x30, 0x11,
+        ],
+    },
+    Fixture {
+        name: "batch_read_words",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF000018001004010000D*0001000003",
+        response: b"D00000FF03FF0000100000199512021130",
+    },
+    Fixture {
+        name: "batch_read_words",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x10,
+            0x00, 0x01, 0x04, 0x00, 0x00, 0x64, 0x00, 0x00, 0xA8, 0x03, 0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00,
+            0x00, 0x95, 0x19, 0x02, 0x12, 0x30, 0x11,
+        ],
+    },
+    Fixture {
+        name: "batch_read_words",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF000018001004010000D*0001000003",
+        response: b"D4000000000000FF03FF0000100000199512021130",
+    },
+    // M100 to M107 read as bits: 0, 0, 0, 1, 0, 0, 1, 1
+    Fixture {
+        name: "batch_read_bits",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x10, 0x00, 0x01, 0x04, 0x01,
+            0x00, 0x64, 0x00, 0x00, 0x90, 0x08, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+            0x11,
+        ],
+    },
+    Fixture {
+        name: "batch_read_bits",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF000018001004010001M*0001000008",
+        response: b"D00000FF03FF00000C000000010011",
+    },
+    Fixture {
+        name: "batch_read_bits",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x10,
+            0x00, 0x01, 0x04, 0x01, 0x00, 0x64, 0x00, 0x00, 0x90, 0x08, 0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x06, 0x00, 0x00,
+            0x00, 0x00, 0x01, 0x00, 0x11,
+        ],
+    },
+    Fixture {
+        name: "batch_read_bits",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF000018001004010001M*0001000008",
+        response: b"D4000000000000FF03FF00000C000000010011",
+    },
+    // D100 to D102 written as words: 1995h, 1202h, 1130h
+    Fixture {
+        name: "batch_write_words",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x12, 0x00, 0x10, 0x00, 0x01, 0x14, 0x00,
+            0x00, 0x64, 0x00, 0x00, 0xA8, 0x03, 0x00, 0x95, 0x19, 0x02, 0x12, 0x30, 0x11,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    Fixture {
+        name: "batch_write_words",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF000024001014010000D*0001000003199512021130",
+        response: b"D00000FF03FF0000040000",
+    },
+    Fixture {
+        name: "batch_write_words",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x12, 0x00, 0x10,
+            0x00, 0x01, 0x14, 0x00, 0x00, 0x64, 0x00, 0x00, 0xA8, 0x03, 0x00, 0x95, 0x19, 0x02,
+            0x12, 0x30, 0x11,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00,
+            0x00,
+        ],
+    },
+    Fixture {
+        name: "batch_write_words",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF000024001014010000D*0001000003199512021130",
+        response: b"D4000000000000FF03FF0000040000",
+    },
+    // M100 to M107 written as bits: 1, 1, 0, 0, 1, 1, 0, 0
+    Fixture {
+        name: "batch_write_bits",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x10, 0x00, 0x10, 0x00, 0x01, 0x14, 0x01,
+            0x00, 0x64, 0x00, 0x00, 0x90, 0x08, 0x00, 0x11, 0x00, 0x11, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    Fixture {
+        name: "batch_write_bits",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF000020001014010001M*000100000811001100",
+        response: b"D00000FF03FF0000040000",
+    },
+    Fixture {
+        name: "batch_write_bits",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x10, 0x00, 0x10,
+            0x00, 0x01, 0x14, 0x01, 0x00, 0x64, 0x00, 0x00, 0x90, 0x08, 0x00, 0x11, 0x00, 0x11,
+            0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00,
+            0x00,
+        ],
+    },
+    Fixture {
+        name: "batch_write_bits",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF000020001014010001M*000100000811001100",
+        response: b"D4000000000000FF03FF0000040000",
+    },
+    // D0 and M100 as word points (0550h, 0001h), D1500 as a dword point (12345678h)
+    Fixture {
+        name: "random_read",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x14, 0x00, 0x10, 0x00, 0x03, 0x04, 0x00,
+            0x00, 0x02, 0x01, 0x00, 0x00, 0x00, 0xA8, 0x64, 0x00, 0x00, 0x90, 0xDC, 0x05, 0x00,
+            0xA8,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x50, 0x05, 0x01,
+            0x00, 0x78, 0x56, 0x34, 0x12,
+        ],
+    },
+    Fixture {
+        name: "random_read",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF0000280010040300000201D*000000M*000100D*001500",
+        response: b"D00000FF03FF00001400000550000112345678",
+    },
+    Fixture {
+        name: "random_read",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x14, 0x00, 0x10,
+            0x00, 0x03, 0x04, 0x00, 0x00, 0x02, 0x01, 0x00, 0x00, 0x00, 0xA8, 0x64, 0x00, 0x00,
+            0x90, 0xDC, 0x05, 0x00, 0xA8,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0A, 0x00, 0x00,
+            0x00, 0x50, 0x05, 0x01, 0x00, 0x78, 0x56, 0x34, 0x12,
+        ],
+    },
+    Fixture {
+        name: "random_read",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF0000280010040300000201D*000000M*000100D*001500",
+        response: b"D4000000000000FF03FF00001400000550000112345678",
+    },
+    // D0 written as a word point (0550h), D1500 as a dword point (12345678h)
+    Fixture {
+        name: "random_write",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x16, 0x00, 0x10, 0x00, 0x02, 0x14, 0x00,
+            0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0xA8, 0x50, 0x05, 0xDC, 0x05, 0x00, 0xA8, 0x78,
+            0x56, 0x34, 0x12,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    Fixture {
+        name: "random_write",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF00002C0010140200000101D*0000000550D*00150012345678",
+        response: b"D00000FF03FF0000040000",
+    },
+    Fixture {
+        name: "random_write",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x16, 0x00, 0x10,
+            0x00, 0x02, 0x14, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0xA8, 0x50, 0x05, 0xDC,
+            0x05, 0x00, 0xA8, 0x78, 0x56, 0x34, 0x12,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00,
+            0x00,
+        ],
+    },
+    Fixture {
+        name: "random_write",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF00002C0010140200000101D*0000000550D*00150012345678",
+        response: b"D4000000000000FF03FF0000040000",
+    },
+    // D0 and D1 (1995h, 1202h) as a word block, M0 to M15 (0030h) as a bit block
+    Fixture {
+        name: "multi_block_read",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x14, 0x00, 0x10, 0x00, 0x06, 0x04, 0x00,
+            0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0xA8, 0x02, 0x00, 0x00, 0x00, 0x00, 0x90, 0x01,
+            0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00, 0x00, 0x95, 0x19, 0x02,
+            0x12, 0x30, 0x00,
+        ],
+    },
+    Fixture {
+        name: "multi_block_read",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF0000280010040600000101D*0000000002M*0000000001",
+        response: b"D00000FF03FF0000100000199512020030",
+    },
+    Fixture {
+        name: "multi_block_read",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x14, 0x00, 0x10,
+            0x00, 0x06, 0x04, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0xA8, 0x02, 0x00, 0x00,
+            0x00, 0x00, 0x90, 0x01, 0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00,
+            0x00, 0x95, 0x19, 0x02, 0x12, 0x30, 0x00,
+        ],
+    },
+    Fixture {
+        name: "multi_block_read",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF0000280010040600000101D*0000000002M*0000000001",
+        response: b"D4000000000000FF03FF0000100000199512020030",
+    },
+    // remote RUN, not forced, devices not cleared
+    Fixture {
+        name: "remote_run",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0A, 0x00, 0x10, 0x00, 0x01, 0x10, 0x00,
+            0x00, 0x01, 0x00, 0x00, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    Fixture {
+        name: "remote_run",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF00001400101001000000010000",
+        response: b"D00000FF03FF0000040000",
+    },
+    Fixture {
+        name: "remote_run",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0A, 0x00, 0x10,
+            0x00, 0x01, 0x10, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00,
+            0x00,
+        ],
+    },
+    Fixture {
+        name: "remote_run",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF00001400101001000000010000",
+        response: b"D4000000000000FF03FF0000040000",
+    },
+    // remote STOP
+    Fixture {
+        name: "remote_stop",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x10, 0x00, 0x02, 0x10, 0x00,
+            0x00, 0x01, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    Fixture {
+        name: "remote_stop",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF0000100010100200000001",
+        response: b"D00000FF03FF0000040000",
+    },
+    Fixture {
+        name: "remote_stop",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x10,
+            0x00, 0x02, 0x10, 0x00, 0x00, 0x01, 0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00,
+            0x00,
+        ],
+    },
+    Fixture {
+        name: "remote_stop",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF0000100010100200000001",
+        response: b"D4000000000000FF03FF0000040000",
+    },
+    // remote RESET
+    Fixture {
+        name: "remote_reset",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x10, 0x00, 0x06, 0x10, 0x00,
+            0x00, 0x01, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    Fixture {
+        name: "remote_reset",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF0000100010100600000001",
+        response: b"D00000FF03FF0000040000",
+    },
+    Fixture {
+        name: "remote_reset",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x10,
+            0x00, 0x06, 0x10, 0x00, 0x00, 0x01, 0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00,
+            0x00,
+        ],
+    },
+    Fixture {
+        name: "remote_reset",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF0000100010100600000001",
+        response: b"D4000000000000FF03FF0000040000",
+    },
+    // CPU model name Q03UDVCPU, model code 0366h
+    Fixture {
+        name: "read_cpu_model",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x06, 0x00, 0x10, 0x00, 0x01, 0x01, 0x00,
+            0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x14, 0x00, 0x00, 0x00, 0x51, 0x30, 0x33,
+            0x55, 0x44, 0x56, 0x43, 0x50, 0x55, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x66,
+            0x03,
+        ],
+    },
+    Fixture {
+        name: "read_cpu_model",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF00000C001001010000",
+        response: b"D00000FF03FF0000180000Q03UDVCPU       0366",
+    },
+    Fixture {
+        name: "read_cpu_model",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x06, 0x00, 0x10,
+            0x00, 0x01, 0x01, 0x00, 0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x14, 0x00, 0x00,
+            0x00, 0x51, 0x30, 0x33, 0x55, 0x44, 0x56, 0x43, 0x50, 0x55, 0x20, 0x20, 0x20, 0x20,
+            0x20, 0x20, 0x20, 0x66, 0x03,
+        ],
+    },
+    Fixture {
+        name: "read_cpu_model",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF00000C001001010000",
+        response: b"D4000000000000FF03FF0000180000Q03UDVCPU       0366",
+    },
+    // loopback test echoing the 4 characters "5A5A"
+    Fixture {
+        name: "loopback_test",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x10, 0x00, 0x19, 0x06, 0x00,
+            0x00, 0x04, 0x00, 0x35, 0x41, 0x35, 0x41,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00, 0x00, 0x04, 0x00, 0x35,
+            0x41, 0x35, 0x41,
+        ],
+    },
+    Fixture {
+        name: "loopback_test",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF00001400100619000000045A5A",
+        response: b"D00000FF03FF00000C000000045A5A",
+    },
+    Fixture {
+        name: "loopback_test",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x10,
+            0x00, 0x19, 0x06, 0x00, 0x00, 0x04, 0x00, 0x35, 0x41, 0x35, 0x41,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00,
+            0x00, 0x04, 0x00, 0x35, 0x41, 0x35, 0x41,
+        ],
+    },
+    Fixture {
+        name: "loopback_test",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF00001400100619000000045A5A",
+        response: b"D4000000000000FF03FF00000C000000045A5A",
+    },
+    // remote password unlock with the 4 character password "1234"
+    Fixture {
+        name: "remote_unlock",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x10, 0x00, 0x30, 0x16, 0x00,
+            0x00, 0x04, 0x00, 0x31, 0x32, 0x33, 0x34,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    Fixture {
+        name: "remote_unlock",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF00001400101630000000041234",
+        response: b"D00000FF03FF0000040000",
+    },
+    Fixture {
+        name: "remote_unlock",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x10,
+            0x00, 0x30, 0x16, 0x00, 0x00, 0x04, 0x00, 0x31, 0x32, 0x33, 0x34,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00,
+            0x00,
+        ],
+    },
+    Fixture {
+        name: "remote_unlock",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF00001400101630000000041234",
+        response: b"D4000000000000FF03FF0000040000",
+    },
+    // remote password lock with the 4 character password "1234"
+    Fixture {
+        name: "remote_lock",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x10, 0x00, 0x31, 0x16, 0x00,
+            0x00, 0x04, 0x00, 0x31, 0x32, 0x33, 0x34,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    Fixture {
+        name: "remote_lock",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF00001400101631000000041234",
+        response: b"D00000FF03FF0000040000",
+    },
+    Fixture {
+        name: "remote_lock",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x10,
+            0x00, 0x31, 0x16, 0x00, 0x00, 0x04, 0x00, 0x31, 0x32, 0x33, 0x34,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00,
+            0x00,
+        ],
+    },
+    Fixture {
+        name: "remote_lock",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF00001400101631000000041234",
+        response: b"D4000000000000FF03FF0000040000",
+    },
+    // ABC.CSV on drive 0 opened for reading without a password, file pointer 1
+    Fixture {
+        name: "open_file",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x17, 0x00, 0x10, 0x00, 0x27, 0x18, 0x00,
+            0x00, 0x20, 0x20, 0x20, 0x20, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x41, 0x42, 0x43,
+            0x2E, 0x43, 0x53, 0x56,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00,
+        ],
+    },
+    Fixture {
+        name: "open_file",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF000023001018270000    000000000007ABC.CSV",
+        response: b"D00000FF03FF00000800000001",
+    },
+    Fixture {
+        name: "open_file",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x17, 0x00, 0x10,
+            0x00, 0x27, 0x18, 0x00, 0x00, 0x20, 0x20, 0x20, 0x20, 0x00, 0x00, 0x00, 0x00, 0x07,
+            0x00, 0x41, 0x42, 0x43, 0x2E, 0x43, 0x53, 0x56,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x04, 0x00, 0x00,
+            0x00, 0x01, 0x00,
+        ],
+    },
+    Fixture {
+        name: "open_file",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF000023001018270000    000000000007ABC.CSV",
+        response: b"D4000000000000FF03FF00000800000001",
+    },
+    // 4 bytes at offset 0 of file pointer 1: DEh, ADh, BEh, EFh
+    Fixture {
+        name: "read_file_chunk",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0E, 0x00, 0x10, 0x00, 0x28, 0x18, 0x00,
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00, 0x00, 0x04, 0x00, 0xDE,
+            0xAD, 0xBE, 0xEF,
+        ],
+    },
+    Fixture {
+        name: "read_file_chunk",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF00001C0010182800000001000000000004",
+        response: b"D00000FF03FF00001000000004DEADBEEF",
+    },
+    Fixture {
+        name: "read_file_chunk",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0E, 0x00, 0x10,
+            0x00, 0x28, 0x18, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00,
+            0x00, 0x04, 0x00, 0xDE, 0xAD, 0xBE, 0xEF,
+        ],
+    },
+    Fixture {
+        name: "read_file_chunk",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF00001C0010182800000001000000000004",
+        response: b"D4000000000000FF03FF00001000000004DEADBEEF",
+    },
+    // file pointer 1 closed
+    Fixture {
+        name: "close_file",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0A, 0x00, 0x10, 0x00, 0x2A, 0x18, 0x00,
+            0x00, 0x01, 0x00, 0x00, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    Fixture {
+        name: "close_file",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF0000140010182A000000010000",
+        response: b"D00000FF03FF0000040000",
+    },
+    Fixture {
+        name: "close_file",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0A, 0x00, 0x10,
+            0x00, 0x2A, 0x18, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00,
+            0x00,
+        ],
+    },
+    Fixture {
+        name: "close_file",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"54000000000000FF03FF0000140010182A000000010000",
+        response: b"D4000000000000FF03FF0000040000",
+    },
+];
+
+/// Frames of the pymcprotocol `Type3E`/`Type4E` calls named in the comments
+/// (and in `name`), with the library defaults: Q series, network 0, PC FFh,
+/// module I/O 03FFh, station 0, monitoring timer 4 (1 s) and serial number 0.
+/// A client with the same settings is `Client::new(host, port, "Q", use_e4)`.
+///
+/// The table is the output of `scripts/pymcprotocol_fixtures.py`, which
+/// runs the calls against pymcprotocol 0.3.0 with its socket replaced and
+/// checks the values it parses from the responses. Regenerate it from the
+/// script instead of editing the frames by hand.
+pub const PYMCPROTOCOL_FIXTURES: &[Fixture] = &[
+    // batchread_wordunits("D100", 3) -> [1, 2, 3]
+    Fixture {
+        name: "batchread_wordunits",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x04, 0x00, 0x01, 0x04, 0x00,
+            0x00, 0x64, 0x00, 0x00, 0xA8, 0x03, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02,
+            0x00, 0x03, 0x00,
+        ],
+    },
+    Fixture {
+        name: "batchread_wordunits",
+        use_e4: true,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x04,
+            0x00, 0x01, 0x04, 0x00, 0x00, 0x64, 0x00, 0x00, 0xA8, 0x03, 0x00,
+        ],
+        response: &[
+            0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00,
+            0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00,
+        ],
+    },
+    Fixture {
+        name: "batchread_wordunits",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF000018000404010000D*0001000003",
+        response: b"D00000FF03FF0000100000000100020003",
+    },
+    // batchwrite_wordunits("D10", [1, -1])
+    Fixture {
+        name: "batchwrite_wordunits",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x10, 0x00, 0x04, 0x00, 0x01, 0x14, 0x00,
+            0x00, 0x0A, 0x00, 0x00, 0xA8, 0x02, 0x00, 0x01, 0x00, 0xFF, 0xFF,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    // batchwrite_bitunits("M0", [1, 0, 1])
+    Fixture {
+        name: "batchwrite_bitunits",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0E, 0x00, 0x04, 0x00, 0x01, 0x14, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x90, 0x03, 0x00, 0x10, 0x10,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    // randomread(["D1000", "D2000"], ["D3000"]) -> [10, 20], [70000]
+    Fixture {
+        name: "randomread",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x14, 0x00, 0x04, 0x00, 0x03, 0x04, 0x00,
+            0x00, 0x02, 0x01, 0xE8, 0x03, 0x00, 0xA8, 0xD0, 0x07, 0x00, 0xA8, 0xB8, 0x0B, 0x00,
+            0xA8,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x14,
+            0x00, 0x70, 0x11, 0x01, 0x00,
+        ],
+    },
+    Fixture {
+        name: "randomread",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF0000280004040300000201D*001000D*002000D*003000",
+        response: b"D00000FF03FF0000140000000A001400011170",
+    },
+    // randomwrite(["D1000"], [5], ["D3000"], [70000])
+    Fixture {
+        name: "randomwrite",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x16, 0x00, 0x04, 0x00, 0x02, 0x14, 0x00,
+            0x00, 0x01, 0x01, 0xE8, 0x03, 0x00, 0xA8, 0x05, 0x00, 0xB8, 0x0B, 0x00, 0xA8, 0x70,
+            0x11, 0x01, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    Fixture {
+        name: "randomwrite",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_ASCII,
+        request: b"500000FF03FF00002C0004140200000101D*0010000005D*00300000011170",
+        response: b"D00000FF03FF0000040000",
+    },
+    // remote_run(clear_mode=0, force_exec=False)
+    Fixture {
+        name: "remote_run",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0A, 0x00, 0x04, 0x00, 0x01, 0x10, 0x00,
+            0x00, 0x01, 0x00, 0x00, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+    // remote_stop()
+    Fixture {
+        name: "remote_stop",
+        use_e4: false,
+        comm_type: consts::COMMTYPE_BINARY,
+        request: &[
+            0x50, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x04, 0x00, 0x02, 0x10, 0x00,
+            0x00, 0x01, 0x00,
+        ],
+        response: &[
+            0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+    },
+];
+
+pub fn fixture(name: &str, use_e4: bool, comm_type: &str) -> Option<&'static Fixture> {
+    FIXTURES
+        .iter()
+        .find(|f| f.name == name && f.use_e4 == use_e4 && f.comm_type == comm_type)
+}
+
+/// Q series client with the header settings the fixture frames were built with.
+pub fn client_for(fixture: &Fixture, host: &str, port: u16) -> Client {
+    let mut client = Client::new(host.to_string(), port, "Q", fixture.use_e4);
+    client.set_comm_type(fixture.comm_type);
+    client
+        .set_monitoring_timer(Duration::from_secs(4))
+        .expect("4 s is a valid monitoring timer");
+    client
+}
+
+/// Panics with the offset and both frames in hex if `actual` differs from
+/// `expected`. ASCII frames are shown as text.
+pub fn assert_frame_eq(actual: &[u8], expected: &[u8]) {
+    if actual == expected {
+        return;
+    }
+    let offset = actual
+        .iter()
+        .zip(expected)
+        .position(|(a, e)| a != e)
+        .unwrap_or(actual.len().min(expected.len()));
+    panic!(
+        "frames differ at byte {}\n  actual:   {}\n  expected: {}",
+        offset,
+        render_frame(actual),
+        render_frame(expected)
+    );
+}
+
+fn render_frame(frame: &[u8]) -> String {
+    if frame
+        .iter()
+        .all(|b| b.is_ascii_alphanumeric() || *b == b'*')
+    {
+        String::from_utf8_lossy(frame).to_string()
+    } else {
+        frame
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
@@ -0,0 +1,132 @@
+use std::error::Error;
+
+use super::client::Client;
+use super::db::DataType;
+
+/// Character encoding of string devices and of Q/L file names and file
+/// contents. Shift-JIS needs the `encoding` feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    #[cfg(feature = "encoding")]
+    ShiftJis,
+}
+
+impl TextEncoding {
+    pub fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+        match self {
+            TextEncoding::Utf8 => Ok(String::from_utf8(bytes.to_vec())?),
+            #[cfg(feature = "encoding")]
+            TextEncoding::ShiftJis => {
+                let (text, had_errors) = encoding_rs::SHIFT_JIS.decode_without_bom_handling(bytes);
+                if had_errors {
+                    return Err("Invalid Shift-JIS text".into());
+                }
+                Ok(text.into_owned())
+            }
+        }
+    }
+
+    /// Like `decode`, replacing invalid sequences with U+FFFD.
+    pub fn decode_lossy(&self, bytes: &[u8]) -> String {
+        match self {
+            TextEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            #[cfg(feature = "encoding")]
+            TextEncoding::ShiftJis => encoding_rs::SHIFT_JIS
+                .decode_without_bom_handling(bytes)
+                .0
+                .into_owned(),
+        }
+    }
+
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        match self {
+            TextEncoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            #[cfg(feature = "encoding")]
+            TextEncoding::ShiftJis => {
+                let (bytes, _, had_errors) = encoding_rs::SHIFT_JIS.encode(text);
+                if had_errors {
+                    return Err(format!("{} cannot be encoded as Shift-JIS", text).into());
+                }
+                Ok(bytes.into_owned())
+            }
+        }
+    }
+}
+
+impl Client {
+    /// Reads a string of at most `max_bytes` bytes stored from `device` on,
+    /// two bytes per word with the low byte first, up to the first NUL.
+    pub fn read_string(&self, device: &str, max_bytes: usize) -> Result<String, Box<dyn Error>> {
+        let mut words = vec![0u16; max_bytes.div_ceil(2)];
+        self.batch_read_words_into(device, &mut words)?;
+        let mut bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        bytes.truncate(max_bytes);
+        if let Some(end) = bytes.iter().position(|&byte| byte == 0) {
+            bytes.truncate(end);
+        }
+        self.text_encoding().decode(&bytes)
+    }
+
+    /// Writes `text` from `device` on in the layout `read_string` expects,
+    /// NUL terminated when it fills less than `max_bytes`.
+    pub fn write_string(
+        &self,
+        device: &str,
+        text: &str,
+        max_bytes: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut bytes = self.text_encoding().encode(text)?;
+        if bytes.len() > max_bytes {
+            return Err(format!(
+                "{} bytes of text do not fit in {} bytes from {}",
+                bytes.len(),
+                max_bytes,
+                device
+            )
+            .into());
+        }
+        if bytes.len() < max_bytes {
+            bytes.push(0);
+        }
+        if bytes.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        let words = bytes
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]) as i64)
+            .collect();
+        self.batch_write(device, words, &DataType::UWORD)
+    }
+}
+
+#[cfg(test)]
+mod tests_text {
+    use super::*;
+    use crate::simulator::Simulator;
+
+    #[test]
+    fn test_strings() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+        client.write_string("D100", "ABC", 8)?;
+        assert_eq!(simulator.get("D100"), 0x4241);
+        assert_eq!(simulator.get("D101"), 0x0043);
+        assert_eq!(client.read_string("D100", 8)?, "ABC");
+        assert_eq!(client.read_string("D100", 2)?, "AB");
+        assert!(client.write_string("D100", "too long", 4).is_err());
+
+        simulator.set("D200", 0xA082);
+        assert!(client.read_string("D200", 2).is_err());
+        #[cfg(feature = "encoding")]
+        {
+            client.set_text_encoding(TextEncoding::ShiftJis);
+            assert_eq!(client.read_string("D200", 2)?, "\u{3042}");
+            client.write_string("D300", "\u{3044}", 2)?;
+            assert_eq!(simulator.get("D300"), 0xA282);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+use std::error::Error;
+
+use super::client::{get_device_type, Client};
+use super::db::{DataType, DeviceConstants};
+use super::tag::Tag;
+
+// Upper bound of word points in one random write frame (Q/L series limit)
+const MAX_RANDOM_WRITE_WORDS: i64 = 160;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteStatus {
+    // passed validation, not sent yet
+    Pending,
+    Written,
+    // rejected by local validation, nothing was sent
+    Invalid(String),
+    // not sent because another item failed validation
+    Skipped,
+    // the frame carrying this item failed
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct WriteItemResult {
+    pub device: String,
+    pub value: i64,
+    pub data_type: DataType,
+    pub status: WriteStatus,
+}
+
+impl WriteItemResult {
+    pub fn is_success(&self) -> bool {
+        self.status == WriteStatus::Written
+    }
+}
+
+// Collects writes, validates all of them, then sends them in order, merging adjacent items into frames
+pub struct WriteTransaction<'a> {
+    client: &'a Client,
+    items: Vec<(String, i64, DataType)>,
+}
+
+impl<'a> WriteTransaction<'a> {
+    pub(crate) fn new(client: &'a Client) -> Self {
+        Self {
+            client,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn write(mut self, device: &str, value: i64, data_type: DataType) -> Self {
+        self.items.push((device.to_string(), value, data_type));
+        self
+    }
+
+    pub fn validate(&self) -> Vec<WriteItemResult> {
+        self.items
+            .iter()
+            .map(|(device, value, data_type)| {
+                let status = match self.validate_item(device, *value, data_type) {
+                    Ok(()) => WriteStatus::Pending,
+                    Err(e) => WriteStatus::Invalid(e.to_string()),
+                };
+                WriteItemResult {
+                    device: device.clone(),
+                    value: *value,
+                    data_type: data_type.clone(),
+                    status,
+                }
+            })
+            .collect()
+    }
+
+    /// Validates every item and, if all are valid, sends them. Frames are
+    /// sent in the order the items were written, so a trigger bit written
+    /// after its data words is set only once the words are written. A
+    /// frame carries only adjacent items of one kind. A failed frame does
+    /// not stop the frames after it.
+    pub fn commit(self) -> Vec<WriteItemResult> {
+        let mut results = self.validate();
+        if results
+            .iter()
+            .any(|result| matches!(result.status, WriteStatus::Invalid(_)))
+        {
+            for result in results.iter_mut() {
+                if result.status == WriteStatus::Pending {
+                    result.status = WriteStatus::Skipped;
+                }
+            }
+            return results;
+        }
+
+        // Frames go out in item order. Adjacent items of one kind share a
+        // frame: consecutive bits a batch write, words a random write.
+        let mut frames: Vec<(bool, Vec<usize>)> = Vec::new();
+        let mut frame_words = 0;
+        for (index, result) in results.iter().enumerate() {
+            let is_bit = result.data_type == DataType::BIT;
+            let words = result.data_type.size() as i64 / 2;
+            let joins = match frames.last() {
+                Some((true, run)) if is_bit => {
+                    self.follows(&results[*run.last().unwrap()].device, &result.device)
+                }
+                Some((false, _)) if !is_bit => frame_words + words <= MAX_RANDOM_WRITE_WORDS,
+                _ => false,
+            };
+            if joins {
+                frames.last_mut().unwrap().1.push(index);
+            } else {
+                frames.push((is_bit, vec![index]));
+                frame_words = 0;
+            }
+            if !is_bit {
+                frame_words += words;
+            }
+        }
+
+        for (is_bit, frame) in frames {
+            let sent = if is_bit {
+                let values = frame.iter().map(|index| results[*index].value).collect();
+                self.client
+                    .batch_write(&results[frame[0]].device, values, &DataType::BIT)
+            } else {
+                let tags = frame
+                    .iter()
+                    .map(|index| {
+                        let result = &results[*index];
+                        Tag::new(
+                            result.device.clone(),
+                            Some(result.value.to_string()),
+                            result.data_type.clone(),
+                        )
+                    })
+                    .collect();
+                self.client.write(tags)
+            };
+            let status = match sent {
+                Ok(()) => WriteStatus::Written,
+                Err(e) => WriteStatus::Failed(e.to_string()),
+            };
+            for index in frame {
+                results[index].status = status.clone();
+            }
+        }
+
+        results
+    }
+
+    // Whether `device` is the point right after `last`, counted in the
+    // numbering of the device type (hexadecimal for X and Y on a Q CPU)
+    fn follows(&self, last: &str, device: &str) -> bool {
+        match (
+            self.client.offset_device(last, 1),
+            self.client.offset_device(device, 0),
+        ) {
+            (Ok(next), Ok(device)) => next == device,
+            _ => false,
+        }
+    }
+
+    fn validate_item(
+        &self,
+        device: &str,
+        value: i64,
+        data_type: &DataType,
+    ) -> Result<(), Box<dyn Error>> {
+        let device_name = get_device_type(device)?;
+        self.client.offset_device(device, 0)?;
+        let device_kind = self.client.device_kind(&device_name)?;
+        let is_bit_device = device_kind == DeviceConstants::BIT_DEVICE;
+        if is_bit_device != (*data_type == DataType::BIT) {
+            return Err(format!(
+                "{:?} cannot be written to {} device",
+                data_type, device_kind
+            )
+            .into());
+        }
+
+        let (min, max) = match data_type {
+            DataType::BIT => (0, 1),
+            DataType::SWORD => (i16::MIN as i64, i16::MAX as i64),
+            DataType::UWORD => (0, u16::MAX as i64),
+            DataType::SDWORD => (i32::MIN as i64, i32::MAX as i64),
+            DataType::UDWORD => (0, u32::MAX as i64),
+            DataType::SLWORD | DataType::ULWORD | DataType::FLOAT | DataType::DOUBLE => {
+                (i64::MIN, i64::MAX)
+            }
+            DataType::CUSTOM { name, .. } => {
+                return Err(format!("Cannot write custom data type \"{}\"", name).into())
+            }
+            DataType::RAW(_) => return Err("Cannot write raw bytes as a number".into()),
+        };
+        if value < min || value > max {
+            return Err(format!("Value {} out of range for {:?}", value, data_type).into());
+        }
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Sends the items of a committed transaction that failed in transit
+    /// again, in as few frames as possible, and updates their status.
+    /// Written, invalid and skipped items are left alone. Returns whether
+    /// every item is written now.
+    pub fn retry_failed_writes(&self, results: &mut [WriteItemResult]) -> bool {
+        let failed: Vec<usize> = (0..results.len())
+            .filter(|index| matches!(results[*index].status, WriteStatus::Failed(_)))
+            .collect();
+        let mut transaction = WriteTransaction::new(self);
+        for index in failed.iter() {
+            let result = &results[*index];
+            transaction = transaction.write(&result.device, result.value, result.data_type.clone());
+        }
+        for (index, retried) in failed.into_iter().zip(transaction.commit()) {
+            results[index].status = retried.status;
+        }
+        results.iter().all(WriteItemResult::is_success)
+    }
+}
+
+#[cfg(test)]
+mod tests_transaction {
+    use super::*;
+    use crate::simulator::Simulator;
+
+    #[test]
+    fn test_write_transaction() -> Result<(), Box<dyn Error>> {
+        let simulator = Simulator::start();
+        let mut client = Client::new("127.0.0.1".to_string(), simulator.port(), "Q", true);
+        client.connect()?;
+
+        let results = client
+            .write_transaction()
+            .write("D100", 70000, DataType::SWORD)
+            .write("M10", 1, DataType::BIT)
+            .write("M20", 1, DataType::SWORD)
+            .commit();
+        assert!(matches!(results[0].status, WriteStatus::Invalid(_)));
+        assert_eq!(results[1].status, WriteStatus::Skipped);
+        assert!(matches!(results[2].status, WriteStatus::Invalid(_)));
+        assert_eq!(simulator.transactions(), 0);
+
+        let results = client
+            .write_transaction()
+            .write("D100", -5, DataType::SWORD)
+            .write("M10", 1, DataType::BIT)
+            .write("M11", 0, DataType::BIT)
+            .write("D200", 70000, DataType::UDWORD)
+            .commit();
+        assert!(results.iter().all(|result| result.is_success()));
+        assert_eq!(simulator.transactions(), 3);
+
+        // Y is numbered in hex on a Q CPU, Y0A follows Y9 and Y10 does not
+        let results = client
+            .write_transaction()
+            .write("Y9", 1, DataType::BIT)
+            .write("Y0A", 1, DataType::BIT)
+            .write("Y10", 1, DataType::BIT)
+            .commit();
+        assert!(results.iter().all(|result| result.is_success()));
+        assert_eq!(simulator.transactions(), 5);
+        assert_eq!(
+            (
+                simulator.get("Y9"),
+                simulator.get("Y0A"),
+                simulator.get("Y10")
+            ),
+            (1, 1, 1)
+        );
+
+        // a trigger bit written after the data goes out after it
+        simulator.clear_history();
+        let results = client
+            .write_transaction()
+            .write("D0", 1, DataType::SWORD)
+            .write("D1", 2, DataType::SWORD)
+            .write("M0", 1, DataType::BIT)
+            .write("D2", 3, DataType::SWORD)
+            .commit();
+        assert!(results.iter().all(|result| result.is_success()));
+        let history: Vec<(usize, String)> = simulator
+            .history()
+            .into_iter()
+            .map(|record| (record.frame, record.device))
+            .collect();
+        let frame = history[0].0;
+        assert_eq!(
+            history,
+            [("D0", 0), ("D1", 0), ("M0", 1), ("D2", 2)]
+                .map(|(device, offset)| (frame + offset, device.to_string()))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+//! Load test against the in-crate simulator. Ignored by default, run with
+//! `cargo test --features test-fixtures --test stress -- --ignored`.
+
+use rs_melsec::client::Client;
+use rs_melsec::db::DataType;
+use rs_melsec::pool::ClientPool;
+use rs_melsec::simulator::Simulator;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const THREADS: usize = 16;
+const TRANSACTIONS: usize = 500;
+const RECONNECT_EVERY: usize = 100;
+
+#[test]
+#[ignore]
+fn concurrent_clients_read_back_their_writes() {
+    let simulator = Simulator::start();
+    let port = simulator.port();
+    let started = Instant::now();
+    let workers: Vec<_> = (0..THREADS)
+        .map(|worker| {
+            thread::spawn(move || {
+                let mut client = Client::new("127.0.0.1".to_string(), port, "Q", worker % 2 == 0);
+                client.connect().unwrap();
+                let device = format!("D{}", worker * 10);
+                for round in 0..TRANSACTIONS {
+                    if round % RECONNECT_EVERY == RECONNECT_EVERY - 1 {
+                        client.close().unwrap();
+                        client.connect().unwrap();
+                    }
+                    let values = vec![round as i64, worker as i64, -(round as i64)];
+                    client
+                        .batch_write(&device, values.clone(), &DataType::SWORD)
+                        .unwrap();
+                    let tags = client
+                        .batch_read(&device, 3, DataType::SWORD, true)
+                        .unwrap();
+                    let read: Vec<i64> = tags
+                        .iter()
+                        .map(|tag| tag.value.as_ref().unwrap().parse().unwrap())
+                        .collect();
+                    assert_eq!(read, values);
+                }
+                client.stats().transactions
+            })
+        })
+        .collect();
+    for worker in workers {
+        // a join that never returns shows up as a test timeout
+        assert_eq!(worker.join().unwrap(), 2 * TRANSACTIONS as u64);
+    }
+    assert!(started.elapsed() < Duration::from_secs(120));
+    assert_eq!(simulator.transactions(), 2 * THREADS * TRANSACTIONS);
+    // every worker only ever touched its own three registers
+    assert_eq!(simulator.memory_size(), 3 * THREADS);
+}
+
+#[test]
+#[ignore]
+fn pool_shared_between_threads() {
+    let simulator = Simulator::start();
+    let pool = Arc::new(ClientPool::connect("127.0.0.1", simulator.port(), "Q", true, 4).unwrap());
+    let workers: Vec<_> = (0..THREADS)
+        .map(|worker| {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || {
+                let device = format!("D{}", 1000 + worker);
+                for round in 0..TRANSACTIONS {
+                    pool.execute(|client| {
+                        client.batch_write(&device, vec![round as i64], &DataType::UWORD)?;
+                        let mut out = [0u16; 1];
+                        client.batch_read_words_into(&device, &mut out)?;
+                        assert_eq!(out[0] as usize, round);
+                        Ok(())
+                    })
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert!(pool.health().iter().all(|health| health.healthy));
+    assert_eq!(simulator.transactions(), 2 * THREADS * TRANSACTIONS);
+}